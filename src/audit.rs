@@ -0,0 +1,241 @@
+use crate::{discord::DISCORD_MESSAGE_LIMIT, AppContext};
+use serde::{Deserialize, Serialize};
+use serenity::all::{CreateAllowedMentions, CreateAttachment, CreateMessage, Http, UserId};
+use std::{path::Path, sync::Arc, time::Duration};
+use tokio::{
+	io::AsyncWriteExt,
+	sync::{watch, Mutex},
+};
+
+/// How often the admin actions are summarized in the admin log channel
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditEntry {
+	pub timestamp: u64,
+	pub user_id: UserId,
+	pub user_name: Box<str>,
+	pub action: Box<str>,
+	pub diff: Option<Box<str>>,
+}
+
+/// Append-only JSON lines log of every admin action
+#[derive(Clone)]
+pub struct AuditLog(Arc<AuditLogInner>);
+impl AuditLog {
+	pub async fn new(path: &Path) -> Result<Self, anyhow::Error> {
+		let file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+
+		Ok(Self(Arc::new(AuditLogInner {
+			path: path.into(),
+			file: Mutex::new(file),
+		})))
+	}
+
+	pub async fn record(&self, user_id: UserId, user_name: &str, action: &str, diff: Option<&str>) {
+		let entry = AuditEntry {
+			timestamp: crate::unix_now(),
+			user_id,
+			user_name: user_name.into(),
+			action: action.into(),
+			diff: diff.map(Into::into),
+		};
+
+		log::info!("Audit: {user_name} ({user_id}) {action}");
+
+		let result = async {
+			let mut line = serde_json::to_vec(&entry)?;
+			line.push(b'\n');
+			self.0.file.lock().await.write_all(&line).await?;
+			Ok::<_, anyhow::Error>(())
+		}
+		.await;
+
+		if let Err(err) = result {
+			log::error!("Failed to write audit log entry ({err})");
+		}
+	}
+
+	pub async fn recent(&self, count: usize) -> Result<Vec<AuditEntry>, anyhow::Error> {
+		let _lock = self.0.file.lock().await;
+
+		let log = tokio::fs::read_to_string(&self.0.path).await?;
+
+		let mut entries = log
+			.lines()
+			.rev()
+			.filter(|line| !line.is_empty())
+			.filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+			.take(count)
+			.collect::<Vec<_>>();
+
+		entries.reverse();
+
+		Ok(entries)
+	}
+}
+
+impl AuditLog {
+	/// Deletes entries from before `cutoff`, a unix timestamp
+	pub async fn prune(&self, cutoff: u64) -> Result<(), anyhow::Error> {
+		self.retain(|entry| entry.timestamp >= cutoff).await.map(drop)
+	}
+
+	/// Every action taken since `cutoff`, a unix timestamp
+	pub async fn since(&self, cutoff: u64) -> Result<Vec<AuditEntry>, anyhow::Error> {
+		let _lock = self.0.file.lock().await;
+
+		let log = tokio::fs::read_to_string(&self.0.path).await?;
+
+		Ok(log
+			.lines()
+			.filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+			.filter(|entry| entry.timestamp >= cutoff)
+			.collect())
+	}
+
+	/// Every action taken by the user
+	pub async fn for_user(&self, user_id: UserId) -> Result<Vec<AuditEntry>, anyhow::Error> {
+		let _lock = self.0.file.lock().await;
+
+		let log = tokio::fs::read_to_string(&self.0.path).await?;
+
+		let mut entries = Vec::new();
+		for entry in log.lines().filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok()) {
+			if entry.user_id == user_id {
+				entries.push(entry);
+			}
+		}
+
+		Ok(entries)
+	}
+
+	/// Rewrites the log with only the entries matching `keep`, returning how many were deleted
+	async fn retain(&self, keep: impl Fn(&AuditEntry) -> bool) -> Result<usize, anyhow::Error> {
+		let mut file = self.0.file.lock().await;
+
+		let log = tokio::fs::read_to_string(&self.0.path).await?;
+
+		let mut kept = String::with_capacity(log.len());
+		let mut deleted = 0;
+		for line in log.lines().filter(|line| !line.is_empty()) {
+			match serde_json::from_str::<AuditEntry>(line) {
+				Ok(entry) if !keep(&entry) => deleted += 1,
+				Ok(_) => {
+					kept.push_str(line);
+					kept.push('\n');
+				}
+				// e.g. cut short by a crash mid-write, which shouldn't stop the rest from being pruned
+				Err(err) => {
+					log::warn!("Leaving malformed audit log entry as it is ({err})");
+					kept.push_str(line);
+					kept.push('\n');
+				}
+			}
+		}
+
+		if deleted > 0 {
+			// Opened in append mode, so writes carry on from the new end
+			file.set_len(0).await?;
+			file.write_all(kept.as_bytes()).await?;
+		}
+
+		Ok(deleted)
+	}
+}
+
+/// Posts the admin actions of the past day to the admin log channel, once a day. Nothing is posted for a quiet day
+pub async fn daily_summary(app_ctx: AppContext, http: watch::Receiver<Option<Arc<Http>>>) {
+	let mut interval = tokio::time::interval(SUMMARY_INTERVAL);
+	interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+	// The first tick is immediate, and there's nothing to summarize yet
+	interval.tick().await;
+
+	loop {
+		interval.tick().await;
+
+		let Some(log_channel_id) = app_ctx
+			.config
+			.get()
+			.await
+			.admin_guild
+			.as_ref()
+			.map(|admin_guild| admin_guild.log_channel_id)
+		else {
+			continue;
+		};
+
+		let entries = match app_ctx.audit.since(crate::unix_now().saturating_sub(SUMMARY_INTERVAL.as_secs())).await {
+			Ok(entries) if entries.is_empty() => continue,
+			Ok(entries) => entries,
+			Err(err) => {
+				log::error!("Failed to read the audit log for the daily summary ({err})");
+				continue;
+			}
+		};
+
+		// Not connected yet, or still reconnecting
+		let Some(http) = http.borrow().clone() else {
+			continue;
+		};
+
+		let summary = summarize(&entries);
+		let message = if summary.len() <= DISCORD_MESSAGE_LIMIT {
+			CreateMessage::new().content(summary)
+		} else {
+			CreateMessage::new()
+				.content(format!("**Daily summary**\n{} admin actions in the last 24 hours", entries.len()))
+				.add_file(CreateAttachment::bytes(summary, "audit_summary.md"))
+		};
+
+		if let Err(err) = log_channel_id
+			.send_message(&http, message.allowed_mentions(CreateAllowedMentions::new()))
+			.await
+		{
+			log::error!("Failed to post the daily summary ({err})");
+		}
+	}
+}
+
+/// Diffs are left out, they're in `/admin audit`
+fn summarize(entries: &[AuditEntry]) -> String {
+	let mut summary = format!("**Daily summary**\n{} admin actions in the last 24 hours\n", entries.len());
+	for entry in entries {
+		summary.push_str(&format!("<t:{}:t> <@{}> {}\n", entry.timestamp, entry.user_id, entry.action));
+	}
+	summary
+}
+
+struct AuditLogInner {
+	path: Box<Path>,
+	file: Mutex<tokio::fs::File>,
+}
+
+#[test]
+fn prune_skips_malformed_lines() {
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			let path = std::env::temp_dir().join(format!("audit_{}.jsonl", uuid::Uuid::new_v4().simple()));
+			let audit = AuditLog::new(&path).await.unwrap();
+
+			audit.record(UserId::new(1), "old", "edited config", None).await;
+			tokio::fs::OpenOptions::new()
+				.append(true)
+				.open(&path)
+				.await
+				.unwrap()
+				.write_all(b"{\"timestamp\":12\n")
+				.await
+				.unwrap();
+
+			assert_eq!(audit.recent(10).await.unwrap().len(), 1);
+			audit.prune(u64::MAX).await.unwrap();
+			assert!(audit.recent(10).await.unwrap().is_empty());
+
+			tokio::fs::remove_file(&path).await.ok();
+		});
+}
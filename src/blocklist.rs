@@ -0,0 +1,77 @@
+use crate::store::JsonStore;
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, UserId};
+use std::{collections::BTreeSet, fmt::Write, path::Path, sync::Arc};
+
+#[derive(Serialize, Deserialize, Default)]
+struct BlockedIds {
+	#[serde(default)]
+	users: BTreeSet<UserId>,
+	#[serde(default)]
+	guilds: BTreeSet<GuildId>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BlockTarget {
+	User(UserId),
+	Guild(GuildId),
+}
+
+/// Persistent list of users and guilds whose messages and commands are ignored, managed with `/admin block`
+#[derive(Clone)]
+pub struct Blocklist(Arc<JsonStore<BlockedIds>>);
+impl Blocklist {
+	pub async fn new(path: &Path) -> Result<Self, anyhow::Error> {
+		Ok(Self(Arc::new(JsonStore::open(path.to_owned()).await?)))
+	}
+
+	pub async fn is_blocked(&self, user_id: UserId, guild_id: Option<GuildId>) -> bool {
+		self.0
+			.read(|blocked| blocked.users.contains(&user_id) || guild_id.is_some_and(|guild_id| blocked.guilds.contains(&guild_id)))
+			.await
+	}
+
+	/// Returns whether the target wasn't already blocked
+	pub async fn block(&self, target: BlockTarget) -> Result<bool, anyhow::Error> {
+		self.0
+			.update(|blocked| match target {
+				BlockTarget::User(user_id) => blocked.users.insert(user_id),
+				BlockTarget::Guild(guild_id) => blocked.guilds.insert(guild_id),
+			})
+			.await
+	}
+
+	/// Returns whether the target was blocked
+	pub async fn unblock(&self, target: BlockTarget) -> Result<bool, anyhow::Error> {
+		self.0
+			.update(|blocked| match target {
+				BlockTarget::User(user_id) => blocked.users.remove(&user_id),
+				BlockTarget::Guild(guild_id) => blocked.guilds.remove(&guild_id),
+			})
+			.await
+	}
+
+	/// Markdown list of everything blocked
+	pub async fn describe(&self) -> String {
+		self.0
+			.read(|blocked| {
+				let mut description = String::new();
+				for user_id in &blocked.users {
+					writeln!(description, "- User <@{user_id}> (`{user_id}`)").ok();
+				}
+				for guild_id in &blocked.guilds {
+					writeln!(description, "- Guild `{guild_id}`").ok();
+				}
+				description
+			})
+			.await
+	}
+}
+impl std::fmt::Display for BlockTarget {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::User(user_id) => write!(f, "user <@{user_id}>"),
+			Self::Guild(guild_id) => write!(f, "guild `{guild_id}`"),
+		}
+	}
+}
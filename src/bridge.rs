@@ -0,0 +1,223 @@
+use crate::{
+	error::{ConfigError, UploadError},
+	pipeline::Uploader,
+	privacy::Secret,
+	yt_dlp::MediaResult,
+};
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use std::sync::LazyLock;
+
+/// Telegram's upload limit for bots
+const TELEGRAM_FILE_SIZE_LIMIT: u64 = 50 * 1000 * 1000;
+
+static BRIDGE_HTTP: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+/// Another platform downloaded media is also forwarded to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BridgeConfig {
+	Telegram {
+		bot_token: Secret,
+		/// Numeric chat ID, or `@username` of a public channel
+		chat_id: String,
+	},
+	Matrix {
+		/// e.g. `https://matrix.org`
+		homeserver: String,
+		access_token: Secret,
+		/// e.g. `!abcdef:matrix.org`
+		room_id: String,
+	},
+}
+impl BridgeConfig {
+	pub fn compile(&self) -> Result<Bridge, ConfigError> {
+		Ok(match self {
+			Self::Telegram { bot_token, chat_id } => Bridge {
+				name: format!("Telegram chat {chat_id}").into(),
+				sink: Box::new(TelegramChat {
+					bot_token: bot_token.clone(),
+					chat_id: chat_id.as_str().into(),
+				}),
+			},
+
+			Self::Matrix {
+				homeserver,
+				access_token,
+				room_id,
+			} => {
+				let homeserver = reqwest::Url::parse(homeserver)
+					.map_err(|err| ConfigError::invalid(format!("Invalid Matrix homeserver {homeserver:?} ({err})")))?;
+				if homeserver.cannot_be_a_base() {
+					return Err(ConfigError::invalid(format!("Matrix homeserver {homeserver} is not a base URL")));
+				}
+
+				let mut upload_url = homeserver.clone();
+				upload_url
+					.path_segments_mut()
+					.unwrap()
+					.pop_if_empty()
+					.extend(["_matrix", "media", "v3", "upload"]);
+
+				let mut send_url = homeserver;
+				send_url
+					.path_segments_mut()
+					.unwrap()
+					.pop_if_empty()
+					.extend(["_matrix", "client", "v3", "rooms", room_id, "send", "m.room.message"]);
+
+				Bridge {
+					name: format!("Matrix room {room_id}").into(),
+					sink: Box::new(MatrixRoom {
+						upload_url,
+						send_url,
+						access_token: access_token.clone(),
+					}),
+				}
+			}
+		})
+	}
+}
+
+pub struct Bridge {
+	pub name: Box<str>,
+	pub sink: Box<dyn Uploader>,
+}
+
+/// Forwards media to each bridge, failures are only logged as the media has already been posted to Discord
+pub async fn forward(bridges: &[Bridge], media: &MediaResult) {
+	for bridge in bridges {
+		if let Err(err) = bridge.sink.upload(media).await {
+			log::error!("Failed to forward {} to {} ({err})", media.path.display(), bridge.name);
+		}
+	}
+}
+
+fn file_name(media: &MediaResult) -> String {
+	media
+		.path
+		.file_name()
+		.map(|file_name| file_name.to_string_lossy().into_owned())
+		.unwrap_or_default()
+}
+
+fn mime_type(media: &MediaResult) -> &'static str {
+	match media.path.extension().and_then(|ext| ext.to_str()) {
+		Some("mp4") => "video/mp4",
+		Some("webm") => "video/webm",
+		Some("mov") => "video/quicktime",
+		Some("mp3") => "audio/mpeg",
+		Some("m4a") => "audio/mp4",
+		Some("ogg" | "opus") => "audio/ogg",
+		Some("gif") => "image/gif",
+		Some("png") => "image/png",
+		Some("jpg" | "jpeg") => "image/jpeg",
+		_ => "application/octet-stream",
+	}
+}
+
+/// Posts media to a Telegram chat through the Bot API
+pub struct TelegramChat {
+	bot_token: Secret,
+	chat_id: Box<str>,
+}
+#[async_trait]
+impl Uploader for TelegramChat {
+	async fn upload(&self, media: &MediaResult) -> Result<(), UploadError> {
+		let (body, size) = crate::file_body(&media.path).await?;
+		if size > TELEGRAM_FILE_SIZE_LIMIT {
+			return Err(UploadError::TooLarge);
+		}
+
+		let mime_type = mime_type(media);
+		let (method, field) = match mime_type.split_once('/') {
+			Some(("video", _)) => ("sendVideo", "video"),
+			Some(("audio", _)) => ("sendAudio", "audio"),
+			_ => ("sendDocument", "document"),
+		};
+
+		let file = reqwest::multipart::Part::stream_with_length(body, size)
+			.file_name(file_name(media))
+			.mime_str(mime_type)?;
+
+		let mut form = reqwest::multipart::Form::new()
+			.text("chat_id", self.chat_id.to_string())
+			.part(field, file);
+		if let Some(title) = &media.metadata.title {
+			form = form.text("caption", title.to_string());
+		}
+
+		BRIDGE_HTTP
+			.post(format!("https://api.telegram.org/bot{}/{method}", self.bot_token.expose()))
+			.multipart(form)
+			.send()
+			.await
+			.and_then(reqwest::Response::error_for_status)
+			// The URL contains the bot token
+			.map_err(reqwest::Error::without_url)?;
+
+		Ok(())
+	}
+}
+
+/// Posts media to a Matrix room through the client-server API
+pub struct MatrixRoom {
+	upload_url: reqwest::Url,
+	/// Missing the transaction ID
+	send_url: reqwest::Url,
+	access_token: Secret,
+}
+#[async_trait]
+impl Uploader for MatrixRoom {
+	async fn upload(&self, media: &MediaResult) -> Result<(), UploadError> {
+		#[derive(Deserialize)]
+		struct Uploaded {
+			content_uri: String,
+		}
+
+		let (body, size) = crate::file_body(&media.path).await?;
+		let file_name = file_name(media);
+		let mime_type = mime_type(media);
+
+		let mut upload_url = self.upload_url.clone();
+		upload_url.query_pairs_mut().append_pair("filename", &file_name);
+
+		let uploaded = BRIDGE_HTTP
+			.post(upload_url)
+			.bearer_auth(self.access_token.expose())
+			.header(reqwest::header::CONTENT_TYPE, mime_type)
+			.header(reqwest::header::CONTENT_LENGTH, size)
+			.body(body)
+			.send()
+			.await?
+			.error_for_status()?
+			.json::<Uploaded>()
+			.await?;
+
+		let msgtype = match mime_type.split_once('/') {
+			Some(("video", _)) => "m.video",
+			Some(("audio", _)) => "m.audio",
+			Some(("image", _)) => "m.image",
+			_ => "m.file",
+		};
+
+		let mut send_url = self.send_url.clone();
+		send_url.path_segments_mut().unwrap().push(&uuid::Uuid::new_v4().to_string());
+
+		BRIDGE_HTTP
+			.put(send_url)
+			.bearer_auth(self.access_token.expose())
+			.json(&serde_json::json!({
+				"msgtype": msgtype,
+				"body": media.metadata.title.as_deref().unwrap_or(&file_name),
+				"filename": file_name,
+				"url": uploaded.content_uri,
+				"info": { "mimetype": mime_type, "size": size },
+			}))
+			.send()
+			.await?
+			.error_for_status()?;
+
+		Ok(())
+	}
+}
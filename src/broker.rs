@@ -0,0 +1,158 @@
+use crate::{
+	error::DownloadError,
+	pipeline::Downloader,
+	progress::{DownloadProgress, ProgressReporter},
+	queue::Priority,
+	yt_dlp::{DownloadOptions, MediaResult},
+};
+use std::{collections::HashMap, path::Path, sync::Arc};
+use tokio::sync::watch;
+
+type SharedResult = Result<Arc<MediaResult>, Arc<DownloadError>>;
+
+/// Downloads shared between all the bots in the process, so the same viral link posted in servers of several bots (or twice in one)
+/// is only downloaded once. Everyone waiting on a download gets their own hard link to the file
+#[derive(Clone)]
+pub struct JobBroker(Arc<JobBrokerInner>);
+
+struct JobBrokerInner {
+	downloader: Arc<dyn Downloader>,
+	/// Downloads in progress, by URL and options
+	in_flight: parking_lot::Mutex<HashMap<Box<str>, watch::Receiver<Option<SharedResult>>>>,
+}
+
+impl JobBroker {
+	pub fn new(downloader: Arc<dyn Downloader>) -> Self {
+		Self(Arc::new(JobBrokerInner {
+			downloader,
+			in_flight: Default::default(),
+		}))
+	}
+
+	/// Downloads `url`, or joins the download already in progress with the same options
+	pub async fn download(&self, url: &str, options: &DownloadOptions) -> Result<MediaResult, DownloadError> {
+		// The same URL with different options (audio only, quality, upload limit...) is a different download, the priority only
+		// decides who gets a slot first and the progress only goes to whoever started it
+		let key = format!(
+			"{url} {:?}",
+			DownloadOptions {
+				priority: Priority::default(),
+				progress: ProgressReporter::default(),
+				..options.clone()
+			}
+		)
+		.into_boxed_str();
+
+		let joined = {
+			let mut in_flight = self.0.in_flight.lock();
+			match in_flight.get(&key) {
+				Some(rx) => Ok(rx.clone()),
+				None => {
+					let (tx, rx) = watch::channel(None);
+					in_flight.insert(key.clone(), rx);
+					Err(tx)
+				}
+			}
+		};
+
+		let mut rx = match joined {
+			Ok(rx) => rx,
+			Err(tx) => return self.lead(key, url, options, tx).await,
+		};
+
+		log::info!("Joining the download of {url} already in progress");
+
+		options.progress.report(DownloadProgress::Downloading { percent: None });
+
+		let result = rx.wait_for(Option::is_some).await.map(|result| result.clone().unwrap());
+		drop(rx);
+
+		let result = match result {
+			Ok(result) => result,
+
+			// The download was cancelled, e.g. with the /download cancel button or a 🛑 reaction
+			Err(_) => return self.0.downloader.download(url, options).await,
+		};
+
+		match result {
+			Ok(media) => Ok(link(&media).await?),
+			Err(err) => Err(DownloadError::Shared(err)),
+		}
+	}
+
+	async fn lead(
+		&self,
+		key: Box<str>,
+		url: &str,
+		options: &DownloadOptions,
+		tx: watch::Sender<Option<SharedResult>>,
+	) -> Result<MediaResult, DownloadError> {
+		let guard = InFlight { broker: self, key };
+		let result = self.0.downloader.download(url, options).await;
+
+		// Nobody can join once it's out of the map, so if nobody has by now the result is ours alone
+		drop(guard);
+		if tx.receiver_count() == 0 {
+			return result;
+		}
+
+		let result = result.map(Arc::new).map_err(Arc::new);
+		tx.send_replace(Some(result.clone()));
+		drop(tx);
+
+		// The original file is deleted once everyone has their own link to it
+		match result {
+			Ok(media) => Ok(link(&media).await?),
+			Err(err) => Err(DownloadError::Shared(err)),
+		}
+	}
+}
+
+/// Removes the download from the map when done, or when cancelled
+struct InFlight<'a> {
+	broker: &'a JobBroker,
+	key: Box<str>,
+}
+impl Drop for InFlight<'_> {
+	fn drop(&mut self) {
+		self.broker.0.in_flight.lock().remove(&self.key);
+	}
+}
+
+/// A copy of `media` under a new name, which is deleted independently of the original
+async fn link(media: &MediaResult) -> Result<MediaResult, std::io::Error> {
+	let mut file_name = uuid::Uuid::new_v4().to_string();
+	if let Some(ext) = media.path.extension() {
+		file_name = format!("{file_name}.{}", ext.to_string_lossy());
+	}
+
+	let path = media.path.parent().unwrap_or(Path::new("")).join(file_name);
+
+	// Hard links are free, but not available on every filesystem
+	if tokio::fs::hard_link(&media.path, &path).await.is_err() {
+		tokio::fs::copy(&media.path, &path).await?;
+	}
+
+	let subtitles = match &media.subtitles {
+		Some(subtitles) => {
+			let subtitles_path = path.with_extension("srt");
+			if tokio::fs::hard_link(subtitles, &subtitles_path).await.is_err() {
+				tokio::fs::copy(subtitles, &subtitles_path).await?;
+			}
+			Some(subtitles_path.into_boxed_path())
+		}
+		None => None,
+	};
+
+	Ok(MediaResult {
+		path: path.into_boxed_path(),
+		url: media.url.clone(),
+		metadata: media.metadata.clone(),
+		stream: media.stream.clone(),
+		reencoded: media.reencoded,
+		timings: media.timings,
+		extractor: media.extractor.clone(),
+		subtitles,
+		log: media.log.clone(),
+	})
+}
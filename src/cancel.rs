@@ -0,0 +1,100 @@
+use serenity::all::{MessageId, UserId};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::Notify;
+
+/// Reaction that stops the downloads of a message's links
+pub const CANCEL_REACTION: &str = "🛑";
+
+struct Running {
+	requester: UserId,
+	cancel: Arc<Notify>,
+	/// Downloads of the message's links still running, one per link
+	count: usize,
+}
+
+/// Downloads of posted links in progress, by the message they were posted in, so whoever asked for them can stop them with
+/// [`CANCEL_REACTION`]. `/download` has its own cancel button
+#[derive(Clone, Default)]
+pub struct CancelMap(Arc<parking_lot::Mutex<HashMap<MessageId, Running>>>);
+impl CancelMap {
+	/// Tracks a download of one of `msg_id`'s links until the returned guard is dropped
+	pub fn track(&self, msg_id: MessageId, requester: UserId) -> Cancellable {
+		let cancel = self
+			.0
+			.lock()
+			.entry(msg_id)
+			.and_modify(|running| running.count += 1)
+			.or_insert_with(|| Running {
+				requester,
+				cancel: Arc::default(),
+				count: 1,
+			})
+			.cancel
+			.clone();
+
+		Cancellable {
+			map: self.clone(),
+			msg_id,
+			cancel,
+		}
+	}
+
+	/// Stops the downloads of `msg_id`'s links if `user_id` asked for them, returning whether there were any
+	pub fn cancel(&self, msg_id: MessageId, user_id: UserId) -> bool {
+		match self.0.lock().get(&msg_id) {
+			Some(running) if running.requester == user_id => {
+				running.cancel.notify_waiters();
+				true
+			}
+			_ => false,
+		}
+	}
+}
+
+/// A tracked download, untracked when dropped
+pub struct Cancellable {
+	map: CancelMap,
+	msg_id: MessageId,
+	cancel: Arc<Notify>,
+}
+impl Cancellable {
+	/// Completes when the requester cancels the download. Dropping whatever it's raced against is what actually stops it
+	pub async fn cancelled(&self) {
+		self.cancel.notified().await
+	}
+}
+impl Drop for Cancellable {
+	fn drop(&mut self) {
+		let mut map = self.map.0.lock();
+		if let Some(running) = map.get_mut(&self.msg_id) {
+			running.count -= 1;
+			if running.count == 0 {
+				map.remove(&self.msg_id);
+			}
+		}
+	}
+}
+
+#[test]
+fn cancel_map() {
+	use serenity::FutureExt;
+
+	let map = CancelMap::default();
+	let (msg_id, poster, someone_else) = (MessageId::new(1), UserId::new(2), UserId::new(3));
+
+	let first = map.track(msg_id, poster);
+	let second = map.track(msg_id, poster);
+
+	assert!(!map.cancel(msg_id, someone_else));
+	assert!(!map.cancel(MessageId::new(4), poster));
+
+	let mut cancelled = Box::pin(first.cancelled());
+	assert!((&mut cancelled).now_or_never().is_none());
+	assert!(map.cancel(msg_id, poster));
+	assert!(cancelled.now_or_never().is_some());
+
+	drop(first);
+	assert!(map.cancel(msg_id, poster));
+	drop(second);
+	assert!(!map.cancel(msg_id, poster));
+}
@@ -0,0 +1,83 @@
+//! Recorded HTTP responses replayed from a local server, so code that talks to GitHub and TikTok can be tested offline and
+//! deterministically. Run the tests with `RECORD_FIXTURES=1` to record the fixtures from the real thing, then check the diff for
+//! anything that shouldn't be committed
+
+use std::{future::Future, path::PathBuf};
+use wiremock::{
+	matchers::{method, path},
+	Mock, MockServer, ResponseTemplate,
+};
+
+/// A recorded response body, and the server replaying it
+pub struct Cassette {
+	fixture: PathBuf,
+	server: MockServer,
+}
+impl Cassette {
+	/// Runs `test` on a runtime of its own, with the URI of a server replaying `fixture` (relative to the crate root) at
+	/// `url_path`. When recording, the request from `request` is sent to the real server first and its response saved as the fixture
+	pub fn run<R, T>(fixture: &str, url_path: &str, request: impl FnOnce() -> R, test: impl FnOnce(String) -> T)
+	where
+		R: Future<Output = reqwest::RequestBuilder>,
+		T: Future<Output = ()>,
+	{
+		tokio::runtime::Builder::new_current_thread()
+			.enable_all()
+			.build()
+			.unwrap()
+			.block_on(async {
+				let cassette = Self::new(fixture).await;
+				if Self::recording() {
+					cassette.record(request().await).await;
+				}
+				cassette.replay(url_path).await;
+
+				test(cassette.uri()).await;
+			});
+	}
+
+	async fn new(fixture: &str) -> Self {
+		Self {
+			fixture: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(fixture),
+			server: MockServer::start().await,
+		}
+	}
+
+	pub fn recording() -> bool {
+		std::env::var_os("RECORD_FIXTURES").is_some_and(|record| record != "0")
+	}
+
+	/// Sends `request` to the real server and saves the response as the fixture
+	async fn record(&self, request: reqwest::RequestBuilder) {
+		let response = request.send().await.unwrap().error_for_status().unwrap();
+		let body = response.bytes().await.unwrap();
+
+		// Pretty printed so re-recordings diff nicely
+		let body = match serde_json::from_slice::<serde_json::Value>(&body) {
+			Ok(json) => serde_json::to_vec_pretty(&json).unwrap(),
+			Err(_) => body.to_vec(),
+		};
+
+		tokio::fs::create_dir_all(self.fixture.parent().unwrap()).await.unwrap();
+		tokio::fs::write(&self.fixture, body).await.unwrap();
+	}
+
+	/// Answers GETs of `url_path`, whatever their query, with the fixture
+	async fn replay(&self, url_path: &str) {
+		let body = tokio::fs::read(&self.fixture)
+			.await
+			.unwrap_or_else(|err| panic!("Missing fixture {} ({err}), record it with RECORD_FIXTURES=1", self.fixture.display()));
+
+		Mock::given(method("GET"))
+			.and(path(url_path))
+			.respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/json"))
+			.expect(1..)
+			.mount(&self.server)
+			.await;
+	}
+
+	/// Where to send requests instead of the real server, e.g. `http://127.0.0.1:1234`
+	fn uri(&self) -> String {
+		self.server.uri()
+	}
+}
@@ -0,0 +1,455 @@
+use super::respond_ephemeral;
+use crate::{
+	blocklist::BlockTarget,
+	discord::{prepare_upload, upload_limit, Prepared, DISCORD_MESSAGE_LIMIT},
+	error::{DownloadError, UploadError},
+	hooks::HookOrigin,
+	pipeline::Downloader,
+	queue::Priority,
+	report::ErrorReport,
+	selftest::SelfTestReport,
+	AppContext,
+};
+use serenity::{
+	all::{
+		CommandInteraction, CommandOptionType, CreateAllowedMentions, CreateAttachment, CreateCommand, CreateCommandOption,
+		CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage, GuildId, Permissions, ResolvedOption,
+		ResolvedValue,
+	},
+	prelude::*,
+};
+use std::time::Instant;
+
+pub async fn register(ctx: &Context, admin_guild_id: GuildId) -> Result<(), anyhow::Error> {
+	admin_guild_id
+		.create_command(
+			ctx,
+			CreateCommand::new("admin")
+				.description("Bot administration")
+				.add_option(CreateCommandOption::new(
+					CommandOptionType::SubCommand,
+					"reload",
+					"Reload the config file from disk",
+				))
+				.add_option(CreateCommandOption::new(
+					CommandOptionType::SubCommand,
+					"rollback",
+					"Put back the config from before the last edit",
+				))
+				.add_option(
+					CreateCommandOption::new(CommandOptionType::SubCommand, "audit", "Show recent admin actions").add_sub_option(
+						CreateCommandOption::new(CommandOptionType::Integer, "count", "Number of entries to show")
+							.min_int_value(1)
+							.max_int_value(50),
+					),
+				)
+				.add_option(
+					CreateCommandOption::new(
+						CommandOptionType::SubCommand,
+						"debug-download",
+						"Run a link through the whole pipeline, and show a diagnostic report (bot owner only)",
+					)
+					.add_sub_option(CreateCommandOption::new(CommandOptionType::String, "url", "URL to download").required(true)),
+				)
+				.add_option(CreateCommandOption::new(
+					CommandOptionType::SubCommand,
+					"selftest",
+					"Download a link from each major site and report which work",
+				))
+				.add_option(block_subcommand(
+					"block",
+					"Ignore a user's or guild's messages and commands, or list who is blocked",
+				))
+				.add_option(block_subcommand("unblock", "Stop ignoring a blocked user or guild"))
+				.default_member_permissions(Permissions::ADMINISTRATOR),
+		)
+		.await?;
+
+	Ok(())
+}
+
+fn block_subcommand(name: &str, description: &str) -> CreateCommandOption {
+	CreateCommandOption::new(CommandOptionType::SubCommand, name, description)
+		.add_sub_option(CreateCommandOption::new(CommandOptionType::User, "user", "User to (un)block"))
+		.add_sub_option(CreateCommandOption::new(
+			CommandOptionType::String,
+			"guild",
+			"ID of the guild to (un)block",
+		))
+}
+
+pub async fn run(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction, options: &[ResolvedOption<'_>]) -> Result<(), anyhow::Error> {
+	let config = app_ctx.config.get().await;
+
+	if !super::check_admin_guild(ctx, command, &config).await? {
+		return Ok(());
+	}
+
+	let Some(ResolvedOption {
+		name,
+		value: ResolvedValue::SubCommand(options),
+		..
+	}) = options.first()
+	else {
+		return Ok(());
+	};
+
+	app_ctx
+		.audit
+		.record(command.user.id, &command.user.name, &format!("ran /admin {name}"), None)
+		.await;
+
+	match *name {
+		"audit" => audit(app_ctx, ctx, command, options).await,
+		"reload" => reload(app_ctx, ctx, command).await,
+		"rollback" => rollback(app_ctx, ctx, command).await,
+		"debug-download" => debug_download(app_ctx, ctx, command, options).await,
+		"selftest" => selftest(app_ctx, ctx, command).await,
+		"block" => block(app_ctx, ctx, command, options, true).await,
+		"unblock" => block(app_ctx, ctx, command, options, false).await,
+		_ => Ok(()),
+	}
+}
+
+async fn reload(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction) -> Result<(), anyhow::Error> {
+	let content = match app_ctx.config.reload().await {
+		Ok(diff) => {
+			app_ctx
+				.audit
+				.record(command.user.id, &command.user.name, "reloaded config from disk", Some(&diff))
+				.await;
+
+			"Config reloaded".to_string()
+		}
+
+		Err(err) => format!("ERROR: {err}"),
+	};
+
+	respond_ephemeral(ctx, command, CreateInteractionResponseMessage::new().content(content)).await
+}
+
+async fn rollback(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction) -> Result<(), anyhow::Error> {
+	let message = match app_ctx.config.rollback().await {
+		Ok(diff) => {
+			app_ctx
+				.audit
+				.record(command.user.id, &command.user.name, "rolled back config", Some(&diff))
+				.await;
+
+			let content = format!("Config rolled back\n```diff\n{diff}\n```");
+			if content.len() <= DISCORD_MESSAGE_LIMIT {
+				CreateInteractionResponseMessage::new().content(content)
+			} else {
+				CreateInteractionResponseMessage::new()
+					.content("Config rolled back")
+					.add_file(CreateAttachment::bytes(diff, "config.diff"))
+			}
+		}
+
+		Err(err) => CreateInteractionResponseMessage::new().content(format!("ERROR: {err}")),
+	};
+
+	respond_ephemeral(ctx, command, message).await
+}
+
+async fn audit(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction, options: &[ResolvedOption<'_>]) -> Result<(), anyhow::Error> {
+	let count = options
+		.iter()
+		.find_map(|option| match (option.name, &option.value) {
+			("count", ResolvedValue::Integer(count)) => Some(*count as usize),
+			_ => None,
+		})
+		.unwrap_or(10);
+
+	let entries = app_ctx.audit.recent(count).await?;
+
+	if entries.is_empty() {
+		return respond_ephemeral(ctx, command, CreateInteractionResponseMessage::new().content("The audit log is empty")).await;
+	}
+
+	let log = entries
+		.iter()
+		.map(|entry| {
+			let mut line = format!("<t:{}:f> <@{}> {}\n", entry.timestamp, entry.user_id, entry.action);
+			if let Some(diff) = &entry.diff {
+				line.push_str(&format!("```diff\n{diff}\n```\n"));
+			}
+			line
+		})
+		.collect::<String>();
+
+	respond_ephemeral(
+		ctx,
+		command,
+		if log.len() <= DISCORD_MESSAGE_LIMIT {
+			CreateInteractionResponseMessage::new().content(log)
+		} else {
+			CreateInteractionResponseMessage::new().add_file(CreateAttachment::bytes(log, "audit.md"))
+		},
+	)
+	.await
+}
+
+async fn block(
+	app_ctx: &AppContext,
+	ctx: &Context,
+	command: &CommandInteraction,
+	options: &[ResolvedOption<'_>],
+	block: bool,
+) -> Result<(), anyhow::Error> {
+	let mut targets = Vec::new();
+	for option in options {
+		match (option.name, &option.value) {
+			("user", ResolvedValue::User(user, _)) => targets.push(BlockTarget::User(user.id)),
+
+			("guild", ResolvedValue::String(guild_id)) => match guild_id.trim().parse::<u64>() {
+				Ok(guild_id) if guild_id != 0 => targets.push(BlockTarget::Guild(GuildId::new(guild_id))),
+				_ => {
+					return respond_ephemeral(
+						ctx,
+						command,
+						CreateInteractionResponseMessage::new().content(format!("`{guild_id}` is not a guild ID")),
+					)
+					.await
+				}
+			},
+
+			_ => {}
+		}
+	}
+
+	if targets.is_empty() {
+		let blocked = app_ctx.blocklist.describe().await;
+		return respond_ephemeral(
+			ctx,
+			command,
+			CreateInteractionResponseMessage::new()
+				.allowed_mentions(CreateAllowedMentions::new())
+				.content(if blocked.is_empty() { "Nobody is blocked".to_string() } else { blocked }),
+		)
+		.await;
+	}
+
+	let mut lines = Vec::with_capacity(targets.len());
+	for target in targets {
+		let changed = match block {
+			true => app_ctx.blocklist.block(target).await?,
+			false => app_ctx.blocklist.unblock(target).await?,
+		};
+
+		lines.push(match (block, changed) {
+			(true, true) => format!("Blocked {target}"),
+			(true, false) => format!("Already blocked {target}"),
+			(false, true) => format!("Unblocked {target}"),
+			(false, false) => format!("Wasn't blocking {target}"),
+		});
+
+		if changed {
+			app_ctx
+				.audit
+				.record(
+					command.user.id,
+					&command.user.name,
+					&format!("{} {target}", if block { "blocked" } else { "unblocked" }),
+					None,
+				)
+				.await;
+		}
+	}
+
+	respond_ephemeral(
+		ctx,
+		command,
+		CreateInteractionResponseMessage::new()
+			.allowed_mentions(CreateAllowedMentions::new())
+			.content(lines.join("\n")),
+	)
+	.await
+}
+
+/// Downloads the canaries through the full pipeline and reports which sites work, as extractors break without any errors of our own
+async fn selftest(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction) -> Result<(), anyhow::Error> {
+	command
+		.create_response(
+			ctx,
+			CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new().ephemeral(true)),
+		)
+		.await?;
+
+	// Canaries shouldn't hold up the links users are waiting on
+	let config = app_ctx.config.get().await;
+	let report = SelfTestReport::run(&app_ctx.yt_dlp, &config, Priority::Passive).await;
+
+	let summary = match report.failed() {
+		0 => "✅ All sites passed".to_string(),
+		failed => format!("❌ {failed} {} failed", if failed == 1 { "site" } else { "sites" }),
+	};
+
+	let content = format!("{summary}\n```\n{report}```");
+	let followup = match content.len() <= DISCORD_MESSAGE_LIMIT {
+		true => CreateInteractionResponseFollowup::new().content(content),
+		false => CreateInteractionResponseFollowup::new()
+			.content(summary)
+			.add_file(CreateAttachment::bytes(report.to_string(), "selftest.txt")),
+	};
+
+	command.create_followup(ctx, followup.ephemeral(true)).await?;
+
+	Ok(())
+}
+
+/// Runs a link through the pipeline with everything captured, to reproduce a failure a user reported
+async fn debug_download(
+	app_ctx: &AppContext,
+	ctx: &Context,
+	command: &CommandInteraction,
+	options: &[ResolvedOption<'_>],
+) -> Result<(), anyhow::Error> {
+	let config = app_ctx.config.get().await;
+
+	if config.root_user_id != Some(command.user.id.get()) {
+		return respond_ephemeral(
+			ctx,
+			command,
+			CreateInteractionResponseMessage::new().content("Only the bot owner can use this command"),
+		)
+		.await;
+	}
+
+	let Some(url) = options.iter().find_map(|option| match (option.name, &option.value) {
+		("url", ResolvedValue::String(url)) => Some(*url),
+		_ => None,
+	}) else {
+		return Ok(());
+	};
+
+	command
+		.create_response(
+			ctx,
+			CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new().ephemeral(true)),
+		)
+		.await?;
+
+	let mut report = ErrorReport::new(url);
+	let mut summary = Vec::new();
+
+	let mut download_options = config.download_options(url);
+	download_options.size_limit = Some(upload_limit(ctx, command.guild_id).await);
+	download_options.priority = Priority::Interactive;
+	download_options.verbose = true;
+
+	let started = Instant::now();
+	let media = app_ctx.yt_dlp.download(url, &download_options).await;
+	let download_time = started.elapsed();
+
+	report.timing("download", download_time);
+	summary.push(format!("download: {:.2}s", download_time.as_secs_f64()));
+
+	let outcome = match media {
+		Ok(mut media) => {
+			if let Some(log) = &media.log {
+				report.log(log);
+			}
+
+			report
+				.timing("encode", media.timings.post_process)
+				.detail("Extractor", &media.extractor)
+				.detail("Re-encoded", media.reencoded)
+				.detail("Title", media.metadata.title.as_deref().unwrap_or_default())
+				.detail("Uploader", media.metadata.uploader.as_deref().unwrap_or_default());
+
+			// The same hooks, scan, content filters, subtitles and dedup lookup as a link posted in a message
+			let preparing = Instant::now();
+			let prepared = prepare_upload(
+				app_ctx,
+				&config,
+				config.link_regex(url).and_then(|regex| regex.subtitles),
+				url,
+				&mut media,
+				HookOrigin {
+					guild_id: command.guild_id,
+					channel_id: command.channel_id,
+					user_id: Some(command.user.id),
+				},
+				download_options.priority,
+			)
+			.await;
+			let prepare_time = preparing.elapsed();
+
+			report.timing("processing", prepare_time);
+			summary.push(format!("processing: {:.2}s", prepare_time.as_secs_f64()));
+
+			report.detail("File", media.path.display()).probe(&media.path).await;
+
+			match prepared {
+				Prepared::Upload {
+					dedup_url: Some(dedup_url), ..
+				} => format!("♻️ Already uploaded as <{dedup_url}>"),
+
+				Prepared::Upload { size, .. } if size > download_options.size_limit() => {
+					report.detail("Size", format!("{size} bytes"));
+					format!("🫃 Too large to upload ({size} bytes)")
+				}
+
+				Prepared::Upload { size, spoiler_filter, .. } => {
+					report.detail("Size", format!("{size} bytes"));
+
+					let uploading = Instant::now();
+					let uploaded = async {
+						let mut file = CreateAttachment::path(&media.path).await?;
+						if let Some(filename) = config.upload_filename(command.guild_id, url, &media) {
+							file.filename = filename;
+						}
+						if spoiler_filter.is_some() {
+							file.filename = format!("SPOILER_{}", file.filename);
+						}
+
+						command
+							.create_followup(ctx, CreateInteractionResponseFollowup::new().ephemeral(true).add_file(file))
+							.await?;
+
+						Ok::<_, UploadError>(())
+					}
+					.await;
+					let upload_time = uploading.elapsed();
+
+					report.timing("upload", upload_time);
+					summary.push(format!("upload: {:.2}s", upload_time.as_secs_f64()));
+
+					match uploaded {
+						Ok(()) => "✅ Downloaded and uploaded".to_owned(),
+						Err(err) => {
+							report.error(&err);
+							"❌ Failed to upload".to_owned()
+						}
+					}
+				}
+
+				Prepared::Rejected => "🚮 Rejected by a hook".to_owned(),
+				Prepared::Filtered => "🚮 Skipped by a content filter".to_owned(),
+				Prepared::Flagged(reason) => format!("🚫 Flagged by the content scanner ({reason})"),
+				Prepared::Failed => "❌ Failed to process, see the log".to_owned(),
+			}
+		}
+
+		Err(err) => {
+			if let DownloadError::YtDlp { stderr, .. } = &err {
+				report.log(stderr);
+			}
+			report.error(&err);
+
+			"❌ Failed to download".to_owned()
+		}
+	};
+
+	report.config(&app_ctx.config).await;
+
+	let followup = CreateInteractionResponseFollowup::new()
+		.ephemeral(true)
+		.content(format!("{outcome} <{url}> (report `{}`)\n```\n{}\n```", report.id, summary.join("\n")))
+		.add_file(CreateAttachment::bytes(report.render(), format!("debug_report_{}.txt", report.id)));
+
+	command.create_followup(ctx, followup).await?;
+
+	Ok(())
+}
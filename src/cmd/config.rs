@@ -0,0 +1,467 @@
+use super::respond_ephemeral;
+use crate::{
+	discord::{await_confirmation, confirm_buttons, render_config_preview, DISCORD_MESSAGE_LIMIT},
+	AppContext,
+};
+use serenity::{
+	all::{
+		CommandInteraction, CommandOptionType, CreateAttachment, CreateCommand, CreateCommandOption, CreateInputText, CreateInteractionResponse,
+		CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateQuickModal, GuildId, InputTextStyle, Message, ModalInteraction,
+		Permissions, ResolvedOption, ResolvedValue, User,
+	},
+	prelude::*,
+};
+use std::time::Duration;
+
+/// How long the link regex modal can be left open
+const MODAL_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// Fields of a link regex with their own input in the modal, everything else goes in the options input as JSON
+const LINK_REGEX_MODAL_FIELDS: [&str; 4] = ["name", "regex", "fixup", "no_video"];
+
+pub async fn register(ctx: &Context, admin_guild_id: GuildId) -> Result<(), anyhow::Error> {
+	admin_guild_id
+		.create_command(
+			ctx,
+			CreateCommand::new("config")
+				.description("Manage the bot config")
+				.add_option(CreateCommandOption::new(
+					CommandOptionType::SubCommand,
+					"export",
+					"Download the current config.json",
+				))
+				.add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "dump", "Show the current config"))
+				.add_option(
+					CreateCommandOption::new(CommandOptionType::SubCommand, "import", "Replace or patch the config from a file")
+						.add_sub_option(
+							CreateCommandOption::new(CommandOptionType::Attachment, "file", "Full config, JSON Patch or JSON Merge Patch")
+								.required(true),
+						)
+						.add_sub_option(CreateCommandOption::new(
+							CommandOptionType::Boolean,
+							"replace",
+							"The file is a full config to replace the current one with, rather than a patch",
+						)),
+				)
+				.add_option(
+					CreateCommandOption::new(CommandOptionType::SubCommand, "get", "Show part of the config").add_sub_option(
+						CreateCommandOption::new(CommandOptionType::String, "path", "JSON pointer, e.g. /guilds/123/retry_button").required(true),
+					),
+				)
+				.add_option(
+					CreateCommandOption::new(CommandOptionType::SubCommand, "set", "Change part of the config")
+						.add_sub_option(
+							CreateCommandOption::new(CommandOptionType::String, "path", "JSON pointer, e.g. /guilds/123/retry_button").required(true),
+						)
+						.add_sub_option(
+							CreateCommandOption::new(
+								CommandOptionType::String,
+								"value",
+								"JSON value, anything that isn't valid JSON is set as a string",
+							)
+							.required(true),
+						),
+				)
+				.add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "add-regex", "Add a link regex"))
+				.add_option(
+					CreateCommandOption::new(CommandOptionType::SubCommand, "edit-regex", "Edit a link regex").add_sub_option(
+						CreateCommandOption::new(CommandOptionType::String, "regex", "Name or regex of the link regex").required(true),
+					),
+				)
+				.add_option(
+					CreateCommandOption::new(CommandOptionType::SubCommand, "remove-regex", "Remove a link regex").add_sub_option(
+						CreateCommandOption::new(CommandOptionType::String, "regex", "Name or regex of the link regex").required(true),
+					),
+				)
+				.default_member_permissions(Permissions::ADMINISTRATOR),
+		)
+		.await?;
+
+	Ok(())
+}
+
+pub async fn run(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction, options: &[ResolvedOption<'_>]) -> Result<(), anyhow::Error> {
+	let config = app_ctx.config.get().await;
+
+	if !super::check_admin_guild(ctx, command, &config).await? {
+		return Ok(());
+	}
+
+	let Some(ResolvedOption {
+		name,
+		value: ResolvedValue::SubCommand(options),
+		..
+	}) = options.first()
+	else {
+		return Ok(());
+	};
+
+	app_ctx
+		.audit
+		.record(command.user.id, &command.user.name, &format!("ran /config {name}"), None)
+		.await;
+
+	match *name {
+		"export" => export(app_ctx, ctx, command).await,
+		"import" => import(app_ctx, ctx, command, options).await,
+		"dump" => dump(app_ctx, ctx, command).await,
+		"get" => get(app_ctx, ctx, command, options).await,
+		"set" => set(app_ctx, ctx, command, options).await,
+		"add-regex" => edit_regex(app_ctx, ctx, command, None).await,
+		"edit-regex" => edit_regex(app_ctx, ctx, command, Some(string_option(options, "regex"))).await,
+		"remove-regex" => remove_regex(app_ctx, ctx, command, string_option(options, "regex")).await,
+		_ => Ok(()),
+	}
+}
+
+fn string_option<'a>(options: &[ResolvedOption<'a>], name: &str) -> &'a str {
+	options
+		.iter()
+		.find_map(|option| match &option.value {
+			ResolvedValue::String(value) if option.name == name => Some(*value),
+			_ => None,
+		})
+		.unwrap_or_default()
+}
+
+/// The interaction a pending config change is posted in response to
+#[derive(Clone, Copy)]
+enum Prompt<'a> {
+	Command(&'a CommandInteraction),
+	/// Submitted link regex modal
+	Modal(&'a ModalInteraction),
+}
+impl Prompt<'_> {
+	fn user(&self) -> &User {
+		match self {
+			Self::Command(command) => &command.user,
+			Self::Modal(modal) => &modal.user,
+		}
+	}
+
+	async fn respond(&self, ctx: &Context, message: CreateInteractionResponseMessage) -> Result<Message, anyhow::Error> {
+		let response = CreateInteractionResponse::Message(message);
+
+		Ok(match self {
+			Self::Command(command) => {
+				command.create_response(ctx, response).await?;
+				command.get_response(ctx).await?
+			}
+			Self::Modal(modal) => {
+				modal.create_response(ctx, response).await?;
+				modal.get_response(ctx).await?
+			}
+		})
+	}
+
+	async fn followup(&self, ctx: &Context, followup: CreateInteractionResponseFollowup) -> Result<(), anyhow::Error> {
+		match self {
+			Self::Command(command) => command.create_followup(ctx, followup).await?,
+			Self::Modal(modal) => modal.create_followup(ctx, followup).await?,
+		};
+		Ok(())
+	}
+}
+
+/// Previews a full config (with `replace`) or patch, and applies it once the user confirms
+async fn confirm_edit(
+	app_ctx: &AppContext,
+	ctx: &Context,
+	prompt: Prompt<'_>,
+	content: &str,
+	replace: bool,
+	action: &str,
+) -> Result<(), anyhow::Error> {
+	let preview = async {
+		let input = app_ctx.config.resolve(content, replace).await?.as_str().to_owned();
+		let preview = app_ctx.config.preview(&input).await?;
+		Ok::<_, anyhow::Error>((input, preview))
+	}
+	.await;
+
+	let (input, preview) = match preview {
+		Ok(preview) => preview,
+		Err(err) => {
+			prompt
+				.respond(
+					ctx,
+					CreateInteractionResponseMessage::new().ephemeral(true).content(format!("ERROR: {err}")),
+				)
+				.await?;
+			return Ok(());
+		}
+	};
+
+	let (preview_content, preview_file) = render_config_preview(preview);
+
+	let mut message = CreateInteractionResponseMessage::new()
+		.content(preview_content)
+		.components(confirm_buttons());
+
+	if let Some(preview_file) = preview_file {
+		message = message.add_file(preview_file);
+	}
+
+	let user = prompt.user();
+
+	if !await_confirmation(ctx, prompt.respond(ctx, message).await?, user.id).await {
+		return Ok(());
+	}
+
+	let followup = match app_ctx.config.edit(&input).await {
+		Ok(diff) => {
+			app_ctx.audit.record(user.id, &user.name, action, Some(&diff)).await;
+
+			format!("Config updated ({action})")
+		}
+
+		Err(err) => format!("ERROR: {err}"),
+	};
+
+	prompt
+		.followup(ctx, CreateInteractionResponseFollowup::new().ephemeral(true).content(followup))
+		.await
+}
+
+async fn get(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction, options: &[ResolvedOption<'_>]) -> Result<(), anyhow::Error> {
+	let path = string_option(options, "path");
+
+	let config = serde_json::from_str::<serde_json::Value>(&app_ctx.config.dump().await?)?;
+
+	let Some(value) = config.pointer(path) else {
+		return respond_ephemeral(
+			ctx,
+			command,
+			CreateInteractionResponseMessage::new().content(format!("`{path}` is not set")),
+		)
+		.await;
+	};
+
+	respond_ephemeral(ctx, command, json_message(&format!("`{path}`"), serde_json::to_string_pretty(value)?)).await
+}
+
+async fn dump(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction) -> Result<(), anyhow::Error> {
+	let dump = app_ctx.config.dump().await?;
+
+	respond_ephemeral(ctx, command, json_message("**Current config**", dump)).await
+}
+
+/// Pretty printed JSON in a code block, or as an attachment if it doesn't fit in a message
+fn json_message(title: &str, json: String) -> CreateInteractionResponseMessage {
+	let content = format!("{title}\n```json\n{json}\n```");
+
+	if content.len() <= DISCORD_MESSAGE_LIMIT {
+		CreateInteractionResponseMessage::new().content(content)
+	} else {
+		CreateInteractionResponseMessage::new()
+			.content(title)
+			.add_file(CreateAttachment::bytes(json, "config.json"))
+	}
+}
+
+async fn set(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction, options: &[ResolvedOption<'_>]) -> Result<(), anyhow::Error> {
+	let path = string_option(options, "path");
+	let value = string_option(options, "value");
+
+	let value = serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+	let patch = serde_json::json!([{ "op": "add", "path": path, "value": value }]);
+
+	confirm_edit(app_ctx, ctx, Prompt::Command(command), &patch.to_string(), false, &format!("set {path}")).await
+}
+
+/// Adds a link regex, or edits the one named `regex`, through a modal
+async fn edit_regex(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction, regex: Option<&str>) -> Result<(), anyhow::Error> {
+	let config = serde_json::from_str::<serde_json::Value>(&app_ctx.config.dump().await?)?;
+
+	let existing = match regex {
+		Some(regex) => match find_link_regex(&config, regex) {
+			Some(existing) => Some(existing),
+			None => {
+				return respond_ephemeral(
+					ctx,
+					command,
+					CreateInteractionResponseMessage::new().content(format!("No link regex `{regex}`")),
+				)
+				.await
+			}
+		},
+		None => None,
+	};
+
+	let fields = existing.map(|(_, link_regex)| link_regex_fields(link_regex)).unwrap_or_default();
+
+	let input = |style, label: &str, value: &str, required| {
+		let input = CreateInputText::new(style, label, "").required(required);
+		match value.is_empty() {
+			true => input,
+			false => input.value(value),
+		}
+	};
+
+	let modal = CreateQuickModal::new(if existing.is_some() { "Edit link regex" } else { "Add link regex" })
+		.timeout(MODAL_TIMEOUT)
+		.field(input(InputTextStyle::Short, "Name", &fields[0], false))
+		.field(input(InputTextStyle::Short, "Regex", &fields[1], true))
+		.field(input(InputTextStyle::Short, "Fixup", &fields[2], false))
+		.field(input(InputTextStyle::Short, "No video fixup", &fields[3], false))
+		.field(input(InputTextStyle::Paragraph, "Other options (JSON object)", &fields[4], false));
+
+	let Some(response) = command.quick_modal(ctx, modal).await? else {
+		return Ok(());
+	};
+
+	let prompt = Prompt::Modal(&response.interaction);
+
+	let link_regex = match link_regex_from_fields(&response.inputs) {
+		Ok(link_regex) => link_regex,
+		Err(err) => {
+			prompt
+				.respond(
+					ctx,
+					CreateInteractionResponseMessage::new().ephemeral(true).content(format!("ERROR: {err}")),
+				)
+				.await?;
+			return Ok(());
+		}
+	};
+
+	// The test fails the patch if the link regex was changed in the meantime
+	let (patch, action) = match existing {
+		Some((index, old)) => (
+			serde_json::json!([
+				{ "op": "test", "path": format!("/link_regexes/{index}"), "value": old },
+				{ "op": "replace", "path": format!("/link_regexes/{index}"), "value": link_regex },
+			]),
+			format!("edited link regex {}", regex.unwrap_or_default()),
+		),
+		None => (
+			serde_json::json!([{ "op": "add", "path": "/link_regexes/-", "value": link_regex }]),
+			"added link regex".to_string(),
+		),
+	};
+
+	confirm_edit(app_ctx, ctx, prompt, &patch.to_string(), false, &action).await
+}
+
+async fn remove_regex(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction, regex: &str) -> Result<(), anyhow::Error> {
+	let config = serde_json::from_str::<serde_json::Value>(&app_ctx.config.dump().await?)?;
+
+	let Some((index, old)) = find_link_regex(&config, regex) else {
+		return respond_ephemeral(
+			ctx,
+			command,
+			CreateInteractionResponseMessage::new().content(format!("No link regex `{regex}`")),
+		)
+		.await;
+	};
+
+	let patch = serde_json::json!([
+		{ "op": "test", "path": format!("/link_regexes/{index}"), "value": old },
+		{ "op": "remove", "path": format!("/link_regexes/{index}") },
+	]);
+
+	confirm_edit(
+		app_ctx,
+		ctx,
+		Prompt::Command(command),
+		&patch.to_string(),
+		false,
+		&format!("removed link regex {regex}"),
+	)
+	.await
+}
+
+/// Index of the link regex with the given name, or failing that the given regex
+fn find_link_regex<'a>(config: &'a serde_json::Value, regex: &str) -> Option<(usize, &'a serde_json::Value)> {
+	let link_regexes = config.get("link_regexes")?.as_array()?;
+
+	["name", "regex"].into_iter().find_map(|field| {
+		link_regexes
+			.iter()
+			.enumerate()
+			.find(|(_, link_regex)| link_regex.get(field).and_then(|value| value.as_str()) == Some(regex))
+	})
+}
+
+/// Values of the modal inputs for a link regex, the options being whatever doesn't have its own input
+fn link_regex_fields(link_regex: &serde_json::Value) -> [String; 5] {
+	let mut options = link_regex.as_object().cloned().unwrap_or_default();
+
+	let mut fields: [String; 5] = Default::default();
+	for (field, value) in fields.iter_mut().zip(LINK_REGEX_MODAL_FIELDS) {
+		if let Some(serde_json::Value::String(string)) = options.remove(value) {
+			*field = string;
+		}
+	}
+
+	if !options.is_empty() {
+		fields[4] = serde_json::to_string_pretty(&options).unwrap_or_default();
+	}
+
+	fields
+}
+
+fn link_regex_from_fields(fields: &[String]) -> Result<serde_json::Value, anyhow::Error> {
+	let mut link_regex = match fields.get(4).map(|options| options.trim()).unwrap_or_default() {
+		"" => serde_json::Map::new(),
+		options => serde_json::from_str(options).map_err(|err| anyhow::anyhow!("Invalid options ({err})"))?,
+	};
+
+	for (name, value) in LINK_REGEX_MODAL_FIELDS.into_iter().zip(fields) {
+		match value.trim() {
+			"" => link_regex.remove(name),
+			value => link_regex.insert(name.to_string(), value.into()),
+		};
+	}
+
+	Ok(link_regex.into())
+}
+
+async fn export(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction) -> Result<(), anyhow::Error> {
+	let dump = app_ctx.config.dump().await?;
+
+	respond_ephemeral(
+		ctx,
+		command,
+		CreateInteractionResponseMessage::new().add_file(CreateAttachment::bytes(dump, "config.json")),
+	)
+	.await
+}
+
+async fn import(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction, options: &[ResolvedOption<'_>]) -> Result<(), anyhow::Error> {
+	let Some(attachment) = options.iter().find_map(|option| match (option.name, &option.value) {
+		("file", ResolvedValue::Attachment(attachment)) => Some(*attachment),
+		_ => None,
+	}) else {
+		return respond_ephemeral(ctx, command, CreateInteractionResponseMessage::new().content("A file is required")).await;
+	};
+
+	let content = async { Ok::<_, anyhow::Error>(String::from_utf8(attachment.download().await?)?) }.await;
+
+	let content = match content {
+		Ok(content) => content,
+		Err(err) => return respond_ephemeral(ctx, command, CreateInteractionResponseMessage::new().content(format!("ERROR: {err}"))).await,
+	};
+
+	let replace = options
+		.iter()
+		.any(|option| matches!((option.name, &option.value), ("replace", ResolvedValue::Boolean(true))));
+
+	confirm_edit(app_ctx, ctx, Prompt::Command(command), &content, replace, "imported config").await
+}
+
+#[test]
+fn link_regex_modal() {
+	let link_regex = serde_json::json!({ "name": "tiktok", "regex": "tiktok\\.com", "audio_only": true });
+
+	let fields = link_regex_fields(&link_regex);
+	assert_eq!(fields[..4], ["tiktok", "tiktok\\.com", "", ""]);
+	assert_eq!(link_regex_from_fields(&fields).unwrap(), link_regex);
+
+	let config = serde_json::json!({ "link_regexes": [{ "regex": "tiktok" }, link_regex] });
+	assert_eq!(find_link_regex(&config, "tiktok").unwrap().0, 1);
+	assert_eq!(find_link_regex(&config, "tiktok\\.com").unwrap().0, 1);
+	assert!(find_link_regex(&config, "youtube").is_none());
+
+	assert!(link_regex_from_fields(&["".into(), "x".into(), "".into(), "".into(), "[1]".into()]).is_err());
+}
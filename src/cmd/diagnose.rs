@@ -0,0 +1,70 @@
+use super::respond_ephemeral;
+use serenity::{
+	all::{Command, CommandInteraction, CreateCommand, CreateInteractionResponseMessage, InteractionContext, Permissions},
+	prelude::*,
+};
+
+/// Every permission the bot uses in a channel where links are posted, and what it's needed for
+const REQUIRED_PERMISSIONS: &[(Permissions, &str, &str)] = &[
+	(Permissions::VIEW_CHANNEL, "View Channel", "seeing messages at all"),
+	(Permissions::SEND_MESSAGES, "Send Messages", "replying with videos"),
+	(Permissions::ATTACH_FILES, "Attach Files", "uploading videos"),
+	(Permissions::EMBED_LINKS, "Embed Links", "fixed up link embeds"),
+	(Permissions::ADD_REACTIONS, "Add Reactions", "reporting errors and progress"),
+	(Permissions::MANAGE_MESSAGES, "Manage Messages", "suppressing the original embed"),
+	(Permissions::READ_MESSAGE_HISTORY, "Read Message History", "replying to messages"),
+];
+
+pub async fn register(ctx: &Context) -> Result<(), anyhow::Error> {
+	Command::create_global_command(
+		ctx,
+		CreateCommand::new("diagnose")
+			.description("Check the bot has every permission it needs in this channel")
+			.contexts(vec![InteractionContext::Guild])
+			.default_member_permissions(Permissions::MANAGE_CHANNELS),
+	)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn run(ctx: &Context, command: &CommandInteraction) -> Result<(), anyhow::Error> {
+	// Discord resolves the bot's permissions in the channel for us, including overwrites
+	let Some(permissions) = command.app_permissions else {
+		return respond_ephemeral(
+			ctx,
+			command,
+			CreateInteractionResponseMessage::new().content("This command can only be used in a server"),
+		)
+		.await;
+	};
+
+	let report = REQUIRED_PERMISSIONS
+		.iter()
+		.map(|(permission, name, reason)| {
+			let status = if permissions.contains(*permission) { '✅' } else { '❌' };
+			format!("{status} **{name}** ({reason})\n")
+		})
+		.collect::<String>();
+
+	let missing = REQUIRED_PERMISSIONS
+		.iter()
+		.fold(Permissions::empty(), |missing, (permission, ..)| missing | *permission)
+		- permissions;
+
+	let summary = if missing.is_empty() {
+		"Everything looks good in this channel".to_string()
+	} else {
+		format!(
+			"Missing permissions, grant them to the bot's role or in this channel's permission overwrites (permission bits {})",
+			missing.bits()
+		)
+	};
+
+	respond_ephemeral(
+		ctx,
+		command,
+		CreateInteractionResponseMessage::new().content(format!("{report}\n{summary}")),
+	)
+	.await
+}
@@ -0,0 +1,618 @@
+use crate::{
+	discord::{
+		await_external_upload, caption_title, content_filter_mentions, external_upload_buttons, hook_payload, metadata_embed, prepare_upload,
+		too_large_notice, upload_limit, Prepared,
+	},
+	error::Error,
+	hooks::{self, HookEvent, HookOrigin},
+	pipeline::Matcher,
+	progress::{DownloadProgress, ProgressReporter},
+	queue::Priority,
+	reply::ReplyBuilder,
+	report::ErrorReport,
+	subtitles::{self, SubtitleDelivery},
+	yt_dlp::{Trim, VideoQuality},
+	AppContext,
+};
+use serenity::{
+	all::{
+		Attachment, ButtonStyle, Command, CommandInteraction, CreateActionRow, CreateAllowedMentions, CreateAttachment, CreateButton, CreateCommand,
+		CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage, EditInteractionResponse,
+		ResolvedOption, ResolvedTarget, ResolvedValue,
+	},
+	prelude::*,
+};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Message context menu command, downloads the first supported link in the message
+pub const CONTEXT_MENU_NAME: &str = "Download & Embed";
+
+#[derive(Clone, Copy)]
+enum Stage {
+	Downloading(DownloadProgress),
+	Processing,
+	/// Serenity sends the whole file in one request without reporting how far along it is, so this is only the stage and how
+	/// long it's taken
+	Uploading {
+		size: u64,
+	},
+}
+impl Stage {
+	fn describe(self, elapsed: Duration) -> String {
+		let elapsed = elapsed.as_secs();
+		match self {
+			Stage::Downloading(DownloadProgress::FetchingMetadata) => format!("🔎 Fetching metadata... ({elapsed}s)"),
+			Stage::Downloading(DownloadProgress::Downloading { percent: Some(percent) }) => {
+				format!("⏳ Downloading {percent:.0}%... ({elapsed}s)")
+			}
+			Stage::Downloading(DownloadProgress::Downloading { percent: None }) => format!("⏳ Downloading... ({elapsed}s)"),
+			Stage::Downloading(DownloadProgress::Reencoding { percent: Some(percent) }) => {
+				format!("⚙️ Re-encoding {percent:.0}%... ({elapsed}s)")
+			}
+			Stage::Downloading(DownloadProgress::Reencoding { percent: None }) => format!("⚙️ Re-encoding... ({elapsed}s)"),
+			Stage::Processing => format!("⚙️ Processing... ({elapsed}s)"),
+			Stage::Uploading { size } => format!("📤 Uploading the {:.1} MB file... ({elapsed}s)", size as f64 / (1024.0 * 1024.0)),
+		}
+	}
+}
+
+/// What to download, a link or a file the user uploaded
+#[derive(Clone, Copy)]
+enum Input<'a> {
+	Url(&'a str),
+	Attachment(&'a Attachment),
+}
+impl<'a> Input<'a> {
+	fn url(self) -> &'a str {
+		match self {
+			Input::Url(url) => url,
+			Input::Attachment(attachment) => &attachment.url,
+		}
+	}
+}
+
+/// What the user asked for besides the link
+#[derive(Clone, Copy, Default)]
+struct DownloadChoices {
+	audio_only: bool,
+	quality: Option<VideoQuality>,
+	trim: Option<Trim>,
+	subtitles: Option<SubtitleDelivery>,
+	gif: bool,
+}
+
+pub async fn register(ctx: &Context) -> Result<(), anyhow::Error> {
+	Command::create_global_command(
+		ctx,
+		CreateCommand::new("download")
+			.description("Download a video from a website using yt-dlp and embed it in the channel")
+			.add_option(CreateCommandOption::new(
+				serenity::all::CommandOptionType::String,
+				"url",
+				"URL of the video",
+			))
+			.add_option(CreateCommandOption::new(
+				serenity::all::CommandOptionType::Attachment,
+				"attachment",
+				"A video or audio file to make embeddable, instead of a URL",
+			))
+			.add_option(CreateCommandOption::new(
+				serenity::all::CommandOptionType::Boolean,
+				"audio",
+				"Only download the audio, e.g. just the song from a video",
+			))
+			.add_option(VideoQuality::CHOICES.iter().fold(
+				CreateCommandOption::new(
+					serenity::all::CommandOptionType::String,
+					"quality",
+					"Video resolution, by default whatever fits in Discord's upload limit",
+				),
+				|option, (name, _)| option.add_string_choice(*name, *name),
+			))
+			.add_option(CreateCommandOption::new(
+				serenity::all::CommandOptionType::String,
+				"start",
+				"Only embed from this timestamp on, e.g. 1:23",
+			))
+			.add_option(CreateCommandOption::new(
+				serenity::all::CommandOptionType::String,
+				"end",
+				"Only embed up to this timestamp, e.g. 2:34",
+			))
+			.add_option(SubtitleDelivery::CHOICES.iter().fold(
+				CreateCommandOption::new(
+					serenity::all::CommandOptionType::String,
+					"subtitles",
+					"Fetch the site's English subtitles, to burn into the video or attach as a file",
+				),
+				|option, (name, _)| option.add_string_choice(*name, *name),
+			))
+			.add_option(CreateCommandOption::new(
+				serenity::all::CommandOptionType::Boolean,
+				"gif",
+				"Post the video as a GIF if it's a short clip without sound",
+			))
+			.kind(serenity::all::CommandType::ChatInput)
+			.add_integration_type(serenity::all::InstallationContext::User)
+			.add_integration_type(serenity::all::InstallationContext::Guild)
+			.contexts(vec![
+				serenity::model::application::InteractionContext::Guild,
+				serenity::model::application::InteractionContext::BotDm,
+				serenity::model::application::InteractionContext::PrivateChannel,
+			])
+			.default_member_permissions(serenity::all::Permissions::SEND_MESSAGES),
+	)
+	.await?;
+
+	Command::create_global_command(
+		ctx,
+		CreateCommand::new(CONTEXT_MENU_NAME)
+			.kind(serenity::all::CommandType::Message)
+			.add_integration_type(serenity::all::InstallationContext::User)
+			.add_integration_type(serenity::all::InstallationContext::Guild)
+			.contexts(vec![
+				serenity::model::application::InteractionContext::Guild,
+				serenity::model::application::InteractionContext::BotDm,
+				serenity::model::application::InteractionContext::PrivateChannel,
+			])
+			.default_member_permissions(serenity::all::Permissions::SEND_MESSAGES),
+	)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn run(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction, options: &[ResolvedOption<'_>]) -> Result<(), anyhow::Error> {
+	// An attachment takes precedence over a URL
+	let input = options
+		.iter()
+		.find_map(|option| match (option.name, &option.value) {
+			("attachment", ResolvedValue::Attachment(attachment)) => Some(Input::Attachment(attachment)),
+			_ => None,
+		})
+		.or_else(|| {
+			options.iter().find_map(|option| match (option.name, &option.value) {
+				("url", ResolvedValue::String(url)) => Some(Input::Url(url)),
+				_ => None,
+			})
+		});
+
+	let error = match input {
+		None => Some("A URL or attachment is required"),
+		Some(Input::Attachment(attachment))
+			if !attachment
+				.content_type
+				.as_deref()
+				.is_some_and(|content_type| content_type.starts_with("video/") || content_type.starts_with("audio/")) =>
+		{
+			Some("The attachment must be a video or audio file")
+		}
+		Some(_) => None,
+	};
+
+	let (Some(input), None) = (input, error) else {
+		return command
+			.create_response(
+				ctx,
+				CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().ephemeral(true).content(error.unwrap_or_default())),
+			)
+			.await
+			.map_err(Into::into);
+	};
+
+	let audio_only = options
+		.iter()
+		.any(|option| matches!((option.name, &option.value), ("audio", ResolvedValue::Boolean(true))));
+
+	let gif = options
+		.iter()
+		.any(|option| matches!((option.name, &option.value), ("gif", ResolvedValue::Boolean(true))));
+
+	let quality = options.iter().find_map(|option| match (option.name, &option.value) {
+		("quality", ResolvedValue::String(quality)) => VideoQuality::from_choice(quality),
+		_ => None,
+	});
+
+	let subtitles = options.iter().find_map(|option| match (option.name, &option.value) {
+		("subtitles", ResolvedValue::String(subtitles)) => SubtitleDelivery::from_choice(subtitles),
+		_ => None,
+	});
+
+	let timestamp = |name| {
+		options.iter().find_map(|option| match &option.value {
+			ResolvedValue::String(timestamp) if option.name == name => Some(*timestamp),
+			_ => None,
+		})
+	};
+
+	let trim = match Trim::parse(timestamp("start"), timestamp("end")) {
+		Ok(trim) => trim,
+		Err(err) => {
+			return command
+				.create_response(
+					ctx,
+					CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().ephemeral(true).content(err)),
+				)
+				.await
+				.map_err(Into::into);
+		}
+	};
+
+	start(
+		app_ctx,
+		ctx,
+		command,
+		input,
+		DownloadChoices {
+			audio_only,
+			quality,
+			trim,
+			subtitles,
+			gif,
+		},
+	)
+	.await
+}
+
+/// Right-click on a message > Apps > Download & Embed, for messages the bot ignored, like ones with several links
+pub async fn run_context_menu(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction) -> Result<(), anyhow::Error> {
+	let Some(ResolvedTarget::Message(message)) = command.data.target() else {
+		return Ok(());
+	};
+
+	let config = app_ctx.config.get_for_guild(message.guild_id.or(command.guild_id)).await;
+	let catch_all = config.is_catch_all_channel(message.guild_id.or(command.guild_id), message.channel_id);
+
+	// The first link in the message that any link regex matches, or failing that a video or audio attachment
+	let input = config
+		.link_regexes
+		.iter()
+		.filter(|regex| catch_all || !regex.catch_all)
+		.filter_map(|regex| regex.find_links(&message.content).first().copied())
+		.min_by_key(|match_| match_.start())
+		.map(|match_| Input::Url(match_.as_str()))
+		.or_else(|| {
+			message
+				.attachments
+				.iter()
+				.find(|attachment| {
+					attachment
+						.content_type
+						.as_deref()
+						.is_some_and(|content_type| content_type.starts_with("video/") || content_type.starts_with("audio/"))
+				})
+				.map(Input::Attachment)
+		});
+
+	let Some(input) = input else {
+		return command
+			.create_response(
+				ctx,
+				CreateInteractionResponse::Message(
+					CreateInteractionResponseMessage::new()
+						.ephemeral(true)
+						.content("No supported link found in this message"),
+				),
+			)
+			.await
+			.map_err(Into::into);
+	};
+
+	start(app_ctx, ctx, command, input, DownloadChoices::default()).await
+}
+
+/// Downloads and posts `input` as a followup, with progress updates and a cancel button in the meantime
+async fn start(
+	app_ctx: &AppContext,
+	ctx: &Context,
+	command: &CommandInteraction,
+	input: Input<'_>,
+	choices: DownloadChoices,
+) -> Result<(), anyhow::Error> {
+	let download_url = input.url();
+
+	command
+		.create_response(&ctx, CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()))
+		.await?;
+
+	let started = Instant::now();
+
+	let progress = command
+		.edit_response(
+			ctx,
+			EditInteractionResponse::new()
+				.content(Stage::Downloading(DownloadProgress::FetchingMetadata).describe(Duration::ZERO))
+				.components(vec![CreateActionRow::Buttons(vec![CreateButton::new("cancel")
+					.label("Cancel")
+					.style(ButtonStyle::Secondary)])]),
+		)
+		.await?;
+
+	let cancel = progress
+		.await_component_interaction(&ctx.shard)
+		.author_id(command.user.id)
+		.custom_ids(vec!["cancel".to_string()]);
+
+	let (stage_tx, stage_rx) = watch::channel(Stage::Downloading(DownloadProgress::FetchingMetadata));
+
+	let work = async {
+		let (result, ()) = tokio::join!(
+			async move {
+				let result = download(app_ctx, ctx, command, input, choices, &stage_tx).await;
+				drop(stage_tx);
+				result
+			},
+			report_progress(ctx, command, stage_rx, started)
+		);
+		result
+	};
+
+	tokio::select! {
+		result = work => {
+			command.delete_response(ctx).await.ok();
+
+			match result {
+				// Discord's own check, e.g. for the video and its subtitles together, which the user can do something about
+				Err(Error::Upload(err)) if err.is_too_large() => {
+					command
+						.create_followup(
+							ctx,
+							CreateInteractionResponseFollowup::new()
+								.ephemeral(true)
+								.content("This media is too large to upload to this server"),
+						)
+						.await?;
+
+					Ok(())
+				}
+				result => Ok(result?),
+			}
+		}
+
+		// Dropping the download aborts any running yt-dlp/ffmpeg process or HTTP upload, and deletes the file
+		Some(interaction) = cancel => {
+			log::info!("{} cancelled /download of {download_url}", command.user.name);
+
+			interaction
+				.create_response(
+					ctx,
+					CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::new().content("❌ Cancelled").components(vec![])),
+				)
+				.await?;
+
+			Ok(())
+		}
+	}
+}
+
+/// Keeps the deferred response updated with the current stage until the download is done
+async fn report_progress(ctx: &Context, command: &CommandInteraction, mut stage_rx: watch::Receiver<Stage>, started: Instant) {
+	loop {
+		if let Ok(Err(_)) = tokio::time::timeout(PROGRESS_INTERVAL, stage_rx.changed()).await {
+			return;
+		}
+
+		let status = stage_rx.borrow().describe(started.elapsed());
+		command.edit_response(ctx, EditInteractionResponse::new().content(status)).await.ok();
+	}
+}
+
+async fn download(
+	app_ctx: &AppContext,
+	ctx: &Context,
+	command: &CommandInteraction,
+	input: Input<'_>,
+	choices: DownloadChoices,
+	stage: &watch::Sender<Stage>,
+) -> Result<(), Error> {
+	let config = app_ctx.config.get_for_guild(command.guild_id).await;
+
+	let download_url = input.url();
+	let mut options = config.download_options(download_url);
+	options.size_limit = Some(upload_limit(ctx, command.guild_id).await);
+	options.priority = Priority::Interactive;
+	options.progress = {
+		let stage = stage.clone();
+		ProgressReporter::new(move |progress| {
+			stage.send_replace(Stage::Downloading(progress));
+		})
+	};
+
+	// Attachments are posted as they are, there's no yt-dlp to extract the audio
+	if matches!(input, Input::Url(_)) {
+		options.audio_only |= choices.audio_only;
+		options.quality = choices.quality;
+		options.subtitles = choices.subtitles.or(options.subtitles);
+	}
+	options.trim = choices.trim;
+	options.gif |= choices.gif;
+
+	let started = Instant::now();
+
+	let media = match input {
+		Input::Url(url) => app_ctx.broker.download(url, &options).await,
+		Input::Attachment(attachment) => app_ctx.yt_dlp.download_file(&attachment.url, &attachment.filename, &options).await,
+	}
+	.map_err(|err| {
+		log::error!("Failed to download {download_url} ({err}) [1]");
+		err
+	});
+
+	let download_time = started.elapsed();
+
+	// Let go of the stage, so the progress updates stop when the download is done
+	options.progress = ProgressReporter::default();
+
+	stage.send_replace(Stage::Processing);
+
+	let origin = HookOrigin {
+		guild_id: command.guild_id,
+		channel_id: command.channel_id,
+		user_id: Some(command.user.id),
+	};
+
+	let ephemeral = |content: &str| CreateInteractionResponseFollowup::new().ephemeral(true).content(content);
+
+	let mut media = match media {
+		Ok(media) => media,
+		Err(err) => {
+			hooks::run(&config, hook_payload(HookEvent::OnFailure, download_url, origin, None, Some(&err))).await;
+
+			let mut report = ErrorReport::new(download_url);
+			log::error!("Failed to download {download_url} ({err}) [2] [report {}]", report.id);
+
+			report
+				.timing("download", download_time)
+				.error(&err)
+				.config(&app_ctx.config)
+				.await
+				.submit(&ctx.http, config.admin_guild.as_ref().map(|admin_guild| admin_guild.log_channel_id))
+				.await;
+
+			command
+				.create_followup(ctx, ephemeral("Failed to download a video from this URL!"))
+				.await?;
+
+			return Ok(());
+		}
+	};
+
+	let whisper_subtitles = config.link_regex(download_url).and_then(|regex| regex.subtitles);
+
+	let (size, spoiler_filter, media_hash, dedup_url) =
+		match prepare_upload(app_ctx, &config, whisper_subtitles, download_url, &mut media, origin, options.priority).await {
+			Prepared::Upload {
+				size,
+				spoiler_filter,
+				media_hash,
+				dedup_url,
+			} => (size, spoiler_filter, media_hash, dedup_url),
+
+			Prepared::Rejected => {
+				command
+					.create_followup(ctx, ephemeral("This media was rejected by the bot operator's hooks"))
+					.await?;
+				return Ok(());
+			}
+
+			Prepared::Filtered => {
+				command
+					.create_followup(ctx, ephemeral("This media was blocked by this server's content filter"))
+					.await?;
+				return Ok(());
+			}
+
+			Prepared::Flagged(reason) => {
+				log::warn!(
+					"Content scanner flagged {download_url} requested by {} ({}) via /download ({reason})",
+					command.user.name,
+					command.user.id
+				);
+				command
+					.create_followup(ctx, ephemeral("This media was rejected by the content scanner"))
+					.await?;
+				return Ok(());
+			}
+
+			Prepared::Failed => {
+				command.create_followup(ctx, ephemeral("Failed to process this media!")).await?;
+				return Ok(());
+			}
+		};
+
+	if dedup_url.is_none() {
+		// Content filtered media stays on Discord where it can be moderated
+		if size > options.size_limit() && config.storage.is_some() && spoiler_filter.is_none() {
+			let notice = command
+				.create_followup(
+					ctx,
+					CreateInteractionResponseFollowup::new()
+						.allowed_mentions(CreateAllowedMentions::new())
+						.content(too_large_notice(size, options.size_limit(), &[], true))
+						.components(external_upload_buttons()),
+				)
+				.await?;
+
+			let (ctx, command, config) = (ctx.clone(), command.clone(), config.clone());
+			tokio::spawn(async move {
+				if !await_external_upload(&ctx, &notice, command.user.id, &config, &media).await {
+					command
+						.edit_followup(&ctx, notice.id, CreateInteractionResponseFollowup::new().components(vec![]))
+						.await
+						.ok();
+				}
+			});
+
+			return Ok(());
+		}
+
+		stage.send_replace(Stage::Uploading { size });
+	}
+
+	let mut files = ReplyBuilder::new(options.size_limit());
+
+	let followup = match dedup_url {
+		Some(dedup_url) => CreateInteractionResponseFollowup::new()
+			.allowed_mentions(content_filter_mentions(spoiler_filter))
+			.content(match spoiler_filter {
+				Some(filter) => format!("{}\n||{dedup_url}||", filter.moderator_ping()),
+				None => dedup_url.into(),
+			}),
+
+		None => {
+			let mut file = CreateAttachment::path(&media.path).await?;
+			if let Some(filename) = config.upload_filename(command.guild_id, download_url, &media) {
+				file.filename = filename;
+			}
+
+			let mut followup = CreateInteractionResponseFollowup::new().allowed_mentions(content_filter_mentions(spoiler_filter));
+
+			if let Some(filter) = spoiler_filter {
+				file.filename = format!("SPOILER_{}", file.filename);
+				followup = followup.content(filter.moderator_ping());
+			} else {
+				let rich_embed = match config.rich_embeds {
+					true => metadata_embed(&media.metadata, download_url, &config.embed_policy(download_url)),
+					false => None,
+				};
+
+				if config.caption_titles {
+					if let Some(caption) = caption_title(&media.metadata, &mut file, None) {
+						// Already the embed's title
+						if rich_embed.is_none() {
+							followup = followup.content(caption);
+						}
+					}
+				}
+
+				if let Some(embed) = rich_embed {
+					followup = followup.add_embed(embed);
+				}
+			}
+
+			let subtitles = match &media.subtitles {
+				Some(srt_path) => Some(subtitles::attachment(srt_path, &file.filename).await?),
+				None => None,
+			};
+
+			files.add_file(file)?;
+			if let Some(subtitles) = subtitles {
+				files.add_file(subtitles)?;
+			}
+			followup
+		}
+	};
+
+	let followups = files.send_followups(ctx, command, followup).await?;
+
+	if let (Some(media_hash), Some(attachment)) = (media_hash, followups.first().and_then(|followup| followup.attachments.first())) {
+		app_ctx.dedup.insert(media_hash, &attachment.url).await;
+	}
+
+	drop(media);
+
+	Ok(())
+}
@@ -0,0 +1,66 @@
+use crate::{config::CompiledConfig, AppContext};
+use serenity::{
+	all::{CommandInteraction, CreateInteractionResponse, CreateInteractionResponseMessage, GuildId},
+	prelude::*,
+};
+
+mod admin;
+mod config;
+mod diagnose;
+mod download;
+mod mydata;
+mod transcribe;
+
+pub async fn register(ctx: &Context, admin_guild_id: Option<GuildId>) -> Result<(), anyhow::Error> {
+	download::register(ctx).await?;
+	diagnose::register(ctx).await?;
+	transcribe::register(ctx).await?;
+	mydata::register(ctx).await?;
+
+	if let Some(admin_guild_id) = admin_guild_id {
+		admin::register(ctx, admin_guild_id).await?;
+		config::register(ctx, admin_guild_id).await?;
+	}
+
+	Ok(())
+}
+
+pub async fn run(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction) -> Result<(), anyhow::Error> {
+	match command.data.name.as_str() {
+		"download" => download::run(app_ctx, ctx, command, &command.data.options()).await,
+		download::CONTEXT_MENU_NAME => download::run_context_menu(app_ctx, ctx, command).await,
+		"transcribe" => transcribe::run(app_ctx, ctx, command, &command.data.options()).await,
+		"diagnose" => diagnose::run(ctx, command).await,
+		"mydata" => mydata::run(app_ctx, ctx, command, &command.data.options()).await,
+		"admin" => admin::run(app_ctx, ctx, command, &command.data.options()).await,
+		"config" => config::run(app_ctx, ctx, command, &command.data.options()).await,
+		_ => Ok(()),
+	}
+}
+
+/// Admin commands are only registered in the admin guild, but they can still show up elsewhere if the admin guild changes
+async fn check_admin_guild(ctx: &Context, command: &CommandInteraction, config: &CompiledConfig) -> Result<bool, anyhow::Error> {
+	if config
+		.admin_guild
+		.as_ref()
+		.is_some_and(|admin_guild| command.guild_id == Some(admin_guild.guild_id))
+	{
+		return Ok(true);
+	}
+
+	respond_ephemeral(
+		ctx,
+		command,
+		CreateInteractionResponseMessage::new().content("This command can only be used in the admin guild"),
+	)
+	.await?;
+
+	Ok(false)
+}
+
+async fn respond_ephemeral(ctx: &Context, command: &CommandInteraction, message: CreateInteractionResponseMessage) -> Result<(), anyhow::Error> {
+	command
+		.create_response(ctx, CreateInteractionResponse::Message(message.ephemeral(true)))
+		.await
+		.map_err(Into::into)
+}
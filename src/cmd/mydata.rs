@@ -0,0 +1,105 @@
+use super::respond_ephemeral;
+use crate::{audit::AuditEntry, redo::ReplyEntry, roster::RosterEntry, AppContext};
+use serde::Serialize;
+use serenity::{
+	all::{
+		Command, CommandInteraction, CommandOptionType, CreateAttachment, CreateCommand, CreateCommandOption, CreateInteractionResponseMessage,
+		GuildId, MessageId, ResolvedOption, ResolvedValue,
+	},
+	prelude::*,
+};
+use std::collections::BTreeMap;
+
+/// Everything stored about a user
+#[derive(Serialize)]
+struct UserData {
+	/// Admin actions they took
+	audit_log: Vec<AuditEntry>,
+	/// Guilds they added us to
+	invited_guilds: BTreeMap<GuildId, RosterEntry>,
+	/// Our replies to links they posted, by reply, kept for a week so they can be redone
+	replies: BTreeMap<MessageId, ReplyEntry>,
+	blocked: bool,
+}
+
+pub async fn register(ctx: &Context) -> Result<(), anyhow::Error> {
+	Command::create_global_command(
+		ctx,
+		CreateCommand::new("mydata")
+			.description("Export or delete what the bot has stored about you")
+			.add_option(CreateCommandOption::new(
+				CommandOptionType::SubCommand,
+				"export",
+				"Download everything the bot has stored about you",
+			))
+			.add_option(CreateCommandOption::new(
+				CommandOptionType::SubCommand,
+				"delete",
+				"Delete what the bot has stored about you, other than admin actions you took",
+			)),
+	)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn run(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction, options: &[ResolvedOption<'_>]) -> Result<(), anyhow::Error> {
+	let Some(ResolvedOption {
+		name,
+		value: ResolvedValue::SubCommand(_),
+		..
+	}) = options.first()
+	else {
+		return Ok(());
+	};
+
+	let user_id = command.user.id;
+
+	match *name {
+		"export" => {
+			let data = UserData {
+				audit_log: app_ctx.audit.for_user(user_id).await?,
+				invited_guilds: app_ctx.roster.invited_by(user_id).await,
+				replies: app_ctx.replies.for_user(user_id).await,
+				blocked: app_ctx.blocklist.is_blocked(user_id, None).await,
+			};
+
+			respond_ephemeral(
+				ctx,
+				command,
+				CreateInteractionResponseMessage::new()
+					.content("Everything the bot has stored about you")
+					.add_file(CreateAttachment::bytes(
+						serde_json::to_vec_pretty(&data)?,
+						format!("mydata_{user_id}.json"),
+					)),
+			)
+			.await
+		}
+
+		"delete" => {
+			let invited_guilds = app_ctx.roster.forget_user(user_id).await?;
+			let replies = app_ctx.replies.forget_user(user_id).await?;
+
+			log::info!("Deleted the stored data of user {user_id} on request");
+
+			// Blocks and admin actions are kept, otherwise deleting your data would be a way around them, or around being held to
+			// account for what you did as an admin
+			let blocked = match app_ctx.blocklist.is_blocked(user_id, None).await {
+				true => "\nYou're still on the blocklist, ask the bot's operator if you'd like to be removed",
+				false => "",
+			};
+
+			respond_ephemeral(
+				ctx,
+				command,
+				CreateInteractionResponseMessage::new().content(format!(
+					"Forgot {replies} links you posted and that you added the bot to {invited_guilds} servers\nThe admin actions you took are kept in the audit log, ask the bot's operator if you'd like them deleted{blocked}"
+				)),
+			)
+			.await
+		}
+
+		_ => Ok(()),
+	}
+}
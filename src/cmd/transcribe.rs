@@ -0,0 +1,129 @@
+use crate::{
+	config::ContentFilterAction,
+	discord::{code_block_or_file, LongText},
+	error::Error,
+	pipeline::Downloader,
+	queue::Priority,
+	whisper::{self, SubtitleMode},
+	AppContext,
+};
+use serenity::{
+	all::{
+		Command, CommandInteraction, CommandOptionType, CreateAttachment, CreateCommand, CreateCommandOption, CreateInteractionResponse,
+		CreateInteractionResponseFollowup, CreateInteractionResponseMessage, InteractionContext, Permissions, ResolvedOption, ResolvedValue,
+	},
+	prelude::*,
+};
+
+pub async fn register(ctx: &Context) -> Result<(), anyhow::Error> {
+	Command::create_global_command(
+		ctx,
+		CreateCommand::new("transcribe")
+			.description("Transcribe the audio of a video using whisper")
+			.add_option(CreateCommandOption::new(CommandOptionType::String, "url", "URL of the video").required(true))
+			.add_option(CreateCommandOption::new(
+				CommandOptionType::Boolean,
+				"translate",
+				"Translate the transcript to English",
+			))
+			.contexts(vec![
+				InteractionContext::Guild,
+				InteractionContext::BotDm,
+				InteractionContext::PrivateChannel,
+			])
+			.default_member_permissions(Permissions::SEND_MESSAGES),
+	)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn run(app_ctx: &AppContext, ctx: &Context, command: &CommandInteraction, options: &[ResolvedOption<'_>]) -> Result<(), anyhow::Error> {
+	let mut url = None;
+	let mut mode = SubtitleMode::Transcribe;
+	for option in options {
+		match (option.name, &option.value) {
+			("url", ResolvedValue::String(value)) => url = Some(*value),
+			("translate", ResolvedValue::Boolean(true)) => mode = SubtitleMode::Translate,
+			_ => {}
+		}
+	}
+
+	let Some(url) = url else {
+		return super::respond_ephemeral(ctx, command, CreateInteractionResponseMessage::new().content("URL is required")).await;
+	};
+
+	let config = app_ctx.config.get().await;
+
+	if cfg!(not(feature = "whisper")) {
+		return super::respond_ephemeral(
+			ctx,
+			command,
+			CreateInteractionResponseMessage::new().content("Transcription is not available, this bot was built without the `whisper` feature"),
+		)
+		.await;
+	}
+
+	let Some(whisper) = &config.whisper else {
+		return super::respond_ephemeral(
+			ctx,
+			command,
+			CreateInteractionResponseMessage::new().content("Transcription is not enabled on this bot"),
+		)
+		.await;
+	};
+
+	command
+		.create_response(&ctx, CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()))
+		.await?;
+
+	// The video itself isn't posted, so only the audio is needed
+	let mut download_options = config.download_options(url);
+	download_options.audio_only = true;
+	download_options.force_reencode = false;
+	download_options.priority = Priority::Interactive;
+
+	let transcript = async {
+		let media = app_ctx.yt_dlp.download(url, &download_options).await?;
+
+		if let Some(filter) = config.content_filter(command.guild_id, &media.metadata) {
+			if filter.action == ContentFilterAction::Skip {
+				log::info!("Skipping transcription of {url} as it matched content filter {}", filter.regex);
+				return Ok::<_, Error>(None);
+			}
+		}
+
+		Ok(Some(whisper::transcribe(whisper, &media.path, mode).await?))
+	}
+	.await;
+
+	let followup = match transcript {
+		Ok(Some(transcript)) if transcript.text.is_empty() => CreateInteractionResponseFollowup::new().content("No speech was detected"),
+
+		Ok(Some(transcript)) => {
+			let captions = CreateAttachment::path(&transcript.srt_path).await?;
+
+			match code_block_or_file(transcript.text.clone(), "", "transcript.txt") {
+				LongText::Inline(content) => CreateInteractionResponseFollowup::new().content(content),
+				LongText::File(file) => CreateInteractionResponseFollowup::new().add_file(file),
+			}
+			.add_file(CreateAttachment::bytes(captions.data, "captions.srt"))
+		}
+
+		Ok(None) => CreateInteractionResponseFollowup::new()
+			.ephemeral(true)
+			.content("This media was blocked by this server's content filter"),
+
+		Err(err) => {
+			log::error!("Failed to transcribe {url} ({err})");
+
+			CreateInteractionResponseFollowup::new()
+				.ephemeral(true)
+				.content("Failed to transcribe this URL!")
+		}
+	};
+
+	command.create_followup(ctx, followup).await?;
+
+	Ok(())
+}
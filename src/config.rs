@@ -1,9 +1,31 @@
+use crate::{
+	bridge::{Bridge, BridgeConfig},
+	error::ConfigError,
+	extractor::{Extractor, PluginConfig, ScriptPlugin},
+	feeds::FeedConfig,
+	filename::FilenameTemplate,
+	hooks::HooksConfig,
+	pipeline::Matcher,
+	privacy::{self, PrivacyConfig, Secret},
+	queue::{self, Priority},
+	selftest::Canary,
+	spotify::SpotifyResolver,
+	storage::StorageConfig,
+	subtitles::SubtitleDelivery,
+	tiktok::{MaxResolution, PageIndicator, TikTokRegion},
+	trailer::TrailerMode,
+	whisper::SubtitleMode,
+	yt_dlp::{DownloadOptions, MediaMetadata, MediaResult},
+};
 use serde::{Deserialize, Serialize};
-use serenity::all::{ChannelId, GuildId};
+use serenity::all::{ChannelId, Embed, GuildId, RoleId};
 use std::{
 	cell::{Cell, RefCell},
-	path::Path,
+	collections::{BTreeMap, HashMap, HashSet},
+	num::NonZeroUsize,
+	path::{Path, PathBuf},
 	sync::{atomic::AtomicU16, Arc},
+	time::Duration,
 };
 use tokio::{
 	fs::{File, OpenOptions},
@@ -11,6 +33,22 @@ use tokio::{
 	sync::Mutex,
 };
 
+const CONFIG_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Curated link regexes shipped with the bot, bump `version` whenever they change
+const BUILTIN_REGEXES: &str = include_str!("builtin_regexes.json");
+
+#[derive(Deserialize)]
+struct BuiltinRegexes {
+	version: u32,
+	link_regexes: Box<[LinkRegex]>,
+}
+impl BuiltinRegexes {
+	fn load() -> Self {
+		serde_json::from_str(BUILTIN_REGEXES).expect("builtin_regexes.json is invalid")
+	}
+}
+
 fn regex_macros(regex: &str) -> String {
 	regex.replace("$URLCHAR", r#"[A-Za-z0-9\-._~:/?#\[\]@!$&'()*+,;=%]"#)
 }
@@ -20,6 +58,129 @@ pub struct Config {
 	pub link_regexes: Box<[LinkRegex]>,
 	pub admin_guild: Option<AdminGuild>,
 	pub root_user_id: Option<u64>,
+	#[serde(default)]
+	pub dedup_uploads: bool,
+	pub scan: Option<ScanConfig>,
+	pub media_blocklist: Option<MediaBlocklist>,
+	#[serde(default)]
+	pub guilds: BTreeMap<GuildId, GuildConfig>,
+	/// Globs, relative to the config file, of extra JSON files containing arrays of link regexes
+	#[serde(default)]
+	pub include: Box<[String]>,
+	/// Appends the builtin link regexes after our own, a link regex with the same `name` as a builtin one replaces it
+	#[serde(default)]
+	pub use_builtin_regexes: bool,
+	/// Only handle slash commands, so the privileged Message Content intent isn't needed. Applied on reconnect
+	#[serde(default)]
+	pub slash_commands_only: bool,
+	/// Enables `/transcribe` and the `subtitles` link regex option
+	#[serde(default)]
+	pub whisper: Option<WhisperConfig>,
+	#[serde(default)]
+	pub twitch: Option<TwitchConfig>,
+	/// Netscape cookies file, relative to the config file, passed to yt-dlp and the fallback scrapers
+	#[serde(default)]
+	pub cookies_file: Option<String>,
+	#[serde(default)]
+	pub hooks: HooksConfig,
+	/// Experimental external extractor scripts, tried in order before the builtin extractors
+	#[serde(default)]
+	pub plugins: Box<[PluginConfig]>,
+	/// Replying to one of our uploads with this (e.g. `!redo audio`) downloads the link again with different options
+	#[serde(default = "Config::default_redo_prefix")]
+	pub redo_prefix: String,
+	/// Strip EXIF (including GPS location) and other metadata from downloaded images, e.g. phone photos shared by link
+	#[serde(default = "Config::default_strip_image_metadata")]
+	pub strip_image_metadata: bool,
+	/// Strip container metadata (handler names, GPS, device tags) from downloaded videos and audio
+	#[serde(default)]
+	pub strip_video_metadata: bool,
+	/// Pipe yt-dlp's download straight into ffmpeg when only stripping metadata, rather than writing the file twice. Falls back to
+	/// the usual download if the pipe fails, e.g. for media that needs merging
+	#[serde(default)]
+	pub pipe_remux: bool,
+	/// Fragments of DASH and HLS streams yt-dlp downloads at once, much faster on high-latency networks. One at a time if unset
+	#[serde(default)]
+	pub concurrent_fragments: Option<NonZeroUsize>,
+	/// Use the media's title as the attachment's alt text, and as the reply's content (or embed title)
+	#[serde(default)]
+	pub caption_titles: bool,
+	/// Describe the media in an embed built from its metadata (title, uploader, duration, views) when the link has no embed of
+	/// its own to copy
+	#[serde(default)]
+	pub rich_embeds: bool,
+	/// File host media too large to upload to Discord goes to, see `GuildConfig::too_large_reply` and
+	/// `GuildConfig::upload_too_large`
+	#[serde(default)]
+	pub storage: Option<StorageConfig>,
+	/// Downloads (yt-dlp processes, extractors and plugins) running at once, the rest wait in a queue. Applied on restart
+	#[serde(default = "Config::default_max_concurrent_downloads")]
+	pub max_concurrent_downloads: usize,
+	/// ffmpeg re-encodes running at once, the rest wait in a queue. The host's cores are split evenly between them. Defaults to one
+	/// per 4 cores. Applied on restart
+	#[serde(default = "Config::default_max_concurrent_encodes")]
+	pub max_concurrent_encodes: usize,
+	#[serde(default)]
+	pub privacy: PrivacyConfig,
+	/// Superseded by `GuildConfig::allowed_channels`, as a single list across guilds stopped every unlisted guild embedding
+	/// anything. Refused rather than ignored, so the restriction isn't silently lifted
+	#[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+	pub allowed_channels: Box<[ChannelId]>,
+	/// Never embed links posted in these channels
+	#[serde(default)]
+	pub blocked_channels: Box<[ChannelId]>,
+	/// Follow the redirects of links as soon as they're seen, while waiting for their embed. Faster embeds, but also requests for
+	/// links that end up ignored
+	#[serde(default)]
+	pub prefetch_metadata: bool,
+	/// Reacting to a message with this emoji (e.g. `⬇️`, or a custom emoji's name) downloads its first link, for messages that
+	/// were skipped like ones with several links or from bots
+	#[serde(default)]
+	pub download_reaction: Option<String>,
+	/// Links downloaded from a single message, each posted as its own reply in order. Messages with more links than this are skipped
+	#[serde(default = "Config::default_max_links_per_message")]
+	pub max_links_per_message: usize,
+	/// User agents sent by yt-dlp, the extractors and link redirect resolution, instead of the builtin one. Each link always gets
+	/// the same one, so its requests look like they're from a single browser
+	#[serde(default)]
+	pub user_agents: Box<[String]>,
+	/// Largest canvas TikTok and plugin slideshows are rendered at, in either orientation. Source images can be 4K or larger
+	#[serde(default)]
+	pub slideshow_max_resolution: MaxResolution,
+	/// Numbers each slide of TikTok and plugin slideshows, e.g. `3/7`. Off if unset
+	#[serde(default)]
+	pub slideshow_page_indicator: Option<PageIndicator>,
+	/// Country, time zone and language TikTok API requests claim to come from, best matching wherever the bot's traffic leaves
+	/// from. Defaults to `GB`, `Europe/London` and `en`
+	#[serde(default)]
+	pub tiktok_region: TikTokRegion,
+	/// Links `/admin selftest` and the startup canary download, one per site. Defaults to a short video on each of the major sites
+	#[serde(default)]
+	pub canaries: Box<[Canary]>,
+	/// Download the `canaries` in the background at startup and log which sites failed, to catch broken extractors early
+	#[serde(default)]
+	pub startup_canary: bool,
+}
+impl Config {
+	fn default_redo_prefix() -> String {
+		"!redo".to_string()
+	}
+
+	fn default_strip_image_metadata() -> bool {
+		true
+	}
+
+	fn default_max_concurrent_downloads() -> usize {
+		4
+	}
+
+	fn default_max_concurrent_encodes() -> usize {
+		(queue::available_cores() / 4).max(1)
+	}
+
+	fn default_max_links_per_message() -> usize {
+		1
+	}
 }
 impl Default for Config {
 	fn default() -> Self {
@@ -27,15 +188,153 @@ impl Default for Config {
 			link_regexes: Box::new([]),
 			admin_guild: None,
 			root_user_id: None,
+			dedup_uploads: false,
+			scan: None,
+			media_blocklist: None,
+			guilds: BTreeMap::new(),
+			include: Box::new([]),
+			use_builtin_regexes: true,
+			slash_commands_only: false,
+			whisper: None,
+			twitch: None,
+			cookies_file: None,
+			hooks: HooksConfig::default(),
+			plugins: Box::new([]),
+			redo_prefix: Config::default_redo_prefix(),
+			strip_image_metadata: Config::default_strip_image_metadata(),
+			strip_video_metadata: false,
+			pipe_remux: false,
+			concurrent_fragments: None,
+			caption_titles: false,
+			rich_embeds: false,
+			storage: None,
+			max_concurrent_downloads: Config::default_max_concurrent_downloads(),
+			max_concurrent_encodes: Config::default_max_concurrent_encodes(),
+			privacy: PrivacyConfig::default(),
+			allowed_channels: Box::new([]),
+			blocked_channels: Box::new([]),
+			prefetch_metadata: false,
+			download_reaction: None,
+			max_links_per_message: Config::default_max_links_per_message(),
+			user_agents: Box::new([]),
+			slideshow_max_resolution: MaxResolution::default(),
+			slideshow_page_indicator: None,
+			tiktok_region: TikTokRegion::default(),
+			canaries: Box::new([]),
+			startup_canary: false,
 		}
 	}
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct LinkRegex {
+	/// Optional identifier, used to override builtin link regexes
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub name: Option<String>,
 	pub regex: String,
 	pub fixup: Option<String>,
 	pub no_video: Option<String>,
+	/// Maximum media duration in seconds, longer media is ignored
+	#[serde(default)]
+	pub max_duration: Option<u64>,
+	#[serde(default)]
+	pub audio_only: bool,
+	#[serde(default)]
+	pub force_reencode: bool,
+	#[serde(default)]
+	pub disable_embed_wait: bool,
+	#[serde(default)]
+	pub target_size_mb: Option<f64>,
+	/// Burns whisper.cpp generated subtitles into the video
+	#[serde(default)]
+	pub subtitles: Option<SubtitleMode>,
+	/// Fetches the site's own English subtitles with yt-dlp, to burn into the video or attach as an .srt
+	#[serde(default)]
+	pub fetch_subtitles: Option<SubtitleDelivery>,
+	/// Posts clips of 30 seconds or less without sound as GIFs
+	#[serde(default)]
+	pub gif: bool,
+	/// Posts a contact sheet of the video along with the fixup link when it's too large to upload
+	#[serde(default)]
+	pub contact_sheet: bool,
+	/// Posts a short low resolution preview clip along with the fixup link when the video is too large to upload
+	#[serde(default)]
+	pub trailer: Option<TrailerMode>,
+	/// Posts the video's thumbnail with its duration, size and a direct link to the stream when it's too large to upload and
+	/// there's no contact sheet or trailer. Needs no decoding, so it's much cheaper than either
+	#[serde(default)]
+	pub thumbnail_fallback: bool,
+	/// How Spotify track links are turned into something downloadable, defaults to the 30 second preview
+	#[serde(default)]
+	pub spotify_resolver: Option<SpotifyResolver>,
+	/// Decide how to download by the link's Content-Type rather than always using yt-dlp, for regexes matching arbitrary hosts
+	#[serde(default)]
+	pub dispatch_by_content_type: bool,
+	/// Which fields of the site's embed are kept when it's copied onto our reply
+	#[serde(default, skip_serializing_if = "EmbedPolicy::is_default")]
+	pub embed: EmbedPolicy,
+}
+
+/// Fields kept when copying a site's embed onto our reply. The embed's video is always dropped, that's what our upload is for
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct EmbedPolicy {
+	pub title: bool,
+	pub description: bool,
+	pub author: bool,
+	pub footer: bool,
+	pub fields: bool,
+	pub thumbnail: bool,
+	pub image: bool,
+	pub provider: bool,
+}
+impl Default for EmbedPolicy {
+	fn default() -> Self {
+		Self {
+			title: true,
+			description: true,
+			author: true,
+			footer: true,
+			fields: true,
+			thumbnail: false,
+			image: false,
+			provider: false,
+		}
+	}
+}
+impl EmbedPolicy {
+	fn is_default(&self) -> bool {
+		*self == Self::default()
+	}
+
+	pub fn apply(&self, embed: &mut Embed) {
+		embed.video = None;
+
+		if !self.title {
+			embed.title = None;
+		}
+		if !self.description {
+			embed.description = None;
+		}
+		if !self.author {
+			embed.author = None;
+		}
+		if !self.footer {
+			embed.footer = None;
+		}
+		if !self.fields {
+			embed.fields.clear();
+		}
+		if !self.thumbnail {
+			embed.thumbnail = None;
+		}
+		if !self.image {
+			embed.image = None;
+		}
+		if !self.provider {
+			embed.provider = None;
+		}
+	}
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -45,10 +344,316 @@ pub struct AdminGuild {
 	pub config_channel_id: ChannelId,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanConfig {
+	/// clamd socket, either a unix socket path or a `host:port` TCP address
+	Clamav { socket: String },
+
+	/// External command that is passed the file path as its last argument.
+	/// Exit code 0 means clean, 1 means flagged, anything else is an error (clamscan convention)
+	Command { command: Box<[String]> },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WhisperConfig {
+	/// whisper.cpp CLI executable
+	#[serde(default = "WhisperConfig::default_command")]
+	pub command: String,
+	/// ggml model name, e.g. `base`, `small.en` or `large-v3`. Downloaded on first use
+	#[serde(default = "WhisperConfig::default_model")]
+	pub model: String,
+	/// Spoken language, detected automatically if not set
+	pub language: Option<String>,
+}
+impl WhisperConfig {
+	fn default_command() -> String {
+		"whisper-cli".to_string()
+	}
+
+	fn default_model() -> String {
+		"base".to_string()
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TwitchConfig {
+	/// `auth-token` cookie of a logged in account, for sub-only VODs
+	pub oauth_token: Option<Secret>,
+	/// Seconds downloaded from a VOD link with a `?t=` timestamp
+	pub vod_segment_length: Option<u64>,
+}
+impl TwitchConfig {
+	fn apply(&self, options: &mut DownloadOptions) {
+		options.twitch_oauth_token = self.oauth_token.as_ref().map(|token| token.expose().into());
+		options.twitch_vod_segment = self.vod_segment_length.map(Duration::from_secs);
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct GuildConfig {
+	#[serde(default)]
+	pub content_filters: Box<[ContentFilter]>,
+	/// Reply to matched links that carry tracking parameters with a clean copy of the link
+	#[serde(default)]
+	pub scrub_tracking_links: bool,
+	/// Only embed links posted in these channels and their threads, if any are listed
+	#[serde(default)]
+	pub allowed_channels: Box<[ChannelId]>,
+	/// Channels where any link at all is downloaded if it turns out to be media
+	#[serde(default)]
+	pub catch_all_channels: Box<[ChannelId]>,
+	/// RSS/Atom feeds whose new entries are downloaded and posted to a channel
+	#[serde(default)]
+	pub feeds: Box<[FeedConfig]>,
+	/// Other platforms media downloaded in this guild is also forwarded to
+	#[serde(default)]
+	pub bridges: Box<[BridgeConfig]>,
+	/// Name uploaded files like `{site}-{uploader}-{title}` instead of a random ID, see `FilenameTemplate` for the placeholders
+	#[serde(default)]
+	pub filename_template: Option<String>,
+	/// Reply to media too large to upload with its size and the fallbacks tried, instead of just reacting with 🫃
+	#[serde(default)]
+	pub too_large_reply: bool,
+	/// Upload media too large for Discord to `Config::storage` straight away and reply with the link, rather than reacting with 🫃
+	/// or offering to in the `too_large_reply`
+	#[serde(default)]
+	pub upload_too_large: bool,
+	/// Reply to links that failed to download with a button for the poster to try again, as extractor failures are often transient
+	#[serde(default)]
+	pub retry_button: bool,
+	/// Don't embed links posted in this guild, slash commands still work
+	#[serde(default)]
+	pub disabled: bool,
+	/// Tried before the global link regexes in this guild, a link regex with the same `name` as a global one replaces it
+	#[serde(default)]
+	pub link_regexes: Box<[LinkRegex]>,
+	/// Overrides `Config::caption_titles` in this guild
+	#[serde(default)]
+	pub caption_titles: Option<bool>,
+	/// Overrides `Config::rich_embeds` in this guild
+	#[serde(default)]
+	pub rich_embeds: Option<bool>,
+	/// Delete messages with a single link and repost them through a webhook under the author's name and avatar, with the media
+	/// attached, instead of replying. Needs the Manage Webhooks and Manage Messages permissions
+	#[serde(default)]
+	pub webhook_repost: bool,
+	/// Post media in a thread under the message with the link, created the first time, instead of replying in the channel. Needs
+	/// the Create Public Threads permission
+	#[serde(default)]
+	pub reply_in_thread: bool,
+	/// Seconds after media is posted for a link that the same link posted again in the channel gets a jump link to it, rather
+	/// than being downloaded and uploaded all over again. Off if unset
+	#[serde(default)]
+	pub repost_window: Option<u64>,
+}
+
+/// Regex matched against the title, uploader and description of downloaded media
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ContentFilter {
+	pub regex: String,
+	pub action: ContentFilterAction,
+	pub moderator_role_id: Option<RoleId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentFilterAction {
+	Skip,
+	Spoiler,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MediaBlocklist {
+	/// 64-bit perceptual hashes, hex encoded
+	pub hashes: Box<[String]>,
+	#[serde(default = "MediaBlocklist::default_max_distance")]
+	pub max_distance: u32,
+}
+impl MediaBlocklist {
+	fn default_max_distance() -> u32 {
+		8
+	}
+}
+
+#[derive(Clone)]
 pub struct CompiledConfig {
 	pub link_regexes: Box<[CompiledLinkRegex]>,
 	pub admin_guild: Option<AdminGuild>,
 	pub root_user_id: Option<u64>,
+	pub dedup_uploads: bool,
+	pub scan: Option<ScanConfig>,
+	pub media_blocklist: Option<CompiledMediaBlocklist>,
+	pub guilds: Arc<HashMap<GuildId, CompiledGuildConfig>>,
+	pub include: Box<[String]>,
+	pub slash_commands_only: bool,
+	pub whisper: Option<WhisperConfig>,
+	pub twitch: Option<TwitchConfig>,
+	/// Resolved against the config file's directory by `ConfigDaemon::compile`
+	pub cookies_file: Option<Arc<Path>>,
+	pub hooks: HooksConfig,
+	pub plugins: Arc<[ScriptPlugin]>,
+	pub redo_prefix: Box<str>,
+	pub strip_image_metadata: bool,
+	pub strip_video_metadata: bool,
+	pub pipe_remux: bool,
+	pub concurrent_fragments: Option<NonZeroUsize>,
+	pub caption_titles: bool,
+	pub rich_embeds: bool,
+	pub storage: Option<StorageConfig>,
+	pub max_concurrent_downloads: usize,
+	pub max_concurrent_encodes: usize,
+	pub privacy: PrivacyConfig,
+	pub blocked_channels: HashSet<ChannelId>,
+	pub prefetch_metadata: bool,
+	pub download_reaction: Option<Box<str>>,
+	pub max_links_per_message: usize,
+	pub user_agents: Arc<[Box<str>]>,
+	pub slideshow_max_resolution: MaxResolution,
+	pub slideshow_page_indicator: Option<Arc<PageIndicator>>,
+	pub tiktok_region: Arc<TikTokRegion>,
+	pub canaries: Box<[Canary]>,
+	pub startup_canary: bool,
+	/// Merged configs of the guilds with overrides, see `CompiledConfig::for_guild`
+	guild_overrides: HashMap<GuildId, Arc<CompiledConfig>>,
+}
+impl CompiledConfig {
+	/// Safe mode config: matches nothing, but keeps whatever is needed to reach the admin guild so the config can be fixed from Discord
+	fn salvage(raw: &[u8]) -> Self {
+		let raw = serde_json::from_slice::<serde_json::Value>(raw).unwrap_or_default();
+
+		Self {
+			admin_guild: raw.get("admin_guild").and_then(|admin_guild| AdminGuild::deserialize(admin_guild).ok()),
+			root_user_id: raw.get("root_user_id").and_then(|root_user_id| root_user_id.as_u64()),
+			..Self::default()
+		}
+	}
+
+	/// The first link regex matching `url`, other than the catch-all which matches any URL
+	pub fn link_regex(&self, url: &str) -> Option<&CompiledLinkRegex> {
+		self.link_regexes.iter().find(|regex| !regex.catch_all && regex.is_match(url))
+	}
+
+	/// Embed fields of the first link regex matching `url`, see `download_options`
+	pub fn embed_policy(&self, url: &str) -> EmbedPolicy {
+		self.link_regex(url).map_or_else(EmbedPolicy::default, |regex| regex.embed)
+	}
+
+	/// Download options of the first link regex matching `url`, used when a URL is given explicitly rather than matched in a message.
+	/// The catch-all regex matches any URL, so it's skipped in favour of the global options
+	pub fn download_options(&self, url: &str) -> DownloadOptions {
+		self.link_regex(url).map(|regex| regex.options.clone()).unwrap_or_else(|| {
+			let mut options = DownloadOptions {
+				cookies_file: self.cookies_file.clone(),
+				plugins: self.plugins.clone(),
+				strip_image_metadata: self.strip_image_metadata,
+				strip_video_metadata: self.strip_video_metadata,
+				pipe_remux: self.pipe_remux,
+				concurrent_fragments: self.concurrent_fragments,
+				user_agents: self.user_agents.clone(),
+				slideshow_max_resolution: self.slideshow_max_resolution,
+				slideshow_page_indicator: self.slideshow_page_indicator.clone(),
+				tiktok_region: self.tiktok_region.clone(),
+				..Default::default()
+			};
+			if let Some(twitch) = &self.twitch {
+				twitch.apply(&mut options);
+			}
+			options
+		})
+	}
+
+	pub fn is_catch_all_channel(&self, guild_id: Option<GuildId>, channel_id: ChannelId) -> bool {
+		guild_id
+			.and_then(|guild_id| self.guilds.get(&guild_id))
+			.is_some_and(|guild| guild.catch_all_channels.contains(&channel_id))
+	}
+
+	/// Options set once for the whole config rather than per link regex
+	fn apply_global_options(&self, regex: &mut CompiledLinkRegex) {
+		if let Some(cookies_file) = &self.cookies_file {
+			regex.options.cookies_file = Some(cookies_file.clone());
+		}
+
+		if let Some(twitch) = &self.twitch {
+			twitch.apply(&mut regex.options);
+		}
+
+		regex.options.plugins = self.plugins.clone();
+		regex.options.strip_image_metadata = self.strip_image_metadata;
+		regex.options.strip_video_metadata = self.strip_video_metadata;
+		regex.options.pipe_remux = self.pipe_remux;
+		regex.options.concurrent_fragments = self.concurrent_fragments;
+		regex.options.user_agents = self.user_agents.clone();
+		regex.options.slideshow_max_resolution = self.slideshow_max_resolution;
+		regex.options.slideshow_page_indicator = self.slideshow_page_indicator.clone();
+		regex.options.tiktok_region = self.tiktok_region.clone();
+	}
+
+	/// Builds the merged config of each guild with overrides, once the global link regexes are final
+	fn merge_guild_overrides(&mut self) {
+		let mut guild_overrides = HashMap::new();
+
+		for (guild_id, guild) in self.guilds.iter().filter(|(_, guild)| guild.has_overrides()) {
+			let mut link_regexes = guild.link_regexes.to_vec();
+			for regex in &mut link_regexes {
+				self.apply_global_options(regex);
+			}
+
+			link_regexes.extend(
+				self.link_regexes
+					.iter()
+					.filter(|regex| regex.name.is_none() || !guild.link_regexes.iter().any(|overridden| overridden.name == regex.name))
+					.cloned(),
+			);
+
+			guild_overrides.insert(
+				*guild_id,
+				Arc::new(CompiledConfig {
+					link_regexes: link_regexes.into_boxed_slice(),
+					caption_titles: guild.caption_titles.unwrap_or(self.caption_titles),
+					rich_embeds: guild.rich_embeds.unwrap_or(self.rich_embeds),
+					..self.clone()
+				}),
+			);
+		}
+
+		self.guild_overrides = guild_overrides;
+	}
+
+	/// This config with the guild's overrides (link regexes, reply style) applied, or this config itself if it has none
+	pub fn for_guild(self: &Arc<Self>, guild_id: Option<GuildId>) -> Arc<Self> {
+		guild_id.and_then(|guild_id| self.guild_overrides.get(&guild_id)).unwrap_or(self).clone()
+	}
+
+	/// Whether links posted in `channel_id` are embedded, per the guild's `allowed_channels`, `blocked_channels` and the guild
+	/// being disabled. Threads go by their `parent_id` channel's lists too
+	pub fn is_embed_channel(&self, guild_id: Option<GuildId>, channel_id: ChannelId, parent_id: Option<ChannelId>) -> bool {
+		let channels = || std::iter::once(channel_id).chain(parent_id);
+
+		!channels().any(|channel_id| self.blocked_channels.contains(&channel_id))
+			&& guild_id.and_then(|guild_id| self.guilds.get(&guild_id)).is_none_or(|guild| {
+				!guild.disabled && (guild.allowed_channels.is_empty() || channels().any(|channel_id| guild.allowed_channels.contains(&channel_id)))
+			})
+	}
+
+	/// Name to upload `media` under, or `None` to keep the downloaded file's name
+	pub fn upload_filename(&self, guild_id: Option<GuildId>, url: &str, media: &MediaResult) -> Option<String> {
+		let template = self.guilds.get(&guild_id?)?.filename_template.as_ref()?;
+		let ext = media.path.extension()?.to_string_lossy();
+		template.render(url, &media.metadata, &ext)
+	}
+
+	pub fn content_filter(&self, guild_id: Option<GuildId>, metadata: &MediaMetadata) -> Option<&CompiledContentFilter> {
+		let guild = self.guilds.get(&guild_id?)?;
+
+		guild.content_filters.iter().find(|filter| {
+			[&metadata.title, &metadata.uploader, &metadata.description]
+				.into_iter()
+				.flatten()
+				.any(|text| filter.regex.is_match(text))
+		})
+	}
 }
 impl Default for CompiledConfig {
 	fn default() -> Self {
@@ -56,66 +661,496 @@ impl Default for CompiledConfig {
 	}
 }
 impl TryFrom<&Config> for CompiledConfig {
-	type Error = anyhow::Error;
+	type Error = ConfigError;
 
 	fn try_from(config: &Config) -> Result<Self, Self::Error> {
+		if !config.allowed_channels.is_empty() {
+			return Err(ConfigError::invalid(
+				"allowed_channels is set per guild now, move the channels to guilds.<guild id>.allowed_channels",
+			));
+		}
+
 		Ok(Self {
 			link_regexes: config
 				.link_regexes
 				.iter()
-				.map(|regex| {
-					Ok::<_, Self::Error>(CompiledLinkRegex {
-						regex: regex::RegexBuilder::new(&regex_macros(&regex.regex)).case_insensitive(true).build()?,
-						fixup: regex.fixup.as_deref().map(Into::into),
-						no_video: regex.no_video.as_deref().map(Into::into),
-					})
-				})
+				.map(CompiledLinkRegex::try_from)
 				.collect::<Result<Vec<_>, _>>()?
 				.into_boxed_slice(),
 
 			admin_guild: config.admin_guild.clone(),
 
 			root_user_id: config.root_user_id,
+
+			dedup_uploads: config.dedup_uploads,
+
+			scan: config.scan.clone(),
+
+			media_blocklist: config
+				.media_blocklist
+				.as_ref()
+				.map(|blocklist| {
+					Ok::<_, Self::Error>(CompiledMediaBlocklist {
+						hashes: blocklist
+							.hashes
+							.iter()
+							.map(|hash| {
+								u64::from_str_radix(hash, 16).map_err(|_| ConfigError::invalid(format!("Invalid media blocklist hash {hash:?}")))
+							})
+							.collect::<Result<_, _>>()?,
+						max_distance: blocklist.max_distance,
+					})
+				})
+				.transpose()?,
+
+			guilds: config
+				.guilds
+				.iter()
+				.map(|(guild_id, guild)| {
+					Ok::<_, Self::Error>((
+						*guild_id,
+						CompiledGuildConfig {
+							content_filters: guild
+								.content_filters
+								.iter()
+								.map(|filter| {
+									Ok::<_, Self::Error>(CompiledContentFilter {
+										regex: regex::RegexBuilder::new(&filter.regex).case_insensitive(true).build()?,
+										action: filter.action,
+										moderator_role_id: filter.moderator_role_id,
+									})
+								})
+								.collect::<Result<_, _>>()?,
+							scrub_tracking_links: guild.scrub_tracking_links,
+							allowed_channels: guild.allowed_channels.iter().copied().collect(),
+							catch_all_channels: guild.catch_all_channels.clone(),
+							feeds: guild.feeds.clone(),
+							bridges: guild
+								.bridges
+								.iter()
+								.map(BridgeConfig::compile)
+								.collect::<Result<_, _>>()
+								.map_err(|err| err.within(format_args!("guild {guild_id}")))?,
+							filename_template: guild.filename_template.as_deref().map(FilenameTemplate::parse).transpose()?,
+							too_large_reply: guild.too_large_reply,
+							upload_too_large: guild.upload_too_large,
+							retry_button: guild.retry_button,
+							disabled: guild.disabled,
+							link_regexes: guild
+								.link_regexes
+								.iter()
+								.map(|regex| CompiledLinkRegex::try_from(regex).map_err(|err| err.within(format_args!("guild {guild_id}"))))
+								.collect::<Result<_, _>>()?,
+							caption_titles: guild.caption_titles,
+							rich_embeds: guild.rich_embeds,
+							webhook_repost: guild.webhook_repost,
+							reply_in_thread: guild.reply_in_thread,
+							repost_window: guild.repost_window.map(Duration::from_secs),
+						},
+					))
+				})
+				.collect::<Result<HashMap<_, _>, _>>()?
+				.into(),
+
+			include: config.include.clone(),
+
+			slash_commands_only: config.slash_commands_only,
+
+			whisper: config.whisper.clone(),
+
+			twitch: config.twitch.clone(),
+
+			cookies_file: None,
+
+			hooks: config.hooks.clone(),
+
+			plugins: config.plugins.iter().map(ScriptPlugin::try_from).collect::<Result<_, _>>()?,
+
+			redo_prefix: config.redo_prefix.as_str().into(),
+
+			strip_image_metadata: config.strip_image_metadata,
+
+			strip_video_metadata: config.strip_video_metadata,
+
+			pipe_remux: config.pipe_remux,
+
+			concurrent_fragments: config.concurrent_fragments,
+
+			caption_titles: config.caption_titles,
+			rich_embeds: config.rich_embeds,
+
+			storage: config.storage.clone(),
+
+			max_concurrent_downloads: config.max_concurrent_downloads,
+
+			max_concurrent_encodes: config.max_concurrent_encodes,
+
+			privacy: config.privacy.clone(),
+
+			blocked_channels: config.blocked_channels.iter().copied().collect(),
+
+			prefetch_metadata: config.prefetch_metadata,
+
+			download_reaction: config.download_reaction.as_deref().map(Into::into),
+
+			max_links_per_message: config.max_links_per_message.max(1),
+
+			user_agents: config
+				.user_agents
+				.iter()
+				.map(|user_agent| user_agent.trim())
+				.filter(|user_agent| !user_agent.is_empty())
+				.map(Into::into)
+				.collect(),
+
+			slideshow_max_resolution: config.slideshow_max_resolution,
+			slideshow_page_indicator: config.slideshow_page_indicator.clone().map(Arc::new),
+			tiktok_region: Arc::new(config.tiktok_region.clone()),
+			canaries: match config.canaries.is_empty() {
+				true => Canary::defaults(),
+				false => config.canaries.clone(),
+			},
+			startup_canary: config.startup_canary,
+
+			guild_overrides: HashMap::new(),
 		})
 	}
 }
 
+pub struct CompiledGuildConfig {
+	pub content_filters: Box<[CompiledContentFilter]>,
+	pub scrub_tracking_links: bool,
+	pub allowed_channels: HashSet<ChannelId>,
+	pub catch_all_channels: Box<[ChannelId]>,
+	pub feeds: Box<[FeedConfig]>,
+	pub bridges: Box<[Bridge]>,
+	pub filename_template: Option<FilenameTemplate>,
+	pub too_large_reply: bool,
+	pub upload_too_large: bool,
+	pub retry_button: bool,
+	pub disabled: bool,
+	/// Without the global link regexes, which `CompiledConfig::for_guild` merges in
+	pub link_regexes: Box<[CompiledLinkRegex]>,
+	pub caption_titles: Option<bool>,
+	pub rich_embeds: Option<bool>,
+	pub webhook_repost: bool,
+	pub reply_in_thread: bool,
+	pub repost_window: Option<Duration>,
+}
+impl CompiledGuildConfig {
+	fn has_overrides(&self) -> bool {
+		!self.link_regexes.is_empty() || self.caption_titles.is_some() || self.rich_embeds.is_some()
+	}
+}
+
+pub struct CompiledContentFilter {
+	pub regex: regex::Regex,
+	pub action: ContentFilterAction,
+	pub moderator_role_id: Option<RoleId>,
+}
+impl CompiledContentFilter {
+	pub fn moderator_ping(&self) -> String {
+		match self.moderator_role_id {
+			Some(role_id) => format!("<@&{role_id}> This media matched a content filter"),
+			None => "This media matched a content filter".to_string(),
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct CompiledMediaBlocklist {
+	pub hashes: Box<[u64]>,
+	pub max_distance: u32,
+}
+
+#[derive(Clone)]
 pub struct CompiledLinkRegex {
+	pub name: Option<Box<str>>,
 	pub regex: regex::Regex,
 	pub fixup: Option<Box<str>>,
-	pub no_video: Option<Box<str>>,
+	pub disable_embed_wait: bool,
+	pub subtitles: Option<SubtitleMode>,
+	pub contact_sheet: bool,
+	pub trailer: Option<TrailerMode>,
+	pub thumbnail_fallback: bool,
+	pub embed: EmbedPolicy,
+	/// Only matches in catch-all channels
+	pub catch_all: bool,
+	/// Links `regex` finds must also match this, for plugins whose regexes pick out their URLs rather than find whole links in a message
+	pub url_filter: Option<regex::Regex>,
+	pub options: DownloadOptions,
+}
+impl Matcher for CompiledLinkRegex {
+	fn is_match(&self, url: &str) -> bool {
+		self.regex.is_match(url) && self.url_filter.as_ref().is_none_or(|url_filter| url_filter.is_match(url))
+	}
+
+	fn find_links<'t>(&self, text: &'t str) -> Vec<regex::Match<'t>> {
+		self.regex
+			.find_iter(text)
+			.filter(|match_| self.url_filter.as_ref().is_none_or(|url_filter| url_filter.is_match(match_.as_str())))
+			.collect()
+	}
+}
+impl CompiledLinkRegex {
+	/// `url` with the fixup applied, if there's one and it changes anything
+	pub fn fixed_up(&self, url: &str) -> Option<String> {
+		self.fixup
+			.as_deref()
+			.map(|fixup| self.regex.replace(url, fixup).into_owned())
+			.filter(|fixed_up| fixed_up != url)
+	}
+}
+impl TryFrom<&LinkRegex> for CompiledLinkRegex {
+	type Error = ConfigError;
+
+	fn try_from(regex: &LinkRegex) -> Result<Self, Self::Error> {
+		if regex.max_duration == Some(0) {
+			return Err(ConfigError::invalid(format!("max_duration for {:?} must be greater than 0", regex.regex)));
+		}
+
+		if regex
+			.target_size_mb
+			.is_some_and(|target_size_mb| !(target_size_mb > 0.0 && target_size_mb <= 500.0))
+		{
+			return Err(ConfigError::invalid(format!(
+				"target_size_mb for {:?} must be between 0 and 500",
+				regex.regex
+			)));
+		}
+
+		if regex.audio_only && regex.force_reencode {
+			return Err(ConfigError::invalid(format!(
+				"audio_only and force_reencode for {:?} are mutually exclusive, audio is never re-encoded",
+				regex.regex
+			)));
+		}
+
+		if regex.audio_only && (regex.subtitles.is_some() || regex.fetch_subtitles.is_some()) {
+			return Err(ConfigError::invalid(format!(
+				"subtitles and fetch_subtitles for {:?} need video, but audio_only is set",
+				regex.regex
+			)));
+		}
+
+		if regex.audio_only && regex.gif {
+			return Err(ConfigError::invalid(format!(
+				"gif for {:?} needs video, but audio_only is set",
+				regex.regex
+			)));
+		}
+
+		if regex.audio_only && (regex.contact_sheet || regex.trailer.is_some()) {
+			return Err(ConfigError::invalid(format!(
+				"contact_sheet and trailer for {:?} need video, but audio_only is set",
+				regex.regex
+			)));
+		}
+
+		Ok(Self {
+			name: regex.name.as_deref().map(Into::into),
+			regex: regex::RegexBuilder::new(&regex_macros(&regex.regex)).case_insensitive(true).build()?,
+			fixup: regex.fixup.as_deref().map(Into::into),
+			disable_embed_wait: regex.disable_embed_wait,
+			subtitles: regex.subtitles,
+			contact_sheet: regex.contact_sheet,
+			trailer: regex.trailer,
+			thumbnail_fallback: regex.thumbnail_fallback,
+			embed: regex.embed,
+			catch_all: false,
+			url_filter: None,
+			options: DownloadOptions {
+				max_duration: regex.max_duration.map(Duration::from_secs),
+				audio_only: regex.audio_only,
+				force_reencode: regex.force_reencode,
+				target_size_mb: regex.target_size_mb,
+				spotify_resolver: regex.spotify_resolver.unwrap_or_default(),
+				// Filled in from the global config once all link regexes are compiled
+				twitch_oauth_token: None,
+				twitch_vod_segment: None,
+				cookies_file: None,
+				dispatch_by_content_type: regex.dispatch_by_content_type,
+				plugins: Arc::default(),
+				strip_image_metadata: false,
+				strip_video_metadata: false,
+				pipe_remux: false,
+				concurrent_fragments: None,
+				quality: None,
+				size_limit: None,
+				priority: Priority::Passive,
+				user_agents: Arc::default(),
+				slideshow_max_resolution: MaxResolution::default(),
+				slideshow_page_indicator: None,
+				tiktok_region: Default::default(),
+				progress: Default::default(),
+				trim: None,
+				subtitles: regex.fetch_subtitles,
+				gif: regex.gif,
+				no_video: regex.no_video.as_deref().map(Into::into),
+				verbose: false,
+			},
+		})
+	}
 }
 
 #[derive(Clone)]
 pub struct ConfigDaemon(Arc<ConfigDaemonInner>);
 impl ConfigDaemon {
-	pub async fn new(config_path: &Path) -> Result<Self, anyhow::Error> {
-		let mut file = OpenOptions::new()
+	async fn open(config_path: &Path) -> Result<File, std::io::Error> {
+		OpenOptions::new()
 			.truncate(false)
 			.write(true)
 			.read(true)
 			.append(false)
 			.create(true)
 			.open(config_path)
-			.await?;
+			.await
+	}
+
+	fn base_dir(config_path: &Path) -> &Path {
+		config_path.parent().unwrap_or(Path::new("."))
+	}
+
+	/// Compiles the config along with the link regexes of any included files and the builtin pack
+	async fn compile(config_path: &Path, config: &Config) -> Result<CompiledConfig, ConfigError> {
+		let mut compiled_config = CompiledConfig::try_from(config)?;
+
+		let mut link_regexes = Vec::from(std::mem::take(&mut compiled_config.link_regexes));
+
+		for path in resolve_includes(Self::base_dir(config_path), &config.include)? {
+			let included = async {
+				let included = tokio::fs::read(&path).await?;
+				serde_json::from_slice::<Vec<LinkRegex>>(&included)?
+					.iter()
+					.map(CompiledLinkRegex::try_from)
+					.collect::<Result<Vec<_>, _>>()
+			}
+			.await
+			.map_err(|err| err.within(path.display()))?;
+
+			link_regexes.extend(included);
+		}
+
+		if config.use_builtin_regexes {
+			let builtin = BuiltinRegexes::load();
+
+			log::debug!("Using builtin link regexes v{}", builtin.version);
+
+			for regex in builtin.link_regexes.iter() {
+				if link_regexes
+					.iter()
+					.any(|overridden| overridden.name.is_some() && overridden.name.as_deref() == regex.name.as_deref())
+				{
+					continue;
+				}
+
+				link_regexes.push(CompiledLinkRegex::try_from(regex).map_err(|err| err.within("builtin link regexes"))?);
+			}
+		}
+
+		// Links for plugins that no other regex matches
+		for plugin in compiled_config.plugins.iter() {
+			link_regexes.push(CompiledLinkRegex {
+				url_filter: Some(plugin.regex().clone()),
+				..CompiledLinkRegex::try_from(&LinkRegex {
+					name: Some(format!("plugin:{}", plugin.name())),
+					regex: "https?://$URLCHAR+".to_string(),
+					..Default::default()
+				})?
+			});
+		}
+
+		// Always last so that every other regex gets a chance to match first
+		link_regexes.push(CompiledLinkRegex {
+			catch_all: true,
+			..CompiledLinkRegex::try_from(&LinkRegex {
+				name: Some("catch-all".to_string()),
+				regex: "https?://$URLCHAR+".to_string(),
+				dispatch_by_content_type: true,
+				..Default::default()
+			})?
+		});
+
+		if compiled_config.whisper.is_none() {
+			if let Some(regex) = link_regexes
+				.iter()
+				.chain(compiled_config.guilds.values().flat_map(|guild| guild.link_regexes.iter()))
+				.find(|regex| regex.subtitles.is_some())
+			{
+				return Err(ConfigError::invalid(format!(
+					"subtitles for {:?} requires whisper to be configured",
+					regex.regex.as_str()
+				)));
+			}
+		}
+
+		if let Some(cookies_file) = &config.cookies_file {
+			let cookies_file = Arc::<Path>::from(Self::base_dir(config_path).join(cookies_file));
+			if !cookies_file.is_file() {
+				return Err(ConfigError::invalid(format!("cookies_file {} does not exist", cookies_file.display())));
+			}
+
+			compiled_config.cookies_file = Some(cookies_file);
+		}
+
+		if let Some(twitch) = &compiled_config.twitch {
+			if twitch.vod_segment_length == Some(0) {
+				return Err(ConfigError::invalid("twitch.vod_segment_length must be greater than 0"));
+			}
+		}
+
+		for regex in &mut link_regexes {
+			compiled_config.apply_global_options(regex);
+		}
+
+		compiled_config.link_regexes = link_regexes.into_boxed_slice();
+
+		compiled_config.merge_guild_overrides();
+
+		Ok(compiled_config)
+	}
+
+	pub async fn new(config_path: &Path) -> Result<Self, anyhow::Error> {
+		let mut file = Self::open(config_path).await?;
 
 		let size = file.metadata().await?.len();
 
-		let config = if size == 0 {
-			Config::default()
-		} else {
-			let mut config = Vec::with_capacity(size as usize);
-			file.read_to_end(&mut config).await?;
-			serde_json::from_slice(&config)?
-		};
+		let mut raw = Vec::with_capacity(size as usize);
+		file.read_to_end(&mut raw).await?;
 
-		let compiled_config = CompiledConfig::try_from(&config)?;
+		let loaded = async {
+			let config = if raw.is_empty() {
+				Config::default()
+			} else {
+				serde_json::from_slice(&raw)?
+			};
+			let compiled_config = Self::compile(config_path, &config).await?;
+			Ok::<_, anyhow::Error>((config, compiled_config))
+		}
+		.await;
+
+		let (compiled_config, safe_mode_error) = match loaded {
+			Ok((config, compiled_config)) => {
+				file.set_len(0).await?;
+				file.seek(std::io::SeekFrom::Start(0)).await?;
+				file.write_all(serde_json::to_string_pretty(&config)?.as_bytes()).await?;
+
+				(compiled_config, None)
+			}
 
-		file.set_len(0).await?;
-		file.seek(std::io::SeekFrom::Start(0)).await?;
-		file.write_all(serde_json::to_string_pretty(&config)?.as_bytes()).await?;
+			Err(err) => {
+				// Don't touch the file, the admin will want to fix it rather than lose it
+				log::error!("Failed to load config, starting in safe mode ({err:#})");
 
-		Ok(Self(Arc::new(ConfigDaemonInner {
+				(CompiledConfig::salvage(&raw), Some(format!("{err:#}").into_boxed_str()))
+			}
+		};
+
+		privacy::set_redaction(compiled_config.privacy.redact);
+
+		let this = Self(Arc::new(ConfigDaemonInner {
+			path: config_path.into(),
+			safe_mode_error: parking_lot::Mutex::new(safe_mode_error),
 			edit_count: AtomicU16::new(0),
 			store: Mutex::new(ConfigStore {
 				file,
@@ -124,10 +1159,132 @@ impl ConfigDaemon {
 					config: Arc::new(compiled_config),
 				},
 			}),
-		})))
+		}));
+
+		tokio::spawn(this.clone().watch());
+
+		Ok(this)
 	}
 
-	pub async fn dump(&self) -> Result<String, anyhow::Error> {
+	/// Hot reloads the config whenever the config file or any included file changes
+	async fn watch(self) {
+		let snapshot = |include: &[String]| {
+			std::iter::once(self.0.path.to_path_buf())
+				.chain(resolve_includes(Self::base_dir(&self.0.path), include).unwrap_or_default())
+				.map(|path| {
+					let modified = std::fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+					(path, modified)
+				})
+				.collect::<Vec<_>>()
+		};
+
+		let mut last = snapshot(&self.get().await.include);
+
+		loop {
+			tokio::time::sleep(CONFIG_WATCH_INTERVAL).await;
+
+			let current = snapshot(&self.get().await.include);
+			if current == last {
+				continue;
+			}
+
+			// The first entry is the config file itself, which our own edits also touch
+			let includes_changed = current[1..] != last[1..];
+
+			match self.reload().await {
+				Ok(diff) if diff.is_empty() && !includes_changed => {}
+				Ok(diff) => log::info!("Config changed on disk, reloaded\n{diff}"),
+				Err(err) => log::error!("Config changed on disk but failed to reload, keeping the current one ({err})"),
+			}
+
+			last = snapshot(&self.get().await.include);
+		}
+	}
+
+	/// Re-reads the config file from disk and swaps it in, returning a diff of the changes.
+	/// The current config is kept if the file on disk is invalid
+	pub async fn reload(&self) -> Result<String, ConfigError> {
+		// Held while reading so we never see an edit half-written
+		let mut store = self.0.store.lock().await;
+
+		// Reopen rather than reuse our handle, the file may have been replaced rather than modified
+		let mut file = Self::open(&self.0.path).await?;
+
+		let mut new = String::new();
+		file.read_to_string(&mut new).await?;
+
+		let compiled_config = Self::compile(&self.0.path, &serde_json::from_str::<Config>(&new)?).await?;
+
+		let mut old = String::new();
+		store.file.seek(std::io::SeekFrom::Start(0)).await?;
+		store.file.read_to_string(&mut old).await?;
+
+		let edit_count = self.0.edit_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+		privacy::set_redaction(compiled_config.privacy.redact);
+
+		store.file = file;
+		store.config = SignedConfig {
+			signature: edit_count + 1,
+			config: Arc::new(compiled_config),
+		};
+
+		*self.0.safe_mode_error.lock() = None;
+
+		Ok(diff(&privacy::redact_secrets(&old), &privacy::redact_secrets(&new)))
+	}
+
+	/// If the config failed to load at startup, the error that put us into safe mode.
+	/// Cleared once a valid config is applied
+	pub fn safe_mode_error(&self) -> Option<Box<str>> {
+		self.0.safe_mode_error.lock().clone()
+	}
+
+	/// With `replace`, takes the input as a full replacement config. Otherwise expands a JSON Patch (array) or JSON Merge Patch
+	/// (object) against the current config
+	pub async fn resolve<'a>(&self, input: &'a str, replace: bool) -> Result<ConfigInput<'a>, ConfigError> {
+		let patch: serde_json::Value = serde_json::from_str(input)?;
+
+		if replace {
+			return match patch {
+				serde_json::Value::Object(_) => Ok(ConfigInput::Full(input)),
+				_ => Err(ConfigError::invalid("Expected a full config object")),
+			};
+		}
+
+		let mut config: serde_json::Value = serde_json::from_str(&self.read().await?)?;
+
+		match patch {
+			serde_json::Value::Array(_) => json_patch::patch(&mut config, &serde_json::from_value::<json_patch::Patch>(patch)?)?,
+			serde_json::Value::Object(_) => json_patch::merge(&mut config, &patch),
+			_ => return Err(ConfigError::invalid("Expected a JSON Merge Patch object or JSON Patch array")),
+		}
+
+		Ok(ConfigInput::Patched(serde_json::to_string_pretty(&config)?))
+	}
+
+	/// Validates a new config and describes how it differs from the current one, without applying it
+	pub async fn preview(&self, new: &str) -> Result<ConfigPreview, ConfigError> {
+		let new_config = self.parse(new).await?;
+		Self::compile(&self.0.path, &new_config).await?;
+
+		let old = self.dump().await?;
+		let old_config = serde_json::from_str::<serde_json::Value>(&old).unwrap_or_default();
+		let new_config = serde_json::to_value(&new_config)?;
+		let new = serde_json::to_string_pretty(&new_config)?;
+
+		Ok(ConfigPreview {
+			summary: summarize(&old_config, &new_config),
+			diff: diff(&old, &privacy::redact_secrets(&new)),
+		})
+	}
+
+	/// The config file, with its secrets redacted
+	pub async fn dump(&self) -> Result<String, ConfigError> {
+		Ok(privacy::redact_secrets(&self.read().await?).into_owned())
+	}
+
+	async fn read(&self) -> Result<String, ConfigError> {
 		let mut store = self.0.store.lock().await;
 
 		let mut dump = String::new();
@@ -138,25 +1295,81 @@ impl ConfigDaemon {
 		Ok(dump)
 	}
 
-	pub async fn edit(&self, new: &str) -> Result<(), anyhow::Error> {
-		let config = serde_json::from_str(new)?;
-		let compiled_config = CompiledConfig::try_from(&config)?;
+	/// Parses a new config, keeping the current values of any secrets it leaves redacted
+	async fn parse(&self, new: &str) -> Result<Config, ConfigError> {
+		let mut new = serde_json::from_str::<serde_json::Value>(new)?;
 
-		{
+		let old = serde_json::from_str::<serde_json::Value>(&self.read().await?).unwrap_or_default();
+		privacy::restore_secrets(&mut new, &old).map_err(|path| ConfigError::Redacted(path.into()))?;
+
+		Ok(serde_json::from_value(new)?)
+	}
+
+	/// Replaces the config, returning a unified diff of the changes
+	pub async fn edit(&self, new: &str) -> Result<String, ConfigError> {
+		let config = self.parse(new).await?;
+		let compiled_config = Self::compile(&self.0.path, &config).await?;
+		let new = serde_json::to_string_pretty(&config)?;
+
+		let diff = {
 			let mut store = self.0.store.lock().await;
-			let edit_count = self.0.edit_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
 
-			store.file.set_len(0).await?;
+			let mut old = String::new();
 			store.file.seek(std::io::SeekFrom::Start(0)).await?;
-			store.file.write_all(serde_json::to_string_pretty(&config)?.as_bytes()).await?;
+			store.file.read_to_string(&mut old).await?;
+
+			// Kept for `/admin rollback`
+			if !old.is_empty() && old != new {
+				tokio::fs::write(Self::previous_path(&self.0.path), &old).await?;
+			}
+
+			let edit_count = self.0.edit_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+			// Written aside and renamed over the config, so the file is never seen half-written by anything reading it
+			let temp_path = Self::temp_path(&self.0.path);
+			tokio::fs::write(&temp_path, &new).await?;
+			tokio::fs::rename(&temp_path, &self.0.path).await?;
+			store.file = Self::open(&self.0.path).await?;
+
+			privacy::set_redaction(compiled_config.privacy.redact);
 
 			store.config = SignedConfig {
 				signature: edit_count + 1,
 				config: Arc::new(compiled_config),
 			};
-		}
 
-		Ok(())
+			*self.0.safe_mode_error.lock() = None;
+
+			diff(&privacy::redact_secrets(&old), &privacy::redact_secrets(&new))
+		};
+
+		Ok(diff)
+	}
+
+	/// Puts back the config from before the last edit, returning a unified diff of the changes. Rolling back again undoes the
+	/// rollback
+	pub async fn rollback(&self) -> Result<String, ConfigError> {
+		let previous = match tokio::fs::read_to_string(Self::previous_path(&self.0.path)).await {
+			Ok(previous) => previous,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Err(ConfigError::NoPreviousConfig),
+			Err(err) => return Err(err.into()),
+		};
+
+		self.edit(&previous).await
+	}
+
+	/// Where the config from before the last edit is kept, next to the config file
+	fn previous_path(config_path: &Path) -> PathBuf {
+		config_path.with_extension("previous.json")
+	}
+
+	fn temp_path(config_path: &Path) -> PathBuf {
+		config_path.with_extension("json.tmp")
+	}
+
+	/// The config with the guild's overrides applied, or just the config outside of guilds
+	pub async fn get_for_guild(&self, guild_id: Option<GuildId>) -> Arc<CompiledConfig> {
+		self.get().await.for_guild(guild_id)
 	}
 
 	pub async fn get(&self) -> Arc<CompiledConfig> {
@@ -197,6 +1410,100 @@ impl ConfigDaemon {
 	}
 }
 
+pub enum ConfigInput<'a> {
+	Full(&'a str),
+	Patched(String),
+}
+impl ConfigInput<'_> {
+	pub fn as_str(&self) -> &str {
+		match self {
+			Self::Full(config) => config,
+			Self::Patched(config) => config,
+		}
+	}
+}
+
+pub struct ConfigPreview {
+	pub summary: String,
+	pub diff: String,
+}
+
+fn summarize(old: &serde_json::Value, new: &serde_json::Value) -> String {
+	let regexes = |config: &serde_json::Value| -> BTreeMap<String, serde_json::Value> {
+		config
+			.get("link_regexes")
+			.and_then(|regexes| regexes.as_array())
+			.into_iter()
+			.flatten()
+			.map(|regex| (regex.get("regex").and_then(|r| r.as_str()).unwrap_or_default().to_owned(), regex.clone()))
+			.collect()
+	};
+
+	let (old_regexes, new_regexes) = (regexes(old), regexes(new));
+
+	let mut summary = String::new();
+
+	for (regex, link_regex) in &new_regexes {
+		match old_regexes.get(regex) {
+			None => summary.push_str(&format!("+ added regex `{regex}`\n")),
+			Some(old_link_regex) if old_link_regex != link_regex => summary.push_str(&format!("~ modified regex `{regex}`\n")),
+			Some(_) => {}
+		}
+	}
+
+	for regex in old_regexes.keys().filter(|regex| !new_regexes.contains_key(*regex)) {
+		summary.push_str(&format!("- removed regex `{regex}`\n"));
+	}
+
+	let keys = old
+		.as_object()
+		.into_iter()
+		.chain(new.as_object())
+		.flat_map(|object| object.keys())
+		.filter(|key| *key != "link_regexes")
+		.collect::<std::collections::BTreeSet<_>>();
+
+	for key in keys {
+		if old.get(key) != new.get(key) {
+			summary.push_str(&format!("~ `{key}` changed\n"));
+		}
+	}
+
+	if summary.is_empty() {
+		summary.push_str("No changes\n");
+	}
+
+	summary
+}
+
+fn resolve_includes(base_dir: &Path, include: &[String]) -> Result<Vec<PathBuf>, ConfigError> {
+	let mut paths = Vec::new();
+
+	for pattern in include {
+		let pattern = base_dir.join(pattern);
+		let pattern = pattern
+			.to_str()
+			.ok_or_else(|| ConfigError::invalid(format!("Invalid include path {}", pattern.display())))?;
+
+		for path in glob::glob(pattern)? {
+			paths.push(path.map_err(glob::GlobError::into_error)?);
+		}
+	}
+
+	paths.sort();
+	paths.dedup();
+
+	Ok(paths)
+}
+
+pub fn diff(old: &str, new: &str) -> String {
+	similar::TextDiff::from_lines(old, new)
+		.unified_diff()
+		.context_radius(2)
+		.header("config.json", "config.json")
+		.to_string()
+}
+
 #[derive(Clone)]
 struct SignedConfig {
 	signature: u16,
@@ -209,6 +1516,8 @@ struct ConfigStore {
 }
 
 struct ConfigDaemonInner {
+	path: Box<Path>,
+	safe_mode_error: parking_lot::Mutex<Option<Box<str>>>,
 	store: Mutex<ConfigStore>,
 	edit_count: AtomicU16,
 }
@@ -217,3 +1526,165 @@ struct ConfigDaemonInner {
 fn default_config_compiles() {
 	let _ = CompiledConfig::default();
 }
+
+#[test]
+fn channel_lists() {
+	let (guild, other_guild) = (GuildId::new(1), GuildId::new(2));
+	let (channel, blocked, unlisted, thread) = (ChannelId::new(1), ChannelId::new(2), ChannelId::new(3), ChannelId::new(4));
+
+	let mut config = Config::default();
+	let compiled = CompiledConfig::try_from(&config).unwrap();
+	assert!(compiled.is_embed_channel(None, channel, None));
+
+	config.blocked_channels = Box::new([blocked]);
+	config.guilds.insert(
+		guild,
+		GuildConfig {
+			allowed_channels: Box::new([channel, blocked]),
+			..Default::default()
+		},
+	);
+	config.guilds.insert(
+		GuildId::new(3),
+		GuildConfig {
+			disabled: true,
+			..Default::default()
+		},
+	);
+	let compiled = CompiledConfig::try_from(&config).unwrap();
+	assert!(compiled.is_embed_channel(Some(guild), channel, None));
+	assert!(compiled.is_embed_channel(Some(guild), thread, Some(channel)));
+	assert!(!compiled.is_embed_channel(Some(guild), blocked, None));
+	assert!(!compiled.is_embed_channel(Some(guild), thread, Some(blocked)));
+	assert!(!compiled.is_embed_channel(Some(guild), unlisted, None));
+	assert!(compiled.is_embed_channel(Some(other_guild), unlisted, None));
+	assert!(!compiled.is_embed_channel(Some(GuildId::new(3)), channel, None));
+
+	config.allowed_channels = Box::new([channel]);
+	assert!(CompiledConfig::try_from(&config).is_err());
+}
+
+#[test]
+fn guild_overrides() {
+	let link_regex = |name: &str, regex: &str| LinkRegex {
+		name: Some(name.to_string()),
+		regex: regex.to_string(),
+		..Default::default()
+	};
+
+	let mut config = Config {
+		link_regexes: Box::new([link_regex("tiktok", "tiktok"), link_regex("reddit", "reddit")]),
+		..Default::default()
+	};
+	config.guilds.insert(
+		GuildId::new(1),
+		GuildConfig {
+			link_regexes: Box::new([link_regex("tiktok", "vm\\.tiktok"), link_regex("bsky", "bsky")]),
+			caption_titles: Some(true),
+			..Default::default()
+		},
+	);
+
+	let mut compiled = CompiledConfig::try_from(&config).unwrap();
+	compiled.merge_guild_overrides();
+	let compiled = Arc::new(compiled);
+
+	let merged = compiled.for_guild(Some(GuildId::new(1)));
+	let regexes = merged.link_regexes.iter().map(|regex| regex.regex.as_str()).collect::<Vec<_>>();
+	assert_eq!(regexes, ["vm\\.tiktok", "bsky", "reddit"]);
+	assert!(merged.caption_titles);
+
+	assert!(Arc::ptr_eq(&compiled.for_guild(Some(GuildId::new(2))), &compiled));
+	assert!(Arc::ptr_eq(&compiled.for_guild(None), &compiled));
+}
+
+#[test]
+fn explicit_url_options() {
+	let catch_all = CompiledLinkRegex {
+		catch_all: true,
+		..CompiledLinkRegex::try_from(&LinkRegex {
+			regex: "https?://$URLCHAR+".to_string(),
+			dispatch_by_content_type: true,
+			..Default::default()
+		})
+		.unwrap()
+	};
+
+	let mut compiled = CompiledConfig::try_from(&Config::default()).unwrap();
+	compiled.link_regexes = Box::new([catch_all]);
+	assert!(!compiled.download_options("https://example.com/video.mp4").dispatch_by_content_type);
+}
+
+#[test]
+fn builtin_regexes_compile() {
+	let builtin = BuiltinRegexes::load();
+	for regex in builtin.link_regexes.iter() {
+		assert!(regex.name.is_some(), "builtin link regex {:?} has no name", regex.regex);
+		CompiledLinkRegex::try_from(regex).unwrap();
+	}
+}
+
+#[test]
+fn rollback() {
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			let dir = std::env::temp_dir().join(format!("config_rollback_{}", uuid::Uuid::new_v4().simple()));
+			tokio::fs::create_dir_all(&dir).await.unwrap();
+			let path = dir.join("config.json");
+
+			let config = ConfigDaemon::new(&path).await.unwrap();
+			assert!(matches!(config.rollback().await, Err(ConfigError::NoPreviousConfig)));
+
+			let max_concurrent_downloads = config.get().await.max_concurrent_downloads;
+			let edited = config.resolve(r#"{"max_concurrent_downloads": 9}"#, false).await.unwrap();
+			config.edit(edited.as_str()).await.unwrap();
+			assert_eq!(config.get().await.max_concurrent_downloads, 9);
+
+			// A merge patch touching the link regexes is still a patch
+			let patched = config.resolve(r#"{"link_regexes": []}"#, false).await.unwrap();
+			assert!(matches!(&patched, ConfigInput::Patched(patched) if patched.contains("max_concurrent_downloads")));
+			assert!(config.resolve("[]", true).await.is_err());
+
+			config.rollback().await.unwrap();
+			assert_eq!(config.get().await.max_concurrent_downloads, max_concurrent_downloads);
+
+			// Rolling back again redoes the edit
+			config.rollback().await.unwrap();
+			assert_eq!(config.get().await.max_concurrent_downloads, 9);
+
+			tokio::fs::remove_dir_all(&dir).await.ok();
+		});
+}
+
+#[test]
+fn plugin_links() {
+	let config = Config {
+		plugins: Box::new([PluginConfig {
+			name: "example".to_string(),
+			regex: r"example\.com/post/\d+".to_string(),
+			command: Box::new(["true".to_string()]),
+		}]),
+		..Default::default()
+	};
+
+	let compiled = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(ConfigDaemon::compile(Path::new("config.json"), &config))
+		.unwrap();
+
+	let links = compiled
+		.link_regexes
+		.iter()
+		.filter(|regex| !regex.catch_all)
+		.flat_map(|regex| regex.find_links("see https://example.com/post/1?a=b and https://example.com/about"))
+		.map(|match_| match_.as_str())
+		.collect::<Vec<_>>();
+	assert_eq!(links, ["https://example.com/post/1?a=b"]);
+
+	assert_eq!(compiled.download_options("https://example.com/post/1").plugins.len(), 1);
+}
@@ -0,0 +1,52 @@
+use crate::error::EncodeError;
+use std::{
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+const COLUMNS: u32 = 4;
+const ROWS: u32 = 3;
+const TILE_WIDTH: u32 = 320;
+
+/// Tiles evenly spaced frames of a video into a single JPEG, returning its path
+pub async fn generate(path: &Path, duration: Duration) -> Result<PathBuf, EncodeError> {
+	let out_path = path.with_extension("sheet.jpg");
+
+	// Sample exactly enough frames to fill the grid across the whole video
+	let fps = (COLUMNS * ROWS) as f64 / duration.as_secs_f64().max(1.0);
+
+	let output = crate::process::ffmpeg()
+		.arg("-y")
+		.arg("-i")
+		.arg(path)
+		.args([
+			"-vf",
+			&format!("fps={fps},scale={TILE_WIDTH}:-2,tile={COLUMNS}x{ROWS}:padding=4:margin=4"),
+			"-frames:v",
+			"1",
+			"-q:v",
+			"4",
+		])
+		.arg(&out_path)
+		.output()
+		.await?;
+
+	if !output.status.success() {
+		tokio::fs::remove_file(&out_path).await.ok();
+		return Err(EncodeError::Failed {
+			status: output.status,
+			stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+		});
+	}
+
+	Ok(out_path)
+}
+
+/// `h:mm:ss` or `m:ss`
+pub fn format_duration(duration: Duration) -> String {
+	let secs = duration.as_secs();
+	match secs / 3600 {
+		0 => format!("{}:{:02}", secs / 60, secs % 60),
+		hours => format!("{hours}:{:02}:{:02}", (secs / 60) % 60, secs % 60),
+	}
+}
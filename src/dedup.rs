@@ -0,0 +1,113 @@
+use crate::store::JsonStore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, path::Path, sync::Arc};
+use tokio::io::AsyncReadExt;
+
+#[derive(Serialize, Deserialize, Default)]
+struct DedupEntries(HashMap<Box<str>, DedupEntry>);
+
+#[derive(Serialize, Deserialize)]
+struct DedupEntry {
+	url: Box<str>,
+	uploaded_at: u64,
+	/// When the URL's CDN signature runs out, zero for entries from before this was tracked
+	#[serde(default)]
+	expires_at: u64,
+}
+
+/// Discord signs attachment URLs for 24 hours, assumed when the URL doesn't say
+const DEFAULT_URL_LIFETIME: u64 = 24 * 60 * 60;
+
+/// How long before its signature expires a URL stops being handed out, so whoever opens the repost still gets the file
+const EXPIRY_MARGIN: u64 = 60 * 60;
+
+/// Persistent map of media SHA-256 to the URL it was previously uploaded to.
+#[derive(Clone)]
+pub struct DedupStore(Arc<JsonStore<DedupEntries>>);
+impl DedupStore {
+	pub async fn new(path: &Path) -> Result<Self, anyhow::Error> {
+		Ok(Self(Arc::new(JsonStore::open(path.to_owned()).await?)))
+	}
+
+	/// Hashes the file at `path`, returning the hash along with any URL the same content was previously uploaded to that is still
+	/// signed for a while.
+	pub async fn lookup(&self, path: &Path) -> Result<(Box<str>, Option<Box<str>>), std::io::Error> {
+		let hash = hash_file(path).await?;
+		let now = crate::unix_now();
+		let url = self
+			.0
+			.read(|entries| {
+				entries
+					.0
+					.get(&hash)
+					.filter(|entry| entry.expires_at > now + EXPIRY_MARGIN)
+					.map(|entry| entry.url.clone())
+			})
+			.await;
+		Ok((hash, url))
+	}
+
+	pub async fn insert(&self, hash: Box<str>, url: &str) {
+		let uploaded_at = crate::unix_now();
+		let expires_at = signature_expiry(url).unwrap_or(uploaded_at + DEFAULT_URL_LIFETIME);
+
+		if let Err(err) = self
+			.0
+			.update(|entries| {
+				entries.0.insert(
+					hash,
+					DedupEntry {
+						url: url.into(),
+						uploaded_at,
+						expires_at,
+					},
+				)
+			})
+			.await
+		{
+			log::error!("Failed to persist dedup store ({err})");
+		}
+	}
+}
+
+impl DedupStore {
+	/// Forgets uploads from before `cutoff`, a unix timestamp
+	pub async fn prune(&self, cutoff: u64) {
+		if let Err(err) = self.0.update(|entries| entries.0.retain(|_, entry| entry.uploaded_at >= cutoff)).await {
+			log::error!("Failed to persist dedup store ({err})");
+		}
+	}
+}
+
+/// The `ex` query parameter of Discord CDN URLs, a hex unix timestamp
+fn signature_expiry(url: &str) -> Option<u64> {
+	let query = url.split_once('?')?.1;
+	let ex = query.split('&').find_map(|param| param.strip_prefix("ex="))?;
+	u64::from_str_radix(ex, 16).ok()
+}
+
+async fn hash_file(path: &Path) -> Result<Box<str>, std::io::Error> {
+	let mut file = tokio::fs::File::open(path).await?;
+	let mut hasher = Sha256::new();
+	let mut buf = vec![0; 64 * 1024];
+
+	loop {
+		let n = file.read(&mut buf).await?;
+		if n == 0 {
+			break;
+		}
+		hasher.update(&buf[..n]);
+	}
+
+	Ok(hasher.finalize().iter().map(|b| format!("{b:02x}")).collect::<String>().into_boxed_str())
+}
+
+#[test]
+fn cdn_signature_expiry() {
+	assert_eq!(
+		signature_expiry("https://cdn.discordapp.com/attachments/1/2/video.mp4?ex=67a1b2c3&is=67a06143&hm=abc&"),
+		Some(0x67a1b2c3)
+	);
+	assert_eq!(signature_expiry("https://cdn.discordapp.com/attachments/1/2/video.mp4"), None);
+}
@@ -1,20 +1,85 @@
-use crate::{cmd, config::CompiledConfig, logging, AppContext};
+use crate::{
+	bridge,
+	cancel::CANCEL_REACTION,
+	cmd,
+	config::{AdminGuild, CompiledConfig, CompiledContentFilter, CompiledLinkRegex, ConfigInput, ConfigPreview, ContentFilterAction, EmbedPolicy},
+	contact_sheet,
+	edits::{SeenLinks, EDIT_WINDOW},
+	error::{EncodeError, Error, ProbeError, UploadError},
+	ffprobe::MediaProbe,
+	hooks::{self, HookEvent, HookOrigin, HookPayload},
+	logging,
+	pipeline::{Matcher, Prober},
+	queue::{DownloadQueue, Priority},
+	redo::RedoArgs,
+	reply::ReplyBuilder,
+	report::ErrorReport,
+	reposts::{RecentUploads, Upload},
+	scan::{self, ScanVerdict},
+	scrub, subtitles, trailer,
+	whisper::{self, SubtitleMode},
+	yt_dlp::{DownloadOptions, MediaMetadata, MediaResult},
+	AppContext,
+};
+use parking_lot::Mutex;
 use serenity::{
 	all::{
-		CreateAllowedMentions, CreateAttachment, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
-		EditMessage, Interaction, Message, MessageUpdateEvent,
+		ButtonStyle, CacheHttp, ChannelId, CreateActionRow, CreateAllowedMentions, CreateAttachment, CreateButton, CreateEmbed, CreateEmbedAuthor,
+		CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, CreateThread, CreateWebhook,
+		EditInteractionResponse, EditMessage, Embed, ExecuteWebhook, Guild, GuildId, Http, Interaction, Message, MessageFlags, MessageId,
+		MessageUpdateEvent, Permissions, PremiumTier, Reaction, ReactionType, Ready, UnavailableGuild, User, UserId, Webhook,
 	},
 	async_trait,
-	futures::StreamExt,
+	futures::{future::BoxFuture, StreamExt},
+	model::guild::audit_log,
 	prelude::*,
 	FutureExt,
 };
-use std::{future::Future, sync::Arc, time::Duration};
+use std::{
+	collections::HashMap,
+	future::Future,
+	ops::Range,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tokio::sync::watch;
 
 pub const DISCORD_FILE_SIZE_LIMIT: u64 = 10 * 1024 * 1024;
+pub const DISCORD_MESSAGE_LIMIT: usize = 2000;
+
+/// Upload limit of a guild, which is raised by server boosts
+pub async fn upload_limit(cache_http: impl CacheHttp, guild_id: Option<GuildId>) -> u64 {
+	let Some(guild_id) = guild_id else {
+		return DISCORD_FILE_SIZE_LIMIT;
+	};
+
+	// Served from the cache when we have the Guilds intent
+	match guild_id.to_partial_guild(cache_http).await {
+		Ok(guild) => premium_tier_upload_limit(guild.premium_tier),
+		Err(err) => {
+			log::warn!("Failed to get the boost tier of guild {guild_id} ({err})");
+			DISCORD_FILE_SIZE_LIMIT
+		}
+	}
+}
+
+fn premium_tier_upload_limit(premium_tier: PremiumTier) -> u64 {
+	match premium_tier {
+		PremiumTier::Tier2 => 50 * 1024 * 1024,
+		PremiumTier::Tier3 => 100 * 1024 * 1024,
+		_ => DISCORD_FILE_SIZE_LIMIT,
+	}
+}
+
+fn discord_bot_permissions(slash_commands_only: bool) -> GatewayIntents {
+	// No privileged intents, for bots in over 100 servers that can't get Message Content approved
+	if slash_commands_only {
+		return GatewayIntents::GUILDS;
+	}
 
-fn discord_bot_permissions() -> GatewayIntents {
-	GatewayIntents::GUILD_MESSAGES
+	// Guilds for join/leave notifications and boost tiers
+	GatewayIntents::GUILDS
+		| GatewayIntents::GUILD_MESSAGES
 		| GatewayIntents::MESSAGE_CONTENT
 		| GatewayIntents::GUILD_MESSAGE_REACTIONS
 		| GatewayIntents::GUILD_MESSAGE_TYPING
@@ -28,21 +93,36 @@ fn discord_bot_permissions() -> GatewayIntents {
 #[derive(Clone)]
 struct DiscordBot {
 	app_ctx: AppContext,
+	slash_commands_only: bool,
+	seen_links: Arc<SeenLinks>,
+	/// Media posted for links in each channel within the guild's `repost_window`
+	recent_uploads: Arc<RecentUploads>,
+	/// Our webhook in each channel links have been reposted in
+	webhooks: Arc<Mutex<HashMap<ChannelId, Webhook>>>,
 }
 impl DiscordBot {
-	async fn generic_message(&self, ctx: Context, mut msg: Message, config: Arc<CompiledConfig>) {
+	async fn generic_message(&self, ctx: Context, msg: Message, config: Arc<CompiledConfig>) {
 		// test whether the bot is alive or not
 		if msg.mentions_me(&ctx.http).await.unwrap_or(false) {
-			// emergency reboot
-			if Some(msg.author.id.get()) == config.root_user_id && msg.content.contains("kill yourself") {
-				msg.react(&ctx.http, '💀').await.ok();
-				std::process::exit(0);
+			match mention_reply(&msg, config.root_user_id) {
+				// emergency reboot
+				Some(MentionReply::Reboot) => {
+					msg.react(&ctx.http, '💀').await.ok();
+					std::process::exit(0);
+				}
+				Some(MentionReply::Wave) => {
+					msg.react(&ctx.http, '👋').await.ok();
+				}
+				None => {}
 			}
-
-			msg.react(&ctx.http, '👋').await.ok();
 			return;
 		}
 
+		self.embed_links(ctx, msg, config).await;
+	}
+
+	/// Downloads and replies to the links in a new or edited message
+	async fn embed_links(&self, ctx: Context, msg: Message, config: Arc<CompiledConfig>) {
 		// Ignore NotSoBot .dl commands
 		if msg.content.trim().starts_with(".dl ") {
 			return;
@@ -53,32 +133,226 @@ impl DiscordBot {
 			return;
 		}
 
-		let mut download_urls = config
-			.link_regexes
-			.iter()
-			.flat_map(|regex| regex.regex.find_iter(&msg.content).map(move |match_| (regex, match_.as_str())))
-			.collect::<Vec<_>>()
-			.into_iter();
+		let mut download_urls = Self::find_links(&config, &msg);
+
+		// Reject messages with too many URLs, they're probably lists of links rather than something to embed
+		if download_urls.is_empty() || download_urls.len() > config.max_links_per_message {
+			return;
+		}
+
+		// Replies are posted in the order the links appear in the message
+		download_urls.sort_by_key(|(_, download_url)| download_url.start());
+
+		// The repost only carries the message's text and our upload, so anything else it has would be lost with the original
+		let repostable = download_urls.len() == 1
+			&& msg.attachments.is_empty()
+			&& msg.sticker_items.is_empty()
+			&& msg.message_reference.is_none()
+			&& msg.content.chars().count() <= DISCORD_MESSAGE_LIMIT
+			&& msg
+				.guild_id
+				.and_then(|guild_id| config.guilds.get(&guild_id))
+				.is_some_and(|guild| guild.webhook_repost)
+			&& can_webhook_repost(&ctx, &msg);
+
+		// Links an edit didn't add were already handled when the message was posted
+		download_urls.retain(|(_, download_url)| self.seen_links.first_seen(msg.id, download_url.as_str()));
+
+		if download_urls.is_empty() {
+			return;
+		}
+
+		let download_urls = download_urls
+			.into_iter()
+			.map(|(download_url_regex, download_url)| {
+				let posted = PostedLink {
+					spoiler: is_spoilered(&msg.content, download_url.range()),
+					repostable,
+					requester: msg.author.id,
+				};
+				(download_url_regex, download_url.as_str().to_owned(), posted)
+			})
+			.collect::<Vec<_>>();
+
+		if config.prefetch_metadata {
+			for (_, download_url, _) in &download_urls {
+				self.app_ctx.yt_dlp.prefetch(download_url, &config.download_options(download_url));
+			}
+		}
+
+		let guild = msg.guild_id.and_then(|guild_id| config.guilds.get(&guild_id));
+		let scrub_tracking_links = guild.is_some_and(|guild| guild.scrub_tracking_links);
+		let repost_window = guild.and_then(|guild| guild.repost_window);
+
+		// The upload is bound to fail, don't bother downloading
+		let can_attach_files = can_attach_files(&ctx, &msg);
+
+		for (download_url_regex, download_url, posted) in download_urls {
+			// Posted again soon after, the earlier upload is still in view
+			if let Some(upload) = repost_window.and_then(|_| self.recent_uploads.get(msg.channel_id, &download_url)) {
+				log::info!(
+					"{download_url} was posted again in channel {}, pointing at the earlier upload",
+					msg.channel_id
+				);
+
+				msg.channel_id
+					.send_message(
+						&ctx,
+						CreateMessage::new()
+							.reference_message(&msg)
+							.allowed_mentions(CreateAllowedMentions::new())
+							.content(format!("Already posted: {}", upload.jump_link())),
+					)
+					.await
+					.ok();
+				continue;
+			}
+
+			if scrub_tracking_links {
+				if let Some(clean_url) = scrub::clean_url(&download_url) {
+					msg.channel_id
+						.send_message(
+							&ctx,
+							CreateMessage::new()
+								.reference_message(&msg)
+								.allowed_mentions(CreateAllowedMentions::new())
+								.content(format!("Link without tracking: <{clean_url}>")),
+						)
+						.await
+						.ok();
+				}
+			}
+
+			if can_attach_files {
+				self.download_link(ctx.clone(), msg.clone(), &config, download_url_regex, &download_url, posted)
+					.await;
+			} else {
+				self.link_instead_of_upload(ctx.clone(), msg.clone(), &config, download_url_regex, &download_url, posted.spoiler)
+					.await;
+			}
+		}
+	}
 
-		let Some((download_url_regex, download_url)) = download_urls.next() else {
+	/// Links in the message matched by a link regex, along with the regex
+	fn find_links<'a, 'm>(config: &'a CompiledConfig, msg: &'m Message) -> Vec<(&'a CompiledLinkRegex, regex::Match<'m>)> {
+		let catch_all = config.is_catch_all_channel(msg.guild_id, msg.channel_id);
+
+		let mut download_urls = Vec::<(&CompiledLinkRegex, regex::Match)>::new();
+		for regex in config.link_regexes.iter().filter(|regex| catch_all || !regex.catch_all) {
+			for match_ in regex.find_links(&msg.content) {
+				// Several regexes (e.g. a user regex and a builtin one) can match the same URL, the first one wins
+				if !download_urls
+					.iter()
+					.any(|(_, other)| other.start() < match_.end() && match_.start() < other.end())
+				{
+					download_urls.push((regex, match_));
+				}
+			}
+		}
+
+		download_urls
+	}
+
+	/// Downloads the first link in a message someone reacted to with the download reaction, even ones `embed_links` skips
+	async fn reaction_download(&self, ctx: Context, msg: Message, config: Arc<CompiledConfig>, user_id: UserId) {
+		let Some((download_url_regex, download_url)) = Self::find_links(&config, &msg)
+			.into_iter()
+			.min_by_key(|(_, download_url)| download_url.start())
+		else {
 			return;
 		};
 
-		// Reject multiple URLs
-		if download_urls.next().is_some() {
+		// Someone else's message, it's not ours to delete and repost
+		let posted = PostedLink {
+			spoiler: is_spoilered(&msg.content, download_url.range()),
+			repostable: false,
+			requester: user_id,
+		};
+		let download_url = download_url.as_str().to_owned();
+
+		// Already downloaded for the message itself or an earlier reaction, or still being downloaded
+		if !self.seen_links.first_seen(msg.id, &download_url) || self.app_ctx.replies.find(msg.id, &download_url).await.is_some() {
+			return;
+		}
+
+		if !can_attach_files(&ctx, &msg) {
+			self.link_instead_of_upload(ctx, msg, &config, download_url_regex, &download_url, posted.spoiler)
+				.await;
 			return;
 		}
 
+		self.download_link(ctx, msg, &config, download_url_regex, &download_url, posted).await;
+	}
+
+	/// Replies with the fixup link, or failing that the media's direct URL if it isn't tied to our IP address, for channels we can't
+	/// upload to
+	async fn link_instead_of_upload(
+		&self,
+		ctx: Context,
+		msg: Message,
+		config: &CompiledConfig,
+		download_url_regex: &CompiledLinkRegex,
+		download_url: &str,
+		spoiler: bool,
+	) {
+		let link = match download_url_regex.fixed_up(download_url) {
+			Some(fixed_up) => fixed_up,
+			None => match self.app_ctx.yt_dlp.direct_url(download_url, &download_url_regex.options).await {
+				Ok(direct_url) => direct_url,
+				Err(err) => {
+					if !err.is_no_media() {
+						log::info!(
+							"Can't upload {download_url} to channel {} and failed to get its direct URL ({err})",
+							msg.channel_id
+						);
+					}
+					return;
+				}
+			},
+		};
+
+		let reply = msg
+			.channel_id
+			.send_message(
+				&ctx,
+				CreateMessage::new()
+					.reference_message(&msg)
+					.allowed_mentions(CreateAllowedMentions::new())
+					.content(if spoiler { format!("||{link}||") } else { link }),
+			)
+			.await;
+
+		if let Ok(reply) = reply {
+			remember_upload(&self.recent_uploads, config, &msg, download_url, &reply);
+		}
+	}
+
+	/// Downloads `download_url` and posts it as a reply to `msg`, or as a repost of it, hidden behind a spoiler if the link was
+	async fn download_link(
+		&self,
+		ctx: Context,
+		mut msg: Message,
+		config: &Arc<CompiledConfig>,
+		download_url_regex: &CompiledLinkRegex,
+		download_url: &str,
+		posted: PostedLink,
+	) {
 		let typing = msg.channel_id.start_typing(&ctx.http);
 
 		let mut replace_embed = {
 			match msg.embeds.len() {
+				// Discord blurs a spoilered link's embed, a copy of it in our reply would give it away
+				_ if posted.spoiler => None,
+				// The repost's embeds are suppressed, and the original is deleted along with its embed
+				_ if posted.repostable => None,
+				0 if download_url_regex.disable_embed_wait => None,
 				0 => {
 					// Wait for message to have an embed, if any
+					let download_url = download_url.to_owned();
 					let mut message_updates = serenity::collector::collect(&ctx.shard, move |ev| match ev {
 						serenity::all::Event::MessageUpdate(MessageUpdateEvent {
 							id, embeds: Some(embeds), ..
-						}) if *id == msg.id => Some(if embeds.len() == 1 { Some(embeds[0].clone()) } else { None }),
+						}) if *id == msg.id => Some(sole_link_embed(embeds, &download_url)),
 						_ => None,
 					});
 
@@ -87,57 +361,151 @@ impl DiscordBot {
 						_ => None,
 					}
 				}
-				1 => Some(msg.embeds[0].clone()),
-				_ => None,
+				_ => sole_link_embed(&msg.embeds, download_url),
 			}
 		};
 
-		let mut result = None;
-		for _ in 0..2 {
-			let result = result.insert(self.app_ctx.yt_dlp.download(download_url).await);
+		let size_limit = upload_limit(&ctx, msg.guild_id).await;
+		let options = DownloadOptions {
+			size_limit: Some(size_limit),
+			..download_url_regex.options.clone()
+		};
 
-			match &*result {
-				Ok(_) => break,
-				Err(err) => {
-					let err = err.to_string();
+		let started = Instant::now();
 
-					if err.contains("yt_dlp.utils.UnsupportedError")
-						|| download_url_regex.no_video.as_deref().is_some_and(|no_video| err.contains(no_video))
-					{
-						// No video at this URL. Just ignore it.
-						return;
+		let cancellable = self.app_ctx.cancels.track(msg.id, posted.requester);
+		let download = async {
+			let mut result = None;
+			for _ in 0..2 {
+				let result = result.insert(self.app_ctx.broker.download(download_url, &options).await);
+
+				match &*result {
+					Ok(_) => break,
+					Err(err) => {
+						if err.is_no_media() {
+							// No video at this URL. Just ignore it.
+							return None;
+						}
 					}
 				}
 			}
-		}
+			result
+		};
+
+		let result = tokio::select! {
+			result = download => result,
+
+			// Dropping the download kills any running yt-dlp/ffmpeg process and deletes its files
+			() = cancellable.cancelled() => {
+				log::info!("Download of {download_url} cancelled by its requester");
+				return;
+			}
+		};
+		drop(cancellable);
+
+		let Some(result) = result else {
+			return;
+		};
 
-		let media = match result.unwrap() {
+		let downloaded = Instant::now();
+
+		let mut media = match result {
 			Ok(media) => media,
+
+			Err(err) if err.wants_fixup() && download_url_regex.fixup.is_some() => {
+				log::warn!("Failed to download {download_url}, replying with its fixup link instead ({err})");
+
+				drop(typing);
+				self.link_instead_of_upload(ctx, msg, config, download_url_regex, download_url, posted.spoiler)
+					.await;
+				return;
+			}
+
 			Err(err) => {
-				log::error!("Failed to download {download_url} ({err}) [3]");
+				let mut report = ErrorReport::new(download_url);
+				log::error!("Failed to download {download_url} ({err}) [3] [report {}]", report.id);
+
+				report
+					.timing("download", downloaded - started)
+					.error(&err)
+					.config(&self.app_ctx.config)
+					.await
+					.submit(&ctx.http, config.admin_guild.as_ref().map(|admin_guild| admin_guild.log_channel_id))
+					.await;
+
+				hooks::run(config, Self::hook_payload(HookEvent::OnFailure, download_url, &msg, None, Some(&err))).await;
 				msg.react(&ctx, '❌').await.ok();
+
+				drop(typing);
+				self.offer_retry(ctx, msg, config, download_url_regex, download_url, posted).await;
 				return;
 			}
 		};
 
-		let media_size = match tokio::fs::metadata(&media.path).await {
-			Ok(metadata) => metadata.len(),
-			Err(err) => {
-				log::error!("Failed to get output file metadata for {download_url} ({err})");
+		let (media_size, spoiler_filter, media_hash, dedup_url) = match prepare_upload(
+			&self.app_ctx,
+			config,
+			download_url_regex.subtitles,
+			download_url,
+			&mut media,
+			hook_origin(&msg),
+			options.priority,
+		)
+		.await
+		{
+			Prepared::Upload {
+				size,
+				spoiler_filter,
+				media_hash,
+				dedup_url,
+			} => (size, spoiler_filter, media_hash, dedup_url),
+
+			Prepared::Rejected | Prepared::Filtered => return,
+
+			Prepared::Flagged(reason) => {
+				log::warn!(
+					"Content scanner flagged {download_url} posted by {} ({}) in channel {} ({reason})",
+					msg.author.name,
+					msg.author.id,
+					msg.channel_id
+				);
+				msg.react(&ctx, '🚫').await.ok();
+				return;
+			}
+
+			Prepared::Failed => {
 				msg.react(&ctx, '❌').await.ok();
 				return;
 			}
 		};
 
-		enum UploadMediaError {
-			TooLarge,
-			Other(serenity::Error),
-		}
+		let uploading = Instant::now();
+
+		let mut result = match (dedup_url, media_size > size_limit) {
+			// Already uploaded this exact file before, just link to it
+			(Some(dedup_url), _) => {
+				let channel_id = Self::reply_channel(&ctx, &msg, config, &media, posted.spoiler || spoiler_filter.is_some()).await;
+
+				channel_id
+					.send_message(
+						&ctx,
+						reply_in(&msg, channel_id)
+							.allowed_mentions(content_filter_mentions(spoiler_filter))
+							.content(match spoiler_filter {
+								Some(filter) => format!("{}\n||{dedup_url}||", filter.moderator_ping()),
+								None if posted.spoiler => format!("||{dedup_url}||"),
+								None => dedup_url.into(),
+							}),
+					)
+					.await
+					.map(Some)
+					.map_err(UploadError::from)
+			}
 
-		let mut result = match media_size > DISCORD_FILE_SIZE_LIMIT {
-			true => Err(UploadMediaError::TooLarge),
-			false => {
-				let file = match CreateAttachment::path(&media.path).await {
+			(None, true) => Err(UploadError::TooLarge),
+
+			(None, false) => {
+				let mut file = match CreateAttachment::path(&media.path).await {
 					Ok(file) => file,
 					Err(err) => {
 						log::error!("Failed to create attachment for {download_url} ({err})");
@@ -146,171 +514,1780 @@ impl DiscordBot {
 					}
 				};
 
-				let mut reply = CreateMessage::new()
-					.reference_message(&msg)
-					.add_file(file)
-					.allowed_mentions(CreateAllowedMentions::new());
-
-				if let Some(embed) = &mut replace_embed {
-					embed.image = None;
-					embed.video = None;
-					embed.thumbnail = None;
-					embed.provider = None;
-					reply = reply.add_embed(CreateEmbed::from(embed.clone()));
+				if let Some(filename) = config.upload_filename(msg.guild_id, download_url, &media) {
+					file.filename = filename;
 				}
 
-				msg.channel_id
-					.send_message(&ctx, reply.clone())
-					.await
-					.map(Some)
-					.map_err(UploadMediaError::Other)
+				// Content filtered media is left as a reply to the original for moderators, and webhook reposts only carry the one file
+				let repost_webhook = match posted.repostable && spoiler_filter.is_none() && media.subtitles.is_none() {
+					true => self
+						.repost_webhook(&ctx, msg.channel_id)
+						.await
+						.inspect_err(|err| log::warn!("Failed to get a webhook to repost {download_url}, replying instead ({err})"))
+						.ok(),
+					false => None,
+				};
+
+				if let Some(webhook) = repost_webhook {
+					if posted.spoiler {
+						file.filename = format!("SPOILER_{}", file.filename);
+					}
+
+					self.webhook_repost(&ctx, &msg, &webhook, file).await
+				} else {
+					let channel_id = Self::reply_channel(&ctx, &msg, config, &media, posted.spoiler || spoiler_filter.is_some()).await;
+
+					let mut reply = reply_in(&msg, channel_id).allowed_mentions(content_filter_mentions(spoiler_filter));
+
+					// Our own embed would give away what a spoilered or content filtered link is, like the caption
+					let rich_embed = match replace_embed.is_none() && config.rich_embeds && !posted.spoiler && spoiler_filter.is_none() {
+						true => metadata_embed(&media.metadata, download_url, &download_url_regex.embed),
+						false => None,
+					};
+
+					if let Some(filter) = spoiler_filter {
+						file.filename = format!("SPOILER_{}", file.filename);
+						reply = reply.content(filter.moderator_ping());
+					} else if posted.spoiler {
+						// No caption either, the title could give it away
+						file.filename = format!("SPOILER_{}", file.filename);
+					} else if config.caption_titles {
+						if let Some(caption) = caption_title(&media.metadata, &mut file, replace_embed.as_mut()) {
+							// Already the embed's title
+							if rich_embed.is_none() {
+								reply = reply.content(caption);
+							}
+						}
+					}
+
+					if let Some(embed) = &mut replace_embed {
+						download_url_regex.embed.apply(embed);
+						reply = reply.add_embed(CreateEmbed::from(embed.clone()));
+					} else if let Some(embed) = rich_embed {
+						reply = reply.add_embed(embed);
+					}
+
+					let subtitles = match &media.subtitles {
+						Some(srt_path) => subtitles::attachment(srt_path, &file.filename)
+							.await
+							.inspect_err(|err| log::warn!("Failed to attach subtitles for {download_url} ({err})"))
+							.ok(),
+						None => None,
+					};
+
+					let mut files = ReplyBuilder::new(size_limit);
+					match files.add_file(file) {
+						Ok(()) => {
+							if let Some(subtitles) = subtitles {
+								files.add_file(subtitles).ok();
+							}
+							files.send(&ctx, channel_id, reply).await.map(|sent| sent.into_iter().next())
+						}
+						Err(err) => Err(err),
+					}
+				}
 			}
 		};
 
-		if let (
-			Err(
-				UploadMediaError::TooLarge
-				| UploadMediaError::Other(serenity::Error::Http(serenity::http::HttpError::UnsuccessfulRequest(serenity::http::ErrorResponse {
-					status_code: serenity::http::StatusCode::PAYLOAD_TOO_LARGE,
-					..
-				}))),
-			),
-			fixup,
-		) = (result.as_ref(), download_url_regex.fixup.as_deref())
-		{
-			if let Some(fixed_up) = fixup
-				.map(|fixup| download_url_regex.regex.replace(download_url, fixup))
-				.filter(|fixed_up| fixed_up != download_url)
-			{
-				result = msg
-					.channel_id
-					.send_message(
-						&ctx,
-						CreateMessage::new()
-							.reference_message(&msg)
-							.allowed_mentions(CreateAllowedMentions::new())
-							.content(fixed_up),
-					)
-					.await
-					.map(|_| None)
-					.map_err(UploadMediaError::Other);
+		// Fallbacks tried for media too large to upload, for the too large reply
+		let mut fallbacks = Vec::new();
+
+		if result.as_ref().is_err_and(UploadError::is_too_large) {
+			if download_url_regex.fixup.is_some() {
+				fallbacks.push("fixup link");
+			}
+			if download_url_regex.contact_sheet || download_url_regex.trailer.is_some() {
+				fallbacks.push("preview");
+			}
+			if download_url_regex.thumbnail_fallback {
+				fallbacks.push("thumbnail");
+			}
+
+			let fixed_up = download_url_regex.fixed_up(download_url);
+
+			let mut preview = match Self::oversized_preview(&media, download_url_regex, self.app_ctx.yt_dlp.queue(), options.priority).await {
+				Ok(preview) => preview,
+				Err(err) => {
+					log::error!("Failed to generate a preview for {download_url} ({err})");
+					None
+				}
+			};
+
+			if preview.is_none() && download_url_regex.thumbnail_fallback {
+				preview = self
+					.thumbnail_preview(&media, media_size, &options, fixed_up.is_none().then_some(download_url))
+					.await;
+			}
+
+			if fixed_up.is_some() || preview.is_some() {
+				let channel_id = Self::reply_channel(&ctx, &msg, config, &media, posted.spoiler || spoiler_filter.is_some()).await;
+
+				let mut reply = reply_in(&msg, channel_id)
+					.allowed_mentions(CreateAllowedMentions::new())
+					.content(fixed_up.as_deref().unwrap_or_default());
+
+				let mut preview_files = ReplyBuilder::new(size_limit);
+
+				if let Some((info, files)) = preview {
+					reply = reply.content(format!("{info}\n{}", fixed_up.as_deref().unwrap_or_default()));
+
+					for mut file in files {
+						if spoiler_filter.is_some() || posted.spoiler {
+							file.filename = format!("SPOILER_{}", file.filename);
+						}
+
+						if let Err(err) = preview_files.add_file(file) {
+							log::warn!("Skipping preview file for {download_url} ({err})");
+						}
+					}
+				}
+
+				result = preview_files.send(&ctx, channel_id, reply).await.map(|sent| {
+					if let Some(reply) = sent.first() {
+						remember_upload(&self.recent_uploads, config, &msg, download_url, reply);
+					}
+					None
+				});
+			}
+		}
+
+		if let (Some(media_hash), Ok(Some(new_msg))) = (media_hash, &result) {
+			if let Some(attachment) = new_msg.attachments.first() {
+				self.app_ctx.dedup.insert(media_hash, &attachment.url).await;
 			}
 		}
 
 		drop(typing);
 
 		match result {
-			Err(
-				UploadMediaError::TooLarge
-				| UploadMediaError::Other(serenity::Error::Http(serenity::http::HttpError::UnsuccessfulRequest(serenity::http::ErrorResponse {
-					status_code: serenity::http::StatusCode::PAYLOAD_TOO_LARGE,
-					..
-				}))),
-			) => {
-				msg.react(&ctx, '🫃').await.ok();
+			Err(err) if err.is_too_large() => {
+				let guild = msg.guild_id.and_then(|guild_id| config.guilds.get(&guild_id));
+
+				// Content filtered media stays on Discord where it can be moderated
+				let upload_too_large = config.storage.is_some() && spoiler_filter.is_none() && guild.is_some_and(|guild| guild.upload_too_large);
+
+				if upload_too_large {
+					// Uploads this large take a while, the message's other links shouldn't wait for it
+					tokio::spawn(Self::upload_too_large(
+						ctx,
+						msg,
+						config.clone(),
+						self.recent_uploads.clone(),
+						download_url.to_owned(),
+						media,
+						media_size,
+						size_limit,
+						posted.spoiler,
+					));
+				} else if guild.is_some_and(|guild| guild.too_large_reply) {
+					Self::too_large_reply(&ctx, &msg, config, media, media_size, size_limit, &fallbacks).await;
+				} else {
+					msg.react(&ctx, '🫃').await.ok();
+				}
 			}
 
-			Err(UploadMediaError::Other(err)) => {
-				log::error!("Failed to send {download_url} ({err} {err:?})");
+			Err(err) => {
+				let mut report = ErrorReport::new(download_url);
+				log::error!("Failed to send {download_url} ({err} {err:?}) [report {}]", report.id);
+
+				report
+					.timing("download", downloaded - started)
+					.timing("encode", media.timings.post_process)
+					.timing("processing", uploading - downloaded)
+					.timing("upload", uploading.elapsed())
+					.error(&err)
+					.probe(&media.path)
+					.await
+					.config(&self.app_ctx.config)
+					.await
+					.submit(&ctx.http, config.admin_guild.as_ref().map(|admin_guild| admin_guild.log_channel_id))
+					.await;
+
+				hooks::run(
+					config,
+					Self::hook_payload(HookEvent::OnFailure, download_url, &msg, Some(&media), Some(&err)),
+				)
+				.await;
 				msg.react(&ctx, '❌').await.ok();
+
+				drop(media);
+				self.offer_retry(ctx, msg, config, download_url_regex, download_url, posted).await;
 			}
 
 			Ok(new_msg) => {
-				if replace_embed.is_some() && msg.edit(&ctx, EditMessage::new().suppress_embeds(true)).await.is_err() {
-					if let Some(mut new_msg) = new_msg {
-						new_msg.edit(&ctx, EditMessage::new().suppress_embeds(true)).await.ok();
-					}
+				let reply_id = new_msg.as_ref().map(|new_msg| new_msg.id);
+
+				if let Some(new_msg) = &new_msg {
+					remember_upload(&self.recent_uploads, config, &msg, download_url, new_msg);
+				}
+
+				let suppressed = replace_embed.is_some() && Self::suppress_duplicate_embed(&ctx, &mut msg, new_msg).await;
+
+				if let Some(reply_id) = reply_id {
+					self.app_ctx
+						.replies
+						.insert(
+							reply_id,
+							msg.id,
+							msg.author.id,
+							download_url,
+							download_url_regex.name.as_deref(),
+							suppressed.then_some(msg.id),
+						)
+						.await;
+				}
+
+				// Only media that made it onto Discord, and none that's hidden there: the bridges can't spoiler it, and content
+				// filtered media is left for the guild's moderators
+				let bridges = msg
+					.guild_id
+					.and_then(|guild_id| config.guilds.get(&guild_id))
+					.map(|guild| &guild.bridges[..])
+					.unwrap_or_default();
+				if reply_id.is_some() && !posted.spoiler && spoiler_filter.is_none() && !bridges.is_empty() {
+					bridge::forward(bridges, &media).await;
 				}
 			}
 		}
 	}
 
-	async fn admin_config_message(&self, ctx: Context, msg: Message, _config: Arc<CompiledConfig>) {
-		let mut content = msg.content.as_str();
+	/// Uploads media too large for Discord to the external file host and replies with the link, or reacts with 🫃 if that fails
+	#[allow(clippy::too_many_arguments)]
+	async fn upload_too_large(
+		ctx: Context,
+		msg: Message,
+		config: Arc<CompiledConfig>,
+		recent_uploads: Arc<RecentUploads>,
+		download_url: String,
+		media: MediaResult,
+		media_size: u64,
+		size_limit: u64,
+		spoiler: bool,
+	) {
+		let Some(storage) = &config.storage else {
+			return;
+		};
 
-		if content == "!dump" {
-			let json = self.app_ctx.config.dump().await.unwrap_or_else(|err| format!("ERROR: {err}"));
+		let url = match storage.upload(&media.path).await {
+			Ok(url) => url,
+			Err(err) => {
+				log::error!("Failed to upload {} to {} ({err})", media.path.display(), storage.name());
+				msg.react(&ctx, '🫃').await.ok();
+				return;
+			}
+		};
 
-			msg.reply(ctx, &json).await.ok();
+		let url = match spoiler {
+			true => format!("||{url}||"),
+			false => url,
+		};
 
-			return;
-		}
+		let channel_id = Self::reply_channel(&ctx, &msg, &config, &media, spoiler).await;
 
-		content = content.trim();
+		let reply = reply_in(&msg, channel_id)
+			.allowed_mentions(CreateAllowedMentions::new())
+			.content(format!("{}\n{url}", too_large_notice(media_size, size_limit, &[], false)));
 
-		content = content
-			.strip_prefix("```json\n")
-			.or_else(|| content.strip_prefix("```\n"))
-			.and_then(|content| content.strip_suffix("\n```"))
-			.unwrap_or(content);
+		match channel_id.send_message(&ctx, reply).await {
+			Ok(reply) => remember_upload(&recent_uploads, &config, &msg, &download_url, &reply),
+			Err(err) => log::error!("Failed to reply with the external upload of too large media ({err})"),
+		}
+	}
 
-		match self.app_ctx.config.edit(content).await {
-			Ok(_) => {
-				msg.react(ctx, '✅').await.ok();
-			}
+	/// Tells the poster how far over the limit the media is and what was tried, offering to upload it to the external file host if
+	/// there is one. Waits for the button in the background, keeping the media around until the offer expires
+	async fn too_large_reply(
+		ctx: &Context,
+		msg: &Message,
+		config: &Arc<CompiledConfig>,
+		media: MediaResult,
+		media_size: u64,
+		size_limit: u64,
+		fallbacks: &[&str],
+	) {
+		let external_upload = config.storage.is_some();
 
+		let mut reply = CreateMessage::new()
+			.reference_message(msg)
+			.allowed_mentions(CreateAllowedMentions::new())
+			.content(too_large_notice(media_size, size_limit, fallbacks, external_upload));
+		if external_upload {
+			reply = reply.components(external_upload_buttons());
+		}
+
+		let notice = match msg.channel_id.send_message(ctx, reply).await {
+			Ok(notice) => notice,
 			Err(err) => {
-				msg.reply(ctx, format!("ERROR: {err}")).await.ok();
+				log::error!("Failed to reply to too large media ({err})");
+				msg.react(ctx, '🫃').await.ok();
+				return;
 			}
+		};
+
+		if !external_upload {
+			return;
 		}
-	}
 
-	fn is_admin_config_message(msg: &Message, config: &CompiledConfig) -> bool {
-		config.admin_guild.as_ref().is_some_and(|admin_guild| {
-			msg.guild_id.is_some_and(|guild_id| guild_id == admin_guild.guild_id) && msg.channel_id == admin_guild.config_channel_id
-		})
+		let (ctx, user_id, config) = (ctx.clone(), msg.author.id, config.clone());
+		tokio::spawn(async move {
+			let mut notice = notice;
+			if !await_external_upload(&ctx, &notice, user_id, &config, &media).await {
+				notice.edit(&ctx, EditMessage::new().components(vec![])).await.ok();
+			}
+		});
 	}
-}
-
-#[async_trait]
-impl EventHandler for DiscordBot {
-	async fn ready(&self, ctx: Context, ready: serenity::all::Ready) {
-		log::info!("Discord bot connected as {}", ready.user.name);
-		log::info!(
-			"Invite link: https://discord.com/oauth2/authorize?client_id={}&permissions=274877966400&integration_type=0&scope=bot",
-			ready.user.id
-		);
-		log::info!("Member of {} guilds", ready.guilds.len());
 
-		cmd::register(&ctx).await.expect("Failed to register /download command");
+	/// Blocked users and guilds are ignored, except in the admin guild so nobody can be locked out of `/admin unblock`
+	async fn is_blocked(&self, config: &CompiledConfig, user_id: UserId, guild_id: Option<GuildId>) -> bool {
+		let in_admin_guild = config
+			.admin_guild
+			.as_ref()
+			.is_some_and(|admin_guild| guild_id == Some(admin_guild.guild_id));
 
-		let config = self.app_ctx.config.get().await;
-
-		if let Some(admin_guild) = &config.admin_guild {
-			logging::connect_discord(admin_guild.log_channel_id, ctx.http.clone()).await;
-		}
+		!in_admin_guild && self.app_ctx.blocklist.is_blocked(user_id, guild_id).await
 	}
 
-	async fn message(&self, ctx: Context, msg: Message) {
-		if msg.author.bot {
+	/// Replies with a retry button if the guild wants one, and downloads the link again if its requester presses it. Waits for the
+	/// button in the background, so the message's other links aren't held up
+	async fn offer_retry(
+		&self,
+		ctx: Context,
+		msg: Message,
+		config: &Arc<CompiledConfig>,
+		download_url_regex: &CompiledLinkRegex,
+		download_url: &str,
+		posted: PostedLink,
+	) {
+		let guild = msg.guild_id.and_then(|guild_id| config.guilds.get(&guild_id));
+		if !guild.is_some_and(|guild| guild.retry_button) {
 			return;
 		}
 
-		let config = self.app_ctx.config.get().await;
+		let prompt = msg
+			.channel_id
+			.send_message(
+				&ctx,
+				CreateMessage::new()
+					.reference_message(&msg)
+					.allowed_mentions(CreateAllowedMentions::new())
+					.content("Failed to download this link, it may work if you try again")
+					.components(vec![CreateActionRow::Buttons(vec![CreateButton::new("retry")
+						.label("Retry")
+						.style(ButtonStyle::Secondary)])]),
+			)
+			.await;
 
-		if Self::is_admin_config_message(&msg, &config) {
-			self.admin_config_message(ctx, msg, config).await;
-		} else {
-			self.generic_message(ctx, msg, config).await;
-		}
-	}
+		let prompt = match prompt {
+			Ok(prompt) => prompt,
+			Err(err) => {
+				log::error!("Failed to offer a retry of {download_url} ({err})");
+				return;
+			}
+		};
+
+		let bot = self.clone();
+		let regex_name = download_url_regex.name.clone();
+		let download_url = download_url.to_owned();
+
+		tokio::spawn(async move {
+			let mut interactions = prompt.await_component_interactions(&ctx.shard).timeout(RETRY_OFFER_TIMEOUT).stream();
+
+			let mut retried = false;
+			while let Some(interaction) = interactions.next().await {
+				if interaction.user.id != posted.requester {
+					interaction
+						.create_response(
+							&ctx,
+							CreateInteractionResponse::Message(
+								CreateInteractionResponseMessage::new()
+									.ephemeral(true)
+									.content("Only whoever asked for this link can retry it"),
+							),
+						)
+						.await
+						.ok();
+					continue;
+				}
+
+				interaction.create_response(&ctx, CreateInteractionResponse::Acknowledge).await.ok();
+				retried = true;
+				break;
+			}
+
+			prompt.delete(&ctx).await.ok();
+
+			if !retried {
+				return;
+			}
+
+			bot.retry(ctx, msg, regex_name, download_url, posted).await;
+		});
+	}
+
+	/// Downloads a link again after its requester pressed the retry button. Boxed as it's recursive
+	fn retry(self, ctx: Context, msg: Message, regex_name: Option<Box<str>>, download_url: String, posted: PostedLink) -> BoxFuture<'static, ()> {
+		Box::pin(async move {
+			// The config may have changed while the button was up
+			let config = self.app_ctx.config.get_for_guild(msg.guild_id).await;
+			let Some(download_url_regex) = find_link_regex(&config, regex_name.as_deref(), &download_url).cloned() else {
+				log::info!("Not retrying {download_url}, its link regex was removed since");
+				return;
+			};
+
+			log::info!("{} retried {download_url}", msg.author.name);
+
+			msg.delete_reaction(&ctx, None, '❌').await.ok();
+
+			self.download_link(ctx, msg, &config, &download_url_regex, &download_url, posted).await;
+		})
+	}
+
+	/// Who added us to the guild, if we can see its audit log
+	async fn guild_inviter(ctx: &Context, guild: &Guild) -> Option<User> {
+		let audit_logs = guild
+			.id
+			.audit_logs(
+				&ctx.http,
+				Some(audit_log::Action::Member(audit_log::MemberAction::BotAdd)),
+				None,
+				None,
+				Some(10),
+			)
+			.await
+			.inspect_err(|err| log::info!("Can't see who added us to guild {} ({err})", guild.id))
+			.ok()?;
+
+		let current_user_id = ctx.cache.current_user().id;
+
+		let entry = audit_logs
+			.entries
+			.iter()
+			.find(|entry| entry.target_id.is_some_and(|target_id| target_id.get() == current_user_id.get()))?;
+
+		audit_logs.users.get(&entry.user_id).cloned()
+	}
+
+	/// Posts to the admin log channel, with the number of guilds we're now in
+	async fn guild_notification(&self, ctx: &Context, notification: String) {
+		let config = self.app_ctx.config.get().await;
+		let Some(admin_guild) = &config.admin_guild else {
+			return;
+		};
+
+		let guild_count = self.app_ctx.roster.count().await;
+
+		if let Err(err) = admin_guild
+			.log_channel_id
+			.send_message(
+				ctx,
+				CreateMessage::new()
+					.allowed_mentions(CreateAllowedMentions::new())
+					.content(format!("{}\nNow in {guild_count} guilds", crate::privacy::redact(&notification))),
+			)
+			.await
+		{
+			log::error!("Failed to post guild notification ({err})");
+		}
+	}
+
+	/// Downloads the link behind one of our replies again, with the options given in the redo command
+	async fn redo_message(&self, ctx: Context, msg: Message, config: Arc<CompiledConfig>, args: &str) {
+		let args = match RedoArgs::parse(args) {
+			Ok(args) => args,
+			Err(unknown) => {
+				msg.reply(
+					&ctx,
+					format!("Unknown option `{unknown}`, usage: `{} [audio|video|hq]`", config.redo_prefix),
+				)
+				.await
+				.ok();
+				return;
+			}
+		};
+
+		// The upload is bound to fail, don't bother downloading
+		if !can_attach_files(&ctx, &msg) {
+			msg.react(&ctx, '❌').await.ok();
+			return;
+		}
+
+		let Some(entry) = self.app_ctx.replies.get(msg.referenced_message.as_ref().unwrap().id).await else {
+			// Too old, or not a download
+			msg.react(&ctx, '❓').await.ok();
+			return;
+		};
+
+		let Some(regex) = find_link_regex(&config, entry.regex.as_deref(), &entry.url) else {
+			// The link regex was removed since
+			msg.react(&ctx, '❓').await.ok();
+			return;
+		};
+
+		let mut regex = regex.clone();
+		regex.disable_embed_wait = true;
+		args.apply(&mut regex.options);
+		if regex.options.audio_only {
+			regex.subtitles = None;
+		}
+
+		// Keep the redone media hidden if the original was
+		let posted = PostedLink {
+			spoiler: msg
+				.referenced_message
+				.as_ref()
+				.is_some_and(|reply| reply.attachments.iter().any(|attachment| attachment.filename.starts_with("SPOILER_"))),
+			repostable: false,
+			requester: msg.author.id,
+		};
+
+		self.download_link(ctx, msg, &config, &regex, &entry.url, posted).await;
+	}
+
+	/// If `msg` is a redo command replying to one of our messages, returns its arguments
+	fn redo_args<'a>(ctx: &Context, msg: &'a Message, config: &CompiledConfig) -> Option<&'a str> {
+		if msg.referenced_message.as_ref()?.author.id != ctx.cache.current_user().id {
+			return None;
+		}
+
+		let args = msg.content.trim().strip_prefix(&*config.redo_prefix)?;
+		(args.is_empty() || args.starts_with(char::is_whitespace)).then_some(args)
+	}
+
+	/// Our reply carries a copy of the original message's embed, so suppress the original's, or if we can't
+	/// (no Manage Messages permission, or the message is already gone) strip the copy from our reply instead. Returns whether the
+	/// original's embed was suppressed
+	async fn suppress_duplicate_embed(ctx: &Context, msg: &mut Message, reply: Option<Message>) -> bool {
+		let Err(err) = msg.edit(ctx, EditMessage::new().suppress_embeds(true)).await else {
+			return true;
+		};
+
+		let Some(mut reply) = reply else {
+			log::warn!("Failed to suppress embeds of {} ({err})", msg.id);
+			return false;
+		};
+
+		if let Err(reply_err) = reply.edit(ctx, EditMessage::new().embeds(vec![]).suppress_embeds(true)).await {
+			log::warn!("Failed to suppress embeds of {} ({err}) or of our reply to it ({reply_err})", msg.id);
+		}
+
+		false
+	}
+
+	/// Where to post media for `msg`: a thread under it in guilds with `reply_in_thread`, named after the media unless that could
+	/// give away a spoiler, or failing that the message's own channel
+	async fn reply_channel(ctx: &Context, msg: &Message, config: &CompiledConfig, media: &MediaResult, spoiler: bool) -> ChannelId {
+		let guild = msg.guild_id.and_then(|guild_id| config.guilds.get(&guild_id));
+		if !guild.is_some_and(|guild| guild.reply_in_thread) {
+			return msg.channel_id;
+		}
+
+		// Threads can't have threads, e.g. for redo commands in a thread we created
+		let in_thread = msg
+			.guild(&ctx.cache)
+			.is_some_and(|guild| guild.threads.iter().any(|thread| thread.id == msg.channel_id));
+		if in_thread {
+			return msg.channel_id;
+		}
+
+		let name = match &media.metadata.title {
+			Some(title) if !spoiler => truncate_chars(title, THREAD_NAME_LIMIT),
+			_ => "Media".to_string(),
+		};
+
+		let err = match msg.channel_id.create_thread_from_message(ctx, msg.id, CreateThread::new(name)).await {
+			Ok(thread) => return thread.id,
+			Err(err) => err,
+		};
+
+		// A thread started from a message shares its ID, so an earlier link in the message may have already created it
+		let existing_thread_id = ChannelId::new(msg.id.get());
+		if existing_thread_id.to_channel(ctx).await.is_ok() {
+			return existing_thread_id;
+		}
+
+		log::warn!(
+			"Failed to create a thread under {} in channel {}, replying instead ({err})",
+			msg.id,
+			msg.channel_id
+		);
+		msg.channel_id
+	}
+
+	/// Our webhook in `channel_id` for reposts, created the first time it's needed
+	async fn repost_webhook(&self, ctx: &Context, channel_id: ChannelId) -> Result<Webhook, serenity::Error> {
+		if let Some(webhook) = self.webhooks.lock().get(&channel_id) {
+			return Ok(webhook.clone());
+		}
+
+		let current_user_id = ctx.cache.current_user().id;
+
+		let existing = channel_id
+			.webhooks(ctx)
+			.await?
+			.into_iter()
+			.find(|webhook| webhook.token.is_some() && webhook.user.as_ref().is_some_and(|user| user.id == current_user_id));
+
+		let webhook = match existing {
+			Some(webhook) => webhook,
+			None => channel_id.create_webhook(ctx, CreateWebhook::new(REPOST_WEBHOOK_NAME)).await?,
+		};
+
+		self.webhooks.lock().insert(channel_id, webhook.clone());
+
+		Ok(webhook)
+	}
+
+	/// Reposts `msg` under its author's name and avatar with `file` attached, then deletes the original
+	async fn webhook_repost(&self, ctx: &Context, msg: &Message, webhook: &Webhook, file: CreateAttachment) -> Result<Option<Message>, UploadError> {
+		let username = msg
+			.member
+			.as_ref()
+			.and_then(|member| member.nick.clone())
+			.unwrap_or_else(|| msg.author.display_name().to_string());
+
+		let repost = ExecuteWebhook::new()
+			.username(username)
+			.avatar_url(msg.author.face())
+			.content(&msg.content)
+			// The link's embed would only repeat the attached media
+			.flags(MessageFlags::SUPPRESS_EMBEDS)
+			.allowed_mentions(CreateAllowedMentions::new())
+			.add_file(file);
+
+		let reposted = match webhook.execute(ctx, true, repost).await {
+			Ok(reposted) => reposted,
+			Err(err) => {
+				// It may have been deleted, look for or create a new one next time
+				self.webhooks.lock().remove(&msg.channel_id);
+				return Err(err.into());
+			}
+		};
+
+		if let Err(err) = msg.delete(ctx).await {
+			log::warn!("Failed to delete {} after reposting it ({err})", msg.id);
+		}
+
+		Ok(reposted)
+	}
+
+	/// Gives the message we replied to its embed back if we suppressed it, as our copy went with the reply, and stops pointing
+	/// reposts of its link at it
+	async fn reply_deleted(&self, ctx: &Context, channel_id: ChannelId, reply_id: MessageId) {
+		self.recent_uploads.remove_message(reply_id);
+
+		let Some(original_id) = self.app_ctx.replies.remove(reply_id).await.and_then(|entry| entry.suppressed_embed) else {
+			return;
+		};
+
+		if let Err(err) = channel_id.edit_message(ctx, original_id, EditMessage::new().suppress_embeds(false)).await {
+			log::warn!("Failed to restore embeds of {original_id} after our reply was deleted ({err})");
+		}
+	}
+
+	async fn admin_config_message(&self, ctx: Context, msg: Message, _config: Arc<CompiledConfig>) {
+		let mut content = msg.content.as_str();
+
+		if content == "!dump" {
+			self.app_ctx.audit.record(msg.author.id, &msg.author.name, "dumped config", None).await;
+
+			let reply = match self.app_ctx.config.dump().await {
+				Ok(json) => code_block_or_file(json, "json", "config.json").into_message(),
+				Err(err) => CreateMessage::new().content(format!("ERROR: {err}")),
+			};
+
+			msg.channel_id.send_message(&ctx, reply.reference_message(&msg)).await.ok();
+
+			return;
+		}
+
+		content = content.trim();
+
+		// A full config replaces the current one only when asked to, otherwise it's merged in as a patch
+		let replace = match content.strip_prefix("!replace") {
+			Some(rest) => {
+				content = rest.trim_start();
+				true
+			}
+			None => false,
+		};
+
+		content = content
+			.strip_prefix("```json\n")
+			.or_else(|| content.strip_prefix("```\n"))
+			.and_then(|content| content.strip_suffix("\n```"))
+			.unwrap_or(content);
+
+		let input = match self.app_ctx.config.resolve(content, replace).await {
+			Ok(input) => input,
+			Err(err) => {
+				msg.reply(ctx, format!("ERROR: {err}")).await.ok();
+				return;
+			}
+		};
+
+		let preview = match self.app_ctx.config.preview(input.as_str()).await {
+			Ok(preview) => preview,
+			Err(err) => {
+				msg.reply(ctx, format!("ERROR: {err}")).await.ok();
+				return;
+			}
+		};
+
+		let (preview_content, preview_file) = render_config_preview(preview);
+
+		let mut prompt = CreateMessage::new()
+			.reference_message(&msg)
+			.content(preview_content)
+			.components(confirm_buttons());
+
+		if let Some(preview_file) = preview_file {
+			prompt = prompt.add_file(preview_file);
+		}
+
+		let prompt = match msg.channel_id.send_message(&ctx, prompt).await {
+			Ok(prompt) => prompt,
+			Err(err) => {
+				log::error!("Failed to send config preview ({err})");
+				return;
+			}
+		};
+
+		if !await_confirmation(&ctx, prompt, msg.author.id).await {
+			return;
+		}
+
+		match self.app_ctx.config.edit(input.as_str()).await {
+			Ok(diff) => {
+				self.app_ctx
+					.audit
+					.record(msg.author.id, &msg.author.name, "edited config", Some(&diff))
+					.await;
+
+				msg.react(&ctx, '✅').await.ok();
+
+				if let ConfigInput::Patched(config) = input {
+					let reply = code_block_or_file(crate::privacy::redact_secrets(&config).into_owned(), "json", "config.json").into_message();
+					msg.channel_id.send_message(&ctx, reply.reference_message(&msg)).await.ok();
+				}
+			}
+
+			Err(err) => {
+				msg.reply(ctx, format!("ERROR: {err}")).await.ok();
+			}
+		}
+	}
+
+	fn hook_payload<'a>(
+		event: HookEvent,
+		url: &'a str,
+		msg: &Message,
+		media: Option<&'a MediaResult>,
+		error: Option<&dyn std::error::Error>,
+	) -> HookPayload<'a> {
+		hook_payload(event, url, hook_origin(msg), media, error)
+	}
+
+	/// Title, duration and whichever of the contact sheet and trailer are enabled, for videos too large to upload
+	async fn oversized_preview(
+		media: &MediaResult,
+		regex: &CompiledLinkRegex,
+		queue: &DownloadQueue,
+		priority: Priority,
+	) -> Result<Option<(String, Vec<CreateAttachment>)>, Error> {
+		if !regex.contact_sheet && regex.trailer.is_none() {
+			return Ok(None);
+		}
+
+		let duration = match &media.stream {
+			Some(stream) => stream.duration,
+			None => match MediaProbe::probe(&media.path, DISCORD_FILE_SIZE_LIMIT).await? {
+				MediaProbe::Probed { duration, .. } => duration,
+				MediaProbe::Corrupt => return Err(ProbeError::Corrupt.into()),
+			},
+		};
+
+		let mut paths = Vec::with_capacity(2);
+
+		if regex.contact_sheet {
+			paths.push(contact_sheet::generate(&media.path, duration).await);
+		}
+
+		if let Some(mode) = regex.trailer {
+			let _permit = queue.encode(priority).await;
+			paths.push(trailer::generate(&media.path, duration, mode, queue.encode_threads()).await);
+		}
+
+		// Post whatever succeeded, a contact sheet alone is still useful if the trailer fails
+		let mut files = Vec::with_capacity(paths.len());
+		let mut last_err = None;
+		for path in paths {
+			let file = match path {
+				Ok(path) => {
+					let file = CreateAttachment::path(&path).await;
+					tokio::fs::remove_file(&path).await.ok();
+					file.map_err(Error::from)
+				}
+				Err(err) => Err(err.into()),
+			};
+
+			match file {
+				Ok(file) => files.push(file),
+				Err(err) => {
+					log::error!("Failed to generate a preview of {} ({err})", media.path.display());
+					last_err = Some(err);
+				}
+			}
+		}
+
+		if let (true, Some(err)) = (files.is_empty(), last_err) {
+			return Err(err);
+		}
+
+		let info = format!(
+			"**{}** ({})",
+			media.metadata.title.as_deref().unwrap_or("Untitled"),
+			contact_sheet::format_duration(duration)
+		);
+
+		Ok(Some((info, files)))
+	}
+
+	/// Thumbnail, title, duration and size, and `page_url` if there's no fixup link to go with it, for videos too large to upload.
+	/// All from yt-dlp's JSON dump, so unlike [`Self::oversized_preview`] nothing has to be decoded. The stream's own URL is signed
+	/// for our IP address, so it's no use to anyone else. `None` if the media has no thumbnail
+	async fn thumbnail_preview(
+		&self,
+		media: &MediaResult,
+		media_size: u64,
+		options: &DownloadOptions,
+		page_url: Option<&str>,
+	) -> Option<(String, Vec<CreateAttachment>)> {
+		let thumbnail = media.metadata.thumbnail.as_ref()?;
+
+		// Capped at the upload limit, a thumbnail too large to upload is left out rather than failing the whole reply
+		let mut files = Vec::with_capacity(1);
+		match self.app_ctx.yt_dlp.download_thumbnail(thumbnail, options).await {
+			Ok(path) => {
+				let file = CreateAttachment::path(&path).await;
+				tokio::fs::remove_file(&path).await.ok();
+
+				match file {
+					Ok(mut file) => {
+						file.filename = thumbnail_filename(thumbnail);
+						files.push(file);
+					}
+					Err(err) => log::warn!("Failed to read thumbnail {thumbnail} ({err})"),
+				}
+			}
+			Err(err) => log::warn!("Failed to download thumbnail {thumbnail} ({err})"),
+		}
+
+		let duration = media
+			.metadata
+			.duration
+			.map(Duration::from_secs_f64)
+			.or(media.stream.as_ref().map(|stream| stream.duration));
+
+		Some((thumbnail_preview_info(&media.metadata, duration, media_size, page_url), files))
+	}
+
+	/// Misconfigured admin guilds otherwise fail silently, config messages and logs just go nowhere
+	async fn validate_admin_guild(ctx: &Context, ready: &Ready, admin_guild: &AdminGuild) -> Vec<String> {
+		if !ready.guilds.iter().any(|guild| guild.id == admin_guild.guild_id) {
+			return vec![format!(
+				"The bot is not a member of the admin guild {}, invite it or fix admin_guild.guild_id",
+				admin_guild.guild_id
+			)];
+		}
+
+		let (guild, member) = match tokio::try_join!(
+			admin_guild.guild_id.to_partial_guild(ctx),
+			admin_guild.guild_id.member(ctx, ready.user.id)
+		) {
+			Ok(res) => res,
+			Err(err) => return vec![format!("Failed to fetch the admin guild {} ({err})", admin_guild.guild_id)],
+		};
+
+		let channels = [
+			(
+				"log_channel_id",
+				admin_guild.log_channel_id,
+				Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES | Permissions::EMBED_LINKS,
+			),
+			(
+				"config_channel_id",
+				admin_guild.config_channel_id,
+				Permissions::VIEW_CHANNEL
+					| Permissions::SEND_MESSAGES
+					| Permissions::READ_MESSAGE_HISTORY
+					| Permissions::ADD_REACTIONS
+					| Permissions::ATTACH_FILES,
+			),
+		];
+
+		let mut warnings = Vec::new();
+
+		for (field, channel_id, required) in channels {
+			let channel = match channel_id.to_channel(ctx).await.map(|channel| channel.guild()) {
+				Ok(Some(channel)) if channel.guild_id == admin_guild.guild_id => channel,
+				Ok(_) => {
+					warnings.push(format!("admin_guild.{field} {channel_id} is not a channel of the admin guild"));
+					continue;
+				}
+				Err(err) => {
+					warnings.push(format!("admin_guild.{field} {channel_id} can't be seen by the bot ({err})"));
+					continue;
+				}
+			};
+
+			let missing = required - guild.user_permissions_in(&channel, &member);
+			if !missing.is_empty() {
+				warnings.push(format!(
+					"The bot is missing permissions in #{} (admin_guild.{field}): {missing} (permission bits {})",
+					channel.name,
+					missing.bits()
+				));
+			}
+		}
+
+		warnings
+	}
+
+	fn is_admin_config_message(msg: &Message, config: &CompiledConfig) -> bool {
+		config.admin_guild.as_ref().is_some_and(|admin_guild| {
+			msg.guild_id.is_some_and(|guild_id| guild_id == admin_guild.guild_id) && msg.channel_id == admin_guild.config_channel_id
+		})
+	}
+}
+
+pub enum LongText {
+	Inline(String),
+	File(CreateAttachment),
+}
+impl LongText {
+	pub fn into_message(self) -> CreateMessage {
+		match self {
+			Self::Inline(content) => CreateMessage::new().content(content),
+			Self::File(file) => CreateMessage::new().add_file(file),
+		}
+	}
+}
+
+/// Wraps `text` in a code block if it fits in a single message, otherwise attaches it as a file
+pub fn code_block_or_file(text: String, lang: &str, filename: &str) -> LongText {
+	let content = format!("```{lang}\n{text}\n```");
+	if content.len() <= DISCORD_MESSAGE_LIMIT {
+		LongText::Inline(content)
+	} else {
+		LongText::File(CreateAttachment::bytes(text, filename))
+	}
+}
+
+/// Renders a config preview as message content, spilling the diff into an attachment if it doesn't fit
+pub fn render_config_preview(preview: ConfigPreview) -> (String, Option<CreateAttachment>) {
+	let content = format!("**Pending config changes**\n{}```diff\n{}\n```", preview.summary, preview.diff);
+
+	// Leave some room for the confirmation status that gets appended later
+	if content.len() <= DISCORD_MESSAGE_LIMIT - 100 {
+		(content, None)
+	} else {
+		(
+			format!("**Pending config changes**\n{}", preview.summary),
+			Some(CreateAttachment::bytes(preview.diff, "config.diff")),
+		)
+	}
+}
+
+/// The link regex named `name`, or failing that the first matching `url`, for downloading a link again after the config may
+/// have changed
+fn find_link_regex<'a>(config: &'a CompiledConfig, name: Option<&str>, url: &str) -> Option<&'a CompiledLinkRegex> {
+	config
+		.link_regexes
+		.iter()
+		.find(|regex| name.is_some() && regex.name.as_deref() == name)
+		.or_else(|| config.link_regexes.iter().find(|regex| regex.is_match(url)))
+}
+
+fn hook_origin(msg: &Message) -> HookOrigin {
+	HookOrigin {
+		guild_id: msg.guild_id,
+		channel_id: msg.channel_id,
+		user_id: Some(msg.author.id),
+	}
+}
+
+pub fn hook_payload<'a>(
+	event: HookEvent,
+	url: &'a str,
+	origin: HookOrigin,
+	media: Option<&'a MediaResult>,
+	error: Option<&dyn std::error::Error>,
+) -> HookPayload<'a> {
+	HookPayload {
+		event,
+		url,
+		path: media.map(|media| media.path.as_ref()),
+		metadata: media.map(|media| &media.metadata),
+		origin,
+		error: error.map(|err| format!("{err:#}")),
+	}
+}
+
+/// What became of downloaded media after [`prepare_upload`]
+pub enum Prepared<'a> {
+	Upload {
+		size: u64,
+		/// The content filter it matched, which means it's posted spoilered for moderators
+		spoiler_filter: Option<&'a CompiledContentFilter>,
+		/// Set when dedup is enabled
+		media_hash: Option<Box<str>>,
+		/// Where the exact same file was uploaded before
+		dedup_url: Option<Box<str>>,
+	},
+	/// Rejected by a hook
+	Rejected,
+	/// Skipped by a content filter
+	Filtered,
+	/// Flagged by the content scanner, for the given reason
+	Flagged(Box<str>),
+	/// Already logged
+	Failed,
+}
+
+/// Everything between downloading and uploading: the hooks, the content scanner and filters, subtitles and the dedup lookup.
+/// Shared by links posted in messages, `/download` and feed entries
+pub async fn prepare_upload<'a>(
+	app_ctx: &AppContext,
+	config: &'a CompiledConfig,
+	subtitles: Option<SubtitleMode>,
+	url: &str,
+	media: &mut MediaResult,
+	origin: HookOrigin,
+	priority: Priority,
+) -> Prepared<'a> {
+	if !hooks::run(config, hook_payload(HookEvent::PostDownload, url, origin, Some(media), None)).await {
+		return Prepared::Rejected;
+	}
+
+	let mut size = match tokio::fs::metadata(&media.path).await {
+		Ok(metadata) => metadata.len(),
+		Err(err) => {
+			log::error!("Failed to get output file metadata for {url} ({err})");
+			return Prepared::Failed;
+		}
+	};
+
+	match scan::screen(config, &media.path).await {
+		Ok(ScanVerdict::Clean) => {}
+		Ok(ScanVerdict::Flagged(reason)) => return Prepared::Flagged(reason),
+		Err(err) => {
+			log::error!("Failed to scan {url} ({err})");
+			return Prepared::Failed;
+		}
+	}
+
+	let spoiler_filter = match config.content_filter(origin.guild_id, &media.metadata) {
+		Some(filter) if filter.action == ContentFilterAction::Skip => {
+			log::info!("Skipping {url} as it matched content filter {}", filter.regex);
+			return Prepared::Filtered;
+		}
+		filter => filter,
+	};
+
+	if let (Some(mode), Some(whisper)) = (subtitles, &config.whisper) {
+		let subtitled = async {
+			let transcript = whisper::transcribe(whisper, &media.path, mode).await?;
+			let subtitled_path = {
+				let queue = app_ctx.yt_dlp.queue();
+				let _permit = queue.encode(priority).await;
+				subtitles::burn(&media.path, &transcript.srt_path, queue.encode_threads()).await?
+			};
+			let subtitled_size = tokio::fs::metadata(&subtitled_path).await?.len();
+			Ok::<_, EncodeError>((subtitled_path, subtitled_size))
+		}
+		.await;
+
+		match subtitled {
+			Ok((subtitled_path, subtitled_size)) => {
+				let unsubtitled_path = std::mem::replace(&mut media.path, subtitled_path.into_boxed_path());
+				tokio::fs::remove_file(unsubtitled_path).await.ok();
+				size = subtitled_size;
+			}
+
+			// Still worth posting the video without subtitles
+			Err(err) => log::error!("Failed to burn subtitles into {url} ({err})"),
+		}
+	}
+
+	if !hooks::run(config, hook_payload(HookEvent::PreUpload, url, origin, Some(media), None)).await {
+		return Prepared::Rejected;
+	}
+
+	// The hook may have modified the file
+	if config.hooks.pre_upload.is_some() {
+		size = tokio::fs::metadata(&media.path).await.map(|metadata| metadata.len()).unwrap_or(size);
+	}
+
+	let (media_hash, dedup_url) = match config.dedup_uploads {
+		true => match app_ctx.dedup.lookup(&media.path).await {
+			Ok((hash, dedup_url)) => (Some(hash), dedup_url),
+			Err(err) => {
+				log::error!("Failed to hash {url} for dedup ({err})");
+				(None, None)
+			}
+		},
+		false => (None, None),
+	};
+
+	Prepared::Upload {
+		size,
+		spoiler_filter,
+		media_hash,
+		dedup_url,
+	}
+}
+
+/// Whether `emoji` is the unicode emoji, or custom emoji with the name, `name`. Ignores emoji variation selectors, which aren't
+/// always typed out in the config
+fn is_reaction(emoji: &ReactionType, name: &str) -> bool {
+	let name = name.trim_matches(|c: char| c == '\u{fe0f}' || c == ':');
+
+	match emoji {
+		ReactionType::Unicode(emoji) => emoji.trim_end_matches('\u{fe0f}') == name,
+		ReactionType::Custom { name: Some(custom), .. } => custom == name,
+		_ => false,
+	}
+}
+
+/// Longest thread name Discord allows
+const THREAD_NAME_LIMIT: usize = 100;
+
+/// A reply to `msg` posted in `channel_id`, which can only reference `msg` if it's in the same channel
+fn reply_in(msg: &Message, channel_id: ChannelId) -> CreateMessage {
+	let reply = CreateMessage::new();
+	if channel_id == msg.channel_id {
+		reply.reference_message(msg)
+	} else {
+		reply
+	}
+}
+
+/// Name of the webhook links are reposted through, which each repost overrides with the author's name
+const REPOST_WEBHOOK_NAME: &str = "Embed Bot";
+
+/// How a link was posted, which decides how its media is posted back
+#[derive(Clone, Copy)]
+struct PostedLink {
+	/// Wrapped in `||spoiler||` tags, so the media is uploaded as a spoiler too
+	spoiler: bool,
+	/// The only link in a message with nothing else a repost would lose, in a guild that wants those reposted through a webhook
+	/// rather than replied to
+	repostable: bool,
+	/// Whoever asked for it to be downloaded, the poster or whoever reacted with the download reaction, who can cancel it
+	requester: UserId,
+}
+
+/// Points later posts of `download_url` in the message's channel at `reply`, for the guild's `repost_window`
+fn remember_upload(recent_uploads: &RecentUploads, config: &CompiledConfig, msg: &Message, download_url: &str, reply: &Message) {
+	let Some(repost_window) = msg
+		.guild_id
+		.and_then(|guild_id| config.guilds.get(&guild_id))
+		.and_then(|guild| guild.repost_window)
+	else {
+		return;
+	};
+
+	let upload = Upload {
+		guild_id: msg.guild_id,
+		channel_id: reply.channel_id,
+		message_id: reply.id,
+	};
+	recent_uploads.insert(msg.channel_id, download_url, upload, repost_window);
+}
+
+/// How to answer a message mentioning the bot
+#[derive(Debug, PartialEq, Eq)]
+enum MentionReply {
+	/// Emergency reboot, only for the root user
+	Reboot,
+	/// Shows the bot is alive
+	Wave,
+}
+
+/// Only for messages as they're posted, an edit would wave again or reboot the bot long after the message was sent
+fn mention_reply(msg: &Message, root_user_id: Option<u64>) -> Option<MentionReply> {
+	if msg.edited_timestamp.is_some() {
+		None
+	} else if Some(msg.author.id.get()) == root_user_id && msg.content.contains("kill yourself") {
+		Some(MentionReply::Reboot)
+	} else {
+		Some(MentionReply::Wave)
+	}
+}
+
+/// Whether the text at `span` is inside a pair of `||spoiler||` markers
+fn is_spoilered(content: &str, span: Range<usize>) -> bool {
+	content[..span.start].matches("||").count() % 2 == 1 && content[span.end..].contains("||")
+}
+
+/// The channel a thread is in, if `channel_id` is a thread we have cached
+fn thread_parent(ctx: &Context, guild_id: Option<GuildId>, channel_id: ChannelId) -> Option<ChannelId> {
+	let guild = ctx.cache.guild(guild_id?)?;
+	guild.threads.iter().find(|thread| thread.id == channel_id)?.parent_id
+}
+
+/// Whether we have Attach Files in the message's channel, according to the cache. Assumed so when it isn't cached, e.g. in DMs
+fn can_attach_files(ctx: &Context, msg: &Message) -> bool {
+	let current_user_id = ctx.cache.current_user().id;
+
+	let Some(guild) = msg.guild(&ctx.cache) else {
+		return true;
+	};
+
+	let Some(member) = guild.members.get(&current_user_id) else {
+		return true;
+	};
+
+	// Threads have their parent channel's permissions
+	let channel = guild.channels.get(&msg.channel_id).or_else(|| {
+		guild
+			.threads
+			.iter()
+			.find(|thread| thread.id == msg.channel_id)
+			.and_then(|thread| thread.parent_id)
+			.and_then(|parent_id| guild.channels.get(&parent_id))
+	});
+
+	channel.is_none_or(|channel| guild.user_permissions_in(channel, member).attach_files())
+}
+
+/// Whether we can create a webhook in the message's channel and delete the message, which reposting it needs. Webhooks belong to
+/// channels, so messages in threads aren't reposted
+fn can_webhook_repost(ctx: &Context, msg: &Message) -> bool {
+	let current_user_id = ctx.cache.current_user().id;
+
+	let Some(guild) = msg.guild(&ctx.cache) else {
+		return false;
+	};
+
+	let (Some(member), Some(channel)) = (guild.members.get(&current_user_id), guild.channels.get(&msg.channel_id)) else {
+		return false;
+	};
+
+	let permissions = guild.user_permissions_in(channel, member);
+	permissions.manage_webhooks() && permissions.manage_messages()
+}
+
+/// How long the poster of a link that failed to download can retry it
+const RETRY_OFFER_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// How long the poster of media too large to upload can ask for it to be uploaded elsewhere
+const EXTERNAL_UPLOAD_OFFER_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// `**Title** (1:23, 48.2 MB)` and the link to the page, if given, without an embed of its own as the thumbnail is the preview
+fn thumbnail_preview_info(metadata: &MediaMetadata, duration: Option<Duration>, size: u64, page_url: Option<&str>) -> String {
+	let mut details = Vec::with_capacity(2);
+	if let Some(duration) = duration {
+		details.push(contact_sheet::format_duration(duration));
+	}
+	details.push(format!("{:.1} MB", size as f64 / (1024.0 * 1024.0)));
+
+	let mut info = format!("**{}** ({})", metadata.title.as_deref().unwrap_or("Untitled"), details.join(", "));
+	if let Some(page_url) = page_url {
+		info.push_str(&format!("\n<{page_url}>"));
+	}
+
+	info
+}
+
+/// The message's embed if it's its only one and it's of `url`, rather than of another link in the message, so our reply can take
+/// it over
+fn sole_link_embed(embeds: &[Embed], url: &str) -> Option<Embed> {
+	match embeds {
+		[embed]
+			if embed
+				.url
+				.as_deref()
+				.is_some_and(|embed_url| embed_url.trim_end_matches('/') == url.trim_end_matches('/')) =>
+		{
+			Some(embed.clone())
+		}
+		_ => None,
+	}
+}
+
+/// Thumbnail URLs often have no extension or a made up one (TikTok's end in `.image`), which Discord won't preview
+fn thumbnail_filename(url: &str) -> String {
+	let path = url.split(['?', '#']).next().unwrap_or_default();
+	let extension = path
+		.rsplit_once('/')
+		.and_then(|(_, name)| name.rsplit_once('.'))
+		.map(|(_, extension)| extension.to_ascii_lowercase())
+		.filter(|extension| matches!(extension.as_str(), "jpg" | "jpeg" | "png" | "webp" | "gif"));
+
+	format!("thumbnail.{}", extension.as_deref().unwrap_or("jpg"))
+}
+
+pub fn too_large_notice(size: u64, size_limit: u64, fallbacks: &[&str], external_upload: bool) -> String {
+	const MB: f64 = 1024.0 * 1024.0;
+
+	let mut notice = format!(
+		"🫃 Too large to upload: **{:.1} MB**, the limit is {:.0} MB",
+		size as f64 / MB,
+		size_limit as f64 / MB
+	);
+
+	if !fallbacks.is_empty() {
+		notice.push_str(&format!("\nTried instead: {} (none available)", fallbacks.join(", ")));
+	}
+
+	if external_upload {
+		notice.push_str("\nPress **Upload elsewhere** to get a link to it instead");
+	}
+
+	notice
+}
+
+pub fn external_upload_buttons() -> Vec<CreateActionRow> {
+	vec![CreateActionRow::Buttons(vec![CreateButton::new("external_upload")
+		.label("Upload elsewhere")
+		.style(ButtonStyle::Secondary)])]
+}
+
+/// Waits for `user_id` to press the [`external_upload_buttons`] on `notice`, then uploads the media to the external file host and
+/// edits the link into the notice. False if nobody pressed it in time, leaving the caller to remove the button
+pub async fn await_external_upload(ctx: &Context, notice: &Message, user_id: UserId, config: &CompiledConfig, media: &MediaResult) -> bool {
+	let Some(storage) = &config.storage else {
+		return false;
+	};
+
+	let interaction = notice
+		.await_component_interaction(&ctx.shard)
+		.author_id(user_id)
+		.timeout(EXTERNAL_UPLOAD_OFFER_TIMEOUT)
+		.await;
+
+	let Some(interaction) = interaction else {
+		return false;
+	};
+
+	interaction
+		.create_response(
+			ctx,
+			CreateInteractionResponse::UpdateMessage(
+				CreateInteractionResponseMessage::new()
+					.content(format!("{}\n\n⏳ Uploading...", notice.content))
+					.components(vec![]),
+			),
+		)
+		.await
+		.ok();
+
+	let content = match storage.upload(&media.path).await {
+		Ok(url) => url,
+		Err(err) => {
+			log::error!("Failed to upload {} to {} ({err})", media.path.display(), storage.name());
+			format!("{}\n\n❌ Upload failed", notice.content)
+		}
+	};
+
+	// Through the interaction, which can also edit the followups of user installed commands
+	interaction.edit_response(ctx, EditInteractionResponse::new().content(content)).await.ok();
+
+	true
+}
+
+pub fn confirm_buttons() -> Vec<CreateActionRow> {
+	vec![CreateActionRow::Buttons(vec![
+		CreateButton::new("confirm").label("Confirm").style(ButtonStyle::Success),
+		CreateButton::new("cancel").label("Cancel").style(ButtonStyle::Secondary),
+	])]
+}
+
+/// Waits for `user_id` to press one of the [`confirm_buttons`] on `prompt`, then removes the buttons
+pub async fn await_confirmation(ctx: &Context, mut prompt: Message, user_id: UserId) -> bool {
+	let interaction = prompt
+		.await_component_interaction(&ctx.shard)
+		.author_id(user_id)
+		.timeout(Duration::from_secs(120))
+		.await;
+
+	let confirmed = interaction.as_ref().is_some_and(|interaction| interaction.data.custom_id == "confirm");
+
+	let status = match (&interaction, confirmed) {
+		(None, _) => "⌛ Timed out",
+		(Some(_), true) => "✅ Confirmed",
+		(Some(_), false) => "❌ Cancelled",
+	};
+
+	let content = format!("{}\n\n{status}", prompt.content);
+
+	match interaction {
+		Some(interaction) => interaction
+			.create_response(
+				ctx,
+				CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::new().content(content).components(vec![])),
+			)
+			.await
+			.ok(),
+		None => prompt.edit(ctx, EditMessage::new().content(content).components(vec![])).await.ok(),
+	};
+
+	confirmed
+}
+
+const ATTACHMENT_DESCRIPTION_LIMIT: usize = 1024;
+const EMBED_TITLE_LIMIT: usize = 256;
+const EMBED_AUTHOR_LIMIT: usize = 256;
+/// Shorter than Discord's message limit, a caption shouldn't dwarf the media
+const CAPTION_LIMIT: usize = 256;
+
+/// Labels an upload with the media's title: as the attachment's alt text, and as the embed's title if it has none.
+/// Without an embed, returns the title to use as the message content
+pub fn caption_title(metadata: &MediaMetadata, file: &mut CreateAttachment, embed: Option<&mut Embed>) -> Option<String> {
+	let title = metadata.title.as_deref().map(str::trim).filter(|title| !title.is_empty())?;
+
+	file.description = Some(truncate_chars(title, ATTACHMENT_DESCRIPTION_LIMIT));
+
+	match embed {
+		Some(embed) => {
+			embed.title.get_or_insert_with(|| truncate_chars(title, EMBED_TITLE_LIMIT));
+			None
+		}
+		None => Some(escape_markdown(&truncate_chars(title, CAPTION_LIMIT))),
+	}
+}
+
+/// An embed describing the media from its metadata, for links without an embed of their own to copy. Keeps to the same
+/// `policy` as a copied embed would. `None` without a title, there'd be little to describe
+pub fn metadata_embed(metadata: &MediaMetadata, url: &str, policy: &EmbedPolicy) -> Option<CreateEmbed> {
+	let title = metadata.title.as_deref().map(str::trim).filter(|title| !title.is_empty())?;
+
+	let mut embed = CreateEmbed::new().url(metadata.webpage_url.as_deref().unwrap_or(url));
+
+	if policy.title {
+		embed = embed.title(truncate_chars(title, EMBED_TITLE_LIMIT));
+	}
+
+	if let (true, Some(uploader)) = (
+		policy.author,
+		metadata.uploader.as_deref().map(str::trim).filter(|uploader| !uploader.is_empty()),
+	) {
+		embed = embed.author(CreateEmbedAuthor::new(truncate_chars(uploader, EMBED_AUTHOR_LIMIT)));
+	}
+
+	if let (true, Some(thumbnail)) = (policy.thumbnail, &metadata.thumbnail) {
+		embed = embed.thumbnail(thumbnail.as_ref());
+	}
+
+	let footer = metadata_embed_footer(metadata);
+	if policy.footer && !footer.is_empty() {
+		embed = embed.footer(CreateEmbedFooter::new(footer));
+	}
+
+	Some(embed)
+}
+
+/// e.g. `YouTube • 3:32 • 1,234,567 views`
+fn metadata_embed_footer(metadata: &MediaMetadata) -> String {
+	let views = metadata.view_count.map(|views| {
+		let digits = views.to_string();
+		let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+		for (i, digit) in digits.chars().enumerate() {
+			if i > 0 && (digits.len() - i) % 3 == 0 {
+				grouped.push(',');
+			}
+			grouped.push(digit);
+		}
+		match views {
+			1 => "1 view".to_string(),
+			_ => format!("{grouped} views"),
+		}
+	});
+
+	[
+		metadata.site.as_deref().map(str::to_string),
+		metadata
+			.duration
+			.filter(|duration| duration.is_finite() && *duration >= 1.0)
+			.map(|duration| contact_sheet::format_duration(Duration::from_secs_f64(duration))),
+		views,
+	]
+	.into_iter()
+	.flatten()
+	.collect::<Vec<_>>()
+	.join(" • ")
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+	match text.char_indices().nth(max_chars - 1) {
+		Some((end, c)) if text.len() > end + c.len_utf8() => format!("{}…", &text[..end]),
+		_ => text.to_string(),
+	}
+}
+
+fn escape_markdown(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		if matches!(
+			c,
+			'\\' | '*' | '_' | '~' | '|' | '`' | '>' | '#' | '-' | '[' | ']' | '(' | ')' | '<' | ':'
+		) {
+			escaped.push('\\');
+		}
+		escaped.push(c);
+	}
+	escaped
+}
+
+/// Only pings the moderator role of a matched content filter, if any
+pub fn content_filter_mentions(filter: Option<&CompiledContentFilter>) -> CreateAllowedMentions {
+	CreateAllowedMentions::new().roles(filter.and_then(|filter| filter.moderator_role_id))
+}
+
+#[async_trait]
+impl EventHandler for DiscordBot {
+	async fn ready(&self, ctx: Context, ready: Ready) {
+		log::info!("Discord bot connected as {}", ready.user.name);
+		log::info!(
+			"Invite link: https://discord.com/oauth2/authorize?client_id={}&permissions=274877966400&integration_type=0&scope=bot",
+			ready.user.id
+		);
+		log::info!("Member of {} guilds", ready.guilds.len());
+
+		let left = self.app_ctx.roster.reconcile(&ready.guilds.iter().map(|guild| guild.id).collect()).await;
+		if left > 0 {
+			log::info!("Removed from {left} guilds while offline");
+		}
+
+		let config = self.app_ctx.config.get().await;
+
+		cmd::register(&ctx, config.admin_guild.as_ref().map(|admin_guild| admin_guild.guild_id))
+			.await
+			.expect("Failed to register commands");
+
+		if let Some(admin_guild) = &config.admin_guild {
+			let warnings = Self::validate_admin_guild(&ctx, &ready, admin_guild).await;
+
+			logging::connect_discord(admin_guild.log_channel_id, ctx.http.clone()).await;
+
+			// Logged after connecting so they still reach the log channel if only the config channel is broken
+			for warning in warnings {
+				log::warn!("{warning}");
+			}
+		}
+
+		if let Some(err) = self.app_ctx.config.safe_mode_error() {
+			log::error!("Running in SAFE MODE because the config failed to load. Post a fixed config in the config channel to recover.\n\n{err}");
+		}
+	}
+
+	async fn guild_create(&self, ctx: Context, guild: Guild, is_new: Option<bool>) {
+		// Also sent for every guild we're already in when connecting
+		if is_new != Some(true) {
+			self.app_ctx.roster.connected(&guild).await;
+			return;
+		}
+
+		let invited_by = Self::guild_inviter(&ctx, &guild).await;
+		self.app_ctx.roster.joined(&guild, invited_by.as_ref().map(|user| user.id)).await;
+
+		let inviter = match &invited_by {
+			Some(user) => format!(", invited by {} ({})", user.name, user.id),
+			None => String::new(),
+		};
+
+		self.guild_notification(
+			&ctx,
+			format!(
+				"📥 Joined **{}** (`{}`), {} members{inviter}",
+				escape_markdown(&guild.name),
+				guild.id,
+				guild.member_count
+			),
+		)
+		.await;
+	}
+
+	async fn guild_delete(&self, ctx: Context, incomplete: UnavailableGuild, full: Option<Guild>) {
+		// An outage, not a removal
+		if incomplete.unavailable {
+			return;
+		}
+
+		let entry = self.app_ctx.roster.left(incomplete.id).await;
+
+		let name = match (&full, &entry) {
+			(Some(guild), _) => guild.name.as_str(),
+			(None, Some(entry)) => &entry.name,
+			(None, None) => "unknown guild",
+		};
+
+		self.guild_notification(&ctx, format!("📤 Left **{}** (`{}`)", escape_markdown(name), incomplete.id))
+			.await;
+	}
+
+	async fn message(&self, ctx: Context, msg: Message) {
+		if msg.author.bot || self.slash_commands_only {
+			return;
+		}
+
+		let config = self.app_ctx.config.get().await;
+
+		if self.is_blocked(&config, msg.author.id, msg.guild_id).await {
+			return;
+		}
+
+		let config = config.for_guild(msg.guild_id);
+
+		if Self::is_admin_config_message(&msg, &config) {
+			self.admin_config_message(ctx, msg, config).await;
+		} else if config.is_embed_channel(msg.guild_id, msg.channel_id, thread_parent(&ctx, msg.guild_id, msg.channel_id)) {
+			match Self::redo_args(&ctx, &msg, &config) {
+				Some(args) => {
+					let args = args.to_owned();
+					self.redo_message(ctx, msg, config, &args).await;
+				}
+				None => self.generic_message(ctx, msg, config).await,
+			}
+		}
+	}
+
+	async fn message_update(&self, ctx: Context, _old: Option<Message>, new: Option<Message>, event: MessageUpdateEvent) {
+		// Embeds resolving fire updates too, only edits to the content can add links
+		if event.content.is_none() || event.edited_timestamp.is_none() || self.slash_commands_only {
+			return;
+		}
+
+		let mut msg = match new {
+			Some(msg) => msg,
+			None => match event.channel_id.message(&ctx, event.id).await {
+				Ok(msg) => msg,
+				Err(err) => {
+					log::error!("Failed to fetch edited message {} ({err})", event.id);
+					return;
+				}
+			},
+		};
+
+		// Messages fetched over HTTP don't have it
+		msg.guild_id = event.guild_id;
+
+		let posted_secs_ago = (crate::unix_now() as i64).saturating_sub(msg.timestamp.unix_timestamp());
+		if msg.author.bot || posted_secs_ago > EDIT_WINDOW.as_secs() as i64 {
+			return;
+		}
+
+		let config = self.app_ctx.config.get().await;
+
+		if self.is_blocked(&config, msg.author.id, msg.guild_id).await {
+			return;
+		}
+
+		let config = config.for_guild(msg.guild_id);
+
+		// Only for the links, mentions were answered when the message was posted
+		if config.is_embed_channel(msg.guild_id, msg.channel_id, thread_parent(&ctx, msg.guild_id, msg.channel_id)) {
+			self.embed_links(ctx, msg, config).await;
+		}
+	}
+
+	async fn message_delete(&self, ctx: Context, channel_id: ChannelId, deleted_message_id: MessageId, _guild_id: Option<GuildId>) {
+		self.reply_deleted(&ctx, channel_id, deleted_message_id).await;
+	}
+
+	async fn message_delete_bulk(&self, ctx: Context, channel_id: ChannelId, deleted_message_ids: Vec<MessageId>, _guild_id: Option<GuildId>) {
+		for deleted_message_id in deleted_message_ids {
+			self.reply_deleted(&ctx, channel_id, deleted_message_id).await;
+		}
+	}
+
+	async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+		if self.slash_commands_only {
+			return;
+		}
+
+		if is_reaction(&reaction.emoji, CANCEL_REACTION) {
+			if let Some(user_id) = reaction.user_id {
+				self.app_ctx.cancels.cancel(reaction.message_id, user_id);
+			}
+			return;
+		}
+
+		let config = self.app_ctx.config.get().await;
+
+		if !config
+			.download_reaction
+			.as_deref()
+			.is_some_and(|download_reaction| is_reaction(&reaction.emoji, download_reaction))
+		{
+			return;
+		}
+
+		let Some(user_id) = reaction.user_id else {
+			return;
+		};
+
+		if user_id == ctx.cache.current_user().id || self.is_blocked(&config, user_id, reaction.guild_id).await {
+			return;
+		}
+
+		let config = config.for_guild(reaction.guild_id);
+
+		if !config.is_embed_channel(
+			reaction.guild_id,
+			reaction.channel_id,
+			thread_parent(&ctx, reaction.guild_id, reaction.channel_id),
+		) {
+			return;
+		}
+
+		let mut msg = match reaction.message(&ctx).await {
+			Ok(msg) => msg,
+			Err(err) => {
+				log::error!("Failed to fetch message {} reacted to for download ({err})", reaction.message_id);
+				return;
+			}
+		};
+
+		// Messages fetched over HTTP don't have it
+		msg.guild_id = reaction.guild_id;
+
+		self.reaction_download(ctx, msg, config, user_id).await;
+	}
 
 	async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
 		if let Interaction::Command(command) = interaction {
-			if command.data.name.as_str() == "download" {
-				if let Err(err) = cmd::run(&self.app_ctx, &ctx, &command, &command.data.options()).await {
-					log::error!("Failed to run /download command: {err}");
+			if self
+				.is_blocked(&*self.app_ctx.config.get().await, command.user.id, command.guild_id)
+				.await
+			{
+				command
+					.create_response(
+						ctx,
+						CreateInteractionResponse::Message(
+							CreateInteractionResponseMessage::new()
+								.ephemeral(true)
+								.content("You can't use this bot here"),
+						),
+					)
+					.await
+					.ok();
+				return;
+			}
 
-					command
-						.create_response(
-							ctx,
-							CreateInteractionResponse::Message(
-								CreateInteractionResponseMessage::new().ephemeral(true).content("Internal error occurred"),
-							),
-						)
-						.await
-						.ok();
-				}
+			if let Err(err) = cmd::run(&self.app_ctx, &ctx, &command).await {
+				log::error!("Failed to run /{} command: {err}", command.data.name);
+
+				command
+					.create_response(
+						ctx,
+						CreateInteractionResponse::Message(
+							CreateInteractionResponseMessage::new().ephemeral(true).content("Internal error occurred"),
+						),
+					)
+					.await
+					.ok();
 			}
 		}
 	}
@@ -318,18 +2295,39 @@ impl EventHandler for DiscordBot {
 
 pub struct DiscordBotDaemon {
 	task: tokio::task::JoinHandle<()>,
+	http: watch::Receiver<Option<Arc<Http>>>,
 }
 impl DiscordBotDaemon {
-	pub async fn new(discord_bot_token: &str, app_ctx: AppContext) -> Result<Self, anyhow::Error> {
+	/// `slash_commands_only` forces slash command only mode regardless of the config
+	pub async fn new(discord_bot_token: &str, app_ctx: AppContext, slash_commands_only: bool) -> Result<Self, anyhow::Error> {
 		let discord_bot_token = discord_bot_token.to_owned();
 
+		let (http_tx, http) = watch::channel(None);
+
 		let task = tokio::spawn(async move {
-			let bot = DiscordBot { app_ctx };
+			// Outlives reconnects, so edits made while reconnecting don't download the message's links again
+			let seen_links = Arc::new(SeenLinks::default());
+			let recent_uploads = Arc::new(RecentUploads::default());
+			let webhooks = Arc::new(Mutex::new(HashMap::new()));
 			let mut first_run = true;
 			loop {
 				let res = async {
-					let mut client = Client::builder(&discord_bot_token, discord_bot_permissions())
-						.event_handler(bot.clone())
+					// Intents can only be chosen when connecting, so changing this in the config takes effect on the next reconnect
+					let slash_commands_only = slash_commands_only || app_ctx.config.get().await.slash_commands_only;
+					if slash_commands_only {
+						log::info!("Running in slash command only mode, links in messages will not be embedded");
+					}
+
+					let bot = DiscordBot {
+						app_ctx: app_ctx.clone(),
+						slash_commands_only,
+						seen_links: seen_links.clone(),
+						recent_uploads: recent_uploads.clone(),
+						webhooks: webhooks.clone(),
+					};
+
+					let mut client = Client::builder(&discord_bot_token, discord_bot_permissions(slash_commands_only))
+						.event_handler(bot)
 						.await?;
 
 					/*
@@ -341,6 +2339,8 @@ impl DiscordBotDaemon {
 					);
 					*/
 
+					http_tx.send_replace(Some(client.http.clone()));
+
 					client.start().await
 				}
 				.await;
@@ -359,7 +2359,12 @@ impl DiscordBotDaemon {
 			}
 		});
 
-		Ok(Self { task })
+		Ok(Self { task, http })
+	}
+
+	/// The bot's HTTP client, for posting outside of event handlers. `None` until the bot first connects, and replaced on reconnect
+	pub fn http(&self) -> watch::Receiver<Option<Arc<Http>>> {
+		self.http.clone()
 	}
 }
 impl Future for DiscordBotDaemon {
@@ -374,3 +2379,162 @@ impl Drop for DiscordBotDaemon {
 		self.task.abort();
 	}
 }
+
+#[test]
+fn edits_never_answer_mentions() {
+	let mut msg = Message::default();
+	msg.author.id = UserId::new(1);
+	msg.content = "<@2> kill yourself".to_owned();
+	assert_eq!(mention_reply(&msg, Some(1)), Some(MentionReply::Reboot));
+	assert_eq!(mention_reply(&msg, Some(3)), Some(MentionReply::Wave));
+
+	msg.edited_timestamp = Some(serenity::model::Timestamp::now());
+	assert_eq!(mention_reply(&msg, Some(1)), None);
+	assert_eq!(mention_reply(&msg, Some(3)), None);
+}
+
+#[test]
+fn caption_title_truncation() {
+	let metadata = MediaMetadata {
+		title: Some(format!("  {}  ", "é".repeat(300)).into()),
+		..Default::default()
+	};
+
+	let mut file = CreateAttachment::bytes(vec![], "video.mp4");
+	let caption = caption_title(&metadata, &mut file, None).unwrap();
+	assert_eq!(caption.chars().count(), CAPTION_LIMIT);
+	assert!(caption.ends_with("é…"));
+	assert_eq!(file.description.unwrap().chars().count(), 300);
+
+	// Exactly at the limit, multibyte or not, fits as it is
+	let metadata = MediaMetadata {
+		title: Some("é".repeat(CAPTION_LIMIT).into()),
+		..Default::default()
+	};
+	let mut file = CreateAttachment::bytes(vec![], "video.mp4");
+	assert_eq!(caption_title(&metadata, &mut file, None).unwrap(), "é".repeat(CAPTION_LIMIT));
+	assert_eq!(truncate_chars("ééé", 3), "ééé");
+	assert_eq!(truncate_chars("éééé", 3), "éé…");
+
+	assert_eq!(escape_markdown("*hi* [x](y)"), "\\*hi\\* \\[x\\]\\(y\\)");
+}
+
+#[test]
+fn metadata_embed_footer_format() {
+	let metadata = MediaMetadata {
+		site: Some("Youtube".into()),
+		duration: Some(212.4),
+		view_count: Some(1234567),
+		..Default::default()
+	};
+	assert_eq!(metadata_embed_footer(&metadata), "Youtube • 3:32 • 1,234,567 views");
+
+	let metadata = MediaMetadata {
+		duration: Some(3723.0),
+		view_count: Some(1),
+		..Default::default()
+	};
+	assert_eq!(metadata_embed_footer(&metadata), "1:02:03 • 1 view");
+
+	let metadata = MediaMetadata {
+		view_count: Some(999),
+		..Default::default()
+	};
+	assert_eq!(metadata_embed_footer(&metadata), "999 views");
+	assert_eq!(metadata_embed_footer(&MediaMetadata::default()), "");
+
+	assert!(metadata_embed(&MediaMetadata::default(), "https://example.com", &EmbedPolicy::default()).is_none());
+}
+
+#[test]
+fn too_large_notice_wording() {
+	assert_eq!(
+		too_large_notice(25 * 1024 * 1024 + 300 * 1024, DISCORD_FILE_SIZE_LIMIT, &[], false),
+		"🫃 Too large to upload: **25.3 MB**, the limit is 10 MB"
+	);
+
+	let notice = too_large_notice(DISCORD_FILE_SIZE_LIMIT + 1, DISCORD_FILE_SIZE_LIMIT, &["fixup link", "preview"], true);
+	assert!(notice.contains("\nTried instead: fixup link, preview (none available)"));
+	assert!(notice.ends_with("**Upload elsewhere** to get a link to it instead"));
+}
+
+#[test]
+fn thumbnail_preview_wording() {
+	let metadata = MediaMetadata {
+		title: Some("Rick Astley - Never Gonna Give You Up".into()),
+		..Default::default()
+	};
+	assert_eq!(
+		thumbnail_preview_info(
+			&metadata,
+			Some(Duration::from_secs(213)),
+			48 * 1024 * 1024 + 200 * 1024,
+			Some("https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+		),
+		"**Rick Astley - Never Gonna Give You Up** (3:33, 48.2 MB)\n<https://www.youtube.com/watch?v=dQw4w9WgXcQ>"
+	);
+	assert_eq!(
+		thumbnail_preview_info(&MediaMetadata::default(), None, 30 * 1024 * 1024, None),
+		"**Untitled** (30.0 MB)"
+	);
+
+	assert_eq!(
+		thumbnail_filename("https://i.ytimg.com/vi/dQw4w9WgXcQ/maxresdefault.webp"),
+		"thumbnail.webp"
+	);
+	assert_eq!(
+		thumbnail_filename("https://p16-sign.tiktokcdn.com/obj/abc~tplv-photomode.image?x=1.png"),
+		"thumbnail.jpg"
+	);
+	assert_eq!(thumbnail_filename("https://example.com/thumb"), "thumbnail.jpg");
+}
+
+#[test]
+fn boosted_upload_limit() {
+	assert_eq!(premium_tier_upload_limit(PremiumTier::Tier1), DISCORD_FILE_SIZE_LIMIT);
+	assert_eq!(premium_tier_upload_limit(PremiumTier::Tier2), 50 * 1024 * 1024);
+	assert_eq!(premium_tier_upload_limit(PremiumTier::Tier3), 100 * 1024 * 1024);
+}
+
+#[test]
+fn download_reaction() {
+	assert!(is_reaction(&ReactionType::Unicode("⬇️".into()), "⬇️"));
+	assert!(is_reaction(&ReactionType::Unicode("⬇️".into()), "⬇"));
+	assert!(is_reaction(&ReactionType::Unicode("⬇".into()), "⬇️"));
+	assert!(!is_reaction(&ReactionType::Unicode("❌".into()), "⬇️"));
+
+	let custom = ReactionType::Custom {
+		animated: false,
+		id: serenity::all::EmojiId::new(1),
+		name: Some("download".into()),
+	};
+	assert!(is_reaction(&custom, "download"));
+	assert!(is_reaction(&custom, ":download:"));
+	assert!(!is_reaction(&custom, "⬇️"));
+}
+
+#[test]
+fn spoilered_links() {
+	let spoilered = |content: &str| {
+		let start = content.find("https").unwrap();
+		let end = content[start..].find(['|', ' ']).map_or(content.len(), |end| start + end);
+		is_spoilered(content, start..end)
+	};
+
+	assert!(spoilered("||https://x.com/a/status/1||"));
+	assert!(spoilered("look ||at this https://x.com/a/status/1 lol||"));
+	assert!(!spoilered("https://x.com/a/status/1"));
+	assert!(!spoilered("||spoiler|| https://x.com/a/status/1"));
+	assert!(!spoilered("||https://x.com/a/status/1"));
+}
+
+#[test]
+fn sole_link_embed_matches_url() {
+	let embed = |url: &str| serde_json::from_value::<Embed>(serde_json::json!({ "url": url })).unwrap();
+
+	let link = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+	assert!(sole_link_embed(&[embed(link)], link).is_some());
+	assert!(sole_link_embed(&[embed("https://example.com/")], link).is_none());
+	assert!(sole_link_embed(&[embed(link), embed("https://example.com/")], link).is_none());
+	assert!(sole_link_embed(&[], link).is_none());
+}
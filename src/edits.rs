@@ -0,0 +1,45 @@
+use parking_lot::Mutex;
+use serenity::all::MessageId;
+use std::{
+	collections::{HashMap, HashSet},
+	time::{Duration, Instant},
+};
+
+/// How long after a message is posted edits to it are checked for new links
+pub const EDIT_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Links already handled in each recent message, so an edit only downloads the links it added rather than all of them again
+#[derive(Default)]
+pub struct SeenLinks(Mutex<HashMap<MessageId, MessageLinks>>);
+impl SeenLinks {
+	/// Records `url` as handled in `message_id`, returning whether it wasn't already
+	pub fn first_seen(&self, message_id: MessageId, url: &str) -> bool {
+		let now = Instant::now();
+
+		let mut seen = self.0.lock();
+		seen.retain(|_, links| now.duration_since(links.first_seen) < EDIT_WINDOW);
+		seen.entry(message_id)
+			.or_insert_with(|| MessageLinks {
+				first_seen: now,
+				urls: HashSet::new(),
+			})
+			.urls
+			.insert(url.into())
+	}
+}
+
+struct MessageLinks {
+	first_seen: Instant,
+	urls: HashSet<Box<str>>,
+}
+
+#[test]
+fn seen_links() {
+	let seen = SeenLinks::default();
+	let (msg, other_msg) = (MessageId::new(1), MessageId::new(2));
+
+	assert!(seen.first_seen(msg, "https://x.com/a/status/1"));
+	assert!(!seen.first_seen(msg, "https://x.com/a/status/1"));
+	assert!(seen.first_seen(msg, "https://x.com/a/status/2"));
+	assert!(seen.first_seen(other_msg, "https://x.com/a/status/1"));
+}
@@ -0,0 +1,320 @@
+use std::{process::ExitStatus, sync::Arc};
+use thiserror::Error;
+
+/// Any error from the download pipeline, by the stage it happened in, for telling apart what went wrong without matching on
+/// messages
+#[derive(Debug, Error)]
+pub enum Error {
+	#[error(transparent)]
+	Download(#[from] DownloadError),
+	#[error(transparent)]
+	Probe(#[from] ProbeError),
+	#[error(transparent)]
+	Encode(#[from] EncodeError),
+	#[error(transparent)]
+	Scan(#[from] ScanError),
+	#[error(transparent)]
+	Upload(#[from] UploadError),
+	#[error(transparent)]
+	Config(#[from] ConfigError),
+}
+impl Error {
+	/// The error of the stage that failed
+	pub fn stage(&self) -> &(dyn std::error::Error + 'static) {
+		match self {
+			Self::Download(err) => err,
+			Self::Probe(err) => err,
+			Self::Encode(err) => err,
+			Self::Scan(err) => err,
+			Self::Upload(err) => err,
+			Self::Config(err) => err,
+		}
+	}
+
+	/// Whether the link should be silently ignored rather than reported as a failure
+	pub fn is_no_media(&self) -> bool {
+		matches!(self, Self::Download(err) if err.is_no_media())
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum DownloadError {
+	/// The link works, but there's no media behind it
+	#[error("No media found")]
+	NoMedia,
+	#[error("Media is longer than the maximum duration")]
+	MaxDurationExceeded,
+	/// yt-dlp succeeded without writing anything
+	#[error("yt-dlp did not create the file")]
+	NoOutput,
+	/// The link points to something that isn't media, with its Content-Type
+	#[error("Link is not to any kind of media ({0})")]
+	UnsupportedContentType(Box<str>),
+	#[error("Exit status: {status}\n\n=========== stderr ===========\n{stderr}\n\n=========== stdout ===========\n{stdout}")]
+	YtDlp { status: ExitStatus, stderr: String, stdout: String },
+	#[error(transparent)]
+	Encode(#[from] EncodeError),
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+	/// A builtin extractor or plugin failed, by its name
+	#[error("{extractor} failed ({source})")]
+	Extractor { extractor: Box<str>, source: ExtractError },
+	/// ffmpeg failed to remux what yt-dlp piped into it
+	#[error("ffmpeg failed to remux the piped download ({status}): {stderr}")]
+	Remux { status: ExitStatus, stderr: String },
+	/// A TikTok photo post that neither the photo API nor yt-dlp could download, which the fixup link may still embed
+	#[error("TikTok photo API failed ({photo})\n\nyt-dlp failed too: {video}")]
+	TikTokPhoto { photo: ExtractError, video: Box<DownloadError> },
+	/// The failure of a download shared with other requests for the same link
+	#[error(transparent)]
+	Shared(Arc<DownloadError>),
+}
+impl DownloadError {
+	pub fn extractor(extractor: &str, source: ExtractError) -> Self {
+		Self::Extractor {
+			extractor: extractor.into(),
+			source,
+		}
+	}
+
+	/// Whether the link should be silently ignored rather than reported as a failure
+	pub fn is_no_media(&self) -> bool {
+		match self {
+			Self::Shared(err) => err.is_no_media(),
+			_ => matches!(self, Self::NoMedia | Self::MaxDurationExceeded | Self::UnsupportedContentType(_)),
+		}
+	}
+
+	/// Whether the link regex's fixup link should be posted instead, as every way of downloading the link failed
+	pub fn wants_fixup(&self) -> bool {
+		match self {
+			Self::Shared(err) => err.wants_fixup(),
+			_ => matches!(self, Self::TikTokPhoto { .. }),
+		}
+	}
+}
+
+/// Following a link's redirects before downloading it failed, which leaves the link to yt-dlp as it was posted
+#[derive(Debug, Error)]
+pub enum RedirectError {
+	#[error(transparent)]
+	Url(#[from] url::ParseError),
+	#[error(transparent)]
+	Http(#[from] reqwest::Error),
+	#[error("More than {0} redirects")]
+	TooMany(usize),
+}
+
+/// Fetching or installing a yt-dlp release failed
+#[derive(Debug, Error)]
+pub enum UpdateError {
+	#[error(transparent)]
+	Http(#[from] reqwest::Error),
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+	/// None of the releases are stable and have a build for this platform
+	#[error("No yt-dlp release found")]
+	NoRelease,
+	#[error("Failed to chmod yt-dlp (status {0})")]
+	Chmod(ExitStatus),
+}
+
+#[derive(Debug, Error)]
+pub enum ProbeError {
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+	#[error("Exit status: {status}\n\n=========== stderr ===========\n{stderr}\n\n=========== stdout ===========\n{stdout}")]
+	FFProbe { status: ExitStatus, stderr: String, stdout: String },
+	#[error("Failed to parse ffprobe output ({0})")]
+	Parse(String),
+	/// ffprobe found no streams or duration
+	#[error("Corrupt media")]
+	Corrupt,
+}
+
+/// A builtin extractor, plugin or TikTok's photo API failed
+#[derive(Debug, Error)]
+pub enum ExtractError {
+	#[error(transparent)]
+	Http(#[from] reqwest::Error),
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+	/// An API response or a script's output that isn't what was expected
+	#[error(transparent)]
+	Json(#[from] serde_json::Error),
+	/// Over the size cap of a direct download, in bytes
+	#[error("File is too large (over {0} bytes)")]
+	TooLarge(u64),
+	/// The page or post has no media where it was looked for
+	#[error("{0}")]
+	NotFound(&'static str),
+	/// TikTok's API refused the request, e.g. for a deleted, private or region blocked post
+	#[error("TikTok API returned status {code} ({message})")]
+	TikTokStatus { code: i64, message: Box<str> },
+	/// A plugin or helper script exited unsuccessfully
+	#[error("Exit status: {status}\n\n=========== stderr ===========\n{stderr}\n\n=========== stdout ===========\n{stdout}")]
+	Script { status: ExitStatus, stderr: String, stdout: String },
+	#[error("Timed out")]
+	Timeout,
+	/// Rendering a slideshow, or running a plugin's ffmpeg arguments
+	#[error(transparent)]
+	Encode(#[from] EncodeError),
+	/// Built without the cargo feature it needs
+	#[error("Built without the `{0}` feature")]
+	Disabled(&'static str),
+}
+
+#[derive(Debug, Error)]
+pub enum EncodeError {
+	#[error(transparent)]
+	Probe(#[from] ProbeError),
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+	/// ffmpeg exited unsuccessfully
+	#[error("Exit status: {status}\n\n=========== stderr ===========\n{stderr}")]
+	Failed { status: ExitStatus, stderr: String },
+	/// The media is too long to fit in the target size at a watchable quality
+	#[error("Bitrate too low")]
+	BitrateTooLow,
+	/// whisper.cpp exited unsuccessfully
+	#[error("whisper.cpp exit status: {status}\n\n=========== stderr ===========\n{stderr}")]
+	Whisper { status: ExitStatus, stderr: String },
+	/// Downloading the whisper model failed
+	#[error("Failed to download the whisper model ({0})")]
+	WhisperModel(#[from] reqwest::Error),
+	/// Built without the cargo feature it needs
+	#[error("Built without the `{0}` feature")]
+	Disabled(&'static str),
+}
+
+/// The content scanner or media blocklist couldn't check a file, which is then not uploaded
+#[derive(Debug, Error)]
+pub enum ScanError {
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+	#[error("Scan command is empty")]
+	EmptyCommand,
+	/// The scan command exited with something other than 0 (clean) or 1 (flagged)
+	#[error("Exit status: {status}\n\n=========== stderr ===========\n{stderr}\n\n=========== stdout ===========\n{stdout}")]
+	Command { status: ExitStatus, stderr: String, stdout: String },
+	#[error("Unexpected clamd response: {0}")]
+	Clamd(Box<str>),
+	/// Hashing frames for the media blocklist failed
+	#[error(transparent)]
+	Hash(#[from] EncodeError),
+}
+
+/// A hook couldn't be run, or exited unsuccessfully
+#[derive(Debug, Error)]
+pub enum HookError {
+	#[error("Hook command is empty")]
+	EmptyCommand,
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+	#[error(transparent)]
+	Json(#[from] serde_json::Error),
+	#[error("Exit status: {status}\n\n=========== stderr ===========\n{stderr}\n\n=========== stdout ===========\n{stdout}")]
+	Failed { status: ExitStatus, stderr: String, stdout: String },
+}
+
+#[derive(Debug, Error)]
+pub enum UploadError {
+	/// Larger than Discord's upload limit
+	#[error("Media is too large to upload")]
+	TooLarge,
+	#[error(transparent)]
+	Discord(Box<serenity::Error>),
+	/// Forwarding to a bridge failed
+	#[error(transparent)]
+	Http(#[from] reqwest::Error),
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+}
+impl UploadError {
+	/// Whether the upload failed because the file is too large, either by our own check or Discord's
+	pub fn is_too_large(&self) -> bool {
+		match self {
+			Self::TooLarge => true,
+			Self::Discord(err) => matches!(
+				**err,
+				serenity::Error::Http(serenity::http::HttpError::UnsuccessfulRequest(serenity::http::ErrorResponse {
+					status_code: serenity::http::StatusCode::PAYLOAD_TOO_LARGE,
+					..
+				}))
+			),
+			Self::Http(err) => err.status() == Some(reqwest::StatusCode::PAYLOAD_TOO_LARGE),
+			Self::Io(_) => false,
+		}
+	}
+}
+impl From<serenity::Error> for UploadError {
+	fn from(err: serenity::Error) -> Self {
+		Self::Discord(Box::new(err))
+	}
+}
+impl From<serenity::Error> for Error {
+	fn from(err: serenity::Error) -> Self {
+		Self::Upload(err.into())
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+	#[error(transparent)]
+	Json(#[from] serde_json::Error),
+	#[error(transparent)]
+	Patch(#[from] json_patch::PatchError),
+	#[error(transparent)]
+	Regex(#[from] regex::Error),
+	/// An `include` pattern that isn't a valid glob
+	#[error(transparent)]
+	Glob(#[from] glob::PatternError),
+	/// The config parsed, but doesn't make sense
+	#[error("{0}")]
+	Invalid(Box<str>),
+	/// A secret left redacted in a new config, by its path
+	#[error("{0} is redacted, set it to the secret again")]
+	Redacted(Box<str>),
+	/// Rolling back a config that was never edited
+	#[error("The config hasn't been edited yet, there's nothing to roll back to")]
+	NoPreviousConfig,
+	/// An error in part of the config, e.g. an included file or a guild's overrides
+	#[error("In {within}: {source}")]
+	Within { within: Box<str>, source: Box<ConfigError> },
+}
+impl ConfigError {
+	pub fn invalid(message: impl Into<Box<str>>) -> Self {
+		Self::Invalid(message.into())
+	}
+
+	/// Says which part of the config the error is in
+	pub fn within(self, within: impl std::fmt::Display) -> Self {
+		Self::Within {
+			within: within.to_string().into(),
+			source: Box::new(self),
+		}
+	}
+}
+
+#[test]
+fn error_messages() {
+	assert_eq!(
+		DownloadError::UnsupportedContentType("application/pdf".into()).to_string(),
+		"Link is not to any kind of media (application/pdf)"
+	);
+	assert!(DownloadError::MaxDurationExceeded.is_no_media());
+	assert!(!DownloadError::Io(std::io::ErrorKind::NotFound.into()).is_no_media());
+	assert_eq!(
+		ConfigError::invalid("gif needs video").within("guild 1").to_string(),
+		"In guild 1: gif needs video"
+	);
+	assert!(UploadError::TooLarge.is_too_large());
+	assert_eq!(RedirectError::TooMany(10).to_string(), "More than 10 redirects");
+	assert_eq!(HookError::EmptyCommand.to_string(), "Hook command is empty");
+
+	assert!(Error::from(DownloadError::NoMedia).is_no_media());
+	assert!(!Error::from(EncodeError::BitrateTooLow).is_no_media());
+	assert_eq!(Error::from(UploadError::TooLarge).stage().to_string(), "Media is too large to upload");
+}
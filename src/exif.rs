@@ -0,0 +1,183 @@
+use std::path::Path;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// Removes EXIF (including GPS location), XMP, IPTC and comments from a JPEG or PNG image in place, without re-encoding it.
+/// Other formats are left untouched
+pub async fn strip_metadata(path: &Path) -> Result<(), std::io::Error> {
+	let data = tokio::fs::read(path).await?;
+
+	let stripped = match path.extension().and_then(|ext| ext.to_str()) {
+		Some("jpg" | "jpeg") => strip_jpeg(&data),
+		Some("png") => strip_png(&data),
+		_ => return Ok(()),
+	};
+
+	match stripped {
+		Some(stripped) if stripped.len() == data.len() => Ok(()),
+		Some(stripped) => tokio::fs::write(path, stripped).await,
+		None => Err(std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			format!("Malformed image {}", path.display()),
+		)),
+	}
+}
+
+/// Drops the APP1 (EXIF, XMP), APP13 (IPTC) and comment segments, or `None` if it isn't a valid JPEG. The EXIF orientation is
+/// written back on its own, otherwise photos taken sideways would show up sideways
+fn strip_jpeg(data: &[u8]) -> Option<Vec<u8>> {
+	let mut rest = data.strip_prefix(&[0xFF, 0xD8])?;
+
+	let mut stripped = Vec::with_capacity(data.len());
+	stripped.extend_from_slice(&[0xFF, 0xD8]);
+
+	loop {
+		let [0xFF, marker, ..] = *rest else {
+			return None;
+		};
+
+		match marker {
+			// Fill byte
+			0xFF => rest = &rest[1..],
+
+			// Start of scan or end of image, the compressed image data follows which is copied verbatim
+			0xDA | 0xD9 => {
+				stripped.extend_from_slice(rest);
+				return Some(stripped);
+			}
+
+			// Markers without a length
+			0x01 | 0xD0..=0xD7 => {
+				stripped.extend_from_slice(&rest[..2]);
+				rest = &rest[2..];
+			}
+
+			_ => {
+				// The length includes itself but not the marker
+				let len = u16::from_be_bytes([*rest.get(2)?, *rest.get(3)?]) as usize;
+				if len < 2 {
+					return None;
+				}
+
+				let segment = rest.get(..2 + len)?;
+				if !matches!(marker, 0xE1 | 0xED | 0xFE) {
+					stripped.extend_from_slice(segment);
+				} else if let Some(orientation) = segment[4..].strip_prefix(EXIF_HEADER).and_then(exif_orientation) {
+					stripped.extend_from_slice(&orientation_segment(orientation));
+				}
+				rest = &rest[2 + len..];
+			}
+		}
+	}
+}
+
+/// The orientation tag of an EXIF block's first IFD, if it's anything but the default upright
+fn exif_orientation(tiff: &[u8]) -> Option<u16> {
+	let u16_at = |offset: usize| -> Option<u16> {
+		let bytes = tiff.get(offset..offset + 2)?.try_into().unwrap();
+		match &tiff[..2] {
+			b"II" => Some(u16::from_le_bytes(bytes)),
+			_ => Some(u16::from_be_bytes(bytes)),
+		}
+	};
+	let u32_at = |offset: usize| -> Option<u32> {
+		let bytes = tiff.get(offset..offset + 4)?.try_into().unwrap();
+		match &tiff[..2] {
+			b"II" => Some(u32::from_le_bytes(bytes)),
+			_ => Some(u32::from_be_bytes(bytes)),
+		}
+	};
+
+	if !matches!(tiff.get(..2)?, b"II" | b"MM") {
+		return None;
+	}
+
+	let ifd = u32_at(4)? as usize;
+	let entries = u16_at(ifd)? as usize;
+
+	// Tag, type, count and value
+	(0..entries)
+		.map(|entry| ifd + 2 + entry * 12)
+		.find(|&entry| u16_at(entry) == Some(ORIENTATION_TAG))
+		.and_then(|entry| u16_at(entry + 8))
+		.filter(|orientation| (2..=8).contains(orientation))
+}
+
+/// An APP1 segment holding nothing but the orientation
+fn orientation_segment(orientation: u16) -> Vec<u8> {
+	let mut segment = vec![0xFF, 0xE1, 0x00, 0x22];
+	segment.extend_from_slice(EXIF_HEADER);
+	// Big endian TIFF header, pointing at the IFD straight after it
+	segment.extend_from_slice(b"MM\x00\x2A\x00\x00\x00\x08");
+	// One entry: SHORT, count 1, value padded to 4 bytes, then no next IFD
+	segment.extend_from_slice(&1u16.to_be_bytes());
+	segment.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+	segment.extend_from_slice(&[0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+	segment.extend_from_slice(&orientation.to_be_bytes());
+	segment.extend_from_slice(&[0; 6]);
+	segment
+}
+
+/// Drops the EXIF, text and timestamp chunks, or `None` if it isn't a valid PNG
+fn strip_png(data: &[u8]) -> Option<Vec<u8>> {
+	let mut rest = data.strip_prefix(PNG_SIGNATURE)?;
+
+	let mut stripped = Vec::with_capacity(data.len());
+	stripped.extend_from_slice(PNG_SIGNATURE);
+
+	while !rest.is_empty() {
+		// Length, type, data, CRC
+		let len = u32::from_be_bytes(rest.get(..4)?.try_into().unwrap()) as usize;
+		let chunk = rest.get(..12 + len)?;
+
+		if !matches!(&chunk[4..8], b"eXIf" | b"tEXt" | b"iTXt" | b"zTXt" | b"tIME") {
+			stripped.extend_from_slice(chunk);
+		}
+		rest = &rest[12 + len..];
+	}
+
+	Some(stripped)
+}
+
+#[test]
+fn strip_image_metadata() {
+	let jfif = [0xFF, 0xE0, 0x00, 0x04, 0xAA, 0xBB];
+	let exif = [0xFF, 0xE1, 0x00, 0x06, b'E', b'x', b'i', b'f'];
+	let scan = [0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xE1, 0x12, 0xFF, 0xD9];
+
+	let jpeg = [&[0xFF, 0xD8][..], &jfif, &exif, &scan].concat();
+	assert_eq!(strip_jpeg(&jpeg).unwrap(), [&[0xFF, 0xD8][..], &jfif, &scan].concat());
+	assert_eq!(strip_jpeg(&jpeg[..8]), None);
+
+	// Rotated 90 degrees, with a GPS IFD pointer that has to go
+	let tiff = [
+		&b"II\x2A\x00\x08\x00\x00\x00\x02\x00"[..],
+		&[0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0],
+		&[0x25, 0x88, 4, 0, 1, 0, 0, 0, 0x1A, 0, 0, 0],
+		&[0; 4],
+	]
+	.concat();
+	let rotated = [
+		&[0xFF, 0xE1][..],
+		&(2 + EXIF_HEADER.len() as u16 + tiff.len() as u16).to_be_bytes(),
+		EXIF_HEADER,
+		&tiff,
+	]
+	.concat();
+	let jpeg = [&[0xFF, 0xD8][..], &jfif, &rotated, &scan].concat();
+	let stripped = strip_jpeg(&jpeg).unwrap();
+	assert_eq!(stripped, [&[0xFF, 0xD8][..], &jfif, &orientation_segment(6), &scan].concat());
+	assert_eq!(exif_orientation(&stripped[2 + jfif.len() + 10..]), Some(6));
+
+	let ihdr = [&[0, 0, 0, 1][..], b"IHDR", &[0], &[1, 2, 3, 4]].concat();
+	let text = [&[0, 0, 0, 2][..], b"tEXt", b"hi", &[1, 2, 3, 4]].concat();
+	let iend = [&[0, 0, 0, 0][..], b"IEND", &[1, 2, 3, 4]].concat();
+
+	let png = [PNG_SIGNATURE, &ihdr, &text, &iend].concat();
+	assert_eq!(strip_png(&png).unwrap(), [PNG_SIGNATURE, &ihdr, &iend].concat());
+	assert_eq!(strip_png(&png[..png.len() - 1]), None);
+}
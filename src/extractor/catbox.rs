@@ -0,0 +1,24 @@
+use super::{ExtractContext, Extractor};
+use crate::{error::ExtractError, yt_dlp::MediaMetadata};
+use serenity::async_trait;
+use std::path::Path;
+
+/// catbox.moe and litterbox links are already direct file URLs
+pub struct Catbox;
+#[async_trait]
+impl Extractor for Catbox {
+	fn name(&self) -> &'static str {
+		"Catbox"
+	}
+
+	fn matches(&self, url: &str) -> bool {
+		regex::Regex::new(r#"^https?://(?:files|litter)\.catbox\.moe/\w+\.(?:mp4|webm|mov|mkv|mp3|ogg|wav|flac|m4a)$"#)
+			.unwrap()
+			.is_match(url)
+	}
+
+	async fn extract(&self, ctx: &ExtractContext<'_>, url: &str, out_path: &Path) -> Result<MediaMetadata, ExtractError> {
+		super::download_file(ctx, url, out_path).await?;
+		Ok(MediaMetadata::default())
+	}
+}
@@ -0,0 +1,50 @@
+use super::{opengraph::meta_property, ExtractContext, Extractor};
+use crate::{error::ExtractError, yt_dlp::MediaMetadata};
+use serenity::async_trait;
+use std::path::Path;
+
+pub struct Imgur;
+#[async_trait]
+impl Extractor for Imgur {
+	fn name(&self) -> &'static str {
+		"Imgur"
+	}
+
+	fn matches(&self, url: &str) -> bool {
+		regex::Regex::new(r#"^https?://(?:[im]\.)?imgur\.com/"#).unwrap().is_match(url)
+	}
+
+	async fn extract(&self, ctx: &ExtractContext<'_>, url: &str, out_path: &Path) -> Result<MediaMetadata, ExtractError> {
+		// gifv is just an HTML page wrapping an mp4 of the same name
+		if let Some(captures) = regex::Regex::new(r#"^https?://i\.imgur\.com/(\w+)\.(?:gifv|mp4|gif)"#)
+			.unwrap()
+			.captures(url)
+		{
+			super::download_file(ctx, &format!("https://i.imgur.com/{}.mp4", &captures[1]), out_path).await?;
+			return Ok(MediaMetadata::default());
+		}
+
+		// Albums, galleries and post pages all advertise their first video
+		let html = ctx
+			.client
+			.get(url)
+			.header("User-Agent", ctx.user_agent)
+			.send()
+			.await?
+			.error_for_status()?
+			.text()
+			.await?;
+
+		let video_url = ["og:video:secure_url", "og:video"]
+			.into_iter()
+			.find_map(|property| meta_property(&html, property))
+			.ok_or(ExtractError::NotFound("No video in this Imgur post"))?;
+
+		super::download_file(ctx, &video_url, out_path).await?;
+
+		Ok(MediaMetadata {
+			title: meta_property(&html, "og:title").map(Into::into),
+			..Default::default()
+		})
+	}
+}
@@ -0,0 +1,136 @@
+use crate::{
+	error::ExtractError,
+	tiktok::{self, SlideshowOptions},
+	yt_dlp::MediaMetadata,
+};
+use serenity::{async_trait, futures::StreamExt};
+use std::path::Path;
+use tokio::{fs::File, io::AsyncWriteExt};
+
+mod catbox;
+mod imgur;
+mod opengraph;
+mod plugin;
+mod streamable;
+mod webm;
+
+pub use plugin::{PluginConfig, ScriptPlugin};
+
+/// Anything bigger couldn't be re-encoded down to an uploadable size in reasonable time anyway
+const MAX_DIRECT_DOWNLOAD_SIZE: u64 = 200 * 1024 * 1024;
+
+/// Context shared by every extractor
+pub struct ExtractContext<'a> {
+	pub client: &'a reqwest::Client,
+	/// Netscape cookies file, if configured
+	pub cookies_file: Option<&'a Path>,
+	/// Sent with every request, the same as yt-dlp's for this download
+	pub user_agent: &'a str,
+	/// Upload limit the media has to fit in, for extractors that can pick a smaller format
+	pub size_limit: u64,
+	/// For extractors that render slideshows from a post's images
+	pub slideshow: SlideshowOptions,
+}
+
+/// Downloads media from a site without yt-dlp
+#[async_trait]
+pub trait Extractor: Send + Sync {
+	fn name(&self) -> &str;
+
+	fn matches(&self, url: &str) -> bool;
+
+	/// Downloads the media at `url` to `out_path` (always an mp4)
+	async fn extract(&self, ctx: &ExtractContext<'_>, url: &str, out_path: &Path) -> Result<MediaMetadata, ExtractError>;
+}
+
+/// Simple hosts where going straight to the file is faster and more reliable than yt-dlp, which is still tried if these fail
+pub fn direct_extractors() -> &'static [&'static dyn Extractor] {
+	&[&imgur::Imgur, &streamable::Streamable, &catbox::Catbox, &webm::Webm]
+}
+
+/// Tried in order when yt-dlp fails to download a URL
+pub fn fallback_extractors() -> &'static [&'static dyn Extractor] {
+	&[&TikTokScript, &opengraph::OpenGraph]
+}
+
+async fn download_file(ctx: &ExtractContext<'_>, url: &str, out_path: &Path) -> Result<(), ExtractError> {
+	download_file_capped(ctx, url, out_path, MAX_DIRECT_DOWNLOAD_SIZE).await
+}
+
+/// Streams `url` to `out_path`, giving up and deleting what was written once it's over `max_size` bytes
+pub async fn download_file_capped(ctx: &ExtractContext<'_>, url: &str, out_path: &Path, max_size: u64) -> Result<(), ExtractError> {
+	let response = ctx
+		.client
+		.get(url)
+		.header("User-Agent", ctx.user_agent)
+		.send()
+		.await?
+		.error_for_status()?;
+
+	if response.content_length().is_some_and(|size| size > max_size) {
+		return Err(ExtractError::TooLarge(max_size));
+	}
+
+	// Streamed to disk, counting as we go, the server may not say how big the file is or may be lying
+	let mut stream = response.bytes_stream();
+	let result = async {
+		let mut file = File::create(out_path).await?;
+		let mut size = 0;
+
+		while let Some(chunk) = stream.next().await {
+			let chunk = chunk?;
+
+			size += chunk.len() as u64;
+			if size > max_size {
+				return Err(ExtractError::TooLarge(max_size));
+			}
+
+			file.write_all(&chunk).await?;
+		}
+
+		file.flush().await?;
+		Ok(())
+	}
+	.await;
+
+	if result.is_err() {
+		tokio::fs::remove_file(out_path).await.ok();
+	}
+
+	result
+}
+
+/// Downloads the URL as-is, for links already known to point straight at a media file
+pub struct DirectFile;
+#[async_trait]
+impl Extractor for DirectFile {
+	fn name(&self) -> &'static str {
+		"direct download"
+	}
+
+	fn matches(&self, _url: &str) -> bool {
+		true
+	}
+
+	async fn extract(&self, ctx: &ExtractContext<'_>, url: &str, out_path: &Path) -> Result<MediaMetadata, ExtractError> {
+		download_file(ctx, url, out_path).await?;
+		Ok(MediaMetadata::default())
+	}
+}
+
+struct TikTokScript;
+#[async_trait]
+impl Extractor for TikTokScript {
+	fn name(&self) -> &'static str {
+		"TikTok download script"
+	}
+
+	fn matches(&self, url: &str) -> bool {
+		url.contains("tiktok.com")
+	}
+
+	async fn extract(&self, ctx: &ExtractContext<'_>, url: &str, out_path: &Path) -> Result<MediaMetadata, ExtractError> {
+		tiktok::run_fallback_download_script(url, out_path, ctx.size_limit).await?;
+		Ok(MediaMetadata::default())
+	}
+}
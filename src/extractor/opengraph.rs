@@ -0,0 +1,97 @@
+use super::{ExtractContext, Extractor};
+use crate::{error::ExtractError, yt_dlp::MediaMetadata};
+use serenity::async_trait;
+use std::path::Path;
+
+/// Facebook serves OpenGraph tags to its own link preview crawler even when it wants everyone else to log in
+const CRAWLER_USER_AGENT: &str = "facebookexternalhit/1.1 (+http://www.facebook.com/externalhit_uatext.php)";
+
+/// Scrapes `og:video` for sites whose yt-dlp extractors don't work anonymously
+pub struct OpenGraph;
+#[async_trait]
+impl Extractor for OpenGraph {
+	fn name(&self) -> &'static str {
+		"OpenGraph scraper"
+	}
+
+	fn matches(&self, url: &str) -> bool {
+		regex::Regex::new(r#"^https?://(?:[^/]+\.)?(?:facebook\.com|fb\.watch|fb\.com|threads\.net|threads\.com)/"#)
+			.unwrap()
+			.is_match(url)
+	}
+
+	async fn extract(&self, ctx: &ExtractContext<'_>, url: &str, out_path: &Path) -> Result<MediaMetadata, ExtractError> {
+		let mut request = ctx.client.get(url).header("User-Agent", CRAWLER_USER_AGENT);
+
+		if let Some(cookies_file) = ctx.cookies_file {
+			if let Some(cookie) = cookie_header(&tokio::fs::read_to_string(cookies_file).await?, url) {
+				request = request.header("Cookie", cookie);
+			}
+		}
+
+		let html = request.send().await?.error_for_status()?.text().await?;
+
+		let video_url = ["og:video:secure_url", "og:video:url", "og:video"]
+			.into_iter()
+			.find_map(|property| meta_property(&html, property))
+			.ok_or(ExtractError::NotFound("No og:video found"))?;
+
+		super::download_file(ctx, &video_url, out_path).await?;
+
+		Ok(MediaMetadata {
+			title: meta_property(&html, "og:title").map(Into::into),
+			uploader: None,
+			description: meta_property(&html, "og:description").map(Into::into),
+			thumbnail: meta_property(&html, "og:image").map(Into::into),
+			..Default::default()
+		})
+	}
+}
+
+pub(super) fn meta_property(html: &str, property: &str) -> Option<String> {
+	let regex = regex::Regex::new(&format!(r#"<meta\s+(?:property|name)="{}"\s+content="([^"]*)""#, regex::escape(property))).unwrap();
+
+	let content = regex.captures(html)?.get(1)?.as_str();
+
+	Some(
+		content
+			.replace("&amp;", "&")
+			.replace("&quot;", "\"")
+			.replace("&#039;", "'")
+			.replace("&lt;", "<")
+			.replace("&gt;", ">"),
+	)
+}
+
+/// Builds a `Cookie` header from the Netscape cookies file entries matching the URL's host
+fn cookie_header(cookies_file: &str, url: &str) -> Option<String> {
+	let host = reqwest::Url::parse(url).ok()?.host_str()?.to_owned();
+
+	let cookies = cookies_file
+		.lines()
+		.filter(|line| !line.starts_with('#') || line.starts_with("#HttpOnly_"))
+		.filter_map(|line| {
+			let mut fields = line.trim_start_matches("#HttpOnly_").split('\t');
+			let domain = fields.next()?;
+			let (name, value) = (fields.nth(4)?, fields.next()?);
+			let domain = domain.trim_start_matches('.');
+			(host == domain || host.ends_with(&format!(".{domain}"))).then(|| format!("{name}={value}"))
+		})
+		.collect::<Vec<_>>();
+
+	(!cookies.is_empty()).then(|| cookies.join("; "))
+}
+
+#[test]
+fn opengraph_parsing() {
+	let html = r#"<head><meta property="og:title" content="Funny &amp; cute" /><meta property="og:video" content="https://video.xx.fbcdn.net/v.mp4?a=1&amp;b=2" /></head>"#;
+	assert_eq!(meta_property(html, "og:title").as_deref(), Some("Funny & cute"));
+	assert_eq!(
+		meta_property(html, "og:video").as_deref(),
+		Some("https://video.xx.fbcdn.net/v.mp4?a=1&b=2")
+	);
+	assert_eq!(meta_property(html, "og:video:secure_url"), None);
+
+	let cookies = "# Netscape HTTP Cookie File\n.facebook.com\tTRUE\t/\tTRUE\t0\tc_user\t123\n.example.com\tTRUE\t/\tTRUE\t0\tother\t1\n";
+	assert_eq!(cookie_header(cookies, "https://www.facebook.com/reel/1").as_deref(), Some("c_user=123"));
+}
@@ -0,0 +1,172 @@
+use super::{download_file, ExtractContext, Extractor};
+use crate::{
+	error::{ConfigError, EncodeError, ExtractError},
+	process, tiktok,
+	yt_dlp::MediaMetadata,
+};
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use std::{path::Path, process::Stdio, time::Duration};
+use tokio::process::Command;
+
+const PLUGIN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Experimental: an external script implementing an extractor, so site-specific workarounds can be updated without rebuilding the bot
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PluginConfig {
+	pub name: String,
+	/// URLs matching this regex are handed to the plugin before the builtin extractors and yt-dlp
+	pub regex: String,
+	/// The URL is passed as the last argument, the plugin prints a `PluginOutput` as JSON to stdout
+	pub command: Box<[String]>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScriptPlugin {
+	name: Box<str>,
+	regex: regex::Regex,
+	command: Box<[String]>,
+}
+impl TryFrom<&PluginConfig> for ScriptPlugin {
+	type Error = ConfigError;
+
+	fn try_from(plugin: &PluginConfig) -> Result<Self, Self::Error> {
+		if plugin.command.is_empty() {
+			return Err(ConfigError::invalid(format!("Plugin {} has no command", plugin.name)));
+		}
+
+		Ok(Self {
+			name: plugin.name.as_str().into(),
+			regex: regex::Regex::new(&plugin.regex)?,
+			command: plugin.command.clone(),
+		})
+	}
+}
+
+#[derive(Deserialize, Debug)]
+struct PluginOutput {
+	media: PluginMedia,
+	title: Option<Box<str>>,
+	uploader: Option<Box<str>>,
+	description: Option<Box<str>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PluginMedia {
+	/// A direct link to the media file
+	File { url: String },
+	/// Images stitched into a video, like TikTok photo posts
+	Slideshow { images: Box<[PluginImage]>, audio: Option<String> },
+	/// ffmpeg arguments, the output path is appended
+	Ffmpeg { args: Box<[String]> },
+}
+
+#[derive(Deserialize, Debug)]
+struct PluginImage {
+	url: String,
+	width: u64,
+	height: u64,
+}
+
+#[async_trait]
+impl Extractor for ScriptPlugin {
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn matches(&self, url: &str) -> bool {
+		self.regex.is_match(url)
+	}
+
+	async fn extract(&self, ctx: &ExtractContext<'_>, url: &str, out_path: &Path) -> Result<MediaMetadata, ExtractError> {
+		let output = tokio::time::timeout(PLUGIN_TIMEOUT, self.run(url))
+			.await
+			.map_err(|_| ExtractError::Timeout)??;
+
+		match output.media {
+			PluginMedia::File { url } => download_file(ctx, &url, out_path).await?,
+
+			PluginMedia::Slideshow { images, audio } => {
+				if images.is_empty() {
+					return Err(ExtractError::NotFound("No images found"));
+				}
+
+				let images = images
+					.iter()
+					.map(|image| tiktok::SlideshowImage {
+						url: &image.url,
+						width: image.width,
+						height: image.height,
+					})
+					.collect::<Vec<_>>();
+
+				tiktok::generate_slideshow_video(out_path, &images, audio.as_deref(), &ctx.slideshow).await?;
+			}
+
+			PluginMedia::Ffmpeg { args } => {
+				let output = process::ffmpeg()
+					.arg("-y")
+					.args(args.iter())
+					.arg(out_path)
+					.kill_on_drop(true)
+					.output()
+					.await?;
+
+				if !output.status.success() {
+					return Err(EncodeError::Failed {
+						status: output.status,
+						stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+					}
+					.into());
+				}
+			}
+		}
+
+		Ok(MediaMetadata {
+			title: output.title,
+			uploader: output.uploader,
+			description: output.description,
+			..Default::default()
+		})
+	}
+}
+impl ScriptPlugin {
+	/// URLs handed to the plugin
+	pub fn regex(&self) -> &regex::Regex {
+		&self.regex
+	}
+
+	async fn run(&self, url: &str) -> Result<PluginOutput, ExtractError> {
+		let (program, args) = self.command.split_first().unwrap();
+
+		let output = Command::new(program)
+			.args(args)
+			.arg(url)
+			.stdin(Stdio::null())
+			.kill_on_drop(true)
+			.output()
+			.await?;
+
+		if !output.status.success() {
+			return Err(ExtractError::Script {
+				status: output.status,
+				stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+				stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+			});
+		}
+
+		Ok(serde_json::from_slice(&output.stdout)?)
+	}
+}
+
+#[test]
+fn plugin_output() {
+	let output = serde_json::from_str::<PluginOutput>(
+		r#"{"media": {"type": "slideshow", "images": [{"url": "https://example.com/1.jpg", "width": 1080, "height": 1920}]}, "title": "Test"}"#,
+	)
+	.unwrap();
+
+	assert!(matches!(output.media, PluginMedia::Slideshow { ref images, audio: None } if images.len() == 1));
+	assert_eq!(output.title.as_deref(), Some("Test"));
+}
@@ -0,0 +1,58 @@
+use super::{ExtractContext, Extractor};
+use crate::{error::ExtractError, yt_dlp::MediaMetadata};
+use serenity::async_trait;
+use std::path::Path;
+
+pub struct Streamable;
+#[async_trait]
+impl Extractor for Streamable {
+	fn name(&self) -> &'static str {
+		"Streamable"
+	}
+
+	fn matches(&self, url: &str) -> bool {
+		shortcode(url).is_some()
+	}
+
+	async fn extract(&self, ctx: &ExtractContext<'_>, url: &str, out_path: &Path) -> Result<MediaMetadata, ExtractError> {
+		let shortcode = shortcode(url).ok_or(ExtractError::NotFound("Not a Streamable video URL"))?;
+
+		let video = ctx
+			.client
+			.get(format!("https://api.streamable.com/videos/{shortcode}"))
+			.header("User-Agent", ctx.user_agent)
+			.send()
+			.await?
+			.error_for_status()?
+			.json::<serde_json::Value>()
+			.await?;
+
+		let video_url = ["/files/mp4/url", "/files/mp4-mobile/url"]
+			.into_iter()
+			.find_map(|pointer| video.pointer(pointer)?.as_str())
+			.ok_or(ExtractError::NotFound("Streamable video has no mp4, it may still be processing"))?;
+
+		// The API hands out protocol relative URLs
+		let video_url = match video_url.strip_prefix("//") {
+			Some(video_url) => format!("https://{video_url}"),
+			None => video_url.to_string(),
+		};
+
+		super::download_file(ctx, &video_url, out_path).await?;
+
+		Ok(MediaMetadata {
+			title: video.get("title").and_then(|title| title.as_str()).map(Into::into),
+			..Default::default()
+		})
+	}
+}
+
+fn shortcode(url: &str) -> Option<&str> {
+	Some(
+		regex::Regex::new(r#"^https?://(?:www\.)?streamable\.com/(?:[eo]/)?(\w+)"#)
+			.unwrap()
+			.captures(url)?
+			.get(1)?
+			.as_str(),
+	)
+}
@@ -0,0 +1,22 @@
+use super::{ExtractContext, Extractor};
+use crate::{error::ExtractError, yt_dlp::MediaMetadata};
+use serenity::async_trait;
+use std::path::Path;
+
+/// Direct links to .webm files, as posted from imageboards. VP9/Vorbis doesn't play on iOS, but post-processing transcodes it
+pub struct Webm;
+#[async_trait]
+impl Extractor for Webm {
+	fn name(&self) -> &'static str {
+		"webm"
+	}
+
+	fn matches(&self, url: &str) -> bool {
+		regex::Regex::new(r#"^https?://[^?#]+\.webm(?:[?#]|$)"#).unwrap().is_match(url)
+	}
+
+	async fn extract(&self, ctx: &ExtractContext<'_>, url: &str, out_path: &Path) -> Result<MediaMetadata, ExtractError> {
+		super::download_file(ctx, url, out_path).await?;
+		Ok(MediaMetadata::default())
+	}
+}
@@ -0,0 +1,360 @@
+use crate::{
+	config::CompiledConfig,
+	discord::{content_filter_mentions, hook_payload, metadata_embed, prepare_upload, upload_limit, Prepared},
+	hooks::{self, HookEvent, HookOrigin},
+	reply::ReplyBuilder,
+	store::JsonStore,
+	yt_dlp::DownloadOptions,
+	AppContext, USER_AGENT,
+};
+use serde::{Deserialize, Serialize};
+use serenity::{
+	all::{ChannelId, CreateAllowedMentions, CreateAttachment, CreateMessage, GuildId, Http},
+	futures::future::join_all,
+};
+use std::{
+	collections::{HashMap, VecDeque},
+	path::Path,
+	sync::Arc,
+	time::Duration,
+};
+use tokio::sync::watch;
+
+const FEED_POLL_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Entry IDs remembered per feed, feeds only list their latest entries anyway
+const FEED_SEEN_LIMIT: usize = 200;
+
+/// An RSS or Atom feed whose new entries are downloaded and posted to a channel
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FeedConfig {
+	/// e.g. `https://www.youtube.com/feeds/videos.xml?channel_id=...`
+	pub url: String,
+	pub channel_id: ChannelId,
+}
+
+/// IDs of the entries already seen, oldest first, by channel and feed URL
+#[derive(Serialize, Deserialize, Default)]
+struct FeedState(HashMap<Box<str>, VecDeque<Box<str>>>);
+
+#[derive(Debug, PartialEq, Eq)]
+struct FeedEntry {
+	id: Box<str>,
+	link: Box<str>,
+	title: Option<Box<str>>,
+}
+
+/// Parses the entries of an RSS or Atom feed, in the order the feed lists them (usually newest first)
+fn parse_feed(xml: &str) -> Result<Vec<FeedEntry>, roxmltree::Error> {
+	let doc = roxmltree::Document::parse(xml)?;
+
+	Ok(doc
+		.descendants()
+		.filter(|node| matches!(node.tag_name().name(), "item" | "entry"))
+		.filter_map(|entry| {
+			let child = |name: &str| entry.children().find(|node| node.tag_name().name() == name);
+
+			// RSS has the link as text, Atom as the href of the alternate link
+			let link = entry
+				.children()
+				.filter(|node| node.tag_name().name() == "link")
+				.find_map(|link| match link.attribute("href") {
+					Some(href) => (link.attribute("rel").unwrap_or("alternate") == "alternate").then_some(href),
+					None => link.text(),
+				})?
+				.trim();
+
+			let id = child("guid")
+				.or_else(|| child("id"))
+				.and_then(|node| node.text())
+				.map(str::trim)
+				.unwrap_or(link);
+
+			Some(FeedEntry {
+				id: id.into(),
+				link: link.into(),
+				title: child("title").and_then(|node| node.text()).map(|title| title.trim().into()),
+			})
+		})
+		.collect())
+}
+
+/// Polls the feeds configured in each guild and posts new entries to their channel
+pub struct FeedWatcher {
+	app_ctx: AppContext,
+	client: reqwest::Client,
+	state: JsonStore<FeedState>,
+}
+impl FeedWatcher {
+	pub async fn new(app_ctx: AppContext, path: &Path) -> Result<Self, anyhow::Error> {
+		Ok(Self {
+			app_ctx,
+			client: reqwest::Client::new(),
+			state: JsonStore::open(path.to_owned()).await?,
+		})
+	}
+
+	/// Posts with the bot's HTTP client, waiting for the bot to connect first
+	pub async fn run(self, mut http: watch::Receiver<Option<Arc<Http>>>) {
+		loop {
+			let http = match http.wait_for(Option::is_some).await {
+				Ok(http) => http.clone().unwrap(),
+				Err(_) => return,
+			};
+
+			let config = self.app_ctx.config.get().await;
+
+			// Downloads are limited by the download queue anyway, one slow feed shouldn't hold up the rest
+			let polls = config.guilds.iter().flat_map(|(guild_id, guild)| {
+				let guild_config = config.for_guild(Some(*guild_id));
+				let http = &http;
+				let this = &self;
+
+				guild.feeds.iter().map(move |feed| {
+					let guild_config = guild_config.clone();
+					async move {
+						if let Err(err) = this.poll(http, &guild_config, *guild_id, feed).await {
+							log::error!("Failed to poll feed {} ({err})", feed.url);
+						}
+					}
+				})
+			});
+
+			join_all(polls).await;
+
+			drop(config);
+
+			tokio::time::sleep(FEED_POLL_INTERVAL).await;
+		}
+	}
+
+	async fn poll(&self, http: &Http, config: &CompiledConfig, guild_id: GuildId, feed: &FeedConfig) -> Result<(), anyhow::Error> {
+		let xml = self
+			.client
+			.get(&feed.url)
+			.header("User-Agent", USER_AGENT)
+			.send()
+			.await?
+			.error_for_status()?
+			.text()
+			.await?;
+
+		let entries = parse_feed(&xml)?;
+
+		// Marked as seen before posting, a failed post is better than the same entry posted on every poll
+		let new_entries = self
+			.state
+			.update(|state| {
+				let key = format!("{} {}", feed.channel_id, feed.url).into_boxed_str();
+				let first_poll = !state.0.contains_key(&key);
+
+				let new_entries = remember_entries(state.0.entry(key).or_default(), entries);
+
+				// Don't flood the channel with the feed's whole backlog when it's first added
+				if first_poll {
+					Vec::new()
+				} else {
+					new_entries
+				}
+			})
+			.await?;
+
+		for entry in new_entries.iter().rev() {
+			if let Err(err) = self.post(http, config, guild_id, feed, entry).await {
+				log::error!("Failed to post {} from feed {} ({err})", entry.link, feed.url);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Posts the entry's media if we can download it, or just its link for Discord to embed otherwise. The media goes through the
+	/// same checks as links posted in messages
+	async fn post(&self, http: &Http, config: &CompiledConfig, guild_id: GuildId, feed: &FeedConfig, entry: &FeedEntry) -> Result<(), anyhow::Error> {
+		let size_limit = upload_limit(http, Some(guild_id)).await;
+
+		let origin = HookOrigin {
+			guild_id: Some(guild_id),
+			channel_id: feed.channel_id,
+			user_id: None,
+		};
+
+		let mut media = match config.link_regex(&entry.link) {
+			Some(regex) => {
+				let options = DownloadOptions {
+					size_limit: Some(size_limit),
+					..regex.options.clone()
+				};
+
+				match self.app_ctx.broker.download(&entry.link, &options).await {
+					Ok(media) => Some((regex, media, options.priority)),
+					Err(err) => {
+						if !err.is_no_media() {
+							log::error!("Failed to download {} from feed {} ({err})", entry.link, feed.url);
+							hooks::run(config, hook_payload(HookEvent::OnFailure, &entry.link, origin, None, Some(&err))).await;
+						}
+						None
+					}
+				}
+			}
+			None => None,
+		};
+
+		let title = entry.title.as_deref().map(|title| format!("**{title}**\n")).unwrap_or_default();
+
+		let mut message = CreateMessage::new().allowed_mentions(CreateAllowedMentions::new());
+		let mut content = format!("{title}{}", entry.link);
+		let mut files = ReplyBuilder::new(size_limit);
+		let mut media_hash = None;
+
+		if let Some((regex, media, priority)) = &mut media {
+			match prepare_upload(&self.app_ctx, config, regex.subtitles, &entry.link, media, origin, *priority).await {
+				Prepared::Upload {
+					size,
+					spoiler_filter,
+					media_hash: hash,
+					dedup_url,
+				} => {
+					media_hash = hash;
+
+					// The title would give away what a content filtered entry is
+					let (title, spoiler) = match spoiler_filter {
+						Some(filter) => {
+							message = message.allowed_mentions(content_filter_mentions(Some(filter)));
+							(format!("{}\n", filter.moderator_ping()), "||")
+						}
+						None => (title, ""),
+					};
+
+					// Too large to upload, the fixup link is the next best thing
+					let link = match (dedup_url, size > size_limit) {
+						(Some(dedup_url), _) => dedup_url.into(),
+						(None, true) => regex.fixed_up(&entry.link).unwrap_or_else(|| entry.link.to_string()),
+						(None, false) => {
+							let mut file = CreateAttachment::path(&media.path).await?;
+
+							if let Some(filename) = config.upload_filename(Some(guild_id), &entry.link, media) {
+								file.filename = filename;
+							}
+
+							if spoiler_filter.is_some() {
+								file.filename = format!("SPOILER_{}", file.filename);
+							} else if config.rich_embeds {
+								if let Some(embed) = metadata_embed(&media.metadata, &entry.link, &regex.embed) {
+									message = message.add_embed(embed);
+								}
+							}
+
+							files.add_file(file)?;
+							format!("<{}>", entry.link)
+						}
+					};
+
+					content = format!("{title}{spoiler}{link}{spoiler}");
+				}
+
+				Prepared::Rejected | Prepared::Filtered => return Ok(()),
+
+				Prepared::Flagged(reason) => {
+					log::warn!("Content scanner flagged {} from feed {} ({reason})", entry.link, feed.url);
+					return Ok(());
+				}
+
+				// Discord can still embed the link
+				Prepared::Failed => {}
+			}
+		}
+
+		let sent = match files.send(http, feed.channel_id, message.content(content)).await {
+			Ok(sent) => sent,
+			Err(err) => {
+				let media = media.as_ref().map(|(_, media, _)| media);
+				hooks::run(config, hook_payload(HookEvent::OnFailure, &entry.link, origin, media, Some(&err))).await;
+				return Err(err.into());
+			}
+		};
+
+		if let (Some(media_hash), Some(attachment)) = (media_hash, sent.first().and_then(|sent| sent.attachments.first())) {
+			self.app_ctx.dedup.insert(media_hash, &attachment.url).await;
+		}
+
+		Ok(())
+	}
+}
+
+/// Marks the feed's entries as seen, returning the ones that weren't already, newest first like the feed
+fn remember_entries(seen: &mut VecDeque<Box<str>>, entries: Vec<FeedEntry>) -> Vec<FeedEntry> {
+	let (old_entries, new_entries): (Vec<_>, Vec<_>) = entries.into_iter().partition(|entry| seen.contains(&entry.id));
+
+	seen.extend(new_entries.iter().rev().map(|entry| entry.id.clone()));
+
+	// Only forget entries the feed no longer lists, however long it is, or they'd be new again next poll
+	let mut excess = seen.len().saturating_sub(FEED_SEEN_LIMIT);
+	seen.retain(|id| {
+		let forget = excess > 0 && !old_entries.iter().chain(&new_entries).any(|entry| entry.id == *id);
+		excess -= forget as usize;
+		!forget
+	});
+
+	new_entries
+}
+
+#[test]
+fn feed_seen_limit() {
+	let entries = |ids: std::ops::Range<usize>| {
+		ids.rev()
+			.map(|id| FeedEntry {
+				id: id.to_string().into(),
+				link: format!("https://example.com/{id}").into(),
+				title: None,
+			})
+			.collect::<Vec<_>>()
+	};
+
+	let mut seen = VecDeque::new();
+	assert_eq!(remember_entries(&mut seen, entries(0..FEED_SEEN_LIMIT + 50)).len(), FEED_SEEN_LIMIT + 50);
+	assert_eq!(seen.len(), FEED_SEEN_LIMIT + 50);
+
+	// A feed longer than the limit doesn't have its oldest entries posted again
+	assert_eq!(remember_entries(&mut seen, entries(0..FEED_SEEN_LIMIT + 51)).len(), 1);
+	assert_eq!(remember_entries(&mut seen, entries(0..FEED_SEEN_LIMIT + 51)).len(), 0);
+
+	// Entries that dropped off the feed are forgotten, oldest first
+	assert_eq!(remember_entries(&mut seen, entries(100..110)).len(), 0);
+	assert_eq!(seen.len(), FEED_SEEN_LIMIT);
+	assert_eq!(seen.front().map(AsRef::as_ref), Some("51"));
+}
+
+#[test]
+fn feed_parsing() {
+	let atom = r#"<?xml version="1.0" encoding="UTF-8"?>
+		<feed xmlns="http://www.w3.org/2005/Atom" xmlns:yt="http://www.youtube.com/xml/schemas/2015">
+			<title>Channel</title>
+			<link rel="alternate" href="https://www.youtube.com/channel/abc"/>
+			<entry>
+				<id>yt:video:dQw4w9WgXcQ</id>
+				<title>Video</title>
+				<link rel="alternate" href="https://www.youtube.com/watch?v=dQw4w9WgXcQ"/>
+			</entry>
+		</feed>"#;
+
+	assert_eq!(
+		parse_feed(atom).unwrap(),
+		[FeedEntry {
+			id: "yt:video:dQw4w9WgXcQ".into(),
+			link: "https://www.youtube.com/watch?v=dQw4w9WgXcQ".into(),
+			title: Some("Video".into()),
+		}]
+	);
+
+	let rss = r#"<rss version="2.0"><channel>
+		<item><title>First</title><link> https://example.com/1 </link><guid>1</guid></item>
+		<item><link>https://example.com/2</link></item>
+	</channel></rss>"#;
+
+	let entries = parse_feed(rss).unwrap();
+	assert_eq!(entries[0].id.as_ref(), "1");
+	assert_eq!(entries[0].link.as_ref(), "https://example.com/1");
+	assert_eq!(entries[1].id.as_ref(), "https://example.com/2");
+	assert_eq!(entries[1].title, None);
+}
@@ -1,25 +1,34 @@
 #![allow(clippy::get_first)]
 
-use anyhow::Context;
 use std::{path::Path, time::Duration};
 
-use crate::discord::DISCORD_FILE_SIZE_LIMIT;
+use crate::{error::ProbeError, pipeline::Prober};
+use serenity::async_trait;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum MediaProbe {
 	Corrupt,
-	Probed { is_discord_compatible: bool, duration: Duration },
+	Probed {
+		is_discord_compatible: bool,
+		has_video: bool,
+		duration: Duration,
+		/// Of the first video stream
+		dimensions: Option<(u32, u32)>,
+		video_codec: Option<Box<str>>,
+		audio_codec: Option<Box<str>>,
+	},
 }
-impl MediaProbe {
-	pub async fn get(path: &Path) -> Result<Self, anyhow::Error> {
+#[async_trait]
+impl Prober for MediaProbe {
+	async fn probe(path: &Path, size_limit: u64) -> Result<Self, ProbeError> {
 		let metadata = tokio::fs::metadata(path).await?;
 
-		let output = tokio::process::Command::new(if cfg!(windows) { "ffprobe.exe" } else { "ffprobe" })
+		let output = crate::process::ffprobe()
 			.args([
 				"-v",
 				"error",
 				"-show_entries",
-				"stream=codec_type,codec_name",
+				"stream=codec_type,codec_name,width,height",
 				"-show_entries",
 				"format=duration",
 				"-of",
@@ -40,19 +49,20 @@ impl MediaProbe {
 		}
 
 		if !output.status.success() {
-			return Err(anyhow::anyhow!(
-				"Exit status: {}\n\n=========== stderr ===========\n{}\n\n=========== stdout ===========\n{}",
-				output.status,
-				stdout,
-				stderr
-			));
+			return Err(ProbeError::FFProbe {
+				status: output.status,
+				stderr: stderr.to_owned(),
+				stdout: stdout.to_owned(),
+			});
 		}
 
-		let output: FFProbeOutput = serde_json::from_str(stdout).context("Failed to parse ffprobe output")?;
+		let output: FFProbeOutput = serde_json::from_str(stdout).map_err(|err| ProbeError::Parse(err.to_string()))?;
+
+		let has_video = output.streams.iter().any(|stream| stream.codec_type == "video");
 
-		let is_discord_compatible = metadata.len() < DISCORD_FILE_SIZE_LIMIT
+		let is_discord_compatible = metadata.len() < size_limit
 			// at least one video stream
-			&& output.streams.iter().any(|stream| stream.codec_type == "video")
+			&& has_video
 			// all video streams are h264 and all audio streams are aac
 			&& output.streams.iter().all(|stream| {
 				(stream.codec_type == "video" && stream.codec_name == "h264") ||
@@ -63,9 +73,22 @@ impl MediaProbe {
 			log::info!("Not compatible with Discord: filesize={} {:#?}", metadata.len(), output);
 		}
 
+		let video = output.streams.iter().find(|stream| stream.codec_type == "video");
+		let audio = output.streams.iter().find(|stream| stream.codec_type == "audio");
+
 		Ok(Self::Probed {
 			is_discord_compatible,
-			duration: Duration::from_secs_f64(output.format.duration.parse::<f64>().context("Failed to parse duration")?),
+			has_video,
+			dimensions: video.and_then(|video| Some((video.width?, video.height?))),
+			video_codec: video.map(|video| video.codec_name.as_str().into()),
+			audio_codec: audio.map(|audio| audio.codec_name.as_str().into()),
+			duration: Duration::from_secs_f64(
+				output
+					.format
+					.duration
+					.parse::<f64>()
+					.map_err(|_| ProbeError::Parse(format!("invalid duration {:?}", output.format.duration)))?,
+			),
 		})
 	}
 }
@@ -80,6 +103,8 @@ struct FFProbeOutput {
 struct FFProbeStream {
 	codec_name: String,
 	codec_type: String,
+	width: Option<u32>,
+	height: Option<u32>,
 }
 
 #[derive(serde::Deserialize, Debug)]
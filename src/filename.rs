@@ -0,0 +1,141 @@
+use crate::{error::ConfigError, yt_dlp::MediaMetadata};
+
+/// Longest title or uploader slug, so the whole name stays readable in Discord's attachment view
+const MAX_SLUG_LEN: usize = 64;
+
+/// Filename for uploaded media like `{site}-{uploader}-{title}`, with the `site`, `title`, `uploader` and `date` placeholders.
+/// Everything is slugified so the name survives Discord's own sanitization
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameTemplate(Box<[Segment]>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+	Literal(Box<str>),
+	Site,
+	Title,
+	Uploader,
+	Date,
+}
+
+impl FilenameTemplate {
+	pub fn parse(template: &str) -> Result<Self, ConfigError> {
+		let mut segments = Vec::new();
+		let mut rest = template;
+
+		while let Some(start) = rest.find('{') {
+			if start > 0 {
+				segments.push(Segment::Literal(rest[..start].into()));
+			}
+
+			let end = rest[start..]
+				.find('}')
+				.ok_or_else(|| ConfigError::invalid(format!("Unclosed placeholder in filename template {template:?}")))?;
+
+			segments.push(match &rest[start + 1..start + end] {
+				"site" => Segment::Site,
+				"title" => Segment::Title,
+				"uploader" => Segment::Uploader,
+				"date" => Segment::Date,
+				placeholder => {
+					return Err(ConfigError::invalid(format!(
+						"Unknown placeholder {{{placeholder}}} in filename template {template:?}"
+					)))
+				}
+			});
+
+			rest = &rest[start + end + 1..];
+		}
+
+		if !rest.is_empty() {
+			segments.push(Segment::Literal(rest.into()));
+		}
+
+		Ok(Self(segments.into_boxed_slice()))
+	}
+
+	/// `None` if the template renders to nothing, e.g. none of its placeholders are known for this media
+	pub fn render(&self, url: &str, metadata: &MediaMetadata, ext: &str) -> Option<String> {
+		let stem = self
+			.0
+			.iter()
+			.map(|segment| match segment {
+				Segment::Literal(literal) => literal.to_string(),
+				Segment::Site => slugify(metadata.site.as_deref().or_else(|| site_from_url(url)).unwrap_or_default(), MAX_SLUG_LEN),
+				Segment::Title => slugify(metadata.title.as_deref().unwrap_or_default(), MAX_SLUG_LEN),
+				Segment::Uploader => slugify(metadata.uploader.as_deref().unwrap_or_default(), MAX_SLUG_LEN),
+				Segment::Date => metadata.upload_date.as_deref().map(format_date).unwrap_or_default(),
+			})
+			.collect::<String>();
+
+		// Also collapses the separators left behind by placeholders with nothing to fill in
+		let stem = slugify(&stem, usize::MAX);
+
+		(!stem.is_empty()).then(|| format!("{stem}.{ext}"))
+	}
+}
+
+/// Lowercase ASCII letters and digits, with runs of anything else collapsed into a single dash (or underscore)
+fn slugify(text: &str, max_len: usize) -> String {
+	let mut slug = String::with_capacity(text.len().min(max_len));
+
+	for c in text.chars() {
+		if slug.len() >= max_len {
+			break;
+		}
+
+		if c.is_ascii_alphanumeric() {
+			slug.push(c.to_ascii_lowercase());
+		} else if !slug.is_empty() && !slug.ends_with(['-', '_']) {
+			slug.push(if c == '_' { '_' } else { '-' });
+		}
+	}
+
+	slug.trim_end_matches(['-', '_']).to_string()
+}
+
+/// yt-dlp's `YYYYMMDD` as `YYYY-MM-DD`
+fn format_date(date: &str) -> String {
+	match (date.get(..4), date.get(4..6), date.get(6..8)) {
+		(Some(year), Some(month), Some(day)) if date.len() == 8 => format!("{year}-{month}-{day}"),
+		_ => String::new(),
+	}
+}
+
+/// e.g. `tiktok` for `https://www.tiktok.com/...`, for media that didn't go through yt-dlp
+fn site_from_url(url: &str) -> Option<&str> {
+	let host = url.split_once("://")?.1.split(['/', '?', '#', ':']).next()?;
+	let host = host.strip_prefix("www.").or_else(|| host.strip_prefix("m.")).unwrap_or(host);
+	host.split('.').next()
+}
+
+#[test]
+fn filename_template() {
+	let metadata = MediaMetadata {
+		title: Some("Cat does a flip!! 😹 #cats".into()),
+		uploader: Some("Some_User".into()),
+		site: Some("TikTok".into()),
+		upload_date: Some("20240131".into()),
+		..Default::default()
+	};
+
+	let template = FilenameTemplate::parse("{site}-{uploader}-{title} ({date})").unwrap();
+	assert_eq!(
+		template.render("https://www.tiktok.com/@some_user/video/1", &metadata, "mp4").as_deref(),
+		Some("tiktok-some_user-cat-does-a-flip-cats-2024-01-31.mp4")
+	);
+
+	let template = FilenameTemplate::parse("{site}-{title}").unwrap();
+	assert_eq!(
+		template
+			.render("https://m.youtube.com/watch?v=1", &MediaMetadata::default(), "webm")
+			.as_deref(),
+		Some("youtube.webm")
+	);
+
+	assert_eq!(
+		FilenameTemplate::parse("{title}").unwrap().render("", &MediaMetadata::default(), "mp4"),
+		None
+	);
+	assert!(FilenameTemplate::parse("{views}").is_err());
+	assert!(FilenameTemplate::parse("{title").is_err());
+}
@@ -1,20 +1,20 @@
 use serde::Deserialize;
 use std::{borrow::Borrow, collections::BTreeSet, time::Duration};
 
+pub const GITHUB_API: &str = "https://api.github.com";
+
 #[derive(Debug)]
 pub struct Releases(pub Vec<Release>);
 impl Releases {
-	pub async fn get(repo: &str, timeout: Duration) -> Result<Self, anyhow::Error> {
-		Ok(Self(
-			reqwest::Client::new()
-				.get(format!("https://api.github.com/repos/{repo}/releases").as_str())
-				.timeout(timeout)
-				.header("User-Agent", env!("CARGO_PKG_NAME"))
-				.send()
-				.await?
-				.json()
-				.await?,
-		))
+	/// Releases of `repo` from `api`, which is [`GITHUB_API`] outside of tests
+	pub async fn get(api: &str, repo: &str, timeout: Duration) -> Result<Self, reqwest::Error> {
+		Ok(Self(Self::request(api, repo).timeout(timeout).send().await?.json().await?))
+	}
+
+	pub fn request(api: &str, repo: &str) -> reqwest::RequestBuilder {
+		reqwest::Client::new()
+			.get(format!("{api}/repos/{repo}/releases").as_str())
+			.header("User-Agent", env!("CARGO_PKG_NAME"))
 	}
 }
 
@@ -0,0 +1,150 @@
+use crate::{config::CompiledConfig, error::HookError, yt_dlp::MediaMetadata};
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId, UserId};
+use std::{path::Path, process::Stdio, time::Duration};
+use tokio::io::AsyncWriteExt;
+
+const HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Commands run at points of the download pipeline. Each is passed the media file path as its last argument (if there is one)
+/// and a JSON [`HookPayload`] on stdin
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HooksConfig {
+	/// After a successful download, before scanning. May modify the file in place, a non-zero exit drops the media
+	pub post_download: Option<Box<[String]>>,
+	/// Right before uploading. May modify the file in place, a non-zero exit drops the media
+	pub pre_upload: Option<Box<[String]>>,
+	/// When downloading or uploading fails. The exit status is ignored
+	pub on_failure: Option<Box<[String]>>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+	PostDownload,
+	PreUpload,
+	OnFailure,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HookPayload<'a> {
+	pub event: HookEvent,
+	pub url: &'a str,
+	pub path: Option<&'a Path>,
+	pub metadata: Option<&'a MediaMetadata>,
+	#[serde(flatten)]
+	pub origin: HookOrigin,
+	pub error: Option<String>,
+}
+
+/// Where the media is being posted, and who asked for it. Feed entries have no user
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct HookOrigin {
+	pub guild_id: Option<GuildId>,
+	pub channel_id: ChannelId,
+	pub user_id: Option<UserId>,
+}
+
+/// Runs the hook configured for `payload.event`, if any. Returns whether the pipeline should carry on
+pub async fn run(config: &CompiledConfig, payload: HookPayload<'_>) -> bool {
+	let command = match payload.event {
+		HookEvent::PostDownload => config.hooks.post_download.as_deref(),
+		HookEvent::PreUpload => config.hooks.pre_upload.as_deref(),
+		HookEvent::OnFailure => config.hooks.on_failure.as_deref(),
+	};
+
+	let Some(command) = command else {
+		return true;
+	};
+
+	match tokio::time::timeout(HOOK_TIMEOUT, exec(command, &payload)).await {
+		Ok(Ok(())) => true,
+
+		Ok(Err(err)) => {
+			log::warn!("{:?} hook failed for {} ({err})", payload.event, payload.url);
+			matches!(payload.event, HookEvent::OnFailure)
+		}
+
+		Err(_) => {
+			log::warn!("{:?} hook timed out for {}", payload.event, payload.url);
+			matches!(payload.event, HookEvent::OnFailure)
+		}
+	}
+}
+
+async fn exec(command: &[String], payload: &HookPayload<'_>) -> Result<(), HookError> {
+	let (program, args) = command.split_first().ok_or(HookError::EmptyCommand)?;
+
+	let mut cmd = tokio::process::Command::new(program);
+	cmd.args(args)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.kill_on_drop(true);
+
+	if let Some(path) = payload.path {
+		cmd.arg(path);
+	}
+
+	let payload = serde_json::to_vec(payload)?;
+
+	let mut child = cmd.spawn()?;
+
+	// Written alongside reading the output, so a hook with lots to say can't stall us writing to it
+	let mut stdin = child.stdin.take().unwrap();
+	let write_payload = async move {
+		match stdin.write_all(&payload).await {
+			// Hooks that don't care about the payload are free to exit without reading it
+			Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+			result => result,
+		}
+	};
+
+	let (written, output) = tokio::join!(write_payload, child.wait_with_output());
+	let output = output?;
+	written?;
+
+	if !output.status.success() {
+		return Err(HookError::Failed {
+			status: output.status,
+			stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+			stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+		});
+	}
+
+	Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn hook_stdin() {
+	let payload = HookPayload {
+		event: HookEvent::PostDownload,
+		url: "https://example.com",
+		path: None,
+		metadata: None,
+		origin: HookOrigin {
+			guild_id: None,
+			channel_id: ChannelId::new(1),
+			user_id: None,
+		},
+		// Larger than a pipe's buffer
+		error: Some("x".repeat(1024 * 1024)),
+	};
+
+	let command = |command: &str| ["sh".to_string(), "-c".to_string(), command.to_string()];
+
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			// Exits without reading the payload
+			exec(&command("true"), &payload).await.unwrap();
+
+			// Writes more than a pipe's buffer before reading the payload
+			exec(&command("head -c 1048576 /dev/zero && cat > /dev/null"), &payload).await.unwrap();
+
+			assert!(exec(&command("exit 1"), &payload).await.is_err());
+		});
+}
@@ -0,0 +1,247 @@
+//! Downloads videos from links posted in Discord and re-uploads them so they embed properly.
+//!
+//! Besides the bot itself ([`App`]), the stages of the download pipeline are public so other bots and tools can reuse them, see
+//! [`pipeline`].
+
+#![allow(clippy::format_collect)]
+
+use std::{future::Future, path::Path, sync::Arc};
+
+use audit::AuditLog;
+use blocklist::Blocklist;
+use broker::JobBroker;
+use cancel::CancelMap;
+use config::ConfigDaemon;
+use dedup::DedupStore;
+use discord::DiscordBotDaemon;
+use feeds::FeedWatcher;
+use preflight::PreflightReport;
+use queue::DownloadQueue;
+use redo::ReplyMap;
+use roster::GuildRoster;
+use yt_dlp::YtDlpDaemon;
+
+mod audit;
+mod blocklist;
+mod bridge;
+mod broker;
+mod cancel;
+#[cfg(test)]
+mod cassette;
+mod cmd;
+pub mod config;
+mod contact_sheet;
+mod dedup;
+mod discord;
+mod edits;
+pub mod error;
+mod exif;
+pub mod extractor;
+mod feeds;
+pub mod ffprobe;
+mod filename;
+mod github;
+mod hooks;
+#[doc(hidden)]
+pub mod logging;
+mod phash;
+pub mod pipeline;
+mod preflight;
+mod privacy;
+mod process;
+pub mod progress;
+pub mod queue;
+mod redirects;
+mod redo;
+mod reply;
+mod report;
+mod reposts;
+mod roster;
+mod scan;
+mod scrub;
+mod selftest;
+pub mod service;
+#[doc(hidden)]
+pub mod setup;
+mod spotify;
+mod storage;
+mod store;
+mod subtitles;
+mod tiktok;
+mod trailer;
+mod twitch;
+mod whisper;
+pub mod yt_dlp;
+
+const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/136.0.0.0 Safari/537.36";
+
+pub struct App {
+	pub ctx: AppContext,
+	pub discord_bots: Vec<DiscordBotDaemon>,
+	feeds: Option<FeedWatcher>,
+}
+impl App {
+	pub async fn new(config_path: &Path, discord_bot_tokens: impl Iterator<Item = &str>, slash_commands_only: bool) -> Result<App, anyhow::Error> {
+		// Persistent state lives next to the config file, which is the only thing guaranteed to survive container restarts
+		let data_dir = config_path.parent().unwrap_or(Path::new("."));
+
+		let config = ConfigDaemon::new(config_path).await?;
+
+		let discord_bot_tokens = discord_bot_tokens.collect::<Vec<_>>();
+
+		let preflight = PreflightReport::run(&*config.get().await, data_dir, &discord_bot_tokens).await;
+		if preflight.failed() {
+			return Err(anyhow::anyhow!("Preflight checks failed\n{preflight}"));
+		}
+		log::info!("Preflight checks passed\n{preflight}");
+
+		let queue = {
+			let config = config.get().await;
+			DownloadQueue::new(config.max_concurrent_downloads, config.max_concurrent_encodes)
+		};
+		let yt_dlp = YtDlpDaemon::new(queue).await?;
+
+		let ctx = AppContext {
+			config,
+			broker: JobBroker::new(Arc::new(yt_dlp.clone())),
+			cancels: CancelMap::default(),
+			yt_dlp,
+			dedup: DedupStore::new(&data_dir.join("dedup.json")).await?,
+			audit: AuditLog::new(&data_dir.join("audit.jsonl")).await?,
+			replies: ReplyMap::new(&data_dir.join("replies.json")).await?,
+			roster: GuildRoster::new(&data_dir.join("guilds.json")).await?,
+			blocklist: Blocklist::new(&data_dir.join("blocklist.json")).await?,
+		};
+
+		let feeds = match discord_bot_tokens.is_empty() {
+			false => Some(FeedWatcher::new(ctx.clone(), &data_dir.join("feeds.json")).await?),
+			true => None,
+		};
+
+		let mut discord_bots = Vec::with_capacity(1);
+		for discord_bot in discord_bot_tokens
+			.into_iter()
+			.map(|discord_bot_token| DiscordBotDaemon::new(discord_bot_token, ctx.clone(), slash_commands_only))
+		{
+			discord_bots.push(discord_bot.await?);
+		}
+
+		Ok(Self { ctx, discord_bots, feeds })
+	}
+
+	/// Runs until the bots stop, or Ctrl-C or SIGTERM
+	pub async fn run(self) -> Result<(), anyhow::Error> {
+		self.run_until(Self::shutdown_signal()).await
+	}
+
+	/// Runs until the bots stop or `shutdown` completes, which is how a service manager stops the bot
+	pub async fn run_until(self, shutdown: impl Future<Output = ()>) -> Result<(), anyhow::Error> {
+		tokio::spawn(Self::reload_on_signal(self.ctx.clone()));
+
+		tokio::spawn(privacy::enforce_retention(self.ctx.clone()));
+
+		tokio::spawn(selftest::startup_canary(self.ctx.clone()));
+
+		// Feed entries and the daily summary are posted by the first bot
+		if let Some(discord_bot) = self.discord_bots.first() {
+			if let Some(feeds) = self.feeds {
+				tokio::spawn(feeds.run(discord_bot.http()));
+			}
+
+			tokio::spawn(audit::daily_summary(self.ctx.clone(), discord_bot.http()));
+		}
+
+		let discord_bots = self.discord_bots;
+		let discord_bots = async {
+			let mut set = tokio::task::JoinSet::new();
+			for discord_bot in discord_bots {
+				set.spawn(discord_bot);
+			}
+			while let Some(res) = set.join_next().await {
+				res.unwrap()?;
+			}
+			Ok::<_, anyhow::Error>(())
+		};
+
+		tokio::select! {
+			discord_bot = discord_bots => discord_bot?,
+
+			_ = shutdown => log::info!("Shutting down..."),
+		}
+
+		Ok(())
+	}
+
+	/// Ctrl-C, or SIGTERM which systemd and launchd stop services with
+	async fn shutdown_signal() {
+		#[cfg(unix)]
+		let sigterm = async {
+			match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+				Ok(mut signal) => {
+					signal.recv().await;
+				}
+				Err(err) => {
+					log::error!("Failed to listen for SIGTERM ({err})");
+					std::future::pending::<()>().await;
+				}
+			}
+		};
+
+		#[cfg(not(unix))]
+		let sigterm = std::future::pending::<()>();
+
+		tokio::select! {
+			_ = tokio::signal::ctrl_c() => log::info!("Received Ctrl-C"),
+			_ = sigterm => log::info!("Received SIGTERM"),
+		}
+	}
+
+	/// Reloads the config from disk on SIGHUP (Ctrl-Break on Windows)
+	async fn reload_on_signal(ctx: AppContext) -> Result<(), std::io::Error> {
+		#[cfg(unix)]
+		let mut signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+
+		#[cfg(windows)]
+		let mut signal = tokio::signal::windows::ctrl_break()?;
+
+		while signal.recv().await.is_some() {
+			log::info!("Received reload signal, reloading config...");
+
+			match ctx.config.reload().await {
+				Ok(diff) => log::info!("Config reloaded\n{diff}"),
+				Err(err) => log::error!("Failed to reload config, keeping the current one ({err})"),
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[derive(Clone)]
+pub struct AppContext {
+	pub yt_dlp: YtDlpDaemon,
+	/// Shares downloads of the same link between all the bots
+	broker: JobBroker,
+	/// Downloads of posted links in progress, which their requesters can cancel
+	cancels: CancelMap,
+	pub config: ConfigDaemon,
+	pub dedup: DedupStore,
+	pub audit: AuditLog,
+	pub replies: ReplyMap,
+	pub roster: GuildRoster,
+	pub blocklist: Blocklist,
+}
+
+/// Streams the file at `path` as a request body rather than reading it all into memory, along with its size
+pub(crate) async fn file_body(path: &Path) -> Result<(reqwest::Body, u64), std::io::Error> {
+	let file = tokio::fs::File::open(path).await?;
+	let size = file.metadata().await?.len();
+	Ok((reqwest::Body::from(file), size))
+}
+
+pub(crate) fn unix_now() -> u64 {
+	std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0)
+}
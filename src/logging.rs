@@ -1,8 +1,11 @@
-use serenity::all::{ChannelId, Colour, CreateEmbed, CreateMessage, Timestamp};
+use serenity::all::{ChannelId, Colour, CreateAttachment, CreateEmbed, CreateMessage};
 use std::sync::{Arc, OnceLock};
 
 type AppLogger = pretty_env_logger::env_logger::Logger;
 
+const EMBED_DESCRIPTION_LIMIT: usize = 4096;
+const TRUNCATED_SUFFIX: &str = "\n… (truncated)";
+
 static LOGGER: OnceLock<DiscordLogger> = OnceLock::new();
 
 struct DiscordLoggerContext {
@@ -31,31 +34,47 @@ impl log::Log for DiscordLogger {
 	}
 
 	fn log(&self, record: &log::Record) {
-		self.logger.log(record);
+		let text = record.args().to_string();
+		let text = crate::privacy::redact(&text);
+
+		self.logger.log(
+			&log::Record::builder()
+				.args(format_args!("{text}"))
+				.level(record.level())
+				.target(record.target())
+				.module_path(record.module_path())
+				.file(record.file())
+				.line(record.line())
+				.build(),
+		);
 
 		if let Some(DiscordLoggerContext { rt, http, channel_id }) = self.ctx.get() {
-			let msg = CreateMessage::new().add_embed({
-				let mut embed = CreateEmbed::new()
-					.timestamp(Timestamp::now())
-					.description(format!("```\n{}\n```", record.args()))
-					.color(match record.level() {
-						log::Level::Info => Colour::DARK_GREEN,
-						log::Level::Warn => Colour::DARK_GOLD,
-						log::Level::Error => Colour::DARK_RED,
-						log::Level::Debug | log::Level::Trace => return,
-					});
-
-				if let Some(module_path) = record.module_path() {
-					embed = embed.title(module_path);
-				}
+			let (color, emoji) = match record.level() {
+				log::Level::Info => (Colour::DARK_GREEN, "ℹ️"),
+				log::Level::Warn => (Colour::DARK_GOLD, "⚠️"),
+				log::Level::Error => (Colour::DARK_RED, "❌"),
+				log::Level::Debug | log::Level::Trace => return,
+			};
 
-				embed
-			});
+			let (description, truncated) = describe_record(&text, crate::unix_now());
+
+			let msg = CreateMessage::new().add_embed(
+				CreateEmbed::new()
+					.title(format!("{emoji} {}", record.module_path().unwrap_or_default()))
+					.description(description)
+					.color(color),
+			);
+
+			// The full record goes along as a file when it doesn't fit in the embed
+			let files = match truncated {
+				true => vec![CreateAttachment::bytes(text.into_owned(), "log.txt")],
+				false => Vec::new(),
+			};
 
 			let channel_id = *channel_id;
 			let http = http.clone();
 			rt.spawn(async move {
-				if let Err(err) = http.send_message(channel_id, Vec::new(), &msg).await {
+				if let Err(err) = http.send_message(channel_id, files, &msg).await {
 					eprintln!("Failed to send log message to Discord: {err} {err:?}");
 				}
 			});
@@ -67,6 +86,29 @@ impl log::Log for DiscordLogger {
 	}
 }
 
+/// Embed description for a log record: a Discord timestamp, shown in each reader's own timezone, and the record in a code block.
+/// Also returns whether the record had to be truncated to fit
+fn describe_record(text: &str, unix_time: u64) -> (String, bool) {
+	let header = format!("<t:{unix_time}:F> (<t:{unix_time}:R>)\n");
+
+	// Would otherwise end the code block early
+	let text = text.replace("```", "`\u{200B}``");
+
+	// The limit is in characters, so counting bytes is conservative
+	let budget = EMBED_DESCRIPTION_LIMIT - header.len() - "```\n\n```".len();
+	let (text, truncated) = if text.len() <= budget {
+		(text, false)
+	} else {
+		let mut end = budget - TRUNCATED_SUFFIX.len();
+		while !text.is_char_boundary(end) {
+			end -= 1;
+		}
+		(format!("{}{TRUNCATED_SUFFIX}", &text[..end]), true)
+	};
+
+	(format!("{header}```\n{text}\n```"), truncated)
+}
+
 pub async fn connect_discord(channel_id: ChannelId, http: Arc<serenity::http::Http>) {
 	let logger = match LOGGER.get() {
 		Some(logger) => logger,
@@ -80,3 +122,18 @@ pub async fn connect_discord(channel_id: ChannelId, http: Arc<serenity::http::Ht
 
 	log::info!("Connected to Discord logging channel");
 }
+
+#[test]
+fn log_record_description() {
+	let (description, truncated) = describe_record("Failed ```x```", 1700000000);
+	assert_eq!(
+		description,
+		"<t:1700000000:F> (<t:1700000000:R>)\n```\nFailed `\u{200B}``x`\u{200B}``\n```"
+	);
+	assert!(!truncated);
+
+	let (description, truncated) = describe_record(&"é".repeat(5000), 1700000000);
+	assert!(description.chars().count() <= EMBED_DESCRIPTION_LIMIT);
+	assert!(description.ends_with("… (truncated)\n```"));
+	assert!(truncated);
+}
@@ -1,74 +1,9 @@
-#![allow(clippy::format_collect)]
-
+use discord_embed_bot::{logging, service, setup, App};
 use std::{
 	borrow::Cow,
 	path::{Path, PathBuf},
 };
 
-use config::ConfigDaemon;
-use discord::DiscordBotDaemon;
-use yt_dlp::YtDlpDaemon;
-
-mod cmd;
-mod config;
-mod discord;
-mod ffprobe;
-mod github;
-mod logging;
-mod tiktok;
-mod yt_dlp;
-
-const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/136.0.0.0 Safari/537.36";
-
-pub struct App {
-	pub discord_bots: Vec<DiscordBotDaemon>,
-}
-impl App {
-	pub async fn new(config_path: &Path, discord_bot_tokens: impl Iterator<Item = &str>) -> Result<App, anyhow::Error> {
-		let ctx = AppContext {
-			config: ConfigDaemon::new(config_path).await?,
-			yt_dlp: YtDlpDaemon::new().await?,
-		};
-
-		let mut discord_bots = Vec::with_capacity(1);
-		for discord_bot in discord_bot_tokens.map(|discord_bot_token| DiscordBotDaemon::new(discord_bot_token, ctx.clone())) {
-			discord_bots.push(discord_bot.await?);
-		}
-
-		Ok(Self { discord_bots })
-	}
-
-	pub async fn run(self) -> Result<(), anyhow::Error> {
-		let ctrlc = tokio::signal::ctrl_c();
-
-		let discord_bots = self.discord_bots;
-		let discord_bots = async {
-			let mut set = tokio::task::JoinSet::new();
-			for discord_bot in discord_bots {
-				set.spawn(discord_bot);
-			}
-			while let Some(res) = set.join_next().await {
-				res.unwrap()?;
-			}
-			Ok::<_, anyhow::Error>(())
-		};
-
-		tokio::select! {
-			discord_bot = discord_bots => discord_bot?,
-
-			_ = ctrlc => log::info!("Received Ctrl-C, shutting down..."),
-		}
-
-		Ok(())
-	}
-}
-
-#[derive(Clone)]
-pub struct AppContext {
-	pub yt_dlp: YtDlpDaemon,
-	pub config: ConfigDaemon,
-}
-
 #[tokio::main]
 async fn main() {
 	logging::DiscordLogger::init(
@@ -83,7 +18,12 @@ async fn main() {
 	log::info!("Starting...");
 
 	let mut discord_bot_token = None;
+	let mut discord_bot_token_path = Cow::Borrowed(Path::new("discord_bot_token"));
 	let mut config_path = Cow::Borrowed(Path::new("config.json"));
+	let mut init = false;
+	let mut slash_commands_only = false;
+	let mut install_service = false;
+	let mut run_as_service = false;
 
 	let mut args = std::env::args();
 	while let Some(arg) = args.next() {
@@ -92,14 +32,42 @@ async fn main() {
 		} else if arg == "--config-path" {
 			config_path = Cow::Owned(PathBuf::from(args.next().expect("Expected a value for --config-path")));
 		} else if arg == "--discord-bot-token-path" {
-			let discord_bot_token_path = PathBuf::from(args.next().expect("Expected a value for --discord-bot-token-path"));
+			discord_bot_token_path = Cow::Owned(PathBuf::from(args.next().expect("Expected a value for --discord-bot-token-path")));
+		} else if arg == "--init" {
+			init = true;
+		} else if arg == "--slash-commands-only" {
+			slash_commands_only = true;
+		} else if arg == "--working-dir" {
+			std::env::set_current_dir(args.next().expect("Expected a value for --working-dir")).expect("Failed to change to --working-dir");
+		} else if arg == "--install-service" {
+			install_service = true;
+		} else if arg == "--run-as-service" {
+			run_as_service = true;
+		}
+	}
+
+	if install_service {
+		// The service runs with the same arguments, from the current directory
+		let service_args = std::env::args().skip(1).filter(|arg| arg != "--install-service").collect::<Vec<_>>();
+		let working_dir = std::env::current_dir().expect("Failed to get the current directory");
+
+		match service::install(&working_dir, &service_args) {
+			Ok(instructions) => log::info!("{instructions}"),
+			Err(err) => log::error!("Failed to install the service ({err})"),
+		}
+		return;
+	}
 
-			discord_bot_token = Some(std::fs::read_to_string(&discord_bot_token_path).expect("Failed to read --discord-bot-token-path"));
+	if init || setup::needed(&config_path) {
+		let ask_token = discord_bot_token.is_none() && !discord_bot_token_path.is_file() && std::env::var_os("DISCORD_BOT_TOKEN").is_none();
+
+		if let Some(token) = setup::run(&config_path, &discord_bot_token_path, ask_token).expect("Setup failed") {
+			discord_bot_token = Some(token);
 		}
 	}
 
-	if discord_bot_token.is_none() && Path::new("discord_bot_token").is_file() {
-		discord_bot_token = Some(std::fs::read_to_string("discord_bot_token").expect("Failed to read discord_bot_token"));
+	if discord_bot_token.is_none() && discord_bot_token_path.is_file() {
+		discord_bot_token = Some(std::fs::read_to_string(&discord_bot_token_path).expect("Failed to read the Discord bot token file"));
 	}
 
 	if discord_bot_token.is_none() {
@@ -108,16 +76,35 @@ async fn main() {
 		}
 	}
 
-	App::new(
-		config_path.as_ref(),
-		discord_bot_token
-			.expect("Expected a --discord-bot-token or --discord-bot-token-path")
-			.trim()
-			.split(&['\n', ';']),
-	)
-	.await
-	.unwrap()
-	.run()
-	.await
-	.unwrap();
+	let discord_bot_token = discord_bot_token.expect("Expected a --discord-bot-token or --discord-bot-token-path");
+
+	#[cfg(windows)]
+	if run_as_service {
+		let config_path = config_path.into_owned();
+
+		service::run_windows_service(move |shutdown| async move {
+			App::new(&config_path, discord_bot_token.trim().split(&['\n', ';']), slash_commands_only)
+				.await?
+				.run_until(async {
+					shutdown.await.ok();
+				})
+				.await
+		})
+		.unwrap();
+
+		return;
+	}
+
+	// launchd and systemd stop services with SIGTERM, which `App::run` already shuts down on
+	#[cfg(not(windows))]
+	if run_as_service {
+		log::info!("Running as a service");
+	}
+
+	App::new(config_path.as_ref(), discord_bot_token.trim().split(&['\n', ';']), slash_commands_only)
+		.await
+		.unwrap()
+		.run()
+		.await
+		.unwrap();
 }
@@ -0,0 +1,78 @@
+use crate::error::EncodeError;
+use std::{f64::consts::PI, path::Path};
+
+const SIZE: usize = 32;
+const LOW_FREQ: usize = 8;
+const MAX_FRAMES: usize = 60;
+
+/// Computes a 64-bit DCT perceptual hash for every sampled frame (one per second) of a video or image
+pub async fn hash_frames(path: &Path) -> Result<Vec<u64>, EncodeError> {
+	let output = crate::process::ffmpeg()
+		.arg("-i")
+		.arg(path)
+		.args([
+			"-vf",
+			&format!("fps=1,scale={SIZE}:{SIZE}:flags=area,format=gray"),
+			"-frames:v",
+			&MAX_FRAMES.to_string(),
+			"-f",
+			"rawvideo",
+			"pipe:1",
+		])
+		.output()
+		.await?;
+
+	if !output.status.success() {
+		return Err(EncodeError::Failed {
+			status: output.status,
+			stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+		});
+	}
+
+	Ok(output.stdout.chunks_exact(SIZE * SIZE).map(phash).collect())
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+	(a ^ b).count_ones()
+}
+
+fn phash(pixels: &[u8]) -> u64 {
+	let cos = |x: usize, u: usize| ((2 * x + 1) as f64 * u as f64 * PI / (2 * SIZE) as f64).cos();
+
+	// Separable 2D DCT-II, only the low frequency corner is needed
+	let mut rows = [[0.0; LOW_FREQ]; SIZE];
+	for (y, row) in rows.iter_mut().enumerate() {
+		for (u, coeff) in row.iter_mut().enumerate() {
+			*coeff = (0..SIZE).map(|x| pixels[y * SIZE + x] as f64 * cos(x, u)).sum();
+		}
+	}
+
+	let mut coeffs = [0.0; LOW_FREQ * LOW_FREQ];
+	for v in 0..LOW_FREQ {
+		for u in 0..LOW_FREQ {
+			coeffs[v * LOW_FREQ + u] = (0..SIZE).map(|y| rows[y][u] * cos(y, v)).sum();
+		}
+	}
+
+	// Median excluding the DC term, which only reflects overall brightness
+	let mut sorted = coeffs[1..].to_vec();
+	sorted.sort_by(f64::total_cmp);
+	let median = sorted[sorted.len() / 2];
+
+	coeffs
+		.iter()
+		.enumerate()
+		.fold(0, |hash, (i, coeff)| if *coeff > median { hash | (1 << i) } else { hash })
+}
+
+#[test]
+fn phash_ignores_brightness() {
+	let image = (0..(SIZE * SIZE) as u32)
+		.map(|i| (i.wrapping_mul(2654435761) >> 24) as u8 % 200)
+		.collect::<Vec<_>>();
+	let brighter = image.iter().map(|p| p.saturating_add(10)).collect::<Vec<_>>();
+	let flipped = image.iter().rev().copied().collect::<Vec<_>>();
+
+	assert_eq!(hamming_distance(phash(&image), phash(&brighter)), 0);
+	assert!(hamming_distance(phash(&image), phash(&flipped)) > 8);
+}
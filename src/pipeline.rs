@@ -0,0 +1,52 @@
+//! The stages media goes through between a link being posted and the file being uploaded, as traits so each can be swapped out
+//! when embedding the pipeline in something other than this bot.
+//!
+//! The bot's own implementations are [`CompiledLinkRegex`] (matching), [`YtDlpDaemon`] (downloading, and encoding with ffmpeg) and
+//! [`MediaProbe`] (probing with ffprobe). Media is uploaded to Discord by the bot itself, and forwarded to its bridges with an
+//! [`Uploader`].
+//!
+//! [`CompiledLinkRegex`]: crate::config::CompiledLinkRegex
+//! [`YtDlpDaemon`]: crate::yt_dlp::YtDlpDaemon
+//! [`MediaProbe`]: crate::ffprobe::MediaProbe
+
+use crate::{
+	error::{DownloadError, EncodeError, ProbeError, UploadError},
+	yt_dlp::{DownloadOptions, MediaResult, Processed},
+};
+use serenity::async_trait;
+use std::path::Path;
+
+/// Picks out the links to download
+pub trait Matcher: Send + Sync {
+	/// Whether `url` on its own is a link to download
+	fn is_match(&self, url: &str) -> bool;
+
+	/// The links to download in `text`, in the order they appear
+	fn find_links<'t>(&self, text: &'t str) -> Vec<regex::Match<'t>>;
+}
+
+/// Downloads the media behind a link, ready to upload
+#[async_trait]
+pub trait Downloader: Send + Sync {
+	async fn download(&self, url: &str, options: &DownloadOptions) -> Result<MediaResult, DownloadError>;
+}
+
+/// Reads a media file's streams and duration
+#[async_trait]
+pub trait Prober: Sized {
+	/// `size_limit` is the upload limit the file has to fit in to be Discord compatible
+	async fn probe(path: &Path, size_limit: u64) -> Result<Self, ProbeError>;
+}
+
+/// Makes a downloaded file playable inline in Discord, and fit in the upload limit
+#[async_trait]
+pub trait Encoder: Send + Sync {
+	/// The processed file may be `path` itself if nothing had to be done
+	async fn encode(&self, path: &Path, options: &DownloadOptions) -> Result<Processed, EncodeError>;
+}
+
+/// Somewhere downloaded media is posted to
+#[async_trait]
+pub trait Uploader: Send + Sync {
+	async fn upload(&self, media: &MediaResult) -> Result<(), UploadError>;
+}
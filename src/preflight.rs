@@ -0,0 +1,188 @@
+use crate::{config::CompiledConfig, pipeline::Matcher, storage::StorageConfig};
+use std::{fmt, future::Future, path::Path, time::Duration};
+
+/// How long a single check can take before it counts as failed
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A TikTok photo link, to tell whether any link regex would send one to the slideshow extractor
+const TIKTOK_PHOTO_URL: &str = "https://www.tiktok.com/@preflight/photo/1";
+
+enum Outcome {
+	Ok(String),
+	/// Some links or features won't work, but the bot can still run
+	Warn(String),
+	/// The bot can't run
+	Fail(String),
+}
+
+struct Check {
+	name: String,
+	outcome: Outcome,
+}
+
+/// Everything the bot needs from the host, checked once at startup so a missing dependency is reported up front instead of as
+/// a failure halfway through someone's download
+pub struct PreflightReport(Vec<Check>);
+impl PreflightReport {
+	pub async fn run(config: &CompiledConfig, data_dir: &Path, discord_bot_tokens: &[&str]) -> Self {
+		let mut checks = Vec::new();
+
+		for tool in ["ffmpeg", "ffprobe"] {
+			checks.push(Check {
+				name: tool.to_string(),
+				outcome: match tool_version(tool).await {
+					Ok(version) => Outcome::Ok(version),
+					Err(err) => Outcome::Fail(format!("{err}, install it and make sure it's on the PATH")),
+				},
+			});
+		}
+
+		if config.link_regexes.iter().any(|regex| regex.is_match(TIKTOK_PHOTO_URL)) {
+			checks.push(Check {
+				name: "node + xbogus".to_string(),
+				outcome: if cfg!(not(feature = "tiktok-photos")) {
+					Outcome::Warn("built without the `tiktok-photos` feature, TikTok photo slideshows won't download".to_string())
+				} else {
+					match xbogus().await {
+						Ok(()) => Outcome::Ok("available".to_string()),
+						Err(err) => Outcome::Warn(format!("{err}, TikTok photo slideshows won't download (`npm install` next to the bot)")),
+					}
+				},
+			});
+		}
+
+		if cfg!(not(feature = "whisper")) && config.whisper.is_some() {
+			checks.push(Check {
+				name: "whisper".to_string(),
+				outcome: Outcome::Warn("configured, but built without the `whisper` feature".to_string()),
+			});
+		}
+
+		if cfg!(not(feature = "s3-upload")) && matches!(config.storage, Some(StorageConfig::S3(_))) {
+			checks.push(Check {
+				name: "s3 storage".to_string(),
+				outcome: Outcome::Warn("configured, but built without the `s3-upload` feature, too large media won't be uploaded".to_string()),
+			});
+		}
+
+		for dir in [Path::new("."), data_dir] {
+			checks.push(Check {
+				name: format!("write access to {}", dir.display()),
+				outcome: match writable(dir).await {
+					Ok(()) => Outcome::Ok("writable".to_string()),
+					Err(err) => Outcome::Fail(err.to_string()),
+				},
+			});
+		}
+
+		for (i, discord_bot_token) in discord_bot_tokens.iter().enumerate() {
+			checks.push(Check {
+				name: format!("Discord bot token #{}", i + 1),
+				outcome: discord_login(discord_bot_token).await,
+			});
+		}
+
+		checks.push(Check {
+			name: "DNS lookup of api.github.com".to_string(),
+			outcome: match timeout(tokio::net::lookup_host(("api.github.com", 443))).await {
+				Ok(_) => Outcome::Ok("resolved".to_string()),
+				Err(err) => Outcome::Warn(format!("{err}, yt-dlp can't be downloaded or updated")),
+			},
+		});
+
+		Self(checks)
+	}
+
+	pub fn failed(&self) -> bool {
+		self.0.iter().any(|check| matches!(check.outcome, Outcome::Fail(_)))
+	}
+}
+impl fmt::Display for PreflightReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for check in &self.0 {
+			let (status, detail) = match &check.outcome {
+				Outcome::Ok(detail) => ('✅', detail),
+				Outcome::Warn(detail) => ('⚠', detail),
+				Outcome::Fail(detail) => ('❌', detail),
+			};
+			writeln!(f, "{status} {}: {detail}", check.name)?;
+		}
+		Ok(())
+	}
+}
+
+async fn timeout<T, E: Into<anyhow::Error>>(future: impl Future<Output = Result<T, E>>) -> Result<T, anyhow::Error> {
+	match tokio::time::timeout(CHECK_TIMEOUT, future).await {
+		Ok(result) => result.map_err(Into::into),
+		Err(_) => Err(anyhow::anyhow!("timed out")),
+	}
+}
+
+/// First line of `tool -version`
+async fn tool_version(tool: &str) -> Result<String, anyhow::Error> {
+	let mut command = match tool {
+		"ffmpeg" => crate::process::ffmpeg(),
+		_ => crate::process::ffprobe(),
+	};
+
+	let output = timeout(command.arg("-version").output())
+		.await
+		.map_err(|_| anyhow::anyhow!("not found"))?;
+	if !output.status.success() {
+		return Err(anyhow::anyhow!("exited with {}", output.status));
+	}
+
+	Ok(String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or_default().to_string())
+}
+
+/// The TikTok slideshow extractor signs its API requests with the xbogus npm package
+async fn xbogus() -> Result<(), anyhow::Error> {
+	let output = timeout(tokio::process::Command::new("node").args(["-e", "require('xbogus')"]).output())
+		.await
+		.map_err(|_| anyhow::anyhow!("node not found"))?;
+
+	if !output.status.success() {
+		return Err(anyhow::anyhow!("xbogus not installed"));
+	}
+
+	Ok(())
+}
+
+async fn writable(dir: &Path) -> Result<(), anyhow::Error> {
+	let probe = dir.join(format!(".preflight-{}", uuid::Uuid::new_v4()));
+	tokio::fs::write(&probe, []).await?;
+	tokio::fs::remove_file(&probe).await?;
+	Ok(())
+}
+
+/// Only a rejected token fails the check, Discord being unreachable for a moment shouldn't stop the bot starting
+async fn discord_login(discord_bot_token: &str) -> Outcome {
+	let http = serenity::all::Http::new(discord_bot_token.trim());
+
+	match tokio::time::timeout(CHECK_TIMEOUT, http.get_current_user()).await {
+		Ok(Ok(user)) => Outcome::Ok(format!("logged in as {}", user.name)),
+		Ok(Err(serenity::Error::Http(serenity::all::HttpError::UnsuccessfulRequest(response)))) if response.status_code.as_u16() == 401 => {
+			Outcome::Fail("rejected by Discord, reset it in the developer portal".to_string())
+		}
+		Ok(Err(err)) => Outcome::Warn(format!("couldn't check it ({err})")),
+		Err(_) => Outcome::Warn("couldn't check it (timed out)".to_string()),
+	}
+}
+
+#[test]
+fn preflight_report() {
+	let check = |name: &str, outcome| Check {
+		name: name.to_string(),
+		outcome,
+	};
+
+	let report = PreflightReport(vec![
+		check("ffmpeg", Outcome::Ok("ffmpeg version 7.1".to_string())),
+		check("node + xbogus", Outcome::Warn("node not found".to_string())),
+	]);
+	assert!(!report.failed());
+	assert_eq!(report.to_string(), "✅ ffmpeg: ffmpeg version 7.1\n⚠ node + xbogus: node not found\n");
+
+	let report = PreflightReport(vec![check("ffprobe", Outcome::Fail("not found".to_string()))]);
+	assert!(report.failed());
+}
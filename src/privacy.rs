@@ -0,0 +1,226 @@
+use crate::AppContext;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+	borrow::Cow,
+	fmt,
+	sync::{
+		atomic::{AtomicU8, Ordering},
+		LazyLock,
+	},
+	time::Duration,
+};
+
+/// How often history older than the retention period is deleted
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+static REDACTION: AtomicU8 = AtomicU8::new(Redaction::Off as u8);
+
+/// URLs, and Discord IDs (snowflakes) which are mostly user, message and channel IDs in our logs
+static REDACTED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"https?://[^\s<>"'`]+|\b\d{17,20}\b"#).unwrap());
+
+/// Config fields holding credentials, see [`redact_secrets`]
+const SECRET_FIELDS: &[&str] = &["bot_token", "access_token", "secret_access_key", "userhash", "oauth_token"];
+
+/// What a secret in the config is shown as. Left in place when the config is edited, it keeps the current value
+pub const REDACTED_SECRET: &str = "<redacted>";
+
+static SECRET_VALUES: LazyLock<Regex> =
+	LazyLock::new(|| Regex::new(&format!(r#"("(?:{})"\s*:\s*)"(?:[^"\\]|\\.)*""#, SECRET_FIELDS.join("|"))).unwrap());
+
+/// Per-process, so hashes can be correlated within a run but not reversed by hashing every possible ID
+static SALT: LazyLock<[u8; 16]> = LazyLock::new(|| uuid::Uuid::new_v4().into_bytes());
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PrivacyConfig {
+	/// How URLs and Discord IDs appear in logs, the log channel and error reports
+	#[serde(default)]
+	pub redact: Redaction,
+	/// Delete stored history (dedup hashes, redo map, audit log, error reports, left guilds) older than this many days
+	#[serde(default)]
+	pub retention_days: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum Redaction {
+	#[default]
+	Off,
+	/// Replaced with a short salted hash, so the same URL or ID can still be followed through the logs
+	Hash,
+	/// Replaced with a placeholder
+	Remove,
+}
+
+/// Applies to everything logged from now on
+pub fn set_redaction(redaction: Redaction) {
+	REDACTION.store(redaction as u8, Ordering::Relaxed);
+}
+
+/// Redacts URLs and Discord IDs in `text` as configured
+pub fn redact(text: &str) -> Cow<'_, str> {
+	let redaction = match REDACTION.load(Ordering::Relaxed) {
+		1 => Redaction::Hash,
+		2 => Redaction::Remove,
+		_ => Redaction::Off,
+	};
+
+	redact_with(text, redaction, &*SALT)
+}
+
+fn redact_with<'a>(text: &'a str, redaction: Redaction, salt: &[u8]) -> Cow<'a, str> {
+	if redaction == Redaction::Off {
+		return Cow::Borrowed(text);
+	}
+
+	REDACTED.replace_all(text, |captures: &Captures| {
+		let matched = &captures[0];
+		let kind = if matched.starts_with("http") { "url" } else { "id" };
+
+		match redaction {
+			Redaction::Hash => {
+				let hash = Sha256::new().chain_update(salt).chain_update(matched).finalize();
+				format!("<{kind}:{}>", hash[..4].iter().map(|byte| format!("{byte:02x}")).collect::<String>())
+			}
+			_ => format!("<{kind}>"),
+		}
+	})
+}
+
+/// A credential in the config, kept out of `Debug` output
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(Box<str>);
+impl Secret {
+	pub fn expose(&self) -> &str {
+		&self.0
+	}
+}
+impl fmt::Debug for Secret {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(REDACTED_SECRET)
+	}
+}
+
+/// Replaces the values of secrets in config JSON, for anywhere the config is shown: dumps, exports, diffs and the audit log.
+/// Works on the text so it keeps its formatting, and still works on a config that doesn't parse
+pub fn redact_secrets(json: &str) -> Cow<'_, str> {
+	SECRET_VALUES.replace_all(json, format!("${{1}}\"{REDACTED_SECRET}\""))
+}
+
+/// Puts the values of secrets `new` left as [`REDACTED_SECRET`] back from `old`, the config it's replacing, so a redacted dump can
+/// be edited and imported again. Returns the path of any that `old` has nothing at
+pub fn restore_secrets(new: &mut serde_json::Value, old: &serde_json::Value) -> Result<(), String> {
+	fn restore(new: &mut serde_json::Value, old: Option<&serde_json::Value>, path: &mut String) -> Result<(), String> {
+		match new {
+			serde_json::Value::Object(object) => {
+				for (key, value) in object {
+					let len = path.len();
+					path.push('/');
+					path.push_str(key);
+
+					let old = old.and_then(|old| old.get(key));
+					if SECRET_FIELDS.contains(&key.as_str()) && value.as_str() == Some(REDACTED_SECRET) {
+						*value = old.filter(|old| old.is_string()).cloned().ok_or_else(|| path.clone())?;
+					} else {
+						restore(value, old, path)?;
+					}
+
+					path.truncate(len);
+				}
+			}
+
+			serde_json::Value::Array(array) => {
+				for (i, value) in array.iter_mut().enumerate() {
+					let len = path.len();
+					path.push_str(&format!("/{i}"));
+					restore(value, old.and_then(|old| old.get(i)), path)?;
+					path.truncate(len);
+				}
+			}
+
+			_ => {}
+		}
+
+		Ok(())
+	}
+
+	restore(new, Some(old), &mut String::new())
+}
+
+/// Deletes stored history older than the configured retention period, for as long as the bot runs
+pub async fn enforce_retention(app_ctx: AppContext) {
+	loop {
+		if let Some(retention_days) = app_ctx.config.get().await.privacy.retention_days {
+			let cutoff = crate::unix_now().saturating_sub(retention_days * 24 * 60 * 60);
+
+			app_ctx.dedup.prune(cutoff).await;
+			app_ctx.replies.prune(cutoff).await;
+			app_ctx.roster.prune(cutoff).await;
+
+			if let Err(err) = app_ctx.audit.prune(cutoff).await {
+				log::error!("Failed to prune the audit log ({err})");
+			}
+
+			if let Err(err) = crate::report::prune(cutoff).await {
+				log::error!("Failed to prune error reports ({err})");
+			}
+		}
+
+		tokio::time::sleep(RETENTION_INTERVAL).await;
+	}
+}
+
+#[test]
+fn log_redaction() {
+	let text = "Failed to download https://www.tiktok.com/@user/video/1 posted by someone (123456789012345678)";
+
+	assert_eq!(redact_with(text, Redaction::Off, b"salt"), text);
+	assert_eq!(
+		redact_with(text, Redaction::Remove, b"salt"),
+		"Failed to download <url> posted by someone (<id>)"
+	);
+
+	let hashed = redact_with(text, Redaction::Hash, b"salt");
+	assert!(!hashed.contains("tiktok") && !hashed.contains("123456789012345678"));
+	assert_eq!(hashed, redact_with(text, Redaction::Hash, b"salt"));
+	assert_ne!(hashed, redact_with(text, Redaction::Hash, b"pepper"));
+
+	// Sizes and durations aren't IDs
+	assert_eq!(
+		redact_with("took 1500ms, 10485760 bytes", Redaction::Remove, b""),
+		"took 1500ms, 10485760 bytes"
+	);
+}
+
+#[test]
+fn secret_redaction() {
+	let config = r#"{
+	"guilds": {
+		"1": {
+			"bridges": [
+				{ "type": "telegram", "bot_token": "123:a\"b", "chat_id": "@chat" },
+				{ "type": "matrix", "homeserver": "https://matrix.org", "access_token": "syt_abc", "room_id": "!a:matrix.org" }
+			]
+		}
+	},
+	"twitch": { "oauth_token": "abcdef0123456789", "vod_segment_length": 60 }
+}"#;
+
+	let redacted = redact_secrets(config);
+	assert!(!redacted.contains("123:a") && !redacted.contains("syt_abc") && !redacted.contains("abcdef0123456789"));
+	assert!(redacted.contains(r#""oauth_token": "<redacted>", "vod_segment_length": 60"#));
+	assert!(redacted.contains(r#""bot_token": "<redacted>", "chat_id": "@chat""#));
+
+	let old = serde_json::from_str::<serde_json::Value>(config).unwrap();
+	let mut new = serde_json::from_str::<serde_json::Value>(&redacted).unwrap();
+	restore_secrets(&mut new, &old).unwrap();
+	assert_eq!(new, old);
+
+	let mut added = serde_json::json!({ "guilds": { "2": { "bridges": [{ "bot_token": REDACTED_SECRET }] } } });
+	assert_eq!(restore_secrets(&mut added, &old), Err("/guilds/2/bridges/0/bot_token".to_string()));
+
+	assert_eq!(format!("{:?}", Secret("syt_abc".into())), REDACTED_SECRET);
+}
@@ -0,0 +1,56 @@
+use std::ffi::OsStr;
+use tokio::process::Command;
+
+/// How much lower than the bot's own CPU priority media tools run at
+#[cfg(unix)]
+const NICENESS: libc::c_int = 10;
+
+/// Lowest priority of the best-effort IO class, see `ioprio_set(2)`
+#[cfg(target_os = "linux")]
+const IOPRIO: libc::c_int = (2 << 13) | 7;
+
+/// Windows' `BELOW_NORMAL_PRIORITY_CLASS` process creation flag
+#[cfg(windows)]
+const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+
+/// A command for one of the heavy media tools (ffmpeg, yt-dlp, whisper), run at reduced CPU and IO priority so a burst of encodes
+/// can't starve the gateway heartbeat and event loop of the bot itself. Killed if the download running it is dropped, e.g. when
+/// it's cancelled
+pub fn media_command(program: impl AsRef<OsStr>) -> Command {
+	let mut command = Command::new(program);
+	command.kill_on_drop(true);
+	lower_priority(&mut command);
+	command
+}
+
+pub fn ffmpeg() -> Command {
+	media_command(if cfg!(windows) { "ffmpeg.exe" } else { "ffmpeg" })
+}
+
+pub fn ffprobe() -> Command {
+	media_command(if cfg!(windows) { "ffprobe.exe" } else { "ffprobe" })
+}
+
+#[cfg(unix)]
+fn lower_priority(command: &mut Command) {
+	// SAFETY: only makes syscalls, which are async-signal-safe, between fork and exec
+	unsafe {
+		command.pre_exec(|| {
+			// Not worth failing the command over, it just runs at normal priority
+			libc::setpriority(libc::PRIO_PROCESS, 0, NICENESS);
+
+			#[cfg(target_os = "linux")]
+			libc::syscall(libc::SYS_ioprio_set, 1 /* IOPRIO_WHO_PROCESS */, 0, IOPRIO);
+
+			Ok(())
+		});
+	}
+}
+
+#[cfg(windows)]
+fn lower_priority(command: &mut Command) {
+	command.creation_flags(BELOW_NORMAL_PRIORITY_CLASS);
+}
+
+#[cfg(not(any(unix, windows)))]
+fn lower_priority(_command: &mut Command) {}
@@ -0,0 +1,125 @@
+use std::{fmt, process::Output, sync::Arc, time::Duration};
+use tokio::{
+	io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+	process::Command,
+};
+
+/// Marks yt-dlp's progress lines among its JSON dump on stdout, see `YT_DLP_PROGRESS_ARGS`
+const YT_DLP_PROGRESS_PREFIX: &str = "[embed-bot-progress] ";
+
+/// Makes yt-dlp print a line of downloaded and total bytes to stdout as it downloads, even though `--dump-json` silences it
+pub const YT_DLP_PROGRESS_ARGS: &[&str] = &[
+	"--progress",
+	"--newline",
+	"--progress-template",
+	"download:[embed-bot-progress] %(progress.downloaded_bytes)s/%(progress.total_bytes,progress.total_bytes_estimate)s",
+];
+
+/// Makes ffmpeg print `key=value` progress lines to stdout instead of its usual stats to stderr
+pub const FFMPEG_PROGRESS_ARGS: &[&str] = &["-progress", "pipe:1", "-nostats"];
+
+/// How far along a download is, for someone waiting on it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DownloadProgress {
+	FetchingMetadata,
+	Downloading { percent: Option<f64> },
+	Reencoding { percent: Option<f64> },
+}
+
+/// Where a download reports its progress to, nowhere by default
+#[derive(Clone, Default)]
+pub struct ProgressReporter(Option<Arc<dyn Fn(DownloadProgress) + Send + Sync>>);
+impl ProgressReporter {
+	pub fn new(report: impl Fn(DownloadProgress) + Send + Sync + 'static) -> Self {
+		Self(Some(Arc::new(report)))
+	}
+
+	pub fn report(&self, progress: DownloadProgress) {
+		if let Some(report) = &self.0 {
+			report(progress);
+		}
+	}
+}
+impl fmt::Debug for ProgressReporter {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ProgressReporter")
+	}
+}
+
+/// Percentage downloaded, if the line is one of yt-dlp's progress lines. `Some(None)` if the total size isn't known
+pub fn yt_dlp_percent(line: &str) -> Option<Option<f64>> {
+	let (downloaded, total) = line.strip_prefix(YT_DLP_PROGRESS_PREFIX)?.trim().split_once('/')?;
+
+	Some(match (downloaded.parse::<f64>(), total.parse::<f64>()) {
+		(Ok(downloaded), Ok(total)) if total > 0.0 => Some((downloaded / total * 100.0).clamp(0.0, 100.0)),
+		_ => None,
+	})
+}
+
+/// Percentage encoded, if the line is ffmpeg's output timestamp from `-progress` and the duration is known
+pub fn ffmpeg_percent(line: &str, duration: Option<Duration>) -> Option<f64> {
+	// Despite the name, `out_time_ms` is in microseconds too
+	let out_time_us = line
+		.strip_prefix("out_time_us=")
+		.or_else(|| line.strip_prefix("out_time_ms="))?
+		.trim()
+		.parse::<f64>()
+		.ok()?;
+
+	let duration = duration.filter(|duration| !duration.is_zero())?;
+
+	Some((out_time_us / 1_000_000.0 / duration.as_secs_f64() * 100.0).clamp(0.0, 100.0))
+}
+
+/// Like `Command::output`, but hands each line of stdout to `on_line` as it's printed. Lines it returns `true` for are left out
+/// of the returned stdout
+pub async fn output_with_progress(cmd: &mut Command, mut on_line: impl FnMut(&str) -> bool) -> std::io::Result<Output> {
+	let mut child = cmd
+		.stdin(std::process::Stdio::null())
+		.stdout(std::process::Stdio::piped())
+		.stderr(std::process::Stdio::piped())
+		.kill_on_drop(true)
+		.spawn()?;
+
+	let stdout = child.stdout.take().unwrap();
+	let mut stderr = child.stderr.take().unwrap();
+
+	let read_stdout = async {
+		let mut kept = Vec::new();
+		let mut lines = BufReader::new(stdout).lines();
+		while let Some(line) = lines.next_line().await? {
+			if !on_line(&line) {
+				kept.extend_from_slice(line.as_bytes());
+				kept.push(b'\n');
+			}
+		}
+		Ok::<_, std::io::Error>(kept)
+	};
+
+	let read_stderr = async {
+		let mut buf = Vec::new();
+		stderr.read_to_end(&mut buf).await?;
+		Ok::<_, std::io::Error>(buf)
+	};
+
+	let (stdout, stderr) = tokio::try_join!(read_stdout, read_stderr)?;
+
+	Ok(Output {
+		status: child.wait().await?,
+		stdout,
+		stderr,
+	})
+}
+
+#[test]
+fn parse_progress() {
+	assert_eq!(yt_dlp_percent("[embed-bot-progress] 2500/10000"), Some(Some(25.0)));
+	assert_eq!(yt_dlp_percent("[embed-bot-progress] 512.0/1024.5"), Some(Some(512.0 / 1024.5 * 100.0)));
+	assert_eq!(yt_dlp_percent("[embed-bot-progress] 2500/NA"), Some(None));
+	assert_eq!(yt_dlp_percent(r#"{"id": "dQw4w9WgXcQ"}"#), None);
+
+	assert_eq!(ffmpeg_percent("out_time_us=5000000", Some(Duration::from_secs(20))), Some(25.0));
+	assert_eq!(ffmpeg_percent("out_time_ms=30000000", Some(Duration::from_secs(20))), Some(100.0));
+	assert_eq!(ffmpeg_percent("out_time_us=5000000", None), None);
+	assert_eq!(ffmpeg_percent("frame=120", Some(Duration::from_secs(20))), None);
+}
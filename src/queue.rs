@@ -0,0 +1,218 @@
+use std::{
+	num::NonZeroUsize,
+	time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+
+/// How long a passive job waits before it's treated as interactive, so a steady stream of slash commands can't starve embeds
+const PRIORITY_AGING: Duration = Duration::from_secs(60);
+
+/// Who is waiting on a job, interactive jobs get the next free slot ahead of passive ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+	/// Links posted in messages
+	#[default]
+	Passive,
+	/// Slash commands, where the user is actively waiting
+	Interactive,
+}
+
+/// Cores available to the bot, which the encodes share
+pub fn available_cores() -> usize {
+	std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Threads given to each encode so that `max_encodes` of them at once use every core, but no more
+fn threads_per_encode(cores: usize, max_encodes: usize) -> usize {
+	(cores / max_encodes.max(1)).max(1)
+}
+
+/// Limits how many downloads (yt-dlp processes, extractors and plugins) and ffmpeg encodes run at once, so a burst of links waits
+/// its turn instead of spawning enough processes to run the host out of memory
+pub struct DownloadQueue {
+	downloads: Slots,
+	encodes: Slots,
+	encode_threads: usize,
+}
+impl DownloadQueue {
+	pub fn new(max_downloads: usize, max_encodes: usize) -> Self {
+		Self {
+			downloads: Slots::new("download", max_downloads.max(1)),
+			encodes: Slots::new("encode", max_encodes.max(1)),
+			encode_threads: threads_per_encode(available_cores(), max_encodes),
+		}
+	}
+
+	/// ffmpeg's `-threads` for an encode, so encodes running at once share the CPU rather than each assuming it has all of it
+	pub fn encode_threads(&self) -> usize {
+		self.encode_threads
+	}
+
+	/// Waits for a download slot, which is held until the permit is dropped
+	pub async fn download(&self, priority: Priority) -> QueuePermit<'_> {
+		self.downloads.acquire(priority).await
+	}
+
+	/// Waits for an encode slot, which is held until the permit is dropped
+	pub async fn encode(&self, priority: Priority) -> QueuePermit<'_> {
+		self.encodes.acquire(priority).await
+	}
+}
+
+/// A semaphore that hands out freed slots by priority rather than first come, first served
+struct Slots {
+	kind: &'static str,
+	state: parking_lot::Mutex<SlotsState>,
+}
+
+struct SlotsState {
+	available: usize,
+	waiters: Vec<Waiter>,
+}
+
+struct Waiter {
+	priority: Priority,
+	queued_at: Instant,
+	tx: oneshot::Sender<()>,
+}
+
+impl Slots {
+	fn new(kind: &'static str, slots: usize) -> Self {
+		Self {
+			kind,
+			state: parking_lot::Mutex::new(SlotsState {
+				available: slots,
+				waiters: Vec::new(),
+			}),
+		}
+	}
+
+	async fn acquire(&self, priority: Priority) -> QueuePermit<'_> {
+		let queued_at = Instant::now();
+
+		let rx = {
+			let mut state = self.state.lock();
+
+			if state.available > 0 {
+				state.available -= 1;
+				return QueuePermit {
+					slots: self,
+					waited: Duration::ZERO,
+				};
+			}
+
+			let (tx, rx) = oneshot::channel();
+			state.waiters.push(Waiter { priority, queued_at, tx });
+			rx
+		};
+
+		log::info!("All {} slots are busy, queueing...", self.kind);
+
+		let mut waiting = Waiting { slots: self, rx: Some(rx) };
+
+		// The sender is only dropped after sending, or if we stopped waiting
+		if let Some(rx) = &mut waiting.rx {
+			rx.await.ok();
+		}
+		waiting.rx = None;
+
+		QueuePermit {
+			slots: self,
+			waited: queued_at.elapsed(),
+		}
+	}
+
+	/// Hands the slot to the next waiter, or frees it if nobody is waiting
+	fn release(&self) {
+		let mut state = self.state.lock();
+
+		loop {
+			let now = Instant::now();
+			let Some(next) = next_waiter(state.waiters.iter().map(|waiter| (waiter.priority, waiter.queued_at)), now) else {
+				state.available += 1;
+				return;
+			};
+
+			// Fails if the waiter has given up, in which case the slot goes to the one after
+			if state.waiters.swap_remove(next).tx.send(()).is_ok() {
+				return;
+			}
+		}
+	}
+}
+
+/// Index of the waiter to get the next slot: the highest priority, taking aging into account, then the longest waiting
+fn next_waiter(waiters: impl Iterator<Item = (Priority, Instant)>, now: Instant) -> Option<usize> {
+	waiters
+		.enumerate()
+		.min_by_key(|(_, (priority, queued_at))| {
+			let priority = match now.duration_since(*queued_at) >= PRIORITY_AGING {
+				true => Priority::Interactive,
+				false => *priority,
+			};
+			(std::cmp::Reverse(priority), *queued_at)
+		})
+		.map(|(index, _)| index)
+}
+
+/// Gives back a slot that was handed to us just as we stopped waiting for it
+struct Waiting<'a> {
+	slots: &'a Slots,
+	rx: Option<oneshot::Receiver<()>>,
+}
+impl Drop for Waiting<'_> {
+	fn drop(&mut self) {
+		if let Some(mut rx) = self.rx.take() {
+			rx.close();
+			if rx.try_recv().is_ok() {
+				self.slots.release();
+			}
+		}
+	}
+}
+
+/// A download or encode slot, freed when dropped
+pub struct QueuePermit<'a> {
+	slots: &'a Slots,
+	waited: Duration,
+}
+impl QueuePermit<'_> {
+	/// How long we were queued for the slot, which job timings leave out
+	pub fn waited(&self) -> Duration {
+		self.waited
+	}
+}
+impl Drop for QueuePermit<'_> {
+	fn drop(&mut self) {
+		self.slots.release();
+	}
+}
+
+#[test]
+fn queue_priority() {
+	let now = Instant::now();
+	let ago = |secs| now - Duration::from_secs(secs);
+
+	assert_eq!(next_waiter(std::iter::empty(), now), None);
+
+	// Interactive jumps ahead, otherwise first come first served
+	let waiters = [
+		(Priority::Passive, ago(10)),
+		(Priority::Interactive, ago(1)),
+		(Priority::Passive, ago(20)),
+	];
+	assert_eq!(next_waiter(waiters.into_iter(), now), Some(1));
+	assert_eq!(next_waiter([waiters[0], waiters[2]].into_iter(), now), Some(1));
+
+	// Until a passive job has waited long enough
+	let waiters = [(Priority::Interactive, ago(1)), (Priority::Passive, ago(PRIORITY_AGING.as_secs() + 1))];
+	assert_eq!(next_waiter(waiters.into_iter(), now), Some(1));
+}
+
+#[test]
+fn encode_threads() {
+	assert_eq!(threads_per_encode(8, 2), 4);
+	assert_eq!(threads_per_encode(6, 4), 1);
+	assert_eq!(threads_per_encode(2, 4), 1);
+	assert_eq!(threads_per_encode(8, 0), 8);
+}
@@ -0,0 +1,215 @@
+//! Follows a link's redirects one at a time rather than leaving it to reqwest, keeping track of the referer and cookies along the
+//! way so yt-dlp can be sent the same headers for the page the link ends up at
+
+use crate::error::RedirectError;
+use reqwest::{
+	header::{HeaderMap, CONTENT_TYPE, COOKIE, LOCATION, REFERER, SET_COOKIE, USER_AGENT},
+	Url,
+};
+use tokio::process::Command;
+
+const MAX_REDIRECTS: usize = 10;
+
+/// Headers of the request that reached a link's final URL, repeated for yt-dlp so it isn't served a different page (or a bot wall)
+#[derive(Debug, Clone, Default)]
+pub struct JobHeaders {
+	pub user_agent: Box<str>,
+	/// The page that redirected to the final URL
+	pub referer: Option<Box<str>>,
+	/// `Cookie` header for the final URL, from the cookies set on the way there
+	pub cookie: Option<Box<str>>,
+}
+impl JobHeaders {
+	pub fn new(user_agent: &str) -> Self {
+		Self {
+			user_agent: user_agent.into(),
+			..Default::default()
+		}
+	}
+
+	pub fn apply_yt_dlp_args(&self, cmd: &mut Command) {
+		cmd.args(["--user-agent", &self.user_agent]);
+
+		if let Some(referer) = &self.referer {
+			cmd.args(["--referer", referer]);
+		}
+
+		// yt-dlp scopes cookies passed as a header to the domain of the URL it's given
+		if let Some(cookie) = &self.cookie {
+			cmd.args(["--add-headers", &format!("Cookie:{cookie}")]);
+		}
+	}
+}
+
+pub struct Resolved {
+	pub url: Url,
+	pub content_type: Option<String>,
+	pub headers: JobHeaders,
+}
+
+/// HEADs `url` with `client`, which must not follow redirects itself, and each link it redirects to in turn
+pub async fn resolve(client: &reqwest::Client, url: &str, user_agent: &str) -> Result<Resolved, RedirectError> {
+	let mut url = Url::parse(url)?;
+	let mut referer = None::<Url>;
+	let mut cookies = CookieJar::default();
+
+	for _ in 0..=MAX_REDIRECTS {
+		let mut request = client.head(url.clone()).header(USER_AGENT, user_agent);
+
+		if let Some(referer) = &referer {
+			request = request.header(REFERER, referer.as_str());
+		}
+		if let Some(cookie) = cookies.header(&url) {
+			request = request.header(COOKIE, cookie);
+		}
+
+		let response = request.send().await?;
+
+		cookies.store(&url, response.headers());
+
+		if response.status().is_redirection() {
+			if let Some(location) = response.headers().get(LOCATION).and_then(|location| location.to_str().ok()) {
+				let next = url.join(location)?;
+				referer = Some(std::mem::replace(&mut url, next));
+				continue;
+			}
+		}
+
+		let response = response.error_for_status()?;
+
+		let content_type = response
+			.headers()
+			.get(CONTENT_TYPE)
+			.and_then(|content_type| content_type.to_str().ok())
+			.and_then(|content_type| content_type.split(';').next())
+			.map(|content_type| content_type.trim().to_ascii_lowercase());
+
+		let headers = JobHeaders {
+			user_agent: user_agent.into(),
+			referer: referer.map(|referer| referer.as_str().into()),
+			cookie: cookies.header(&url).map(Into::into),
+		};
+
+		return Ok(Resolved { url, content_type, headers });
+	}
+
+	Err(RedirectError::TooMany(MAX_REDIRECTS))
+}
+
+struct Cookie {
+	/// The host that set it, or its `Domain` attribute, which also covers subdomains
+	domain: String,
+	host_only: bool,
+	name: String,
+	value: String,
+}
+impl Cookie {
+	fn matches(&self, host: &str) -> bool {
+		match self.host_only {
+			true => host == self.domain,
+			false => domain_matches(host, &self.domain),
+		}
+	}
+}
+
+/// Whether `host` is `domain` or one of its subdomains
+fn domain_matches(host: &str, domain: &str) -> bool {
+	host == domain || host.strip_suffix(domain).is_some_and(|subdomain| subdomain.ends_with('.'))
+}
+
+/// Just enough of a cookie jar to carry cookies across redirects, paths and expiry dates are ignored
+#[derive(Default)]
+struct CookieJar(Vec<Cookie>);
+impl CookieJar {
+	fn store(&mut self, url: &Url, headers: &HeaderMap) {
+		let Some(host) = url.host_str() else {
+			return;
+		};
+
+		for set_cookie in headers.get_all(SET_COOKIE).iter().filter_map(|set_cookie| set_cookie.to_str().ok()) {
+			let mut attributes = set_cookie.split(';').map(str::trim);
+
+			let Some((name, value)) = attributes.next().and_then(|pair| pair.split_once('=')) else {
+				continue;
+			};
+
+			let mut cookie = Cookie {
+				domain: host.to_ascii_lowercase(),
+				host_only: true,
+				name: name.trim().to_string(),
+				value: value.trim().to_string(),
+			};
+			let mut expired = false;
+			let mut foreign = false;
+
+			for attribute in attributes {
+				let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+
+				if key.eq_ignore_ascii_case("domain") {
+					let domain = value.trim_start_matches('.').to_ascii_lowercase();
+					if domain.is_empty() {
+						continue;
+					}
+
+					// A site can't set cookies for another
+					foreign = !domain_matches(&cookie.domain, &domain);
+					cookie.domain = domain;
+					cookie.host_only = false;
+				} else if key.eq_ignore_ascii_case("max-age") {
+					expired = value.parse::<i64>().is_ok_and(|max_age| max_age <= 0);
+				}
+			}
+
+			if foreign {
+				continue;
+			}
+
+			self.0.retain(|existing| existing.name != cookie.name || existing.domain != cookie.domain);
+
+			if !expired {
+				self.0.push(cookie);
+			}
+		}
+	}
+
+	fn header(&self, url: &Url) -> Option<String> {
+		let host = url.host_str()?.to_ascii_lowercase();
+
+		let cookie = self
+			.0
+			.iter()
+			.filter(|cookie| cookie.matches(&host))
+			.map(|cookie| format!("{}={}", cookie.name, cookie.value))
+			.collect::<Vec<_>>()
+			.join("; ");
+
+		(!cookie.is_empty()).then_some(cookie)
+	}
+}
+
+#[test]
+fn cookie_jar() {
+	let mut jar = CookieJar::default();
+
+	let mut headers = HeaderMap::new();
+	headers.append(SET_COOKIE, "session=abc; Path=/; HttpOnly".parse().unwrap());
+	headers.append(SET_COOKIE, "consent=yes; Domain=.example.com".parse().unwrap());
+	headers.append(SET_COOKIE, "tracker=1; Domain=evil.com".parse().unwrap());
+	jar.store(&Url::parse("https://link.example.com/abc").unwrap(), &headers);
+
+	assert_eq!(
+		jar.header(&Url::parse("https://link.example.com/").unwrap()).as_deref(),
+		Some("session=abc; consent=yes")
+	);
+	assert_eq!(
+		jar.header(&Url::parse("https://www.example.com/video").unwrap()).as_deref(),
+		Some("consent=yes")
+	);
+	assert_eq!(jar.header(&Url::parse("https://evil.com/").unwrap()), None);
+	assert_eq!(jar.header(&Url::parse("https://notexample.com/").unwrap()), None);
+
+	let mut headers = HeaderMap::new();
+	headers.append(SET_COOKIE, "consent=; Domain=example.com; Max-Age=0".parse().unwrap());
+	jar.store(&Url::parse("https://www.example.com/").unwrap(), &headers);
+	assert_eq!(jar.header(&Url::parse("https://www.example.com/video").unwrap()), None);
+}
@@ -0,0 +1,202 @@
+use crate::{
+	store::JsonStore,
+	yt_dlp::{DownloadOptions, VideoQuality},
+};
+use serde::{Deserialize, Serialize};
+use serenity::all::{MessageId, UserId};
+use std::{
+	collections::{BTreeMap, HashMap},
+	path::Path,
+	sync::Arc,
+};
+
+/// How long our replies can be redone for
+const REPLY_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Default)]
+struct ReplyEntries(HashMap<MessageId, ReplyEntry>);
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReplyEntry {
+	pub url: Box<str>,
+	/// Name of the link regex that matched, if it has one
+	pub regex: Option<Box<str>>,
+	/// The message with the link, `None` for replies from before this was recorded
+	#[serde(default)]
+	pub message_id: Option<MessageId>,
+	/// Who posted the link, `None` for replies from before this was recorded
+	#[serde(default)]
+	pub author_id: Option<UserId>,
+	/// The message we replied to, if we suppressed its embed, so it can be restored if our reply is deleted
+	#[serde(default)]
+	pub suppressed_embed: Option<MessageId>,
+	created_at: u64,
+}
+
+/// Persistent map of our replies to the link they were downloaded from, so they can be redone with different options
+#[derive(Clone)]
+pub struct ReplyMap(Arc<JsonStore<ReplyEntries>>);
+impl ReplyMap {
+	pub async fn new(path: &Path) -> Result<Self, anyhow::Error> {
+		Ok(Self(Arc::new(JsonStore::open(path.to_owned()).await?)))
+	}
+
+	pub async fn get(&self, reply_id: MessageId) -> Option<ReplyEntry> {
+		self.0.read(|entries| entries.0.get(&reply_id).cloned()).await
+	}
+
+	/// Our reply to `url` in `message_id`, if it's still around
+	pub async fn find(&self, message_id: MessageId, url: &str) -> Option<MessageId> {
+		self.0
+			.read(|entries| {
+				entries
+					.0
+					.iter()
+					.find(|(_, entry)| entry.message_id == Some(message_id) && &*entry.url == url)
+					.map(|(reply_id, _)| *reply_id)
+			})
+			.await
+	}
+
+	pub async fn insert(
+		&self,
+		reply_id: MessageId,
+		message_id: MessageId,
+		author_id: UserId,
+		url: &str,
+		regex: Option<&str>,
+		suppressed_embed: Option<MessageId>,
+	) {
+		let created_at = crate::unix_now();
+
+		if let Err(err) = self
+			.0
+			.update(|entries| {
+				entries.0.retain(|_, entry| created_at.saturating_sub(entry.created_at) < REPLY_TTL_SECS);
+				entries.0.insert(
+					reply_id,
+					ReplyEntry {
+						url: url.into(),
+						regex: regex.map(Into::into),
+						message_id: Some(message_id),
+						author_id: Some(author_id),
+						suppressed_embed,
+						created_at,
+					},
+				);
+			})
+			.await
+		{
+			log::error!("Failed to persist reply map ({err})");
+		}
+	}
+}
+
+impl ReplyMap {
+	/// Forgets a reply that was deleted, returning what we knew about it
+	pub async fn remove(&self, reply_id: MessageId) -> Option<ReplyEntry> {
+		// Most deleted messages aren't ours, don't rewrite the file for them
+		self.get(reply_id).await?;
+
+		let mut removed = None;
+		if let Err(err) = self.0.update(|entries| removed = entries.0.remove(&reply_id)).await {
+			log::error!("Failed to persist reply map ({err})");
+		}
+		removed
+	}
+
+	/// Our replies to links the user posted, by reply
+	pub async fn for_user(&self, user_id: UserId) -> BTreeMap<MessageId, ReplyEntry> {
+		self.0
+			.read(|entries| {
+				entries
+					.0
+					.iter()
+					.filter(|(_, entry)| entry.author_id == Some(user_id))
+					.map(|(reply_id, entry)| (*reply_id, entry.clone()))
+					.collect()
+			})
+			.await
+	}
+
+	/// Forgets our replies to links the user posted, returning how many there were
+	pub async fn forget_user(&self, user_id: UserId) -> Result<usize, anyhow::Error> {
+		self.0
+			.update(|entries| {
+				let before = entries.0.len();
+				entries.0.retain(|_, entry| entry.author_id != Some(user_id));
+				before - entries.0.len()
+			})
+			.await
+	}
+
+	/// Forgets replies from before `cutoff`, a unix timestamp
+	pub async fn prune(&self, cutoff: u64) {
+		if let Err(err) = self.0.update(|entries| entries.0.retain(|_, entry| entry.created_at >= cutoff)).await {
+			log::error!("Failed to persist reply map ({err})");
+		}
+	}
+}
+
+/// Options of a redo command, e.g. `!redo audio hq`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RedoArgs {
+	pub audio_only: Option<bool>,
+	/// Picks the best quality there is, still within the link regex's size and duration limits
+	pub hq: bool,
+}
+impl RedoArgs {
+	/// Returns the unrecognized word on failure
+	pub fn parse(args: &str) -> Result<Self, &str> {
+		let mut redo = Self::default();
+
+		for arg in args.split_whitespace() {
+			match arg.to_ascii_lowercase().as_str() {
+				"audio" => redo.audio_only = Some(true),
+				"video" => redo.audio_only = Some(false),
+				"hq" => redo.hq = true,
+				_ => return Err(arg),
+			}
+		}
+
+		Ok(redo)
+	}
+
+	pub fn apply(&self, options: &mut DownloadOptions) {
+		if let Some(audio_only) = self.audio_only {
+			options.audio_only = audio_only;
+		}
+
+		// The operator's limits stay, they're what the bot can afford to download and encode
+		if self.hq {
+			options.quality = Some(VideoQuality::Best);
+		}
+	}
+}
+
+#[test]
+fn redo_args() {
+	assert_eq!(
+		RedoArgs::parse(" Audio  hq"),
+		Ok(RedoArgs {
+			audio_only: Some(true),
+			hq: true
+		})
+	);
+	assert_eq!(RedoArgs::parse(""), Ok(RedoArgs::default()));
+	assert_eq!(RedoArgs::parse("audio 4k"), Err("4k"));
+}
+
+#[test]
+fn redo_hq_keeps_limits() {
+	let mut options = DownloadOptions {
+		max_duration: Some(std::time::Duration::from_secs(600)),
+		target_size_mb: Some(8.0),
+		..Default::default()
+	};
+	RedoArgs::parse("hq").unwrap().apply(&mut options);
+
+	assert_eq!(options.quality, Some(VideoQuality::Best));
+	assert_eq!(options.max_duration, Some(std::time::Duration::from_secs(600)));
+	assert_eq!(options.target_size_mb, Some(8.0));
+}
@@ -0,0 +1,122 @@
+use crate::error::UploadError;
+use serenity::{
+	all::{
+		CacheHttp, ChannelId, CommandInteraction, CreateAllowedMentions, CreateAttachment, CreateInteractionResponseFollowup, CreateMessage, Message,
+	},
+	prelude::*,
+};
+
+pub const DISCORD_ATTACHMENT_LIMIT: usize = 10;
+
+/// Packs attachments into as few messages as Discord's per-message attachment count and upload size limits allow
+pub struct ReplyBuilder {
+	batches: Vec<Vec<CreateAttachment>>,
+	size_limit: u64,
+}
+impl ReplyBuilder {
+	/// `size_limit` is the upload limit of the guild, see [`upload_limit`](crate::discord::upload_limit)
+	pub fn new(size_limit: u64) -> Self {
+		Self {
+			batches: Vec::new(),
+			size_limit,
+		}
+	}
+
+	/// Fails with [`UploadError::TooLarge`] if the file is too large to upload at all
+	pub fn add_file(&mut self, file: CreateAttachment) -> Result<(), UploadError> {
+		let size = file.data.len() as u64;
+
+		if size > self.size_limit {
+			return Err(UploadError::TooLarge);
+		}
+
+		match self.batches.iter_mut().find(|batch| {
+			batch.len() < DISCORD_ATTACHMENT_LIMIT && batch.iter().map(|file| file.data.len() as u64).sum::<u64>() + size <= self.size_limit
+		}) {
+			Some(batch) => batch.push(file),
+			None => self.batches.push(vec![file]),
+		}
+
+		Ok(())
+	}
+
+	/// Sends `message` with the first batch of files, and the rest as replies to it
+	pub async fn send(self, cache_http: impl CacheHttp, channel_id: ChannelId, message: CreateMessage) -> Result<Vec<Message>, UploadError> {
+		let mut batches = self.batches.into_iter();
+
+		let first = channel_id
+			.send_message(&cache_http, message.add_files(batches.next().unwrap_or_default()))
+			.await?;
+
+		let mut sent = Vec::with_capacity(1 + batches.len());
+		for batch in batches {
+			sent.push(
+				channel_id
+					.send_message(
+						&cache_http,
+						CreateMessage::new()
+							.reference_message(&first)
+							.allowed_mentions(CreateAllowedMentions::new())
+							.add_files(batch),
+					)
+					.await?,
+			);
+		}
+		sent.insert(0, first);
+
+		Ok(sent)
+	}
+
+	/// Sends `followup` with the first batch of files, and the rest as further followups
+	pub async fn send_followups(
+		self,
+		ctx: &Context,
+		command: &CommandInteraction,
+		followup: CreateInteractionResponseFollowup,
+	) -> Result<Vec<Message>, UploadError> {
+		let mut batches = self.batches.into_iter();
+
+		let mut sent = vec![
+			command
+				.create_followup(ctx, followup.add_files(batches.next().unwrap_or_default()))
+				.await?,
+		];
+
+		for batch in batches {
+			sent.push(
+				command
+					.create_followup(
+						ctx,
+						CreateInteractionResponseFollowup::new()
+							.allowed_mentions(CreateAllowedMentions::new())
+							.add_files(batch),
+					)
+					.await?,
+			);
+		}
+
+		Ok(sent)
+	}
+}
+
+#[test]
+fn reply_batching() {
+	let size_limit = crate::discord::DISCORD_FILE_SIZE_LIMIT;
+	let mut reply = ReplyBuilder::new(size_limit);
+
+	for i in 0..12 {
+		reply.add_file(CreateAttachment::bytes(vec![0; 1024], format!("{i}.jpg"))).unwrap();
+	}
+	assert_eq!(reply.batches.iter().map(Vec::len).collect::<Vec<_>>(), [10, 2]);
+
+	// Doesn't fit alongside the small files, but the next small file still goes in the first batch with room
+	let large = size_limit as usize - 1024;
+	reply.add_file(CreateAttachment::bytes(vec![0; large], "large.mp4")).unwrap();
+	reply.add_file(CreateAttachment::bytes(vec![0; 1024], "12.jpg")).unwrap();
+	assert_eq!(reply.batches.iter().map(Vec::len).collect::<Vec<_>>(), [10, 3, 1]);
+
+	assert!(matches!(
+		reply.add_file(CreateAttachment::bytes(vec![0; size_limit as usize + 1], "huge.mp4")),
+		Err(UploadError::TooLarge)
+	));
+}
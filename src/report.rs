@@ -0,0 +1,360 @@
+use crate::{
+	config::ConfigDaemon,
+	discord::DISCORD_FILE_SIZE_LIMIT,
+	error::{DownloadError, Error},
+	ffprobe::MediaProbe,
+	pipeline::Prober,
+	privacy,
+};
+use parking_lot::Mutex;
+use serenity::all::{ChannelId, CreateAllowedMentions, CreateAttachment, CreateMessage, Http};
+use sha2::{Digest, Sha256};
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	path::{Path, PathBuf},
+	sync::LazyLock,
+	time::{Duration, Instant},
+};
+
+const REPORTS_DIR: &str = "error_reports";
+
+/// Reports kept on disk, the oldest are deleted first
+const MAX_REPORTS: usize = 100;
+
+/// Lines of yt-dlp's stdout and stderr kept, the interesting part is almost always at the end
+const OUTPUT_TAIL_LINES: usize = 50;
+
+/// Reports of the same kind of error are uploaded at most this often, so a broken extractor doesn't flood the admin log channel.
+/// They're all still written to disk
+const UPLOAD_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// When a report of each kind of error was last uploaded, and how many have been held back since
+static UPLOADED: LazyLock<Mutex<HashMap<Box<str>, LastUpload>>> = LazyLock::new(Default::default);
+
+struct LastUpload {
+	at: Instant,
+	held_back: usize,
+}
+
+/// Diagnostics for a failed download, written to disk and uploaded to the admin log channel so extractor failures can be debugged
+/// without access to the server. Also used for `/admin debug-download`, where the download may well succeed
+pub struct ErrorReport {
+	/// Short ID to find the report from the log line, and vice versa
+	pub id: Box<str>,
+	url: Box<str>,
+	/// How long each stage of the pipeline took, in order
+	timings: Vec<(&'static str, Duration)>,
+	details: Vec<(&'static str, String)>,
+	error: Option<String>,
+	/// What went wrong, for throttling uploads. Reports without an error aren't throttled
+	kind: Option<Box<str>>,
+	/// yt-dlp's whole verbose output, for `/admin debug-download`
+	log: Option<String>,
+	probe: Option<String>,
+	config_hash: Option<String>,
+}
+impl ErrorReport {
+	pub fn new(url: &str) -> Self {
+		Self {
+			id: uuid::Uuid::new_v4().simple().to_string()[..8].into(),
+			url: url.into(),
+			timings: Vec::new(),
+			details: Vec::new(),
+			error: None,
+			kind: None,
+			log: None,
+			probe: None,
+			config_hash: None,
+		}
+	}
+
+	pub fn timing(&mut self, stage: &'static str, duration: Duration) -> &mut Self {
+		self.timings.push((stage, duration));
+		self
+	}
+
+	pub fn detail(&mut self, label: &'static str, value: impl ToString) -> &mut Self {
+		self.details.push((label, value.to_string()));
+		self
+	}
+
+	pub fn error(&mut self, err: &(dyn std::error::Error + 'static)) -> &mut Self {
+		let err = match err.downcast_ref::<Error>() {
+			Some(err) => err.stage(),
+			None => err,
+		};
+
+		let err = match err.downcast_ref::<DownloadError>() {
+			Some(DownloadError::Shared(err)) => err.as_ref(),
+			_ => err,
+		};
+
+		self.error = Some(match err.downcast_ref::<DownloadError>() {
+			Some(DownloadError::YtDlp { status, stderr, stdout }) => format!(
+				"yt-dlp exit status: {status}\n\n=========== stderr (tail) ===========\n{}\n\n=========== stdout (tail) ===========\n{}",
+				tail(stderr, OUTPUT_TAIL_LINES),
+				tail(stdout, OUTPUT_TAIL_LINES)
+			),
+			_ => format!("{err}\n\n{err:?}"),
+		});
+		self.kind = Some(error_kind(err).into());
+		self
+	}
+
+	pub fn log(&mut self, log: &str) -> &mut Self {
+		self.log = Some(log.to_owned());
+		self
+	}
+
+	/// Adds ffprobe's view of the downloaded file, if the failure happened after the download
+	pub async fn probe(&mut self, path: &Path) -> &mut Self {
+		self.probe = Some(match MediaProbe::probe(path, DISCORD_FILE_SIZE_LIMIT).await {
+			Ok(probe) => format!("{probe:#?}"),
+			Err(err) => format!("ffprobe failed: {err}"),
+		});
+		self
+	}
+
+	/// Hash of the config file, to tell whether a config change is to blame without leaking its contents
+	pub async fn config(&mut self, config: &ConfigDaemon) -> &mut Self {
+		self.config_hash = config
+			.dump()
+			.await
+			.ok()
+			.map(|dump| Sha256::digest(dump.as_bytes())[..8].iter().map(|byte| format!("{byte:02x}")).collect());
+		self
+	}
+
+	/// Redacted as configured throughout, yt-dlp's output and the error are full of URLs too
+	pub fn render(&self) -> String {
+		let mut report = format!("Error report {}\nURL: {}\nTime: {}\n", self.id, self.url, crate::unix_now());
+
+		if let Some(config_hash) = &self.config_hash {
+			writeln!(report, "Config: {config_hash}").ok();
+		}
+
+		for (label, value) in &self.details {
+			writeln!(report, "{label}: {value}").ok();
+		}
+
+		if !self.timings.is_empty() {
+			report.push_str("\n=========== timings ===========\n");
+			for (stage, duration) in &self.timings {
+				writeln!(report, "{stage}: {:.2}s", duration.as_secs_f64()).ok();
+			}
+		}
+
+		if let Some(error) = &self.error {
+			write!(report, "\n=========== error ===========\n{error}\n").ok();
+		}
+
+		if let Some(probe) = &self.probe {
+			write!(report, "\n=========== ffprobe ===========\n{probe}\n").ok();
+		}
+
+		if let Some(log) = &self.log {
+			write!(report, "\n=========== yt-dlp log ===========\n{log}\n").ok();
+		}
+
+		privacy::redact(&report).into_owned()
+	}
+
+	/// Writes the report to disk, and uploads it to the admin log channel if there is one
+	pub async fn submit(&self, http: &Http, log_channel_id: Option<ChannelId>) {
+		let report = self.render();
+
+		match self.write(&report).await {
+			Ok(path) => log::info!("Wrote error report {} to {}", self.id, path.display()),
+			Err(err) => log::error!("Failed to write error report {} ({err})", self.id),
+		}
+
+		let Some(log_channel_id) = log_channel_id else {
+			return;
+		};
+
+		let held_back = match &self.kind {
+			Some(kind) => match may_upload(kind) {
+				Some(held_back) => held_back,
+				None => {
+					log::info!("Not uploading error report {}, one for {kind} was uploaded recently", self.id);
+					return;
+				}
+			},
+			None => 0,
+		};
+
+		let mut content = format!("Error report `{}` for <{}>", self.id, privacy::redact(&self.url));
+		if held_back > 0 {
+			write!(
+				content,
+				"\n{held_back} more {} since the last upload are only on disk",
+				self.kind.as_deref().unwrap_or_default()
+			)
+			.ok();
+		}
+
+		if let Err(err) = log_channel_id
+			.send_message(
+				http,
+				CreateMessage::new()
+					.allowed_mentions(CreateAllowedMentions::new())
+					.content(content)
+					.add_file(CreateAttachment::bytes(report, format!("error_report_{}.txt", self.id))),
+			)
+			.await
+		{
+			log::error!("Failed to upload error report {} ({err})", self.id);
+		}
+	}
+
+	async fn write(&self, report: &str) -> Result<PathBuf, std::io::Error> {
+		tokio::fs::create_dir_all(REPORTS_DIR).await?;
+
+		// Prefixed with the time so the oldest sort first
+		let path = Path::new(REPORTS_DIR).join(format!("{}_{}.txt", crate::unix_now(), self.id));
+		tokio::fs::write(&path, report).await?;
+
+		let mut reports = Vec::new();
+		let mut dir = tokio::fs::read_dir(REPORTS_DIR).await?;
+		while let Some(entry) = dir.next_entry().await? {
+			reports.push(entry.path());
+		}
+
+		if reports.len() > MAX_REPORTS {
+			reports.sort();
+			for old in &reports[..reports.len() - MAX_REPORTS] {
+				tokio::fs::remove_file(old).await.ok();
+			}
+		}
+
+		Ok(path)
+	}
+}
+
+/// Deletes reports written before `cutoff`, a unix timestamp
+pub async fn prune(cutoff: u64) -> Result<(), std::io::Error> {
+	let mut dir = match tokio::fs::read_dir(REPORTS_DIR).await {
+		Ok(dir) => dir,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+		Err(err) => return Err(err),
+	};
+
+	while let Some(entry) = dir.next_entry().await? {
+		let written_at = entry
+			.file_name()
+			.to_str()
+			.and_then(|file_name| file_name.split_once('_'))
+			.and_then(|(written_at, _)| written_at.parse::<u64>().ok());
+
+		if written_at.is_some_and(|written_at| written_at < cutoff) {
+			tokio::fs::remove_file(entry.path()).await?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Whether a report of `kind` may be uploaded now, and if so how many were held back since the last one
+fn may_upload(kind: &str) -> Option<usize> {
+	let mut uploaded = UPLOADED.lock();
+
+	match uploaded.get_mut(kind) {
+		Some(last) if last.at.elapsed() < UPLOAD_INTERVAL => {
+			last.held_back += 1;
+			None
+		}
+		Some(last) => {
+			last.at = Instant::now();
+			Some(std::mem::take(&mut last.held_back))
+		}
+		None => {
+			uploaded.insert(
+				kind.into(),
+				LastUpload {
+					at: Instant::now(),
+					held_back: 0,
+				},
+			);
+			Some(0)
+		}
+	}
+}
+
+/// yt-dlp failures are told apart by extractor, anything else by its error variant
+fn error_kind(err: &(dyn std::error::Error + 'static)) -> String {
+	match err.downcast_ref::<DownloadError>() {
+		Some(DownloadError::YtDlp { stderr, .. }) => {
+			let extractor = stderr
+				.lines()
+				.filter_map(|line| line.strip_prefix("ERROR: ["))
+				.find_map(|line| line.split_once(']'))
+				.map(|(extractor, _)| extractor);
+
+			match extractor {
+				Some(extractor) => format!("yt-dlp {extractor} errors"),
+				None => "yt-dlp errors".to_owned(),
+			}
+		}
+
+		Some(DownloadError::Extractor { extractor, .. }) => format!("{extractor} errors"),
+
+		_ => format!(
+			"{} errors",
+			format!("{err:?}").split(|c: char| !c.is_alphanumeric()).next().unwrap_or_default()
+		),
+	}
+}
+
+fn tail(text: &str, lines: usize) -> &str {
+	let Some(skip) = lines.checked_sub(1) else {
+		return "";
+	};
+
+	match text.trim_end().rmatch_indices('\n').nth(skip) {
+		Some((start, _)) => &text[start + 1..],
+		None => text,
+	}
+}
+
+#[test]
+fn error_report() {
+	assert_eq!(tail("a\nb\nc\n", 2), "b\nc\n");
+	assert_eq!(tail("a\nb", 5), "a\nb");
+	assert_eq!(tail("a\nb", 0), "");
+
+	let mut report = ErrorReport::new("https://example.com/video");
+	report.timing("download", Duration::from_millis(1500)).error(&DownloadError::YtDlp {
+		status: std::process::ExitStatus::default(),
+		stderr: (0..100).map(|i| format!("line {i}\n")).collect(),
+		stdout: String::new(),
+	});
+
+	let report = report.render();
+	assert!(report.contains("download: 1.50s"));
+	assert!(report.contains("line 99") && report.contains("line 50\n") && !report.contains("line 49\n"));
+
+	// Told apart by the stage's own error, not the wrapper
+	let mut report = ErrorReport::new("https://example.com/video");
+	report.error(&Error::from(DownloadError::NoOutput));
+	assert_eq!(report.kind.as_deref(), Some("NoOutput errors"));
+}
+
+#[test]
+fn upload_throttling() {
+	let kind = error_kind(&DownloadError::YtDlp {
+		status: std::process::ExitStatus::default(),
+		stderr: "[youtube] abc: Downloading webpage\nERROR: [youtube] abc: Sign in to confirm you're not a bot\n".into(),
+		stdout: String::new(),
+	});
+	assert_eq!(kind, "yt-dlp youtube errors");
+	assert_eq!(error_kind(&DownloadError::NoOutput), "NoOutput errors");
+
+	assert_eq!(may_upload(&kind), Some(0));
+	assert_eq!(may_upload(&kind), None);
+	assert_eq!(may_upload(&kind), None);
+	assert_eq!(may_upload("NoOutput errors"), Some(0));
+
+	UPLOADED.lock().get_mut(kind.as_str()).unwrap().at -= UPLOAD_INTERVAL;
+	assert_eq!(may_upload(&kind), Some(2));
+}
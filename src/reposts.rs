@@ -0,0 +1,89 @@
+use parking_lot::Mutex;
+use serenity::all::{ChannelId, GuildId, MessageId};
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+/// Where a link's media was posted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Upload {
+	pub guild_id: Option<GuildId>,
+	pub channel_id: ChannelId,
+	pub message_id: MessageId,
+}
+impl Upload {
+	pub fn jump_link(&self) -> String {
+		self.message_id.link(self.channel_id, self.guild_id)
+	}
+}
+
+struct RecentUpload {
+	upload: Upload,
+	expires_at: Instant,
+}
+
+/// Media recently posted for links in each channel, so the same link posted again soon after can be pointed at the earlier
+/// upload rather than downloaded and uploaded all over again. Only kept for the guild's `repost_window`
+#[derive(Default)]
+pub struct RecentUploads(Mutex<HashMap<(ChannelId, Box<str>), RecentUpload>>);
+impl RecentUploads {
+	/// The upload for `url` if it was posted in `channel_id` within the window it was inserted with
+	pub fn get(&self, channel_id: ChannelId, url: &str) -> Option<Upload> {
+		let now = Instant::now();
+
+		self.0
+			.lock()
+			.get(&(channel_id, normalize(url).into()))
+			.filter(|recent| recent.expires_at > now)
+			.map(|recent| recent.upload)
+	}
+
+	pub fn insert(&self, channel_id: ChannelId, url: &str, upload: Upload, window: Duration) {
+		let now = Instant::now();
+
+		let mut recent = self.0.lock();
+		recent.retain(|_, recent| recent.expires_at > now);
+		recent.insert(
+			(channel_id, normalize(url).into()),
+			RecentUpload {
+				upload,
+				expires_at: now + window,
+			},
+		);
+	}
+}
+
+impl RecentUploads {
+	/// Forgets uploads in a message that was deleted, so later posts of their links are downloaded again
+	pub fn remove_message(&self, message_id: MessageId) {
+		self.0.lock().retain(|_, recent| recent.upload.message_id != message_id);
+	}
+}
+
+/// The same link shared from an app usually differs only in its tracking parameters
+fn normalize(url: &str) -> String {
+	crate::scrub::clean_url(url).unwrap_or_else(|| url.to_string())
+}
+
+#[test]
+fn recent_uploads() {
+	let recent = RecentUploads::default();
+	let (channel, other_channel) = (ChannelId::new(1), ChannelId::new(2));
+	let upload = Upload {
+		guild_id: Some(GuildId::new(3)),
+		channel_id: channel,
+		message_id: MessageId::new(4),
+	};
+
+	recent.insert(channel, "https://www.tiktok.com/@a/video/1", upload, Duration::from_secs(60));
+	recent.insert(channel, "https://www.tiktok.com/@a/video/2", upload, Duration::ZERO);
+
+	assert_eq!(recent.get(channel, "https://www.tiktok.com/@a/video/1?utm_source=copy"), Some(upload));
+	assert_eq!(recent.get(other_channel, "https://www.tiktok.com/@a/video/1"), None);
+	assert_eq!(recent.get(channel, "https://www.tiktok.com/@a/video/2"), None);
+	assert_eq!(upload.jump_link(), "https://discord.com/channels/3/1/4");
+
+	recent.remove_message(upload.message_id);
+	assert_eq!(recent.get(channel, "https://www.tiktok.com/@a/video/1"), None);
+}
@@ -0,0 +1,194 @@
+use crate::store::JsonStore;
+use serde::{Deserialize, Serialize};
+use serenity::all::{Guild, GuildId, UserId};
+use std::{
+	collections::{BTreeMap, HashSet},
+	path::Path,
+	sync::Arc,
+};
+
+/// A guild the bot is, or was, a member of
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RosterEntry {
+	pub name: Box<str>,
+	pub member_count: u64,
+	pub joined_at: u64,
+	/// `None` while we're still a member
+	pub left_at: Option<u64>,
+	/// From the guild's audit log, if we could see it when we joined
+	pub invited_by: Option<UserId>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Roster(BTreeMap<GuildId, RosterEntry>);
+
+/// Persistent list of the guilds the bots have been added to, kept up to date from the gateway
+#[derive(Clone)]
+pub struct GuildRoster(Arc<JsonStore<Roster>>);
+impl GuildRoster {
+	pub async fn new(path: &Path) -> Result<Self, anyhow::Error> {
+		Ok(Self(Arc::new(JsonStore::open(path.to_owned()).await?)))
+	}
+
+	/// Adds the guild, or refreshes its name and member count if it's already known
+	pub async fn joined(&self, guild: &Guild, invited_by: Option<UserId>) {
+		let now = crate::unix_now();
+
+		if let Err(err) = self
+			.0
+			.update(|roster| {
+				let entry = roster.0.entry(guild.id).or_insert_with(|| RosterEntry {
+					name: Default::default(),
+					member_count: 0,
+					joined_at: now,
+					left_at: None,
+					invited_by: None,
+				});
+
+				// Rejoined after being removed
+				if entry.left_at.take().is_some() {
+					entry.joined_at = now;
+					entry.invited_by = None;
+				}
+
+				entry.name = guild.name.as_str().into();
+				entry.member_count = guild.member_count;
+				entry.invited_by = invited_by.or(entry.invited_by);
+			})
+			.await
+		{
+			log::error!("Failed to persist guild roster ({err})");
+		}
+	}
+
+	/// Sent for every guild we're in when connecting, which refreshes its member count without writing the roster for each one.
+	/// Only guilds we joined while offline, or that were renamed, are written
+	pub async fn connected(&self, guild: &Guild) {
+		let now = crate::unix_now();
+
+		if let Err(err) = self
+			.0
+			.update_if(|roster| {
+				let entry = roster.0.entry(guild.id).or_insert_with(|| RosterEntry {
+					name: Default::default(),
+					member_count: 0,
+					joined_at: now,
+					left_at: None,
+					invited_by: None,
+				});
+
+				let mut changed = false;
+				if entry.left_at.take().is_some() {
+					entry.joined_at = now;
+					entry.invited_by = None;
+					changed = true;
+				}
+				if *entry.name != *guild.name {
+					entry.name = guild.name.as_str().into();
+					changed = true;
+				}
+				entry.member_count = guild.member_count;
+
+				(changed, ())
+			})
+			.await
+		{
+			log::error!("Failed to persist guild roster ({err})");
+		}
+	}
+
+	/// Marks guilds we were removed from while offline as left, given every guild we're in on connecting. Returns how many
+	pub async fn reconcile(&self, guild_ids: &HashSet<GuildId>) -> usize {
+		let now = crate::unix_now();
+
+		match self
+			.0
+			.update_if(|roster| {
+				let mut left = 0;
+				for (_, entry) in roster
+					.0
+					.iter_mut()
+					.filter(|(guild_id, entry)| entry.left_at.is_none() && !guild_ids.contains(guild_id))
+				{
+					entry.left_at = Some(now);
+					left += 1;
+				}
+				(left > 0, left)
+			})
+			.await
+		{
+			Ok(left) => left,
+			Err(err) => {
+				log::error!("Failed to persist guild roster ({err})");
+				0
+			}
+		}
+	}
+
+	/// Marks the guild as left, returning what we knew about it
+	pub async fn left(&self, guild_id: GuildId) -> Option<RosterEntry> {
+		let now = crate::unix_now();
+
+		match self
+			.0
+			.update(|roster| {
+				let entry = roster.0.get_mut(&guild_id)?;
+				entry.left_at = Some(now);
+				Some(entry.clone())
+			})
+			.await
+		{
+			Ok(entry) => entry,
+			Err(err) => {
+				log::error!("Failed to persist guild roster ({err})");
+				None
+			}
+		}
+	}
+
+	/// Forgets guilds we left before `cutoff`, a unix timestamp
+	pub async fn prune(&self, cutoff: u64) {
+		if let Err(err) = self
+			.0
+			.update(|roster| roster.0.retain(|_, entry| entry.left_at.is_none_or(|left_at| left_at >= cutoff)))
+			.await
+		{
+			log::error!("Failed to persist guild roster ({err})");
+		}
+	}
+
+	/// Guilds the user added us to
+	pub async fn invited_by(&self, user_id: UserId) -> BTreeMap<GuildId, RosterEntry> {
+		self.0
+			.read(|roster| {
+				roster
+					.0
+					.iter()
+					.filter(|(_, entry)| entry.invited_by == Some(user_id))
+					.map(|(guild_id, entry)| (*guild_id, entry.clone()))
+					.collect()
+			})
+			.await
+	}
+
+	/// Forgets that the user added us to any guilds, returning how many
+	pub async fn forget_user(&self, user_id: UserId) -> Result<usize, anyhow::Error> {
+		self.0
+			.update(|roster| {
+				roster
+					.0
+					.values_mut()
+					.filter(|entry| entry.invited_by == Some(user_id))
+					.map(|entry| entry.invited_by = None)
+					.count()
+			})
+			.await
+	}
+
+	/// Number of guilds we're currently a member of
+	pub async fn count(&self) -> usize {
+		self.0
+			.read(|roster| roster.0.values().filter(|entry| entry.left_at.is_none()).count())
+			.await
+	}
+}
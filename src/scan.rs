@@ -0,0 +1,100 @@
+use crate::{
+	config::{CompiledConfig, ScanConfig},
+	error::ScanError,
+	phash,
+};
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub enum ScanVerdict {
+	Clean,
+	Flagged(Box<str>),
+}
+
+/// Runs every configured check on a downloaded file before it may be uploaded
+pub async fn screen(config: &CompiledConfig, path: &Path) -> Result<ScanVerdict, ScanError> {
+	if let Some(scan) = &config.scan {
+		if let verdict @ ScanVerdict::Flagged(_) = self::scan(scan, path).await? {
+			return Ok(verdict);
+		}
+	}
+
+	if let Some(blocklist) = &config.media_blocklist {
+		for frame_hash in phash::hash_frames(path).await? {
+			if let Some((hash, distance)) = blocklist
+				.hashes
+				.iter()
+				.map(|hash| (hash, phash::hamming_distance(*hash, frame_hash)))
+				.find(|(_, distance)| *distance <= blocklist.max_distance)
+			{
+				return Ok(ScanVerdict::Flagged(
+					format!("BLOCKLIST MATCH: frame hash {frame_hash:016x} matched blocklisted hash {hash:016x} (distance {distance})").into(),
+				));
+			}
+		}
+	}
+
+	Ok(ScanVerdict::Clean)
+}
+
+async fn scan(config: &ScanConfig, path: &Path) -> Result<ScanVerdict, ScanError> {
+	match config {
+		ScanConfig::Clamav { socket } => {
+			#[cfg(unix)]
+			if socket.starts_with('/') {
+				return clamd_instream(tokio::net::UnixStream::connect(socket).await?, path).await;
+			}
+
+			clamd_instream(tokio::net::TcpStream::connect(socket.as_str()).await?, path).await
+		}
+
+		ScanConfig::Command { command } => {
+			let (program, args) = command.split_first().ok_or(ScanError::EmptyCommand)?;
+
+			let output = tokio::process::Command::new(program).args(args).arg(path).output().await?;
+
+			match output.status.code() {
+				Some(0) => Ok(ScanVerdict::Clean),
+				Some(1) => Ok(ScanVerdict::Flagged(String::from_utf8_lossy(&output.stdout).trim().into())),
+				_ => Err(ScanError::Command {
+					status: output.status,
+					stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+					stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+				}),
+			}
+		}
+	}
+}
+
+/// Streams the file to clamd using the INSTREAM protocol
+async fn clamd_instream(mut stream: impl AsyncRead + AsyncWrite + Unpin, path: &Path) -> Result<ScanVerdict, ScanError> {
+	let mut file = tokio::fs::File::open(path).await?;
+	let mut buf = vec![0; 64 * 1024];
+
+	stream.write_all(b"zINSTREAM\0").await?;
+
+	loop {
+		let n = file.read(&mut buf).await?;
+
+		stream.write_all(&(n as u32).to_be_bytes()).await?;
+
+		if n == 0 {
+			break;
+		}
+
+		stream.write_all(&buf[..n]).await?;
+	}
+
+	let mut response = String::new();
+	stream.read_to_string(&mut response).await?;
+
+	let response = response.trim_end_matches('\0').trim();
+
+	if response.ends_with("OK") {
+		Ok(ScanVerdict::Clean)
+	} else if let Some(signature) = response.strip_suffix("FOUND") {
+		Ok(ScanVerdict::Flagged(signature.trim_start_matches("stream:").trim().into()))
+	} else {
+		Err(ScanError::Clamd(response.into()))
+	}
+}
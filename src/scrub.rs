@@ -0,0 +1,62 @@
+use reqwest::Url;
+
+/// Query parameters that only exist to track who shared a link and where
+const TRACKING_PARAMS: &[&str] = &[
+	"fbclid",
+	"gclid",
+	"igsh",
+	"igshid",
+	"mibextid",
+	"si",
+	"feature",
+	"pp",
+	"s",
+	"ref",
+	"ref_src",
+	"ref_url",
+	"share_id",
+	"share_app_id",
+	"share_item_id",
+	"sender_device",
+	"sender_web_id",
+	"is_from_webapp",
+	"is_copy_url",
+	"web_id",
+	"rdt",
+	"_t",
+	"_r",
+];
+
+/// Strips tracking query parameters from a share link, returning `None` if there was nothing to strip
+pub fn clean_url(url: &str) -> Option<String> {
+	let mut url = Url::parse(url).ok()?;
+
+	let is_tracking = |key: &str| key.starts_with("utm_") || TRACKING_PARAMS.contains(&key);
+
+	let query = url.query_pairs().into_owned().collect::<Vec<_>>();
+	if !query.iter().any(|(key, _)| is_tracking(key)) {
+		return None;
+	}
+
+	let kept = query.into_iter().filter(|(key, _)| !is_tracking(key)).collect::<Vec<_>>();
+	if kept.is_empty() {
+		url.set_query(None);
+	} else {
+		url.query_pairs_mut().clear().extend_pairs(kept);
+	}
+
+	Some(url.into())
+}
+
+#[test]
+fn clean_url_strips_tracking() {
+	assert_eq!(
+		clean_url("https://www.instagram.com/reel/abc123/?igsh=MTc4MmM1YmI2Ng==").as_deref(),
+		Some("https://www.instagram.com/reel/abc123/")
+	);
+	assert_eq!(
+		clean_url("https://youtube.com/watch?v=dQw4w9WgXcQ&si=xyz&utm_source=share").as_deref(),
+		Some("https://youtube.com/watch?v=dQw4w9WgXcQ")
+	);
+	assert_eq!(clean_url("https://x.com/user/status/123"), None);
+}
@@ -0,0 +1,206 @@
+use crate::{
+	config::CompiledConfig,
+	discord::DISCORD_FILE_SIZE_LIMIT,
+	error::DownloadError,
+	ffprobe::MediaProbe,
+	pipeline::Prober,
+	queue::Priority,
+	yt_dlp::{MediaResult, YtDlpDaemon},
+	AppContext,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	fmt,
+	path::Path,
+	time::{Duration, Instant},
+};
+
+/// How long a single site's download and encode can take before it counts as failed
+const CANARY_TIMEOUT: Duration = Duration::from_secs(3 * 60);
+
+/// A short video on each of the major sites, from yt-dlp's own extractor tests so they're unlikely to disappear
+const DEFAULT_CANARIES: &[(&str, &str)] = &[
+	("YouTube", "https://www.youtube.com/watch?v=jNQXAC9IVRw"),
+	("TikTok", "https://www.tiktok.com/@scout2015/video/6718335390845095173"),
+	("Instagram", "https://www.instagram.com/p/aye83DjauH/"),
+	("Twitter", "https://twitter.com/freethenipple/status/643211948184596480"),
+	("Reddit", "https://www.reddit.com/r/videos/comments/6rrwyj/that_small_heart_attack/"),
+	("Twitch", "https://clips.twitch.tv/FaintLightGullWholeWheat"),
+];
+
+/// A link `/admin selftest` and the startup canary download, to catch a site's extractor breaking before users do
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Canary {
+	/// Name shown in the report, e.g. `YouTube`
+	pub site: String,
+	pub url: String,
+}
+impl Canary {
+	pub fn defaults() -> Box<[Self]> {
+		DEFAULT_CANARIES
+			.iter()
+			.map(|(site, url)| Self {
+				site: site.to_string(),
+				url: url.to_string(),
+			})
+			.collect()
+	}
+}
+
+struct SiteResult {
+	site: String,
+	time: Duration,
+	outcome: Result<String, String>,
+}
+
+/// Whether each canary downloaded, through the same pipeline as posted links, into something Discord can play
+pub struct SelfTestReport(Vec<SiteResult>);
+impl SelfTestReport {
+	pub async fn run(yt_dlp: &YtDlpDaemon, config: &CompiledConfig, priority: Priority) -> Self {
+		// Out of the way of real downloads, and of `yt_dlp_out` being cleared on startup
+		let dir = std::env::temp_dir().join(format!("discord-embed-bot-selftest-{}", uuid::Uuid::new_v4().simple()));
+
+		let results = config.canaries.iter().map(|canary| async {
+			let started = Instant::now();
+
+			let outcome = match tokio::time::timeout(CANARY_TIMEOUT, Self::check(yt_dlp, config, &canary.url, &dir, priority)).await {
+				Ok(outcome) => outcome,
+				Err(_) => Err(format!("timed out after {}s", CANARY_TIMEOUT.as_secs())),
+			};
+
+			SiteResult {
+				site: canary.site.clone(),
+				time: started.elapsed(),
+				outcome,
+			}
+		});
+
+		let report = Self(serenity::futures::future::join_all(results).await);
+
+		tokio::fs::remove_dir_all(&dir).await.ok();
+
+		report
+	}
+
+	async fn check(yt_dlp: &YtDlpDaemon, config: &CompiledConfig, url: &str, dir: &Path, priority: Priority) -> Result<String, String> {
+		let mut options = config.download_options(url);
+		options.size_limit = Some(DISCORD_FILE_SIZE_LIMIT);
+		options.priority = priority;
+
+		let media = yt_dlp.download_in(url, dir, &options).await.map_err(|err| failure_summary(&err))?;
+
+		// Deleted along with `media`
+		Self::verify(&media, options.size_limit()).await
+	}
+
+	async fn verify(media: &MediaResult, size_limit: u64) -> Result<String, String> {
+		let size = tokio::fs::metadata(&media.path).await.map_err(|err| err.to_string())?.len();
+
+		match MediaProbe::probe(&media.path, size_limit).await.map_err(|err| err.to_string())? {
+			MediaProbe::Probed {
+				is_discord_compatible: true,
+				video_codec,
+				audio_codec,
+				..
+			} => Ok(format!(
+				"{:.1} MB {}/{} via {}",
+				size as f64 / (1024.0 * 1024.0),
+				video_codec.as_deref().unwrap_or("none"),
+				audio_codec.as_deref().unwrap_or("none"),
+				media.extractor
+			)),
+
+			MediaProbe::Probed {
+				video_codec, audio_codec, ..
+			} => Err(format!(
+				"downloaded, but Discord can't play it ({:.1} MB {}/{})",
+				size as f64 / (1024.0 * 1024.0),
+				video_codec.as_deref().unwrap_or("none"),
+				audio_codec.as_deref().unwrap_or("none")
+			)),
+
+			MediaProbe::Corrupt => Err("downloaded, but the file is corrupt".to_string()),
+		}
+	}
+
+	pub fn failed(&self) -> usize {
+		self.0.iter().filter(|result| result.outcome.is_err()).count()
+	}
+}
+impl fmt::Display for SelfTestReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for result in &self.0 {
+			let (status, detail) = match &result.outcome {
+				Ok(detail) => ('✅', detail),
+				Err(detail) => ('❌', detail),
+			};
+			writeln!(f, "{status} {} ({:.1}s): {detail}", result.site, result.time.as_secs_f64())?;
+		}
+		Ok(())
+	}
+}
+
+/// The line of a failure that says what went wrong: yt-dlp's `ERROR:` line rather than its exit status, otherwise the first line
+fn failure_summary(err: &DownloadError) -> String {
+	match err {
+		DownloadError::Shared(err) => failure_summary(err),
+		DownloadError::YtDlp { stderr, .. } => match stderr.lines().find(|line| line.starts_with("ERROR:")) {
+			Some(line) => line.to_string(),
+			None => err.to_string().lines().next().unwrap_or_default().to_string(),
+		},
+		_ => err.to_string().lines().next().unwrap_or_default().to_string(),
+	}
+}
+
+/// Runs the self test in the background after startup, if the config asks for it, and logs any sites that failed
+pub async fn startup_canary(app_ctx: AppContext) {
+	let config = app_ctx.config.get().await;
+	if !config.startup_canary {
+		return;
+	}
+
+	let report = SelfTestReport::run(&app_ctx.yt_dlp, &config, Priority::Passive).await;
+
+	match report.failed() {
+		0 => log::info!("Startup canary passed\n{report}"),
+		failed => log::warn!("Startup canary failed for {failed} of {} sites\n{report}", report.0.len()),
+	}
+}
+
+#[test]
+fn report_format() {
+	let report = SelfTestReport(vec![
+		SiteResult {
+			site: "YouTube".to_string(),
+			time: Duration::from_millis(4210),
+			outcome: Ok("0.7 MB h264/aac via yt-dlp".to_string()),
+		},
+		SiteResult {
+			site: "TikTok".to_string(),
+			time: Duration::from_millis(900),
+			outcome: Err("Unable to extract webpage video data".to_string()),
+		},
+	]);
+
+	assert_eq!(
+		report.to_string(),
+		"✅ YouTube (4.2s): 0.7 MB h264/aac via yt-dlp\n❌ TikTok (0.9s): Unable to extract webpage video data\n"
+	);
+	assert_eq!(report.failed(), 1);
+}
+
+#[test]
+fn yt_dlp_failure_summary() {
+	let err = DownloadError::YtDlp {
+		status: std::process::ExitStatus::default(),
+		stderr: "[debug] Command-line config: []\n[youtube] jNQXAC9IVRw: Downloading webpage\nERROR: [youtube] jNQXAC9IVRw: Sign in to confirm you're not a bot\n"
+			.to_string(),
+		stdout: String::new(),
+	};
+
+	assert_eq!(
+		failure_summary(&DownloadError::Shared(std::sync::Arc::new(err))),
+		"ERROR: [youtube] jNQXAC9IVRw: Sign in to confirm you're not a bot"
+	);
+	assert_eq!(failure_summary(&DownloadError::NoMedia), DownloadError::NoMedia.to_string());
+}
@@ -0,0 +1,254 @@
+//! Running the bot as a managed background service for self-hosters off Linux: a launchd agent on macOS and a Service Control
+//! Manager service on Windows. Linux uses the systemd unit next to the Dockerfile
+
+use std::path::Path;
+
+pub const SERVICE_NAME: &str = "discord-embed-bot";
+
+const LAUNCHD_LABEL: &str = "io.venner.discord-embed-bot";
+
+/// Registers this executable to run as a service from `working_dir` with `args`, returning how to start it
+pub fn install(working_dir: &Path, args: &[String]) -> Result<String, anyhow::Error> {
+	// Anyone who can list services can read their arguments, so the token can't go in them. Services don't inherit the shell's
+	// environment either, so a file is the only other way to hand it over
+	if args.iter().any(|arg| arg == "--discord-bot-token") {
+		return Err(anyhow::anyhow!(
+			"refusing to store the bot token in the service definition, save it to a file and pass it with --discord-bot-token-path instead"
+		));
+	}
+
+	let exe = std::env::current_exe()?;
+
+	let mut service_args = vec![
+		"--run-as-service".to_string(),
+		"--working-dir".to_string(),
+		working_dir.display().to_string(),
+	];
+
+	// The service manager doesn't start the bot from here, so a relative token path would point somewhere else
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		service_args.push(arg.clone());
+		if arg == "--discord-bot-token-path" {
+			if let Some(path) = args.next() {
+				service_args.push(working_dir.join(path).display().to_string());
+			}
+		}
+	}
+
+	install_service(&exe, working_dir, &service_args)
+}
+
+#[cfg(target_os = "macos")]
+fn install_service(exe: &Path, working_dir: &Path, args: &[String]) -> Result<String, anyhow::Error> {
+	let home = std::env::var_os("HOME").ok_or_else(|| anyhow::anyhow!("HOME isn't set"))?;
+	let plist_path = Path::new(&home).join("Library/LaunchAgents").join(format!("{LAUNCHD_LABEL}.plist"));
+
+	std::fs::create_dir_all(plist_path.parent().unwrap())?;
+	std::fs::write(&plist_path, launchd_plist(exe, working_dir, args))?;
+
+	Ok(format!("Installed {}, start it with `launchctl load -w {0}`", plist_path.display()))
+}
+
+#[cfg(windows)]
+fn install_service(exe: &Path, _working_dir: &Path, args: &[String]) -> Result<String, anyhow::Error> {
+	use windows_service::{
+		service::{ServiceAccess, ServiceErrorControl, ServiceInfo, ServiceStartType, ServiceType},
+		service_manager::{ServiceManager, ServiceManagerAccess},
+	};
+
+	let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE)?;
+
+	let service = manager.create_service(
+		&ServiceInfo {
+			name: SERVICE_NAME.into(),
+			display_name: "Discord Embed Bot".into(),
+			service_type: ServiceType::OWN_PROCESS,
+			start_type: ServiceStartType::AutoStart,
+			error_control: ServiceErrorControl::Normal,
+			executable_path: exe.to_owned(),
+			launch_arguments: args.iter().map(Into::into).collect(),
+			dependencies: vec![],
+			account_name: None,
+			account_password: None,
+		},
+		ServiceAccess::CHANGE_CONFIG,
+	)?;
+	service.set_description(env!("CARGO_PKG_DESCRIPTION"))?;
+
+	Ok(format!("Installed the {SERVICE_NAME} service, start it with `sc start {SERVICE_NAME}`"))
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn install_service(_exe: &Path, _working_dir: &Path, _args: &[String]) -> Result<String, anyhow::Error> {
+	Err(anyhow::anyhow!(
+		"Services are only supported on macOS and Windows, use discord_embed_bot.service with systemd"
+	))
+}
+
+/// A launchd agent that starts the bot at login and restarts it if it exits, logging next to the config
+pub fn launchd_plist(exe: &Path, working_dir: &Path, args: &[String]) -> String {
+	let log_path = working_dir.join(format!("{SERVICE_NAME}.log"));
+
+	let program_arguments = std::iter::once(exe.display().to_string())
+		.chain(args.iter().cloned())
+		.map(|arg| format!("\t\t<string>{}</string>\n", escape_xml(&arg)))
+		.collect::<String>();
+
+	format!(
+		r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>Label</key>
+	<string>{LAUNCHD_LABEL}</string>
+	<key>ProgramArguments</key>
+	<array>
+{program_arguments}	</array>
+	<key>WorkingDirectory</key>
+	<string>{}</string>
+	<key>RunAtLoad</key>
+	<true/>
+	<key>KeepAlive</key>
+	<true/>
+	<key>StandardOutPath</key>
+	<string>{}</string>
+	<key>StandardErrorPath</key>
+	<string>{1}</string>
+</dict>
+</plist>
+"#,
+		escape_xml(&working_dir.display().to_string()),
+		escape_xml(&log_path.display().to_string()),
+	)
+}
+
+fn escape_xml(text: &str) -> String {
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Hands `run` to the Service Control Manager, which calls it on its own thread and resolves its argument when the service is
+/// stopped. Blocks until the service has stopped
+#[cfg(windows)]
+pub fn run_windows_service<F, Fut>(run: F) -> Result<(), anyhow::Error>
+where
+	F: FnOnce(tokio::sync::oneshot::Receiver<()>) -> Fut + Send + 'static,
+	Fut: std::future::Future<Output = Result<(), anyhow::Error>> + Send + 'static,
+{
+	windows::run(run)
+}
+
+#[cfg(windows)]
+mod windows {
+	use super::SERVICE_NAME;
+	use std::{ffi::OsString, future::Future, pin::Pin, sync::Mutex, time::Duration};
+	use tokio::sync::oneshot;
+	use windows_service::{
+		define_windows_service,
+		service::{ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType},
+		service_control_handler::{self, ServiceControlHandlerResult},
+		service_dispatcher,
+	};
+
+	type ServiceRun = Box<dyn FnOnce(oneshot::Receiver<()>) -> Pin<Box<dyn Future<Output = Result<(), anyhow::Error>> + Send>> + Send>;
+
+	/// What the service runs, and the runtime to run it on, for the dispatcher's thread to pick up
+	static SERVICE: Mutex<Option<(tokio::runtime::Handle, ServiceRun)>> = Mutex::new(None);
+
+	define_windows_service!(ffi_service_main, service_main);
+
+	pub fn run<F, Fut>(run: F) -> Result<(), anyhow::Error>
+	where
+		F: FnOnce(oneshot::Receiver<()>) -> Fut + Send + 'static,
+		Fut: Future<Output = Result<(), anyhow::Error>> + Send + 'static,
+	{
+		*SERVICE.lock().unwrap() = Some((tokio::runtime::Handle::current(), Box::new(move |shutdown| Box::pin(run(shutdown)))));
+
+		tokio::task::block_in_place(|| service_dispatcher::start(SERVICE_NAME, ffi_service_main))?;
+
+		Ok(())
+	}
+
+	fn service_main(_args: Vec<OsString>) {
+		let Some((runtime, run)) = SERVICE.lock().unwrap().take() else {
+			return;
+		};
+
+		let (shutdown_tx, shutdown_rx) = oneshot::channel();
+		let mut shutdown_tx = Some(shutdown_tx);
+
+		let status_handle = service_control_handler::register(SERVICE_NAME, move |control| match control {
+			ServiceControl::Stop | ServiceControl::Shutdown => {
+				if let Some(shutdown_tx) = shutdown_tx.take() {
+					shutdown_tx.send(()).ok();
+				}
+				ServiceControlHandlerResult::NoError
+			}
+			ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+			_ => ServiceControlHandlerResult::NotImplemented,
+		});
+
+		let status_handle = match status_handle {
+			Ok(status_handle) => status_handle,
+			Err(err) => {
+				log::error!("Failed to register the service control handler ({err})");
+				return;
+			}
+		};
+
+		let status = |current_state, controls_accepted, exit_code| ServiceStatus {
+			service_type: ServiceType::OWN_PROCESS,
+			current_state,
+			controls_accepted,
+			exit_code,
+			checkpoint: 0,
+			wait_hint: Duration::default(),
+			process_id: None,
+		};
+
+		status_handle
+			.set_service_status(status(
+				ServiceState::Running,
+				ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+				ServiceExitCode::Win32(0),
+			))
+			.ok();
+
+		let exit_code = match runtime.block_on(run(shutdown_rx)) {
+			Ok(()) => 0,
+			Err(err) => {
+				log::error!("Service stopped with an error ({err})");
+				1
+			}
+		};
+
+		status_handle
+			.set_service_status(status(
+				ServiceState::Stopped,
+				ServiceControlAccept::empty(),
+				ServiceExitCode::Win32(exit_code),
+			))
+			.ok();
+	}
+}
+
+#[test]
+fn install_refuses_token() {
+	let err = install(Path::new("/opt/bot"), &["--discord-bot-token".to_string(), "secret".to_string()]).unwrap_err();
+	assert!(err.to_string().contains("refusing to store the bot token"));
+}
+
+#[test]
+fn launchd_plist_arguments() {
+	let plist = launchd_plist(
+		Path::new("/opt/bot/discord-embed-bot"),
+		Path::new("/opt/bot"),
+		&["--run-as-service".to_string(), "--config-path".to_string(), "a&b.json".to_string()],
+	);
+
+	assert!(plist.contains(
+		"\t\t<string>/opt/bot/discord-embed-bot</string>\n\t\t<string>--run-as-service</string>\n\t\t<string>--config-path</string>\n\t\t<string>a&amp;b.json</string>\n\t</array>"
+	));
+	assert!(plist.contains("<key>WorkingDirectory</key>\n\t<string>/opt/bot</string>"));
+	assert!(plist.contains("<string>/opt/bot/discord-embed-bot.log</string>"));
+}
@@ -0,0 +1,141 @@
+use crate::config::{AdminGuild, Config};
+use std::{
+	io::{BufRead, IsTerminal, Write},
+	path::Path,
+	str::FromStr,
+};
+
+/// Whether to run the setup wizard without being asked to, i.e. this is a fresh install and someone is there to answer
+pub fn needed(config_path: &Path) -> bool {
+	is_empty(config_path) && std::io::stdin().is_terminal()
+}
+
+fn is_empty(path: &Path) -> bool {
+	std::fs::metadata(path).map(|metadata| metadata.len() == 0).unwrap_or(true)
+}
+
+/// Interactive first-run setup, writes the config file and (if it wasn't already provided) the Discord bot token file.
+/// Returns the entered token, if any
+pub fn run(config_path: &Path, discord_bot_token_path: &Path, ask_token: bool) -> Result<Option<String>, anyhow::Error> {
+	println!("discord-embed-bot setup");
+	println!("Leave a question blank to skip it.\n");
+
+	if !is_empty(config_path) && !ask_yes_no(&format!("{} already exists, overwrite it?", config_path.display()), false)? {
+		return Ok(None);
+	}
+
+	let discord_bot_token = if ask_token {
+		Some(ask_with("Discord bot token", |token| {
+			if token.is_empty() {
+				Err("A token is required")
+			} else {
+				Ok(token.to_string())
+			}
+		})?)
+	} else {
+		None
+	};
+
+	let mut config = Config::default();
+
+	let guild_id = ask_optional_id("Admin guild ID")?;
+	if let Some(guild_id) = guild_id {
+		config.admin_guild = Some(AdminGuild {
+			guild_id,
+			log_channel_id: ask_id("Admin log channel ID")?,
+			config_channel_id: ask_id("Admin config channel ID")?,
+		});
+	}
+
+	config.use_builtin_regexes = ask_yes_no(
+		"Enable the builtin link regexes (TikTok, Instagram, Twitter/X, Reddit, YouTube Shorts, Facebook)?",
+		true,
+	)?;
+
+	if let Some(dir) = config_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+		std::fs::create_dir_all(dir)?;
+	}
+
+	std::fs::write(config_path, serde_json::to_string_pretty(&config)?)?;
+	println!("Wrote {}", config_path.display());
+
+	if let Some(discord_bot_token) = &discord_bot_token {
+		write_secret(discord_bot_token_path, discord_bot_token)?;
+		println!("Wrote {}", discord_bot_token_path.display());
+	}
+
+	println!();
+
+	Ok(discord_bot_token)
+}
+
+/// Writes a file only its owner can read
+fn write_secret(path: &Path, contents: &str) -> Result<(), std::io::Error> {
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create(true).truncate(true);
+
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+	let mut file = options.open(path)?;
+
+	// The mode only applies to new files
+	#[cfg(unix)]
+	file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+
+	file.write_all(contents.as_bytes())
+}
+
+fn ask(question: &str) -> Result<String, anyhow::Error> {
+	print!("{question}: ");
+	std::io::stdout().flush()?;
+
+	let mut answer = String::new();
+	if std::io::stdin().lock().read_line(&mut answer)? == 0 {
+		return Err(anyhow::anyhow!("Setup aborted"));
+	}
+
+	Ok(answer.trim().to_string())
+}
+
+/// Asks until `parse` accepts the answer
+fn ask_with<T>(question: &str, parse: impl Fn(&str) -> Result<T, &'static str>) -> Result<T, anyhow::Error> {
+	loop {
+		match parse(&ask(question)?) {
+			Ok(answer) => return Ok(answer),
+			Err(err) => println!("{err}"),
+		}
+	}
+}
+
+fn ask_id<T: FromStr>(question: &str) -> Result<T, anyhow::Error> {
+	ask_with(question, |answer| {
+		answer
+			.parse()
+			.map_err(|_| "Expected a Discord ID (enable developer mode and right click → Copy ID)")
+	})
+}
+
+fn ask_optional_id<T: FromStr>(question: &str) -> Result<Option<T>, anyhow::Error> {
+	ask_with(question, |answer| {
+		if answer.is_empty() {
+			Ok(None)
+		} else {
+			answer
+				.parse()
+				.map(Some)
+				.map_err(|_| "Expected a Discord ID (enable developer mode and right click → Copy ID)")
+		}
+	})
+}
+
+fn ask_yes_no(question: &str, default: bool) -> Result<bool, anyhow::Error> {
+	let hint = if default { "Y/n" } else { "y/N" };
+
+	ask_with(&format!("{question} [{hint}]"), |answer| match answer.to_ascii_lowercase().as_str() {
+		"" => Ok(default),
+		"y" | "yes" => Ok(true),
+		"n" | "no" => Ok(false),
+		_ => Err("Expected y or n"),
+	})
+}
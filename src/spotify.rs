@@ -0,0 +1,88 @@
+use crate::{error::ExtractError, USER_AGENT};
+use serde::{Deserialize, Serialize};
+
+/// How to get downloadable audio for a Spotify track, which yt-dlp can't download directly
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpotifyResolver {
+	/// Spotify's own 30 second preview
+	#[default]
+	Preview,
+	/// The first YouTube search result for the artist and title
+	YoutubeSearch,
+}
+
+pub struct SpotifyTrack {
+	pub title: Box<str>,
+	pub artist: Option<Box<str>>,
+	preview_url: Option<Box<str>>,
+}
+impl SpotifyTrack {
+	/// URL to hand to yt-dlp in place of the Spotify link, if there's anything to download
+	pub fn resolve(&self, resolver: SpotifyResolver) -> Option<String> {
+		match resolver {
+			SpotifyResolver::Preview => self.preview_url.as_deref().map(ToOwned::to_owned),
+
+			SpotifyResolver::YoutubeSearch => Some(match &self.artist {
+				Some(artist) => format!("ytsearch1:{artist} - {}", self.title),
+				None => format!("ytsearch1:{}", self.title),
+			}),
+		}
+	}
+}
+
+pub fn get_spotify_track_id_from_url(url: &str) -> Option<&str> {
+	Some(
+		regex::RegexBuilder::new(r#"https?://open\.spotify\.com/(?:intl-[\w-]+/)?track/(\w+)"#)
+			.build()
+			.unwrap()
+			.captures(url)?
+			.get(1)
+			.unwrap()
+			.as_str(),
+	)
+}
+
+/// Scrapes track details from the embed player, which unlike the Web API needs no credentials
+pub async fn get_track(client: &reqwest::Client, track_id: &str) -> Result<SpotifyTrack, ExtractError> {
+	let html = client
+		.get(format!("https://open.spotify.com/embed/track/{track_id}"))
+		.header("User-Agent", USER_AGENT)
+		.send()
+		.await?
+		.error_for_status()?
+		.text()
+		.await?;
+
+	let next_data = regex::Regex::new(r#"<script id="__NEXT_DATA__" type="application/json">(.+?)</script>"#)
+		.unwrap()
+		.captures(&html)
+		.and_then(|captures| serde_json::from_str::<serde_json::Value>(captures.get(1)?.as_str()).ok())
+		.ok_or(ExtractError::NotFound("Failed to find Spotify embed data"))?;
+
+	let entity = next_data
+		.pointer("/props/pageProps/state/data/entity")
+		.ok_or(ExtractError::NotFound("Failed to find Spotify track in embed data"))?;
+
+	Ok(SpotifyTrack {
+		title: entity
+			.get("name")
+			.and_then(|name| name.as_str())
+			.ok_or(ExtractError::NotFound("Spotify track has no name"))?
+			.into(),
+		artist: (|| entity.get("artists")?.as_array()?.first()?.get("name")?.as_str())().map(Into::into),
+		preview_url: (|| entity.get("audioPreview")?.get("url")?.as_str())().map(Into::into),
+	})
+}
+
+#[test]
+fn spotify_track_id() {
+	assert_eq!(
+		get_spotify_track_id_from_url("https://open.spotify.com/intl-de/track/4cOdK2wGLETKBW3PvgPWqT?si=abc"),
+		Some("4cOdK2wGLETKBW3PvgPWqT")
+	);
+	assert_eq!(
+		get_spotify_track_id_from_url("https://open.spotify.com/album/4cOdK2wGLETKBW3PvgPWqT"),
+		None
+	);
+}
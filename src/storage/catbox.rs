@@ -0,0 +1,75 @@
+use super::{unexpected_response, StorageBackend, STORAGE_HTTP};
+use crate::{error::UploadError, privacy::Secret, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use std::path::Path;
+
+const CATBOX_API: &str = "https://catbox.moe/user/api.php";
+const LITTERBOX_API: &str = "https://litterbox.catbox.moe/resources/internals/api.php";
+
+/// catbox.moe, which keeps files forever, or litterbox.catbox.moe if they should expire
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CatboxConfig {
+	/// Uploads to the catbox.moe account with this hash, anonymous if unset. Not used by litterbox
+	#[serde(default)]
+	pub userhash: Option<Secret>,
+	/// Uploads to litterbox instead, which deletes files after `1h`, `12h`, `24h` or `72h`
+	#[serde(default)]
+	pub expiry: Option<String>,
+}
+
+#[async_trait]
+impl StorageBackend for CatboxConfig {
+	fn name(&self) -> &str {
+		match self.expiry {
+			Some(_) => "litterbox.catbox.moe",
+			None => "catbox.moe",
+		}
+	}
+
+	async fn upload(&self, path: &Path) -> Result<String, UploadError> {
+		let file_name = path
+			.file_name()
+			.map(|file_name| file_name.to_string_lossy().into_owned())
+			.unwrap_or_default();
+
+		let mut form = reqwest::multipart::Form::new().text("reqtype", "fileupload");
+
+		let api = match &self.expiry {
+			Some(expiry) => {
+				form = form.text("time", expiry.clone());
+				LITTERBOX_API
+			}
+			None => {
+				if let Some(userhash) = &self.userhash {
+					form = form.text("userhash", userhash.expose().to_string());
+				}
+				CATBOX_API
+			}
+		};
+
+		let (body, size) = crate::file_body(path).await?;
+		form = form.part(
+			"fileToUpload",
+			reqwest::multipart::Part::stream_with_length(body, size).file_name(file_name),
+		);
+
+		let url = STORAGE_HTTP
+			.post(api)
+			.header("User-Agent", USER_AGENT)
+			.multipart(form)
+			.send()
+			.await?
+			.error_for_status()?
+			.text()
+			.await?;
+
+		// Errors come back as plain text with a 200 too
+		let url = url.trim();
+		if !url.starts_with("https://") {
+			return Err(unexpected_response(api, url));
+		}
+
+		Ok(url.to_string())
+	}
+}
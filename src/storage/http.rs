@@ -0,0 +1,64 @@
+use super::{unexpected_response, StorageBackend, STORAGE_HTTP};
+use crate::{error::UploadError, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use std::{collections::BTreeMap, path::Path};
+
+/// Anything that takes a multipart upload and responds with the file's URL as plain text, e.g.
+/// `https://litterbox.catbox.moe/resources/internals/api.php` or `https://0x0.st`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HttpStorageConfig {
+	pub url: String,
+	/// Name of the multipart field the file is sent in
+	#[serde(default = "HttpStorageConfig::default_field")]
+	pub field: String,
+	/// Extra multipart fields, e.g. `{"reqtype": "fileupload", "time": "72h"}` for litterbox
+	#[serde(default)]
+	pub form: BTreeMap<String, String>,
+}
+impl HttpStorageConfig {
+	fn default_field() -> String {
+		"file".to_string()
+	}
+}
+
+#[async_trait]
+impl StorageBackend for HttpStorageConfig {
+	fn name(&self) -> &str {
+		&self.url
+	}
+
+	async fn upload(&self, path: &Path) -> Result<String, UploadError> {
+		let file_name = path
+			.file_name()
+			.map(|file_name| file_name.to_string_lossy().into_owned())
+			.unwrap_or_default();
+
+		let mut form = reqwest::multipart::Form::new();
+		for (name, value) in &self.form {
+			form = form.text(name.clone(), value.clone());
+		}
+		let (body, size) = crate::file_body(path).await?;
+		form = form.part(
+			self.field.clone(),
+			reqwest::multipart::Part::stream_with_length(body, size).file_name(file_name),
+		);
+
+		let url = STORAGE_HTTP
+			.post(&self.url)
+			.header("User-Agent", USER_AGENT)
+			.multipart(form)
+			.send()
+			.await?
+			.error_for_status()?
+			.text()
+			.await?;
+
+		let url = url.trim();
+		if !url.starts_with("http") {
+			return Err(unexpected_response(&self.url, url));
+		}
+
+		Ok(url.to_string())
+	}
+}
@@ -0,0 +1,74 @@
+//! External file hosts media too large for Discord is uploaded to instead, linked to in the reply
+
+use crate::error::UploadError;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use std::{path::Path, sync::LazyLock};
+
+mod catbox;
+mod http;
+mod s3;
+
+pub use catbox::CatboxConfig;
+pub use http::HttpStorageConfig;
+pub use s3::S3Config;
+
+static STORAGE_HTTP: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+/// Where media too large for Discord is uploaded, picked by `backend`, e.g. `{"backend": "catbox", "expiry": "72h"}`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum StorageConfig {
+	Http(HttpStorageConfig),
+	Catbox(CatboxConfig),
+	S3(S3Config),
+}
+impl StorageConfig {
+	fn backend(&self) -> &dyn StorageBackend {
+		match self {
+			Self::Http(http) => http,
+			Self::Catbox(catbox) => catbox,
+			Self::S3(s3) => s3,
+		}
+	}
+
+	/// The host or bucket, for logs
+	pub fn name(&self) -> &str {
+		self.backend().name()
+	}
+
+	/// Uploads the file and returns its URL
+	pub async fn upload(&self, path: &Path) -> Result<String, UploadError> {
+		self.backend().upload(path).await
+	}
+}
+
+#[async_trait]
+trait StorageBackend: Send + Sync {
+	fn name(&self) -> &str;
+
+	async fn upload(&self, path: &Path) -> Result<String, UploadError>;
+}
+
+fn unexpected_response(host: &str, response: &str) -> UploadError {
+	UploadError::Io(std::io::Error::new(
+		std::io::ErrorKind::InvalidData,
+		format!("Unexpected response from {host}: {response}"),
+	))
+}
+
+#[test]
+fn storage_config() {
+	let config = serde_json::from_str::<StorageConfig>(r#"{"backend": "catbox", "expiry": "72h"}"#).unwrap();
+	assert_eq!(config.name(), "litterbox.catbox.moe");
+
+	let config = serde_json::from_str::<StorageConfig>(r#"{"backend": "http", "url": "https://0x0.st"}"#).unwrap();
+	assert!(matches!(config, StorageConfig::Http(HttpStorageConfig { ref field, .. }) if field == "file"));
+
+	let s3 = r#"{"backend": "s3", "endpoint": "https://example.r2.cloudflarestorage.com", "bucket": "media", "region": "auto",
+		"access_key_id": "id", "secret_access_key": "secret"}"#;
+	let config = serde_json::from_str::<StorageConfig>(s3).unwrap();
+	assert_eq!(config.name(), "media");
+	assert!(!format!("{config:?}").contains("\"secret\""));
+	assert!(!crate::privacy::redact_secrets(s3).contains("\"secret\""));
+}
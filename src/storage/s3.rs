@@ -0,0 +1,247 @@
+use super::{unexpected_response, StorageBackend};
+use crate::{error::UploadError, privacy::Secret};
+#[cfg(feature = "s3-upload")]
+use hmac::{Hmac, Mac};
+#[cfg(feature = "s3-upload")]
+use reqwest::{
+	header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE},
+	Url,
+};
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+#[cfg(feature = "s3-upload")]
+use sha2::{Digest, Sha256};
+use std::path::Path;
+#[cfg(feature = "s3-upload")]
+use time::{macros::format_description, OffsetDateTime};
+
+#[cfg(feature = "s3-upload")]
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// The body is streamed from disk rather than hashed up front, so it's left out of the signature
+#[cfg(feature = "s3-upload")]
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// An S3-compatible bucket, e.g. AWS S3, Cloudflare R2, Backblaze B2 or MinIO. Uploads need to be publicly readable, through the
+/// bucket's policy or `public_url`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(not(feature = "s3-upload"), allow(dead_code))]
+pub struct S3Config {
+	/// e.g. `https://s3.eu-west-2.amazonaws.com` or `https://<account id>.r2.cloudflarestorage.com`
+	pub endpoint: String,
+	pub bucket: String,
+	/// `auto` for R2
+	#[serde(default = "S3Config::default_region")]
+	pub region: String,
+	pub access_key_id: String,
+	pub secret_access_key: Secret,
+	/// Prepended to the name of each upload, e.g. `embeds/`
+	#[serde(default)]
+	pub prefix: String,
+	/// Where uploads are downloaded from instead of the endpoint, e.g. a CDN or R2's public bucket URL. The upload's key is
+	/// appended to it
+	#[serde(default)]
+	pub public_url: Option<String>,
+	/// Address the bucket as `<bucket>.<endpoint host>` rather than `<endpoint>/<bucket>`
+	#[serde(default)]
+	pub virtual_hosted: bool,
+}
+impl S3Config {
+	fn default_region() -> String {
+		"us-east-1".to_string()
+	}
+}
+#[cfg(feature = "s3-upload")]
+impl S3Config {
+	/// `key` must already be encoded, see `encode_key`
+	fn object_url(&self, key: &str) -> Result<Url, UploadError> {
+		let mut url = Url::parse(&self.endpoint).map_err(|err| unexpected_response(&self.endpoint, &err.to_string()))?;
+
+		if self.virtual_hosted {
+			let host = format!("{}.{}", self.bucket, url.host_str().unwrap_or_default());
+			url.set_host(Some(&host))
+				.map_err(|err| unexpected_response(&self.endpoint, &err.to_string()))?;
+			url.set_path(&format!("/{key}"));
+		} else {
+			url.set_path(&format!("/{}/{key}", self.bucket));
+		}
+
+		Ok(url)
+	}
+
+	/// `Authorization` header of an AWS Signature Version 4 signed PUT to `url`
+	fn authorization(&self, url: &Url, amz_date: &str) -> String {
+		let host = match url.port() {
+			Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+			None => url.host_str().unwrap_or_default().to_string(),
+		};
+
+		let canonical_request = format!(
+			"PUT\n{}\n\nhost:{host}\nx-amz-content-sha256:{UNSIGNED_PAYLOAD}\nx-amz-date:{amz_date}\n\n{SIGNED_HEADERS}\n{UNSIGNED_PAYLOAD}",
+			url.path()
+		);
+
+		let date = &amz_date[..8];
+		let scope = format!("{date}/{}/s3/aws4_request", self.region);
+		let string_to_sign = format!("AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}", hex(&Sha256::digest(canonical_request)));
+
+		let signature = hmac_sha256(
+			&signing_key(self.secret_access_key.expose(), date, &self.region, "s3"),
+			string_to_sign.as_bytes(),
+		);
+
+		format!(
+			"AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={SIGNED_HEADERS}, Signature={}",
+			self.access_key_id,
+			hex(&signature)
+		)
+	}
+}
+
+#[async_trait]
+impl StorageBackend for S3Config {
+	fn name(&self) -> &str {
+		&self.bucket
+	}
+
+	#[cfg(feature = "s3-upload")]
+	async fn upload(&self, path: &Path) -> Result<String, UploadError> {
+		let ext = path.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+
+		let key = encode_key(&format!("{}{}.{ext}", self.prefix, uuid::Uuid::new_v4()));
+		let url = self.object_url(&key)?;
+
+		let (body, size) = crate::file_body(path).await?;
+		let amz_date = amz_date(OffsetDateTime::now_utc());
+
+		let response = super::STORAGE_HTTP
+			.put(url.clone())
+			.header("x-amz-date", &amz_date)
+			.header("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+			.header(AUTHORIZATION, self.authorization(&url, &amz_date))
+			// So browsers play it rather than download it
+			.header(CONTENT_TYPE, content_type(&ext))
+			// S3 doesn't take chunked uploads without signing each chunk
+			.header(CONTENT_LENGTH, size)
+			.body(body)
+			.send()
+			.await?;
+
+		if !response.status().is_success() {
+			let status = response.status();
+			let body = response.text().await.unwrap_or_default();
+			return Err(unexpected_response(&self.endpoint, &format!("{status} {body}")));
+		}
+
+		Ok(match &self.public_url {
+			Some(public_url) => format!("{}/{key}", public_url.trim_end_matches('/')),
+			None => url.to_string(),
+		})
+	}
+
+	#[cfg(not(feature = "s3-upload"))]
+	async fn upload(&self, _path: &Path) -> Result<String, UploadError> {
+		Err(unexpected_response(&self.endpoint, "built without the `s3-upload` feature"))
+	}
+}
+
+#[cfg(feature = "s3-upload")]
+fn content_type(ext: &str) -> &'static str {
+	match ext {
+		"mp4" => "video/mp4",
+		"webm" => "video/webm",
+		"mov" => "video/quicktime",
+		"gif" => "image/gif",
+		"mp3" => "audio/mpeg",
+		"m4a" => "audio/mp4",
+		_ => "application/octet-stream",
+	}
+}
+
+/// Percent-encodes everything but unreserved characters and `/`, as S3 expects of the path it signs
+#[cfg(feature = "s3-upload")]
+fn encode_key(key: &str) -> String {
+	let mut encoded = String::with_capacity(key.len());
+	for byte in key.bytes() {
+		match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+			_ => encoded.push_str(&format!("%{byte:02X}")),
+		}
+	}
+	encoded
+}
+
+/// `YYYYMMDDTHHMMSSZ`
+#[cfg(feature = "s3-upload")]
+fn amz_date(time: OffsetDateTime) -> String {
+	time.to_offset(time::UtcOffset::UTC)
+		.format(format_description!("[year][month][day]T[hour][minute][second]Z"))
+		.unwrap()
+}
+
+#[cfg(feature = "s3-upload")]
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> [u8; 32] {
+	let key = hmac_sha256(format!("AWS4{secret_access_key}").as_bytes(), date.as_bytes());
+	let key = hmac_sha256(&key, region.as_bytes());
+	let key = hmac_sha256(&key, service.as_bytes());
+	hmac_sha256(&key, b"aws4_request")
+}
+
+#[cfg(feature = "s3-upload")]
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+	let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+	mac.update(message);
+	mac.finalize().into_bytes().into()
+}
+
+#[cfg(feature = "s3-upload")]
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(feature = "s3-upload")]
+#[test]
+fn s3_signature() {
+	assert_eq!(
+		hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+		"5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+	);
+	assert_eq!(
+		hex(&signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20150830", "us-east-1", "iam")),
+		"c4afb1cc5771d871763a393e44b703571b55cc28424d1a5e86da6ed3c154a4b9"
+	);
+
+	let unix = |secs| OffsetDateTime::from_unix_timestamp(secs).unwrap();
+	assert_eq!(amz_date(unix(1440938160)), "20150830T123600Z");
+	assert_eq!(amz_date(unix(951782400)), "20000229T000000Z");
+
+	assert_eq!(encode_key("embeds/my clip+1.mp4"), "embeds/my%20clip%2B1.mp4");
+
+	let config = S3Config {
+		endpoint: "https://s3.eu-west-2.amazonaws.com".to_string(),
+		bucket: "media".to_string(),
+		region: "eu-west-2".to_string(),
+		access_key_id: "AKIDEXAMPLE".to_string(),
+		secret_access_key: serde_json::from_str(r#""wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY""#).unwrap(),
+		prefix: String::new(),
+		public_url: None,
+		virtual_hosted: false,
+	};
+
+	let url = config.object_url("clip.mp4").unwrap();
+	assert_eq!(url.as_str(), "https://s3.eu-west-2.amazonaws.com/media/clip.mp4");
+
+	let virtual_hosted = S3Config {
+		virtual_hosted: true,
+		..config.clone()
+	};
+	assert_eq!(
+		virtual_hosted.object_url("clip.mp4").unwrap().as_str(),
+		"https://media.s3.eu-west-2.amazonaws.com/clip.mp4"
+	);
+
+	assert_eq!(
+		config.authorization(&url, "20150830T123600Z"),
+		"AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/eu-west-2/s3/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature=12033a5e73aa5f1a87039dfb0dc86f7b87cceaec0e388a322a7f73e445d65b0c"
+	);
+}
@@ -0,0 +1,47 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+
+pub struct JsonStore<T> {
+	path: Box<Path>,
+	data: Mutex<T>,
+}
+impl<T: Serialize + DeserializeOwned + Default> JsonStore<T> {
+	pub async fn open(path: PathBuf) -> Result<Self, anyhow::Error> {
+		let data = match tokio::fs::read(&path).await {
+			Ok(data) if !data.is_empty() => serde_json::from_slice(&data)?,
+			Ok(_) => T::default(),
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => T::default(),
+			Err(err) => return Err(err.into()),
+		};
+
+		Ok(Self {
+			path: path.into_boxed_path(),
+			data: Mutex::new(data),
+		})
+	}
+
+	pub async fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		f(&*self.data.lock().await)
+	}
+
+	pub async fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, anyhow::Error> {
+		self.update_if(|data| (true, f(data))).await
+	}
+
+	/// Like `update`, but only written to disk if `f` says it changed something worth persisting
+	pub async fn update_if<R>(&self, f: impl FnOnce(&mut T) -> (bool, R)) -> Result<R, anyhow::Error> {
+		let mut data = self.data.lock().await;
+
+		let (changed, ret) = f(&mut data);
+
+		if changed {
+			// Write to a temporary file first so a crash mid-write can't corrupt the store
+			let tmp_path = self.path.with_extension("tmp");
+			tokio::fs::write(&tmp_path, serde_json::to_vec(&*data)?).await?;
+			tokio::fs::rename(&tmp_path, &self.path).await?;
+		}
+
+		Ok(ret)
+	}
+}
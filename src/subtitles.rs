@@ -0,0 +1,94 @@
+use crate::{error::EncodeError, process};
+use serde::{Deserialize, Serialize};
+use serenity::all::CreateAttachment;
+use std::path::{Path, PathBuf};
+
+/// English subtitles, falling back to the site's automatic captions, converted to .srt for ffmpeg and Discord's file preview
+pub const YT_DLP_SUBTITLE_ARGS: &[&str] = &["--write-subs", "--write-auto-subs", "--sub-langs", "en.*", "--convert-subs", "srt"];
+
+/// What to do with the subtitles a site has for a video
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubtitleDelivery {
+	/// Re-encode the video with them drawn on
+	Burn,
+	/// Post the .srt alongside the video
+	Attach,
+}
+impl SubtitleDelivery {
+	pub const CHOICES: [(&'static str, Self); 2] = [("burn in", Self::Burn), ("attach .srt", Self::Attach)];
+
+	pub fn from_choice(choice: &str) -> Option<Self> {
+		Self::CHOICES.iter().find(|(name, _)| *name == choice).map(|(_, delivery)| *delivery)
+	}
+}
+
+/// The .srt yt-dlp wrote next to `media_path`, if the site had any subtitles. yt-dlp writes one per matching language, the
+/// plainest one (`en` over `en-GB` or `en-orig`) is kept and the rest deleted
+pub async fn take(media_path: &Path) -> Option<PathBuf> {
+	let dir = media_path.parent()?;
+	let prefix = format!("{}.", media_path.file_stem()?.to_string_lossy());
+
+	let mut found = Vec::new();
+	let mut entries = tokio::fs::read_dir(dir).await.ok()?;
+	while let Ok(Some(entry)) = entries.next_entry().await {
+		let file_name = entry.file_name().to_string_lossy().into_owned();
+		if file_name.starts_with(&prefix) && file_name.ends_with(".srt") {
+			found.push(entry.path());
+		}
+	}
+
+	found.sort_by_key(|path| path.as_os_str().len());
+
+	let mut found = found.into_iter();
+	let srt_path = found.next();
+	for extra in found {
+		tokio::fs::remove_file(extra).await.ok();
+	}
+
+	srt_path
+}
+
+/// The .srt at `srt_path`, named after the video it's posted with so the two are easy to pair up when downloaded
+pub async fn attachment(srt_path: &Path, video_filename: &str) -> Result<CreateAttachment, serenity::Error> {
+	let mut file = CreateAttachment::path(srt_path).await?;
+	file.filename = match video_filename.rsplit_once('.') {
+		Some((stem, _)) => format!("{stem}.srt"),
+		None => format!("{video_filename}.srt"),
+	};
+	Ok(file)
+}
+
+/// Re-encodes the video at `media_path` with the subtitles at `srt_path` burned in, returning the path of the new file
+pub async fn burn(media_path: &Path, srt_path: &Path, threads: usize) -> Result<PathBuf, EncodeError> {
+	let out_path = media_path.with_extension("subtitled.mp4");
+
+	// The subtitles filter parses its argument, so the path needs escaping
+	let srt_path = srt_path.to_string_lossy().replace('\\', "/").replace(':', "\\:").replace('\'', "\\'");
+
+	let output = process::ffmpeg()
+		.arg("-y")
+		.arg("-i")
+		.arg(media_path)
+		.args([
+			"-vf",
+			&format!("subtitles='{srt_path}'"),
+			"-c:a",
+			"copy",
+			"-threads",
+			&threads.to_string(),
+		])
+		.arg(&out_path)
+		.output()
+		.await?;
+
+	if !output.status.success() {
+		tokio::fs::remove_file(&out_path).await.ok();
+		return Err(EncodeError::Failed {
+			status: output.status,
+			stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+		});
+	}
+
+	Ok(out_path)
+}
@@ -0,0 +1,315 @@
+//! Requests to the TikTok web app's API, made to look like they come from the web app in a browser
+
+use super::{SlideshowImage, TikTokRegion};
+use crate::error::ExtractError;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// Sent with API requests and signed into their X-Bogus, which the `browser_version` parameter has to match
+pub const TIKTOK_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/132.0.0.0 Safari/537.36";
+
+impl TikTokRegion {
+	/// e.g. `en-GB`
+	pub fn browser_language(&self) -> String {
+		format!("{}-{}", self.language, self.region)
+	}
+
+	pub fn accept_language(&self) -> String {
+		format!("{},{};q=0.5", self.browser_language(), self.language)
+	}
+}
+
+/// Query of the web app's request for a post's details, `/api/item/detail/`
+pub struct ItemDetailQuery<'a> {
+	host: &'a str,
+	item_id: &'a str,
+	region: &'a TikTokRegion,
+	device_id: u64,
+	odin_id: u64,
+}
+impl<'a> ItemDetailQuery<'a> {
+	/// With a new device and odin ID, so requests don't all look like they're from one (soon blocked) browser
+	pub fn new(item_id: &'a str, region: &'a TikTokRegion) -> Self {
+		Self {
+			host: "https://www.tiktok.com",
+			item_id,
+			region,
+			device_id: random_id(),
+			odin_id: random_id(),
+		}
+	}
+
+	/// Where to send the query instead of TikTok, for replaying recorded responses
+	#[cfg(test)]
+	pub fn host(mut self, host: &'a str) -> Self {
+		self.host = host;
+		self
+	}
+
+	#[cfg(test)]
+	fn device_id(mut self, device_id: u64) -> Self {
+		self.device_id = device_id;
+		self
+	}
+
+	#[cfg(test)]
+	fn odin_id(mut self, odin_id: u64) -> Self {
+		self.odin_id = odin_id;
+		self
+	}
+
+	pub fn url(&self) -> String {
+		let browser_language = self.region.browser_language();
+		let browser_version = TIKTOK_USER_AGENT.trim_start_matches("Mozilla/");
+		let device_id = self.device_id.to_string();
+		let odin_id = self.odin_id.to_string();
+
+		let params: [(&str, Cow<str>); 30] = [
+			("aid", "1988".into()),
+			("app_language", self.region.language.as_str().into()),
+			("app_name", "tiktok_web".into()),
+			("browser_language", browser_language.into()),
+			("browser_name", "Mozilla".into()),
+			("browser_online", "true".into()),
+			("browser_platform", "Win32".into()),
+			("browser_version", browser_version.into()),
+			("channel", "tiktok_web".into()),
+			("cookie_enabled", "false".into()),
+			("coverFormat", "2".into()),
+			("data_collection_enabled", "false".into()),
+			("device_id", device_id.into()),
+			("device_platform", "web_pc".into()),
+			("focus_state", "true".into()),
+			("from_page", "user".into()),
+			("history_len", "2".into()),
+			("is_fullscreen", "false".into()),
+			("is_page_visible", "true".into()),
+			("language", self.region.language.as_str().into()),
+			("odinId", odin_id.into()),
+			("os", "windows".into()),
+			("priority_region", "".into()),
+			("referer", "".into()),
+			("region", self.region.region.as_str().into()),
+			("screen_height", "1314".into()),
+			("screen_width", "2562".into()),
+			("tz_name", self.region.tz_name.as_str().into()),
+			("user_is_login", "false".into()),
+			("webcast_language", self.region.language.as_str().into()),
+		];
+
+		let mut url = format!("{}/api/item/detail/?", self.host);
+		for (name, value) in params {
+			url.push_str(name);
+			url.push('=');
+			url.push_str(&encode_component(&value));
+			url.push('&');
+		}
+		url.push_str("itemId=");
+		url.push_str(&encode_component(self.item_id));
+
+		url
+	}
+}
+
+/// Response to an [`ItemDetailQuery`]. Only what slideshows need is modelled, and nearly all of it is optional, as TikTok changes
+/// the schema without notice and a missing field should mean a missing image rather than a failed download
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemDetail {
+	/// Non-zero for deleted, private and region blocked posts
+	#[serde(default)]
+	pub status_code: i64,
+	pub status_msg: Option<String>,
+	pub item_info: Option<ItemInfo>,
+}
+impl ItemDetail {
+	/// The post's images in order, skipping any with missing fields, and the URL of its sound
+	pub fn slideshow(&self) -> Result<(Vec<SlideshowImage<'_>>, Option<&str>), ExtractError> {
+		if self.status_code != 0 {
+			return Err(ExtractError::TikTokStatus {
+				code: self.status_code,
+				message: self.status_msg.as_deref().unwrap_or("no message").into(),
+			});
+		}
+
+		let item = self
+			.item_info
+			.as_ref()
+			.map(|item_info| &item_info.item_struct)
+			.ok_or(ExtractError::NotFound("Failed to extract images"))?;
+
+		let images = item
+			.image_post
+			.as_ref()
+			.ok_or(ExtractError::NotFound("Failed to extract images"))?
+			.images
+			.iter()
+			.filter_map(|image| {
+				Some(SlideshowImage {
+					width: image.image_width.as_ref()?.get()?,
+					height: image.image_height.as_ref()?.get()?,
+					url: image.image_url.as_ref()?.url_list.first()?,
+				})
+			})
+			.collect();
+
+		let music = item.music.as_ref().and_then(|music| music.play_url.as_ref()?.get());
+
+		Ok((images, music))
+	}
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemInfo {
+	pub item_struct: ItemStruct,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemStruct {
+	/// Only on photo posts
+	pub image_post: Option<ImagePost>,
+	pub music: Option<Music>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ImagePost {
+	#[serde(default)]
+	pub images: Vec<Image>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Image {
+	pub image_width: Option<Dimension>,
+	pub image_height: Option<Dimension>,
+	#[serde(rename = "imageURL")]
+	pub image_url: Option<UrlList>,
+}
+
+/// Pixels, which some responses quote as strings
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Dimension {
+	Number(u64),
+	String(String),
+}
+impl Dimension {
+	pub fn get(&self) -> Option<u64> {
+		match self {
+			Self::Number(pixels) => Some(*pixels),
+			Self::String(pixels) => pixels.parse().ok(),
+		}
+	}
+}
+
+/// Mirrors of the same file, best first
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UrlList {
+	#[serde(default)]
+	pub url_list: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Music {
+	pub play_url: Option<PlayUrl>,
+}
+
+/// The web API gives the sound's URL, the app API (and sometimes the web one) a list of mirrors
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum PlayUrl {
+	Url(String),
+	UrlList(UrlList),
+}
+impl PlayUrl {
+	pub fn get(&self) -> Option<&str> {
+		match self {
+			Self::Url(url) => Some(url.as_str()).filter(|url| !url.is_empty()),
+			Self::UrlList(url_list) => url_list.url_list.first().map(String::as_str),
+		}
+	}
+}
+
+/// 19 digits starting with 7, like the IDs the web app is given
+fn random_id() -> u64 {
+	7_000_000_000_000_000_000 + (uuid::Uuid::new_v4().as_u128() % 1_000_000_000_000_000_000) as u64
+}
+
+/// Like the browser encodes query parameters, which the X-Bogus is signed over
+fn encode_component(value: &str) -> String {
+	let mut encoded = String::with_capacity(value.len());
+	for byte in value.bytes() {
+		match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'!' | b'~' | b'*' | b'\'' | b'(' | b')' | b',' => {
+				encoded.push(byte as char)
+			}
+			_ => encoded.push_str(&format!("%{byte:02X}")),
+		}
+	}
+	encoded
+}
+
+#[test]
+fn item_detail_query() {
+	let region = TikTokRegion::default();
+
+	assert_eq!(
+		ItemDetailQuery::new("7460552162892860718", &region)
+			.device_id(7461615928682841622)
+			.odin_id(7461615911201063958)
+			.url(),
+		"https://www.tiktok.com/api/item/detail/?aid=1988&app_language=en&app_name=tiktok_web&browser_language=en-GB&browser_name=Mozilla&browser_online=true&browser_platform=Win32&browser_version=5.0%20(Windows%20NT%2010.0%3B%20Win64%3B%20x64)%20AppleWebKit%2F537.36%20(KHTML,%20like%20Gecko)%20Chrome%2F132.0.0.0%20Safari%2F537.36&channel=tiktok_web&cookie_enabled=false&coverFormat=2&data_collection_enabled=false&device_id=7461615928682841622&device_platform=web_pc&focus_state=true&from_page=user&history_len=2&is_fullscreen=false&is_page_visible=true&language=en&odinId=7461615911201063958&os=windows&priority_region=&referer=&region=GB&screen_height=1314&screen_width=2562&tz_name=Europe%2FLondon&user_is_login=false&webcast_language=en&itemId=7460552162892860718"
+	);
+
+	let region = TikTokRegion {
+		region: "US".to_string(),
+		tz_name: "America/New_York".to_string(),
+		language: "en".to_string(),
+	};
+	let url = ItemDetailQuery::new("1", &region).url();
+	assert!(url.contains("&browser_language=en-US&"));
+	assert!(url.contains("&region=US&"));
+	assert!(url.contains("&tz_name=America%2FNew_York&"));
+	assert_eq!(region.accept_language(), "en-US,en;q=0.5");
+
+	let (a, b) = (ItemDetailQuery::new("1", &region), ItemDetailQuery::new("1", &region));
+	assert_ne!(a.device_id, b.device_id);
+	assert!((7_000_000_000_000_000_000..8_000_000_000_000_000_000).contains(&a.odin_id));
+}
+
+#[test]
+fn item_detail_fixtures() {
+	let detail = serde_json::from_str::<ItemDetail>(include_str!("fixtures/photo_post.json")).unwrap();
+	let (images, music) = detail.slideshow().unwrap();
+	assert_eq!(
+		images.iter().map(|image| (image.width, image.height)).collect::<Vec<_>>(),
+		[(1080, 1440), (1080, 1920), (1440, 1080)]
+	);
+	assert!(images[0].url.starts_with("https://p16-sign-va.tiktokcdn.com/tos-maliva-i-photomode-us/"));
+	assert_eq!(
+		music,
+		Some("https://sf16-ies-music-va.tiktokcdn.com/obj/musically-maliva-obj/7460552178532789035.mp3")
+	);
+
+	// Dimensions as strings, the sound as a list of mirrors and an image without any URLs
+	let detail = serde_json::from_str::<ItemDetail>(include_str!("fixtures/photo_post_drifted.json")).unwrap();
+	let (images, music) = detail.slideshow().unwrap();
+	assert_eq!(images.iter().map(|image| (image.width, image.height)).collect::<Vec<_>>(), [(1080, 1350)]);
+	assert_eq!(
+		music,
+		Some("https://sf77-ies-music-va.tiktokcdn.com/obj/tos-useast2a-ve-2774/oQfCEAgDeAUIUlEkICDhIbfZSAqFQQZtBAAe8")
+	);
+
+	let detail = serde_json::from_str::<ItemDetail>(include_str!("fixtures/video_post.json")).unwrap();
+	assert_eq!(detail.slideshow().unwrap_err().to_string(), "Failed to extract images");
+
+	let detail = serde_json::from_str::<ItemDetail>(include_str!("fixtures/unavailable.json")).unwrap();
+	assert_eq!(
+		detail.slideshow().unwrap_err().to_string(),
+		"TikTok API returned status 10204 (item doesn't exist)"
+	);
+}
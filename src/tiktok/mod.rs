@@ -1,95 +1,260 @@
-use std::path::{Path, PathBuf};
+use crate::{
+	error::{EncodeError, ExtractError},
+	process,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+};
 use tokio::{io::AsyncWriteExt, process::Command};
 
+#[cfg(feature = "tiktok-photos")]
+mod api;
+
 // TODO for slideshows with one image, just output the image
 
-struct SlideshowImage<'a> {
-	url: &'a str,
-	width: u64,
-	height: u64,
+/// How long each image is shown for
+const SLIDE_SECONDS: f64 = 2.5;
+
+/// Stills compress well, so there's no point going past this even when the size limit allows it
+const MAX_SLIDESHOW_VIDEO_KBPS: u64 = 6000;
+const MIN_SLIDESHOW_VIDEO_KBPS: u64 = 300;
+const SLIDESHOW_AUDIO_KBPS: u64 = 128;
+
+#[derive(Debug)]
+pub struct SlideshowImage<'a> {
+	pub url: &'a str,
+	pub width: u64,
+	pub height: u64,
+}
+
+/// Largest canvas a slideshow is rendered at. Applies in either orientation, so 1080x1920 also caps landscape slideshows to
+/// 1920x1080
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxResolution {
+	pub width: u64,
+	pub height: u64,
+}
+impl Default for MaxResolution {
+	fn default() -> Self {
+		Self { width: 1080, height: 1920 }
+	}
+}
+
+/// Where API requests claim to come from. Best kept to where the bot's traffic leaves from, as TikTok blocks requests that
+/// don't add up
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct TikTokRegion {
+	/// ISO 3166 country code, e.g. `US`
+	pub region: String,
+	/// IANA time zone, e.g. `America/New_York`
+	pub tz_name: String,
+	/// ISO 639 language code, e.g. `en`
+	pub language: String,
+}
+impl Default for TikTokRegion {
+	fn default() -> Self {
+		Self {
+			region: "GB".to_string(),
+			tz_name: "Europe/London".to_string(),
+			language: "en".to_string(),
+		}
+	}
+}
+/// Which corner of the slideshow the page indicator sits in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum IndicatorPosition {
+	TopLeft,
+	#[default]
+	TopRight,
+	BottomLeft,
+	BottomRight,
 }
 
-pub async fn extract_slideshow_images(photo_id: &str, out: &Path) -> Result<PathBuf, anyhow::Error> {
-	let api_url = format!("https://www.tiktok.com/api/item/detail/?aid=1988&app_language=en&app_name=tiktok_web&browser_language=en-GB&browser_name=Mozilla&browser_online=true&browser_platform=Win32&browser_version=5.0%20(Windows%20NT%2010.0%3B%20Win64%3B%20x64)%20AppleWebKit%2F537.36%20(KHTML,%20like%20Gecko)%20Chrome%2F132.0.0.0%20Safari%2F537.36&channel=tiktok_web&cookie_enabled=false&coverFormat=2&data_collection_enabled=false&device_id=7461615928682841622&device_platform=web_pc&focus_state=true&from_page=user&history_len=2&is_fullscreen=false&is_page_visible=true&language=en&odinId=7461615911201063958&os=windows&priority_region=&referer=&region=GB&screen_height=1314&screen_width=2562&tz_name=Europe%2FLondon&user_is_login=false&webcast_language=en&itemId={}", photo_id);
+/// Numbers each slide (`3/7`), so viewers know how many there are
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PageIndicator {
+	#[serde(default)]
+	pub position: IndicatorPosition,
+	/// In pixels, scaled to the slideshow's height if unset
+	#[serde(default)]
+	pub font_size: Option<u64>,
+	/// ffmpeg color, e.g. `white` or `#ffcc00`
+	#[serde(default = "PageIndicator::default_font_color")]
+	pub font_color: String,
+	/// Color of the box behind the text, e.g. `black@0.5` for half transparent black. No box if unset
+	#[serde(default = "PageIndicator::default_box_color")]
+	pub box_color: Option<String>,
+	/// Font file to draw with, fontconfig's default sans-serif font if unset
+	#[serde(default)]
+	pub font_file: Option<String>,
+}
+impl PageIndicator {
+	fn default_font_color() -> String {
+		"white".to_string()
+	}
 
-	let xbogus = {
-		let mut node = Command::new("node")
-			.arg("-")
-			.stdin(std::process::Stdio::piped())
-			.stdout(std::process::Stdio::piped())
-			.stderr(std::process::Stdio::piped())
-			.spawn()?;
+	fn default_box_color() -> Option<String> {
+		Some("black@0.5".to_string())
+	}
 
-		node.stdin
-			.take()
-			.unwrap()
-			.write_all(
-				format!(r#"console.log(require('xbogus')({:?}, 'Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/132.0.0.0 Safari/537.36'));"#,
-					api_url
-				)
-				.as_bytes(),
-			)
-			.await?;
-
-		let output = node.wait_with_output().await?;
-		if !output.status.success() {
-			return Err(anyhow::anyhow!(
-				"Exit status: {}\n\n=========== stderr ===========\n{}\n\n=========== stdout ===========\n{}",
-				output.status,
-				String::from_utf8_lossy(&output.stderr),
-				String::from_utf8_lossy(&output.stdout)
+	/// drawtext filter counting up a slide every `SLIDE_SECONDS`, for a `height` pixel tall canvas
+	fn drawtext_filter(&self, image_count: usize, height: u64) -> String {
+		let font_size = self.font_size.unwrap_or(height / 24).max(8);
+		let margin = font_size / 2;
+
+		let (x, y) = match self.position {
+			IndicatorPosition::TopLeft => (format!("{margin}"), format!("{margin}")),
+			IndicatorPosition::TopRight => (format!("w-tw-{margin}"), format!("{margin}")),
+			IndicatorPosition::BottomLeft => (format!("{margin}"), format!("h-th-{margin}")),
+			IndicatorPosition::BottomRight => (format!("w-tw-{margin}"), format!("h-th-{margin}")),
+		};
+
+		let mut filter = format!(
+			"drawtext=text='%{{eif\\:trunc(t/{SLIDE_SECONDS})+1\\:d}}/{image_count}':x={x}:y={y}:fontsize={font_size}:fontcolor={}",
+			escape_filter_value(&self.font_color)
+		);
+
+		if let Some(box_color) = &self.box_color {
+			filter.push_str(&format!(
+				":box=1:boxcolor={}:boxborderw={}",
+				escape_filter_value(box_color),
+				(margin / 2).max(1)
 			));
 		}
 
-		String::from_utf8_lossy(&output.stdout).trim().to_string()
+		if let Some(font_file) = &self.font_file {
+			filter.push_str(&format!(":fontfile='{}'", escape_filter_value(font_file)));
+		}
+
+		filter
+	}
+}
+
+/// Escapes a value from the config for use as a filter option
+fn escape_filter_value(value: &str) -> String {
+	value.replace('\\', "/").replace(':', "\\:").replace('\'', "\\'").replace(',', "\\,")
+}
+
+/// How large a slideshow video can get, and what's drawn over it
+#[derive(Debug, Clone, Default)]
+pub struct SlideshowOptions {
+	pub max_resolution: MaxResolution,
+	/// Upload limit the video has to fit in
+	pub size_limit: u64,
+	pub page_indicator: Option<Arc<PageIndicator>>,
+}
+
+/// Fits every image, capped to `max` with the aspect ratio kept. Always even, which yuv420p needs
+fn canvas_size(images: &[SlideshowImage<'_>], max: MaxResolution) -> (u64, u64) {
+	let (w, h) = images.iter().fold((0, 0), |(w, h), image| (w.max(image.width), h.max(image.height)));
+
+	let (long, short) = (max.width.max(max.height), max.width.min(max.height));
+	let (max_w, max_h) = match w > h {
+		true => (long, short),
+		false => (short, long),
 	};
 
-	let api_data = tiktok_http_get(&format!("{api_url}&X-Bogus={xbogus}"))
-		.send()
-		.await?
-		.json::<serde_json::Value>()
-		.await?;
+	let scale = f64::min(1.0, f64::min(max_w as f64 / w as f64, max_h as f64 / h as f64));
+	let fit = |size: u64| ((size as f64 * scale) as u64 / 2 * 2).max(2);
 
-	let images = (|| api_data.get("itemInfo")?.get("itemStruct")?.get("imagePost")?.get("images")?.as_array())()
-		.ok_or_else(|| anyhow::anyhow!("Failed to extract images"))?
-		.iter()
-		.filter_map(|image| {
-			Some(SlideshowImage {
-				width: image.get("imageWidth")?.as_u64()?,
-				height: image.get("imageHeight")?.as_u64()?,
-				url: image.get("imageURL")?.get("urlList")?.as_array()?.first()?.as_str()?,
-			})
-		})
-		.collect::<Vec<_>>();
+	(fit(w), fit(h))
+}
 
-	let music = (|| api_data.get("itemInfo")?.get("itemStruct")?.get("music")?.get("playUrl")?.as_str())();
+/// Video bitrate that fits `image_count` slides and their audio in `size_limit`, leaving some room for the container
+fn video_bitrate_kbps(image_count: usize, size_limit: u64) -> u64 {
+	let duration = image_count as f64 * SLIDE_SECONDS;
+	let total_kbps = (size_limit as f64 * 0.9 * 8.0 / 1000.0 / duration) as u64;
+
+	total_kbps
+		.saturating_sub(SLIDESHOW_AUDIO_KBPS)
+		.clamp(MIN_SLIDESHOW_VIDEO_KBPS, MAX_SLIDESHOW_VIDEO_KBPS)
+}
+
+#[cfg(feature = "tiktok-photos")]
+pub async fn extract_slideshow_images(
+	photo_id: &str,
+	out: &Path,
+	region: &TikTokRegion,
+	options: &SlideshowOptions,
+) -> Result<PathBuf, ExtractError> {
+	let api_url = api::ItemDetailQuery::new(photo_id, region).url();
+
+	let detail = fetch_item_detail(&sign(&api_url).await?, region).await?;
+	let (images, music) = detail.slideshow()?;
 
 	if images.is_empty() {
-		return Err(anyhow::anyhow!("No images found"));
+		return Err(ExtractError::NotFound("No images found"));
 	}
 
 	let out = out.with_extension("mp4");
 
-	generate_slideshow_video(&out, &images, music).await?;
+	generate_slideshow_video(&out, &images, music, options).await?;
 
 	if !Path::new(&out).is_file() {
-		return Err(anyhow::anyhow!("Failed to generate slideshow - file was not created"));
+		return Err(ExtractError::NotFound("Failed to generate slideshow - file was not created"));
 	}
 
 	Ok(out)
 }
 
-fn tiktok_http_get(url: &str) -> reqwest::RequestBuilder {
+#[cfg(not(feature = "tiktok-photos"))]
+pub async fn extract_slideshow_images(
+	_photo_id: &str,
+	_out: &Path,
+	_region: &TikTokRegion,
+	_options: &SlideshowOptions,
+) -> Result<PathBuf, ExtractError> {
+	Err(ExtractError::Disabled("tiktok-photos"))
+}
+
+/// `api_url` with the X-Bogus signature TikTok checks API requests for, which is generated by the `xbogus` npm package
+#[cfg(feature = "tiktok-photos")]
+async fn sign(api_url: &str) -> Result<String, ExtractError> {
+	let mut node = Command::new("node")
+		.arg("-")
+		.stdin(std::process::Stdio::piped())
+		.stdout(std::process::Stdio::piped())
+		.stderr(std::process::Stdio::piped())
+		.kill_on_drop(true)
+		.spawn()?;
+
+	node.stdin
+		.take()
+		.unwrap()
+		.write_all(format!(r#"console.log(require('xbogus')({:?}, {:?}));"#, api_url, api::TIKTOK_USER_AGENT).as_bytes())
+		.await?;
+
+	let output = node.wait_with_output().await?;
+	if !output.status.success() {
+		return Err(ExtractError::Script {
+			status: output.status,
+			stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+			stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+		});
+	}
+
+	let xbogus = String::from_utf8_lossy(&output.stdout);
+	Ok(format!("{api_url}&X-Bogus={}", xbogus.trim()))
+}
+
+#[cfg(feature = "tiktok-photos")]
+async fn fetch_item_detail(signed_url: &str, region: &TikTokRegion) -> Result<api::ItemDetail, ExtractError> {
+	Ok(tiktok_http_get(signed_url, region).send().await?.json::<api::ItemDetail>().await?)
+}
+
+#[cfg(feature = "tiktok-photos")]
+fn tiktok_http_get(url: &str, region: &TikTokRegion) -> reqwest::RequestBuilder {
 	static TIKTOK_HTTP: std::sync::LazyLock<reqwest::Client> = std::sync::LazyLock::new(reqwest::Client::new);
 
 	TIKTOK_HTTP
 		.get(url)
-		.header(
-			"User-Agent",
-			"Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/132.0.0.0 Safari/537.36",
-		)
+		.header("User-Agent", api::TIKTOK_USER_AGENT)
 		.header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")
-		.header("Accept-Language", "en-US,en;q=0.5")
+		.header("Accept-Language", region.accept_language())
 		.header("Sec-Fetch-Mode", "navigate")
 		.header("Accept-Encoding", "gzip, deflate, br")
 }
@@ -106,10 +271,18 @@ pub fn get_tiktok_photo_id_from_url(url: &str) -> Option<&str> {
 	)
 }
 
-async fn generate_slideshow_video(out: &Path, images: &[SlideshowImage<'_>], music: Option<&str>) -> Result<(), anyhow::Error> {
-	let (w, h) = images.iter().fold((0, 0), |(w, h), image| (w.max(image.width), h.max(image.height)));
+pub async fn generate_slideshow_video(
+	out: &Path,
+	images: &[SlideshowImage<'_>],
+	music: Option<&str>,
+	options: &SlideshowOptions,
+) -> Result<(), EncodeError> {
+	let (w, h) = canvas_size(images, options.max_resolution);
+
+	// Known up front, so the video comes out uploadable without a second encode
+	let video_kbps = video_bitrate_kbps(images.len(), options.size_limit);
 
-	let mut ffmpeg = Command::new("ffmpeg");
+	let mut ffmpeg = process::ffmpeg();
 
 	ffmpeg
 		.stdin(std::process::Stdio::piped())
@@ -130,16 +303,43 @@ async fn generate_slideshow_video(out: &Path, images: &[SlideshowImage<'_>], mus
 		ffmpeg.args(["-i", music]);
 	}
 
+	let mut video_filter = format!("scale={w}:{h}:force_original_aspect_ratio=decrease:flags=lanczos:eval=frame,pad={w}:{h}:-1:-1:eval=frame");
+
+	// Nothing to count with a single image
+	if let (Some(page_indicator), 2..) = (&options.page_indicator, images.len()) {
+		video_filter.push(',');
+		video_filter.push_str(&page_indicator.drawtext_filter(images.len(), h));
+	}
+
+	video_filter.push_str(",format=yuv420p");
+
 	ffmpeg.args([
 		"-map",
 		"0:v",
 		"-map",
 		"1:a",
 		"-vf",
-		&format!("scale={w}:{h}:force_original_aspect_ratio=decrease:eval=frame,pad={w}:{h}:-1:-1:eval=frame,format=yuv420p"),
+		&video_filter,
 		"-filter_complex",
 		"[1:0] apad",
 		"-shortest",
+		"-c:v",
+		"libx264",
+		"-b:v",
+		&format!("{video_kbps}k"),
+		"-maxrate",
+		&format!("{video_kbps}k"),
+		"-bufsize",
+		&format!("{}k", video_kbps * 2),
+		"-c:a",
+		"aac",
+		"-b:a",
+		&format!("{SLIDESHOW_AUDIO_KBPS}k"),
+		"-movflags",
+		"+faststart",
+		// Don't carry over metadata from the images
+		"-map_metadata",
+		"-1",
 	]);
 
 	ffmpeg.arg(out);
@@ -150,7 +350,7 @@ async fn generate_slideshow_video(out: &Path, images: &[SlideshowImage<'_>], mus
 		"{}file '{}'\nduration 0",
 		images
 			.iter()
-			.map(|image| format!("file '{}'\nduration 2.5\n", image.url))
+			.map(|image| format!("file '{}'\nduration {SLIDE_SECONDS}\n", image.url))
 			.collect::<String>(),
 		images.last().unwrap().url // Add an extra image to prevent the last image from being cut off
 	);
@@ -160,38 +360,36 @@ async fn generate_slideshow_video(out: &Path, images: &[SlideshowImage<'_>], mus
 	let ffmpeg = ffmpeg.wait_with_output().await?;
 
 	if !ffmpeg.status.success() {
-		return Err(anyhow::anyhow!(
-			"Exit status: {}\n\n=========== stderr ===========\n{}\n\n=========== stdout ===========\n{}",
-			ffmpeg.status,
-			String::from_utf8_lossy(&ffmpeg.stderr),
-			String::from_utf8_lossy(&ffmpeg.stdout)
-		));
+		return Err(EncodeError::Failed {
+			status: ffmpeg.status,
+			stderr: String::from_utf8_lossy(&ffmpeg.stderr).into_owned(),
+		});
 	}
 
 	Ok(())
 }
 
-pub async fn run_fallback_download_script(url: &str, out_path: &Path) -> Result<(), anyhow::Error> {
+pub async fn run_fallback_download_script(url: &str, out_path: &Path, max_size: u64) -> Result<(), ExtractError> {
 	log::info!("Trying TikTok fallback download script for URL: {}", url);
 
 	let output = Command::new("python3")
 		.arg(std::env::current_dir()?.join("src/tiktok/tiktok.py"))
 		.arg(url)
 		.arg("--max-size")
-		.arg("10000000")
+		.arg(max_size.to_string())
 		.env("FFMPEG_PATH", "ffmpeg")
 		.env("PYTHONIOENCODING", "utf-8")
 		.current_dir(out_path.parent().unwrap())
+		.kill_on_drop(true)
 		.output()
 		.await?;
 
 	if !output.status.success() {
-		return Err(anyhow::anyhow!(
-			"Exit status: {}\n\n=========== stderr ===========\n{}\n\n=========== stdout ===========\n{}",
-			output.status,
-			String::from_utf8_lossy(&output.stderr),
-			String::from_utf8_lossy(&output.stdout),
-		));
+		return Err(ExtractError::Script {
+			status: output.status,
+			stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+			stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+		});
 	}
 
 	#[derive(Debug, serde::Deserialize)]
@@ -211,6 +409,7 @@ pub async fn run_fallback_download_script(url: &str, out_path: &Path) -> Result<
 	Ok(())
 }
 
+#[cfg(feature = "tiktok-photos")]
 #[test]
 fn test_slideshow() {
 	std::fs::create_dir_all("yt_dlp_out").unwrap();
@@ -219,6 +418,80 @@ fn test_slideshow() {
 		.enable_all()
 		.build()
 		.unwrap()
-		.block_on(extract_slideshow_images("7460552162892860718", Path::new("yt_dlp_out/test.mp4")))
+		.block_on(extract_slideshow_images(
+			"7460552162892860718",
+			Path::new("yt_dlp_out/test.mp4"),
+			&TikTokRegion::default(),
+			&SlideshowOptions {
+				max_resolution: MaxResolution::default(),
+				size_limit: 10 * 1024 * 1024,
+				page_indicator: None,
+			},
+		))
 		.unwrap();
 }
+
+#[cfg(feature = "tiktok-photos")]
+#[test]
+fn item_detail_replay() {
+	use crate::cassette::Cassette;
+
+	const PHOTO_ID: &str = "7460552162892860718";
+
+	let region = &TikTokRegion::default();
+
+	Cassette::run(
+		"src/tiktok/fixtures/item_detail.json",
+		"/api/item/detail/",
+		|| async {
+			let signed_url = sign(&api::ItemDetailQuery::new(PHOTO_ID, region).url()).await.unwrap();
+			tiktok_http_get(&signed_url, region)
+		},
+		|uri| async move {
+			let api_url = api::ItemDetailQuery::new(PHOTO_ID, region).host(&uri).url();
+			let detail = fetch_item_detail(&api_url, region).await.unwrap();
+
+			let (images, music) = detail.slideshow().unwrap();
+			assert!(!images.is_empty());
+			assert!(images
+				.iter()
+				.all(|image| image.width > 0 && image.height > 0 && image.url.starts_with("https://")));
+			assert!(music.is_some());
+		},
+	);
+}
+
+#[test]
+fn slideshow_canvas() {
+	let image = |width, height| SlideshowImage { url: "", width, height };
+
+	assert_eq!(canvas_size(&[image(720, 1280), image(640, 640)], MaxResolution::default()), (720, 1280));
+	assert_eq!(canvas_size(&[image(3024, 4032)], MaxResolution::default()), (1080, 1440));
+	assert_eq!(canvas_size(&[image(4032, 3024)], MaxResolution::default()), (1440, 1080));
+	assert_eq!(canvas_size(&[image(2161, 3841)], MaxResolution::default()), (1080, 1918));
+
+	assert_eq!(video_bitrate_kbps(4, 10 * 1024 * 1024), MAX_SLIDESHOW_VIDEO_KBPS);
+	assert_eq!(video_bitrate_kbps(35, 10 * 1024 * 1024), 734);
+	assert_eq!(video_bitrate_kbps(1000, 10 * 1024 * 1024), MIN_SLIDESHOW_VIDEO_KBPS);
+}
+
+#[test]
+fn slideshow_page_indicator() {
+	let indicator = serde_json::from_str::<PageIndicator>("{}").unwrap();
+	assert_eq!(
+		indicator.drawtext_filter(7, 1920),
+		r"drawtext=text='%{eif\:trunc(t/2.5)+1\:d}/7':x=w-tw-40:y=40:fontsize=80:fontcolor=white:box=1:boxcolor=black@0.5:boxborderw=20"
+	);
+
+	let indicator = PageIndicator {
+		position: IndicatorPosition::BottomLeft,
+		font_size: Some(32),
+		font_color: "#ffcc00".to_string(),
+		box_color: None,
+		font_file: Some(r"C:\Fonts\Inter.ttf".to_string()),
+	};
+	assert_eq!(
+		indicator.drawtext_filter(3, 1080),
+		r"drawtext=text='%{eif\:trunc(t/2.5)+1\:d}/3':x=16:y=h-th-16:fontsize=32:fontcolor=#ffcc00:fontfile='C\:/Fonts/Inter.ttf'"
+	);
+}
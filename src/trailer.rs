@@ -0,0 +1,81 @@
+use crate::error::EncodeError;
+use serde::{Deserialize, Serialize};
+use std::{
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+const TRAILER_LENGTH: f64 = 15.0;
+const SEGMENTS: u32 = 5;
+const MAX_TRAILER_SIZE: &str = "3M";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrailerMode {
+	/// The first 15 seconds
+	Start,
+	/// Evenly spaced segments across the whole video, 15 seconds in total
+	Sampled,
+}
+
+/// Encodes a short low resolution preview clip of a video, returning its path
+pub async fn generate(path: &Path, duration: Duration, mode: TrailerMode, threads: usize) -> Result<PathBuf, EncodeError> {
+	let out_path = path.with_extension("trailer.mp4");
+
+	let duration = duration.as_secs_f64();
+
+	let mut command = crate::process::ffmpeg();
+	command.arg("-y").arg("-i").arg(path);
+
+	match mode {
+		TrailerMode::Sampled if duration > TRAILER_LENGTH => {
+			let interval = duration / SEGMENTS as f64;
+			let segment = TRAILER_LENGTH / SEGMENTS as f64;
+
+			command.args([
+				"-vf",
+				&format!("select='lt(mod(t,{interval}),{segment})',setpts=N/FRAME_RATE/TB,scale=-2:240,fps=15"),
+				"-af",
+				&format!("aselect='lt(mod(t,{interval}),{segment})',asetpts=N/SR/TB"),
+			]);
+		}
+
+		TrailerMode::Start | TrailerMode::Sampled => {
+			command.args(["-t", &TRAILER_LENGTH.to_string(), "-vf", "scale=-2:240,fps=15"]);
+		}
+	}
+
+	let output = command
+		.args([
+			"-c:v",
+			"libx264",
+			"-preset",
+			"veryfast",
+			"-b:v",
+			"1200k",
+			"-c:a",
+			"aac",
+			"-b:a",
+			"64k",
+			"-movflags",
+			"+faststart",
+			"-threads",
+			&threads.to_string(),
+			// Hard cap in case the bitrate overshoots
+			"-fs",
+			MAX_TRAILER_SIZE,
+		])
+		.arg(&out_path)
+		.output()
+		.await?;
+
+	if !output.status.success() {
+		tokio::fs::remove_file(&out_path).await.ok();
+		return Err(EncodeError::Failed {
+			status: output.status,
+			stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+		});
+	}
+
+	Ok(out_path)
+}
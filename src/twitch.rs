@@ -0,0 +1,48 @@
+use std::{path::Path, time::Duration};
+
+/// Default length of the segment downloaded from a VOD link with a timestamp
+pub const DEFAULT_VOD_SEGMENT: Duration = Duration::from_secs(60);
+
+pub fn is_twitch_clip_url(url: &str) -> bool {
+	regex::Regex::new(r#"^https?://(?:clips\.twitch\.tv/|(?:www\.|m\.)?twitch\.tv/\w+/clip/)"#)
+		.unwrap()
+		.is_match(url)
+}
+
+/// The `?t=1h2m3s` timestamp of a VOD link
+pub fn get_twitch_vod_timestamp_from_url(url: &str) -> Option<Duration> {
+	let captures = regex::Regex::new(r#"^https?://(?:www\.|m\.)?twitch\.tv/videos/\d+\?(?:.*&)?t=(?:(\d+)h)?(?:(\d+)m)?(?:(\d+)s?)?(?:&|$)"#)
+		.unwrap()
+		.captures(url)?;
+
+	let unit = |i: usize| captures.get(i).and_then(|n| n.as_str().parse::<u64>().ok()).unwrap_or(0);
+
+	match unit(1) * 3600 + unit(2) * 60 + unit(3) {
+		0 => None,
+		secs => Some(Duration::from_secs(secs)),
+	}
+}
+
+/// Writes a Netscape cookies file for yt-dlp, which is how its Twitch extractor picks up an OAuth token
+pub async fn write_cookies_file(path: &Path, oauth_token: &str) -> Result<(), std::io::Error> {
+	tokio::fs::write(
+		path,
+		format!("# Netscape HTTP Cookie File\n.twitch.tv\tTRUE\t/\tTRUE\t0\tauth-token\t{oauth_token}\n"),
+	)
+	.await
+}
+
+#[test]
+fn twitch_vod_timestamp() {
+	assert_eq!(
+		get_twitch_vod_timestamp_from_url("https://www.twitch.tv/videos/123456?t=1h2m3s"),
+		Some(Duration::from_secs(3723))
+	);
+	assert_eq!(
+		get_twitch_vod_timestamp_from_url("https://www.twitch.tv/videos/123456?filter=all&t=2m"),
+		Some(Duration::from_secs(120))
+	);
+	assert_eq!(get_twitch_vod_timestamp_from_url("https://www.twitch.tv/videos/123456"), None);
+	assert!(is_twitch_clip_url("https://clips.twitch.tv/FunnyClipName-abc123"));
+	assert!(is_twitch_clip_url("https://www.twitch.tv/streamer/clip/FunnyClipName-abc123"));
+}
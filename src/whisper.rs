@@ -0,0 +1,158 @@
+#[cfg(feature = "whisper")]
+use crate::process;
+use crate::{config::WhisperConfig, error::EncodeError};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "whisper")]
+use serenity::futures::StreamExt;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "whisper")]
+use tokio::{fs::File, io::AsyncWriteExt};
+
+#[cfg(feature = "whisper")]
+const WHISPER_MODELS_DIR: &str = "whisper_models";
+#[cfg(feature = "whisper")]
+const WHISPER_MODELS_URL: &str = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main";
+
+/// Serializes model downloads so concurrent transcriptions don't download the same model twice
+#[cfg(feature = "whisper")]
+static MODEL_DOWNLOAD: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubtitleMode {
+	/// Subtitles in the spoken language
+	Transcribe,
+	/// Subtitles translated to English
+	Translate,
+}
+
+pub struct Transcript {
+	pub text: String,
+	pub srt_path: PathBuf,
+}
+impl Drop for Transcript {
+	fn drop(&mut self) {
+		std::fs::remove_file(&self.srt_path).ok();
+	}
+}
+
+/// Downloads the ggml model on first use, whisper.cpp itself has to be installed separately
+#[cfg(feature = "whisper")]
+async fn model_path(config: &WhisperConfig) -> Result<PathBuf, EncodeError> {
+	let model = config
+		.model
+		.chars()
+		.map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '-' })
+		.collect::<String>();
+
+	let path = Path::new(WHISPER_MODELS_DIR).join(format!("ggml-{model}.bin"));
+
+	let _lock = MODEL_DOWNLOAD.lock().await;
+
+	if path.is_file() {
+		return Ok(path);
+	}
+
+	log::info!("Downloading whisper model {model}");
+
+	tokio::fs::create_dir_all(WHISPER_MODELS_DIR).await?;
+
+	// Models are hundreds of megabytes, so they're streamed to disk rather than held in memory
+	let mut model_stream = reqwest::get(format!("{WHISPER_MODELS_URL}/ggml-{model}.bin"))
+		.await?
+		.error_for_status()?
+		.bytes_stream();
+
+	// Download to a temporary file first so an interrupted download isn't mistaken for a complete model
+	let tmp_path = path.with_extension("bin.tmp");
+	let result = async {
+		let mut file = File::create(&tmp_path).await?;
+		while let Some(chunk) = model_stream.next().await {
+			file.write_all(&chunk?).await?;
+		}
+		file.flush().await?;
+		Ok::<_, EncodeError>(())
+	}
+	.await;
+
+	if let Err(err) = result {
+		tokio::fs::remove_file(&tmp_path).await.ok();
+		return Err(err);
+	}
+	tokio::fs::rename(&tmp_path, &path).await?;
+
+	log::info!("Downloaded whisper model {model}");
+
+	Ok(path)
+}
+
+/// Runs the audio of `media_path` through whisper.cpp, producing a plain text transcript and SRT subtitles
+#[cfg(feature = "whisper")]
+pub async fn transcribe(config: &WhisperConfig, media_path: &Path, mode: SubtitleMode) -> Result<Transcript, EncodeError> {
+	let model_path = model_path(config).await?;
+
+	// whisper.cpp only accepts 16 kHz mono WAV
+	let wav_path = media_path.with_extension("whisper.wav");
+	let output = process::ffmpeg()
+		.arg("-y")
+		.arg("-i")
+		.arg(media_path)
+		.args(["-vn", "-ar", "16000", "-ac", "1", "-c:a", "pcm_s16le"])
+		.arg(&wav_path)
+		.output()
+		.await?;
+
+	if !output.status.success() {
+		tokio::fs::remove_file(&wav_path).await.ok();
+		return Err(EncodeError::Failed {
+			status: output.status,
+			stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+		});
+	}
+
+	let out_stem = media_path.with_extension("whisper");
+
+	let mut command = process::media_command(&config.command);
+	command
+		.arg("-m")
+		.arg(&model_path)
+		.arg("-f")
+		.arg(&wav_path)
+		.args(["-osrt", "-otxt", "-of"])
+		.arg(&out_stem)
+		.args(["-l", config.language.as_deref().unwrap_or("auto")]);
+
+	if mode == SubtitleMode::Translate {
+		command.arg("--translate");
+	}
+
+	let output = command.output().await;
+
+	tokio::fs::remove_file(&wav_path).await.ok();
+
+	let output = output?;
+
+	let srt_path = out_stem.with_extension("srt");
+	let txt_path = out_stem.with_extension("txt");
+
+	let text = tokio::fs::read_to_string(&txt_path).await;
+	tokio::fs::remove_file(&txt_path).await.ok();
+
+	if !output.status.success() {
+		tokio::fs::remove_file(&srt_path).await.ok();
+		return Err(EncodeError::Whisper {
+			status: output.status,
+			stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+		});
+	}
+
+	Ok(Transcript {
+		text: text?.trim().to_string(),
+		srt_path,
+	})
+}
+
+#[cfg(not(feature = "whisper"))]
+pub async fn transcribe(_config: &WhisperConfig, _media_path: &Path, _mode: SubtitleMode) -> Result<Transcript, EncodeError> {
+	Err(EncodeError::Disabled("whisper"))
+}
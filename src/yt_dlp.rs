@@ -1,14 +1,32 @@
-use crate::{ffprobe::MediaProbe, github, tiktok, USER_AGENT};
-use anyhow::Context;
+use crate::{
+	discord::DISCORD_FILE_SIZE_LIMIT,
+	error::{DownloadError, EncodeError, ExtractError, UpdateError},
+	exif,
+	extractor::{self, ExtractContext, Extractor, ScriptPlugin},
+	ffprobe::MediaProbe,
+	github,
+	pipeline::{Downloader, Encoder, Prober},
+	process,
+	progress::{self, DownloadProgress, ProgressReporter},
+	queue::{DownloadQueue, Priority},
+	redirects::{self, JobHeaders},
+	spotify::{self, SpotifyResolver},
+	subtitles::{self, SubtitleDelivery},
+	tiktok::{self, MaxResolution, PageIndicator, SlideshowOptions, TikTokRegion},
+	twitch, USER_AGENT,
+};
+use serenity::async_trait;
 use std::{
 	borrow::Cow,
+	collections::{hash_map::Entry, HashMap},
+	num::NonZeroUsize,
 	path::{Path, PathBuf},
-	sync::Arc,
+	process::Stdio,
+	sync::{Arc, LazyLock},
 	time::{Duration, Instant},
 };
 use tokio::{
 	fs::File,
-	process::Command,
 	sync::{Mutex, RwLock},
 };
 
@@ -27,22 +45,44 @@ const YT_DLP_EXE: &str = {
 	}
 };
 
+// Follows a format selector, from `video_format_selector` or `VideoQuality`
+const YT_DLP_VIDEO_ARGS: &[&str] = &["-S", "vcodec:h264", "--merge-output-format", "mp4"];
+
+// Clips are progressive h264 mp4s, pick a quality that fits rather than the source
+const YT_DLP_TWITCH_CLIP_ARGS: &[&str] = &["--merge-output-format", "mp4"];
+
+const YT_DLP_AUDIO_ARGS: &[&str] = &["-f", "bestaudio/best", "-x", "--audio-format", "mp3"];
+
 const YT_DLP_ARGS: &[&str] = &[
-	"-f",
-	"http*[filesize<10M]/best[filesize<10MB]/http*[filesize<8M]+http*[filesize<2M]/http*[filesize<8M]/bestvideo[filesize<8MB]+bestaudio[filesize<2MB]/bestvideo[filesize<8MB]+bestaudio/best/bestvideo+bestaudio",
-	"-S",
-	"vcodec:h264",
-	"--merge-output-format",
-	"mp4",
 	"--ignore-config",
 	"--verbose",
 	"--no-playlist",
 	"--no-warnings",
-	"-o",
+	"--dump-json",
+	"--no-simulate",
 ];
 
+const MB: u64 = 1024 * 1024;
+
+/// How long a prefetched link probe is kept for its download
+const PREFETCH_TTL: Duration = Duration::from_secs(60);
+
+/// Longer clips make GIFs too large (and too tedious) to be worth it
+const GIF_MAX_DURATION: Duration = Duration::from_secs(30);
+
+/// Maximum width and frame rate of each attempt at fitting a GIF in the size limit
+const GIF_STEPS: [(u32, u32); 3] = [(480, 15), (360, 12), (240, 10)];
+
+/// Thumbnails are small, a host taking longer than this to send one isn't worth waiting on
+const THUMBNAIL_TIMEOUT: Duration = Duration::from_secs(15);
+
+const YT_DLP_REPO: &str = "yt-dlp/yt-dlp-nightly-builds";
+
 const YT_DLP_UPDATE_CHECK_INTERVAL: Duration = Duration::from_secs(30 * 60); // 30 mins
 
+/// Timestamps like `1h2m3s`, `2m` or `83`
+static UNIT_TIMESTAMP: LazyLock<regex::Regex> = LazyLock::new(|| regex::Regex::new(r"^(?:(\d+)h)?(?:(\d+)m)?(?:(\d+)s?)?$").unwrap());
+
 #[derive(Debug)]
 struct YtDlpRelease {
 	tag_name: Box<str>,
@@ -50,34 +90,42 @@ struct YtDlpRelease {
 	size: u64,
 }
 impl YtDlpRelease {
-	async fn latest() -> Result<Self, anyhow::Error> {
+	async fn latest() -> Result<Self, UpdateError> {
+		Self::latest_from(github::GITHUB_API).await
+	}
+
+	/// The latest release on the GitHub API at `api`
+	async fn latest_from(api: &str) -> Result<Self, UpdateError> {
 		log::info!("Grabbing latest yt-dlp release...");
 
-		let (tag_name, (browser_download_url, size)) = github::Releases::get("yt-dlp/yt-dlp-nightly-builds", Duration::from_secs(7))
-			.await?
-			.0
-			.into_iter()
-			.find_map(|release| {
-				if !release.draft && !release.prerelease {
-					Some((
-						release.tag_name,
-						release.assets.into_iter().find_map(|asset| {
-							if asset.name.as_ref() == YT_DLP_EXE {
-								Some((asset.browser_download_url, asset.size))
-							} else {
-								None
-							}
-						})?,
-					))
-				} else {
-					None
-				}
-			})
-			.ok_or_else(|| anyhow::anyhow!("No release found"))?;
+		let releases = github::Releases::get(api, YT_DLP_REPO, Duration::from_secs(7)).await?;
+		let release = Self::latest_of(releases).ok_or(UpdateError::NoRelease)?;
+
+		log::info!("Latest yt-dlp release: {}", release.tag_name);
 
-		log::info!("Latest yt-dlp release: {}", tag_name);
+		Ok(release)
+	}
 
-		Ok(YtDlpRelease {
+	/// The newest of `releases` that isn't a draft or prerelease, and has a build for this platform
+	fn latest_of(releases: github::Releases) -> Option<Self> {
+		let (tag_name, (browser_download_url, size)) = releases.0.into_iter().find_map(|release| {
+			if !release.draft && !release.prerelease {
+				Some((
+					release.tag_name,
+					release.assets.into_iter().find_map(|asset| {
+						if asset.name.as_ref() == YT_DLP_EXE {
+							Some((asset.browser_download_url, asset.size))
+						} else {
+							None
+						}
+					})?,
+				))
+			} else {
+				None
+			}
+		})?;
+
+		Some(YtDlpRelease {
 			tag_name,
 			browser_download_url,
 			size,
@@ -90,12 +138,12 @@ pub struct YtDlp {
 	exe_path: Box<Path>,
 }
 impl YtDlp {
-	pub async fn new() -> Result<Self, anyhow::Error> {
+	pub async fn new() -> Result<Self, UpdateError> {
 		let release = YtDlpRelease::latest().await?;
 		Self::download_release(release).await
 	}
 
-	async fn download_release(release: YtDlpRelease) -> Result<Self, anyhow::Error> {
+	async fn download_release(release: YtDlpRelease) -> Result<Self, UpdateError> {
 		log::info!("Downloading yt-dlp release {}", release.tag_name);
 
 		let YtDlpRelease {
@@ -162,22 +210,120 @@ impl YtDlp {
 			let output = tokio::process::Command::new("chmod").arg("+x").arg(exe_path.as_ref()).output().await?;
 
 			if !output.status.success() {
-				return Err(anyhow::anyhow!("Failed to chmod yt-dlp (status {})", output.status));
+				return Err(UpdateError::Chmod(output.status));
 			}
 		}
 
 		Ok(Self { tag_name, exe_path })
 	}
 
-	pub async fn download(&self, url: &str, out_path: &Path) -> Result<DownloadedMedia, anyhow::Error> {
+	pub async fn download(
+		&self,
+		url: &str,
+		out_path: &Path,
+		options: &DownloadOptions,
+		headers: &JobHeaders,
+		queue: &DownloadQueue,
+	) -> Result<MediaResult, DownloadError> {
 		log::info!("Downloading {url} to {}", out_path.display());
 
-		let output = Command::new(self.exe_path.as_ref())
-			.args(YT_DLP_ARGS)
-			.arg(out_path)
-			.arg(url)
-			.output()
-			.await?;
+		if can_pipe_remux(url, options) {
+			match self.download_piped(url, out_path, options, headers, queue).await {
+				Ok((dump, download_time, log)) => {
+					let processed = self.post_process_inner(out_path, options, queue, true, true).await?;
+					let mut media = MediaResult::from_dump(processed, download_time, dump);
+					media.log = log;
+					return Ok(media);
+				}
+				Err(err) if err.is_no_media() => return Err(err),
+				Err(err) => {
+					log::warn!("Failed to pipe {url} into ffmpeg, downloading to a file instead ({err})");
+					tokio::fs::remove_file(out_path).await.ok();
+				}
+			}
+		}
+
+		let mut cmd = process::media_command(self.exe_path.as_ref());
+
+		match options.quality {
+			_ if options.audio_only => cmd.args(YT_DLP_AUDIO_ARGS),
+			Some(quality) => cmd.args(["-f", &quality.format_selector(options.size_limit())]).args(YT_DLP_VIDEO_ARGS),
+			None if twitch::is_twitch_clip_url(url) => cmd
+				.args(["-f", &format!("best[filesize<{}M]/best[height<=720]/best", options.size_limit() / MB)])
+				.args(YT_DLP_TWITCH_CLIP_ARGS),
+			None => cmd.args(["-f", &video_format_selector(options.size_limit())]).args(YT_DLP_VIDEO_ARGS),
+		}
+		.args(YT_DLP_ARGS)
+		.args(progress::YT_DLP_PROGRESS_ARGS);
+
+		headers.apply_yt_dlp_args(&mut cmd);
+
+		if options.subtitles.is_some() {
+			cmd.args(subtitles::YT_DLP_SUBTITLE_ARGS);
+		}
+
+		if let Some(trim) = options.trim {
+			cmd.args(["--download-sections", &trim.download_sections(), "--force-keyframes-at-cuts"]);
+		}
+		// Only download the linked segment of a VOD rather than the whole stream
+		else if let Some(start) = twitch::get_twitch_vod_timestamp_from_url(url) {
+			let end = start + options.twitch_vod_segment.unwrap_or(twitch::DEFAULT_VOD_SEGMENT);
+			cmd.args(["--download-sections", &format!("*{}-{}", start.as_secs(), end.as_secs())]);
+		}
+
+		let cookies_path = match &options.twitch_oauth_token {
+			Some(oauth_token) if url.contains("twitch.tv") => {
+				let cookies_path = out_path.with_extension("cookies.txt");
+				twitch::write_cookies_file(&cookies_path, oauth_token).await?;
+				cmd.arg("--cookies").arg(&cookies_path);
+				Some(cookies_path)
+			}
+			_ => {
+				if let Some(cookies_file) = &options.cookies_file {
+					cmd.arg("--cookies").arg(cookies_file.as_ref());
+				}
+				None
+			}
+		};
+
+		if let Some(duration_filter) = duration_filter(options) {
+			cmd.args(["--match-filter", &duration_filter]);
+		}
+
+		// Separate video and audio streams are usually fragmented, and each fragment is a round trip
+		if let Some(concurrent_fragments) = options.concurrent_fragments {
+			cmd.args(["--concurrent-fragments", &concurrent_fragments.to_string()]);
+		}
+
+		// Audio extraction changes the extension after download, so let yt-dlp pick the intermediate one
+		if options.audio_only {
+			cmd.arg("-o").arg(out_path.with_extension("%(ext)s"));
+		} else {
+			cmd.arg("-o").arg(out_path);
+		}
+
+		let (output, download_time) = {
+			let _permit = queue.download(options.priority).await;
+			let started = Instant::now();
+			options.progress.report(DownloadProgress::Downloading { percent: None });
+
+			let output = progress::output_with_progress(cmd.arg(url), |line| match progress::yt_dlp_percent(line) {
+				Some(percent) => {
+					options.progress.report(DownloadProgress::Downloading { percent });
+					true
+				}
+				None => false,
+			})
+			.await;
+
+			(output, started.elapsed())
+		};
+
+		if let Some(cookies_path) = cookies_path {
+			tokio::fs::remove_file(cookies_path).await.ok();
+		}
+
+		let output = output?;
 
 		log::info!("Downloaded {url} to {}", out_path.display());
 
@@ -188,37 +334,367 @@ impl YtDlp {
 		}
 
 		if !output.status.success() {
-			return Err(anyhow::anyhow!(
-				"Exit status: {}\n\n=========== stderr ===========\n{}\n\n=========== stdout ===========\n{}",
-				output.status,
-				String::from_utf8_lossy(&output.stderr),
-				String::from_utf8_lossy(&output.stdout)
-			));
+			let stderr = String::from_utf8_lossy(&output.stderr);
+
+			if is_unsupported(&stderr, options) {
+				return Err(DownloadError::NoMedia);
+			}
+
+			return Err(DownloadError::YtDlp {
+				status: output.status,
+				stderr: stderr.into_owned(),
+				stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+			});
 		} else if !out_path.exists() {
-			return Err(anyhow::anyhow!("yt-dlp did not create the file"));
+			if String::from_utf8_lossy(&output.stdout).contains("does not pass filter")
+				|| String::from_utf8_lossy(&output.stderr).contains("does not pass filter")
+			{
+				return Err(DownloadError::MaxDurationExceeded);
+			}
+
+			return Err(DownloadError::NoOutput);
 		}
 
+		let mut srt_path = match options.subtitles {
+			Some(_) => subtitles::take(out_path).await,
+			None => None,
+		};
+
 		let mut out_path = Cow::Borrowed(out_path);
+		let mut burned = false;
+
+		if let (Some(SubtitleDelivery::Burn), Some(subtitles_path)) = (options.subtitles, &srt_path) {
+			let subtitled = {
+				let _permit = queue.encode(options.priority).await;
+				subtitles::burn(&out_path, subtitles_path, queue.encode_threads()).await
+			};
+
+			match subtitled {
+				Ok(subtitled_path) => {
+					tokio::fs::remove_file(out_path.as_ref()).await.ok();
+					out_path = Cow::Owned(subtitled_path);
+					burned = true;
+				}
 
-		let reencode_duration = match MediaProbe::get(out_path.as_ref()).await? {
-			MediaProbe::Probed {
-				is_discord_compatible: true, ..
-			} => None,
+				// Still worth posting the video without subtitles
+				Err(err) => log::error!("Failed to burn subtitles into {url} ({err})"),
+			}
 
-			MediaProbe::Probed {
-				is_discord_compatible: false,
+			if let Some(srt_path) = srt_path.take() {
+				tokio::fs::remove_file(srt_path).await.ok();
+			}
+		}
+
+		let processed = match self.post_process_inner(&out_path, options, queue, false, true).await {
+			Ok(processed) => processed,
+			Err(err) => {
+				if let Some(srt_path) = srt_path {
+					tokio::fs::remove_file(srt_path).await.ok();
+				}
+				return Err(err.into());
+			}
+		};
+
+		let dump = std::str::from_utf8(&output.stdout)
+			.ok()
+			.and_then(|stdout| serde_json::from_str::<YtDlpJsonDump>(stdout).ok());
+
+		let mut media = MediaResult::from_dump(processed, download_time, dump);
+		media.reencoded |= burned;
+		media.subtitles = srt_path.map(PathBuf::into_boxed_path);
+		media.log = options.verbose.then(|| String::from_utf8_lossy(&output.stderr).into_owned());
+		Ok(media)
+	}
+
+	/// Downloads a single file format to yt-dlp's stdout, remuxed by ffmpeg from its stdin to `out_path` without metadata, so the
+	/// media is only written to disk once. Returns yt-dlp's JSON dump, and its output if [`DownloadOptions::verbose`]
+	async fn download_piped(
+		&self,
+		url: &str,
+		out_path: &Path,
+		options: &DownloadOptions,
+		headers: &JobHeaders,
+		queue: &DownloadQueue,
+	) -> Result<(Option<YtDlpJsonDump>, Duration, Option<String>), DownloadError> {
+		let info_path = out_path.with_extension("info.json");
+
+		let mut yt_dlp = process::media_command(self.exe_path.as_ref());
+
+		yt_dlp
+			.args([
+				"--ignore-config",
+				"--no-playlist",
+				"--no-warnings",
+				"--no-simulate",
+				"-S",
+				"vcodec:h264",
+				"-f",
+			])
+			.arg(piped_format_selector(options.size_limit()))
+			.args(["--print-to-file", "%()j"])
+			.arg(&info_path);
+
+		headers.apply_yt_dlp_args(&mut yt_dlp);
+
+		if let Some(cookies_file) = &options.cookies_file {
+			yt_dlp.arg("--cookies").arg(cookies_file.as_ref());
+		}
+
+		if let Some(duration_filter) = duration_filter(options) {
+			yt_dlp.args(["--match-filter", &duration_filter]);
+		}
+
+		if let Some(concurrent_fragments) = options.concurrent_fragments {
+			yt_dlp.args(["--concurrent-fragments", &concurrent_fragments.to_string()]);
+		}
+
+		if options.verbose {
+			yt_dlp.arg("--verbose");
+		}
+
+		yt_dlp
+			.args(["-o", "-"])
+			.arg(url)
+			.stdin(Stdio::null())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.kill_on_drop(true);
+
+		let mut ffmpeg = process::ffmpeg();
+
+		ffmpeg
+			.args([
+				"-y",
+				"-i",
+				"pipe:0",
+				"-map",
+				"0",
+				"-c",
+				"copy",
+				"-map_metadata",
+				"-1",
+				"-movflags",
+				"+faststart",
+				"-f",
+				"mp4",
+			])
+			.arg(out_path)
+			.stdout(Stdio::null())
+			.stderr(Stdio::piped())
+			.kill_on_drop(true);
+
+		let (yt_dlp, ffmpeg, download_time) = {
+			let _permit = queue.download(options.priority).await;
+			let started = Instant::now();
+			options.progress.report(DownloadProgress::Downloading { percent: None });
+
+			let mut yt_dlp = yt_dlp.spawn()?;
+			let pipe: Stdio = yt_dlp
+				.stdout
+				.take()
+				.ok_or_else(|| std::io::Error::other("yt-dlp has no stdout"))?
+				.try_into()?;
+			let ffmpeg = ffmpeg.stdin(pipe).output();
+
+			let (yt_dlp, ffmpeg) = tokio::join!(yt_dlp.wait_with_output(), ffmpeg);
+			(yt_dlp, ffmpeg, started.elapsed())
+		};
+
+		let info = tokio::fs::read(&info_path).await;
+		tokio::fs::remove_file(&info_path).await.ok();
+
+		let (yt_dlp, ffmpeg) = (yt_dlp?, ffmpeg?);
+
+		// yt-dlp logs to stderr when the media goes to stdout
+		let stderr = String::from_utf8_lossy(&yt_dlp.stderr);
+
+		if is_unsupported(&stderr, options) {
+			return Err(DownloadError::NoMedia);
+		} else if stderr.contains("does not pass filter") {
+			return Err(DownloadError::MaxDurationExceeded);
+		} else if !yt_dlp.status.success() {
+			return Err(DownloadError::YtDlp {
+				status: yt_dlp.status,
+				stderr: stderr.into_owned(),
+				stdout: String::new(),
+			});
+		} else if !ffmpeg.status.success() || !out_path.is_file() {
+			return Err(DownloadError::Remux {
+				status: ffmpeg.status,
+				stderr: String::from_utf8_lossy(&ffmpeg.stderr).into_owned(),
+			});
+		}
+
+		log::info!("Downloaded {url} to {} through ffmpeg", out_path.display());
+
+		Ok((
+			info.ok().and_then(|info| serde_json::from_slice::<YtDlpJsonDump>(&info).ok()),
+			download_time,
+			options.verbose.then(|| stderr.into_owned()),
+		))
+	}
+
+	/// URL of a single file with both video and audio (or just audio) that Discord can embed and anyone can open, without
+	/// downloading it
+	pub async fn direct_url(&self, url: &str, options: &DownloadOptions, queue: &DownloadQueue) -> Result<String, DownloadError> {
+		let mut cmd = process::media_command(self.exe_path.as_ref());
+
+		cmd.args(["--ignore-config", "--no-playlist", "--no-warnings", "--get-url", "-f"]);
+		if options.audio_only {
+			cmd.arg("bestaudio/best");
+		} else {
+			cmd.arg(format!(
+				"best[vcodec!=none][acodec!=none][filesize<{}M]/best[vcodec!=none][acodec!=none]",
+				options.size_limit() / MB
+			));
+		}
+
+		cmd.args(["--user-agent", options.user_agent(url)]);
+
+		if let Some(cookies_file) = &options.cookies_file {
+			cmd.arg("--cookies").arg(cookies_file.as_ref());
+		}
+
+		let output = {
+			let _permit = queue.download(options.priority).await;
+			cmd.arg(url).kill_on_drop(true).output().await?
+		};
+
+		if !output.status.success() {
+			return Err(DownloadError::YtDlp {
+				status: output.status,
+				stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+				stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+			});
+		}
+
+		// Separate video and audio streams come out as a line each, and need merging
+		match String::from_utf8_lossy(&output.stdout).trim().lines().collect::<Vec<_>>()[..] {
+			[direct_url] if is_shareable_url(direct_url) => Ok(direct_url.to_string()),
+			_ => Err(DownloadError::NoMedia),
+		}
+	}
+
+	/// Makes a downloaded file embeddable: renders a waveform for audio-only media and re-encodes anything Discord can't play
+	pub async fn post_process(&self, out_path: &Path, options: &DownloadOptions, queue: &DownloadQueue) -> Result<Processed, EncodeError> {
+		self.post_process_inner(out_path, options, queue, false, false).await
+	}
+
+	/// `metadata_stripped` is whether ffmpeg has already written the file without its metadata, `trimmed` whether yt-dlp already
+	/// only downloaded the trimmed section
+	async fn post_process_inner(
+		&self,
+		out_path: &Path,
+		options: &DownloadOptions,
+		queue: &DownloadQueue,
+		mut metadata_stripped: bool,
+		trimmed: bool,
+	) -> Result<Processed, EncodeError> {
+		// Only the encodes take an encode slot, the probes and remuxes are cheap
+		let started = Instant::now();
+		let mut queued = Duration::ZERO;
+		let mut reencoded = false;
+
+		let mut out_path = Cow::Borrowed(out_path);
+
+		if let (Some(trim), false) = (options.trim, trimmed) {
+			out_path = Cow::Owned(Self::trim(out_path.as_ref(), trim).await?);
+		}
+
+		let probe = match options.audio_only {
+			true => None,
+			false => Some(MediaProbe::probe(out_path.as_ref(), options.size_limit()).await?),
+		};
+
+		// Audio-only sources (podcasts, SoundCloud...) get a waveform so they still play inline
+		if let Some(MediaProbe::Probed {
+			has_video: false, duration, ..
+		}) = probe
+		{
+			log::info!("No video stream, rendering a waveform video...");
+
+			let rendered = {
+				let permit = queue.encode(options.priority).await;
+				queued += permit.waited();
+				self.render_waveform(out_path.as_ref(), duration, options, queue.encode_threads()).await
+			};
+
+			match rendered {
+				Ok(new_out_path) => {
+					out_path = Cow::Owned(new_out_path);
+					metadata_stripped = true;
+					reencoded = true;
+				}
+				Err(EncodeError::BitrateTooLow) => log::warn!("Audio too long to fit a waveform video, rendering skipped"),
+				Err(err) => log::error!("Failed to render waveform video: {err}"),
+			}
+		}
+
+		// Short clips without sound loop as GIFs, which Discord autoplays
+		let gif_duration = match &probe {
+			Some(MediaProbe::Probed {
+				has_video: true,
+				audio_codec: None,
 				duration,
-			} => Some(Some(duration)),
+				..
+			}) if options.gif && *duration <= GIF_MAX_DURATION => Some(*duration),
+			_ => None,
+		};
+
+		let mut gif = false;
+
+		if let Some(duration) = gif_duration {
+			log::info!("Short clip without sound, encoding a GIF...");
 
-			MediaProbe::Corrupt => Some(None),
+			let encoded = {
+				let permit = queue.encode(options.priority).await;
+				queued += permit.waited();
+				self.encode_gif(out_path.as_ref(), duration, options, queue.encode_threads()).await
+			};
+
+			match encoded {
+				Ok(new_out_path) => {
+					out_path = Cow::Owned(new_out_path);
+					metadata_stripped = true;
+					reencoded = true;
+					gif = true;
+				}
+				Err(EncodeError::BitrateTooLow) => log::warn!("Clip too large to fit as a GIF, keeping the video"),
+				Err(err) => log::error!("Failed to encode GIF: {err}"),
+			}
+		}
+
+		let reencode_duration = match probe {
+			_ if gif => None,
+
+			None | Some(MediaProbe::Probed { has_video: false, .. }) => None,
+
+			Some(MediaProbe::Probed {
+				is_discord_compatible,
+				duration,
+				..
+			}) if !is_discord_compatible || options.force_reencode => Some(Some(duration)),
+
+			Some(MediaProbe::Probed { .. }) => None,
+
+			Some(MediaProbe::Corrupt) => Some(None),
 		};
 
 		if let Some(reencode_duration) = reencode_duration {
-			log::info!("Video is corrupt or incompatible with Discord, re-encoding...");
+			log::info!("Video is corrupt, incompatible with Discord or forced to re-encode, re-encoding...");
+
+			let reencoded_path = {
+				let permit = queue.encode(options.priority).await;
+				queued += permit.waited();
+				self.reencode_video(out_path.as_ref(), reencode_duration, options, queue.encode_threads())
+					.await
+			};
 
-			match self.reencode_video(out_path.as_ref(), reencode_duration).await {
+			match reencoded_path {
 				Ok(new_out_path) => {
 					out_path = Cow::Owned(new_out_path);
+					metadata_stripped = true;
+					reencoded = true;
 
 					log::info!(
 						"Successfully re-encoded video. New size: {}",
@@ -226,7 +702,7 @@ impl YtDlp {
 					);
 
 					if cfg!(debug_assertions) {
-						let reencoded_probe = MediaProbe::get(out_path.as_ref()).await;
+						let reencoded_probe = MediaProbe::probe(out_path.as_ref(), options.size_limit()).await;
 						assert!(
 							matches!(
 								reencoded_probe,
@@ -240,40 +716,110 @@ impl YtDlp {
 					}
 				}
 
-				Err(ReencodeVideoError::Io(err)) => log::error!("Failed to re-encode video: {err}"),
+				Err(EncodeError::BitrateTooLow) => log::warn!("Bitrate too low for this video, re-encoding skipped"),
 
-				Err(ReencodeVideoError::BitrateTooLow) => log::warn!("Bitrate too low for this video, re-encoding skipped"),
+				Err(err) => log::error!("Failed to re-encode video: {err}"),
 			}
 		}
 
-		let url = (|| {
-			let stdout = std::str::from_utf8(&output.stdout).ok()?;
-			let dump = serde_json::from_str::<YtDlpJsonDump>(stdout).ok()?;
-
-			if dump.requested_downloads.len() == 1 {
-				Some(dump.requested_downloads[0].url.as_str().into())
-			} else {
-				Some(dump.url.into_boxed_str())
+		if options.strip_video_metadata && !metadata_stripped {
+			match Self::remux_without_metadata(out_path.as_ref()).await {
+				Ok(new_out_path) => out_path = Cow::Owned(new_out_path),
+				Err(err) => log::error!("Failed to strip metadata: {err}"),
 			}
-		})();
+		}
+
+		// Stripping metadata doesn't touch the streams, so the first probe still holds unless something was encoded
+		let probe = match (probe, reencoded) {
+			(Some(probe @ MediaProbe::Probed { .. }), false) => Some(probe),
+			_ => MediaProbe::probe(out_path.as_ref(), options.size_limit()).await.ok(),
+		};
 
-		Ok(DownloadedMedia { path: out_path.into(), url })
+		Ok(Processed {
+			path: out_path.into_owned(),
+			reencoded,
+			stream: probe.and_then(StreamInfo::from_probe),
+			time: started.elapsed().saturating_sub(queued),
+		})
 	}
 
-	async fn reencode_video(&self, path: &Path, reencode_duration: Option<Duration>) -> Result<PathBuf, ReencodeVideoError> {
+	/// Cuts the file down to `trim` without re-encoding, so the cuts land on the nearest keyframes
+	async fn trim(path: &Path, trim: Trim) -> Result<PathBuf, EncodeError> {
+		let ext = path.extension().map(|ext| ext.to_string_lossy()).unwrap_or_default();
+		let trimmed_path = path.with_file_name(format!("{}_trimmed.{ext}", path.file_stem().unwrap().to_string_lossy()));
+
+		let output = process::ffmpeg()
+			.args(trim.ffmpeg_args())
+			.arg("-i")
+			.arg(path)
+			.args(["-map", "0", "-c", "copy"])
+			.arg(&trimmed_path)
+			.kill_on_drop(true)
+			.output()
+			.await?;
+
+		if output.status.success() && trimmed_path.is_file() {
+			tokio::fs::remove_file(path).await.ok();
+			Ok(trimmed_path)
+		} else {
+			tokio::fs::remove_file(&trimmed_path).await.ok();
+			Err(EncodeError::Failed {
+				status: output.status,
+				stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+			})
+		}
+	}
+
+	/// Copies the streams into a new file without any of the container or stream metadata
+	async fn remux_without_metadata(path: &Path) -> Result<PathBuf, EncodeError> {
+		let ext = path.extension().map(|ext| ext.to_string_lossy()).unwrap_or_default();
+		let remuxed_path = path.with_file_name(format!("{}_remuxed.{ext}", path.file_stem().unwrap().to_string_lossy()));
+
+		let mut cmd = process::ffmpeg();
+
+		cmd.arg("-i").arg(path).args(["-map", "0", "-c", "copy", "-map_metadata", "-1"]);
+
+		if matches!(&*ext, "mp4" | "mov" | "m4a") {
+			cmd.args(["-movflags", "+faststart"]);
+		}
+
+		let output = cmd.arg(&remuxed_path).kill_on_drop(true).output().await?;
+
+		if output.status.success() && remuxed_path.is_file() {
+			tokio::fs::remove_file(path).await.ok();
+			Ok(remuxed_path)
+		} else {
+			tokio::fs::remove_file(&remuxed_path).await.ok();
+			Err(EncodeError::Failed {
+				status: output.status,
+				stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+			})
+		}
+	}
+
+	async fn reencode_video(
+		&self,
+		path: &Path,
+		reencode_duration: Option<Duration>,
+		options: &DownloadOptions,
+		threads: usize,
+	) -> Result<PathBuf, EncodeError> {
 		let reencoded_path = path.with_file_name(format!("{}_reencoded.mp4", path.file_stem().unwrap().to_string_lossy()));
 
-		let bitrates = reencode_duration.map(|duration| Self::calculate_bitrates(10.0, duration.as_secs_f64()));
+		let target_size_mb = options.target_size_mb.unwrap_or(options.size_limit() as f64 / MB as f64);
+
+		let bitrates = reencode_duration.map(|duration| Self::calculate_bitrates(target_size_mb, duration.as_secs_f64()));
 
-		let mut cmd = Command::new(if cfg!(windows) { "ffmpeg.exe" } else { "ffmpeg" });
+		let mut cmd = process::ffmpeg();
 
 		cmd.arg("-i")
 			.arg(path)
-			.args(["-vcodec", "libx264", "-acodec", "aac", "-movflags", "+faststart"]);
+			.args(["-vcodec", "libx264", "-acodec", "aac", "-movflags", "+faststart"])
+			.args(["-threads", &threads.to_string()]);
 
 		if let Some((video_bitrate_kbps, audio_bitrate_kbps)) = bitrates {
 			if video_bitrate_kbps < 800.0 {
-				return Err(ReencodeVideoError::BitrateTooLow);
+				return Err(EncodeError::BitrateTooLow);
 			}
 
 			cmd.args(["-b:v", &format!("{video_bitrate_kbps:.0}k"), "-b:a", &format!("{audio_bitrate_kbps:.0}k")]);
@@ -281,7 +827,21 @@ impl YtDlp {
 			cmd.args(["-crf", "23"]); // Hope for the best
 		}
 
-		let output = cmd.arg(&reencoded_path).output().await.map_err(ReencodeVideoError::Io)?;
+		if options.strip_video_metadata {
+			cmd.args(["-map_metadata", "-1"]);
+		}
+
+		cmd.args(progress::FFMPEG_PROGRESS_ARGS);
+
+		options.progress.report(DownloadProgress::Reencoding { percent: None });
+
+		let output = progress::output_with_progress(cmd.arg(&reencoded_path), |line| {
+			if let Some(percent) = progress::ffmpeg_percent(line, reencode_duration) {
+				options.progress.report(DownloadProgress::Reencoding { percent: Some(percent) });
+			}
+			false
+		})
+		.await?;
 
 		if output.status.success() && reencoded_path.is_file() {
 			match (cfg!(debug_assertions), tokio::fs::remove_file(path).await) {
@@ -292,12 +852,127 @@ impl YtDlp {
 
 			Ok(reencoded_path)
 		} else {
-			Err(ReencodeVideoError::Io(std::io::Error::other(format!(
-				"Exit status: {}\n\n=========== stderr ===========\n{}\n\n=========== stdout ===========\n{}",
-				output.status,
-				String::from_utf8_lossy(&output.stderr),
-				String::from_utf8_lossy(&output.stdout)
-			))))
+			Err(EncodeError::Failed {
+				status: output.status,
+				stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+			})
+		}
+	}
+
+	/// Encodes a GIF with a palette generated from the clip itself, stepping down the resolution and frame rate until it fits in
+	/// the size limit
+	async fn encode_gif(&self, path: &Path, duration: Duration, options: &DownloadOptions, threads: usize) -> Result<PathBuf, EncodeError> {
+		let gif_path = path.with_file_name(format!("{}_gif.gif", path.file_stem().unwrap().to_string_lossy()));
+
+		let size_limit = options
+			.target_size_mb
+			.map_or(options.size_limit(), |target_size_mb| (target_size_mb * MB as f64) as u64);
+
+		options.progress.report(DownloadProgress::Reencoding { percent: None });
+
+		for (max_width, fps) in GIF_STEPS {
+			let mut cmd = process::ffmpeg();
+
+			cmd.arg("-y")
+				.arg("-i")
+				.arg(path)
+				.args([
+					"-vf",
+					&format!(
+						"fps={fps},scale='min({max_width},iw)':-2:flags=lanczos,split[a][b];[a]palettegen=stats_mode=diff[p];[b][p]paletteuse=dither=bayer:bayer_scale=5:diff_mode=rectangle"
+					),
+					"-an",
+					"-loop",
+					"0",
+					"-map_metadata",
+					"-1",
+					"-threads",
+					&threads.to_string(),
+				])
+				.args(progress::FFMPEG_PROGRESS_ARGS);
+
+			let output = progress::output_with_progress(cmd.arg(&gif_path), |line| {
+				if let Some(percent) = progress::ffmpeg_percent(line, Some(duration)) {
+					options.progress.report(DownloadProgress::Reencoding { percent: Some(percent) });
+				}
+				false
+			})
+			.await?;
+
+			if !output.status.success() || !gif_path.is_file() {
+				tokio::fs::remove_file(&gif_path).await.ok();
+				return Err(EncodeError::Failed {
+					status: output.status,
+					stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+				});
+			}
+
+			if tokio::fs::metadata(&gif_path).await?.len() < size_limit {
+				tokio::fs::remove_file(path).await.ok();
+				return Ok(gif_path);
+			}
+
+			log::info!("GIF at {max_width}px and {fps} fps is too large, trying smaller...");
+		}
+
+		tokio::fs::remove_file(&gif_path).await.ok();
+		Err(EncodeError::BitrateTooLow)
+	}
+
+	async fn render_waveform(&self, path: &Path, duration: Duration, options: &DownloadOptions, threads: usize) -> Result<PathBuf, EncodeError> {
+		let waveform_path = path.with_file_name(format!("{}_waveform.mp4", path.file_stem().unwrap().to_string_lossy()));
+
+		let target_size_mb = options.target_size_mb.unwrap_or(options.size_limit() as f64 / MB as f64);
+
+		// A waveform compresses far better than real footage, so it can get by with much less bitrate
+		let (video_bitrate_kbps, audio_bitrate_kbps) = Self::calculate_bitrates(target_size_mb, duration.as_secs_f64());
+		if video_bitrate_kbps < 100.0 {
+			return Err(EncodeError::BitrateTooLow);
+		}
+
+		let output = process::ffmpeg()
+			.arg("-i")
+			.arg(path)
+			.args([
+				"-filter_complex",
+				"[0:a]showwaves=s=640x360:mode=cline:rate=25:colors=white,format=yuv420p[v]",
+				"-map",
+				"[v]",
+				"-map",
+				"0:a",
+				"-vcodec",
+				"libx264",
+				"-acodec",
+				"aac",
+				"-b:v",
+				&format!("{:.0}k", video_bitrate_kbps.min(500.0)),
+				"-b:a",
+				&format!("{audio_bitrate_kbps:.0}k"),
+				"-movflags",
+				"+faststart",
+				"-shortest",
+				"-threads",
+				&threads.to_string(),
+			])
+			.args(if options.strip_video_metadata {
+				&["-map_metadata", "-1"][..]
+			} else {
+				&[]
+			})
+			.arg(&waveform_path)
+			.kill_on_drop(true)
+			.output()
+			.await?;
+
+		if output.status.success() && waveform_path.is_file() {
+			tokio::fs::remove_file(path).await.ok();
+			Ok(waveform_path)
+		} else {
+			tokio::fs::remove_file(&waveform_path).await.ok();
+			Err(EncodeError::Failed {
+				status: output.status,
+				stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+			})
 		}
 	}
 
@@ -321,13 +996,24 @@ impl YtDlp {
 	}
 }
 
-enum ReencodeVideoError {
-	Io(std::io::Error),
-	BitrateTooLow,
+/// Where a link redirects to, the Content-Type it ends up at, and the headers that got it there
+#[derive(Clone)]
+struct LinkProbe {
+	url: String,
+	content_type: Option<String>,
+	headers: JobHeaders,
 }
 
+/// Shared with the download while the probe is still in flight
+type PrefetchedProbe = Arc<tokio::sync::OnceCell<Option<LinkProbe>>>;
+
 struct YtDlpDaemonInner {
 	client: reqwest::Client,
+	/// Doesn't follow redirects, `redirects::resolve` does so itself
+	probe_client: reqwest::Client,
+	queue: DownloadQueue,
+	/// Links probed ahead of their download, by URL
+	prefetched: parking_lot::Mutex<HashMap<Box<str>, PrefetchedProbe>>,
 	yt_dlp: RwLock<YtDlp>,
 	last_update_check: Mutex<Instant>,
 }
@@ -335,7 +1021,7 @@ struct YtDlpDaemonInner {
 #[derive(Clone)]
 pub struct YtDlpDaemon(Arc<YtDlpDaemonInner>);
 impl YtDlpDaemon {
-	pub async fn new() -> Result<Self, anyhow::Error> {
+	pub async fn new(queue: DownloadQueue) -> Result<Self, UpdateError> {
 		log::info!("Initializing yt-dlp daemon...");
 
 		if Path::new("yt_dlp_out").exists() {
@@ -344,12 +1030,15 @@ impl YtDlpDaemon {
 
 		Ok(Self(Arc::new(YtDlpDaemonInner {
 			client: reqwest::Client::new(),
+			probe_client: reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).build()?,
+			queue,
+			prefetched: Default::default(),
 			yt_dlp: RwLock::new(YtDlp::new().await?),
 			last_update_check: Mutex::new(Instant::now()),
 		})))
 	}
 
-	pub async fn update(&self) -> Result<(), anyhow::Error> {
+	pub async fn update(&self) -> Result<(), UpdateError> {
 		log::info!("Automatic yt-dlp daemon update check...");
 
 		let release = YtDlpRelease::latest().await?;
@@ -370,74 +1059,355 @@ impl YtDlpDaemon {
 		Ok(())
 	}
 
-	pub async fn download(&self, url: &str) -> Result<DownloadedMedia, anyhow::Error> {
-		let path = uuid::Uuid::new_v4().to_string();
-		let path = Path::new("yt_dlp_out").join(path).into_boxed_path();
+	/// Like [`Downloader::download`], into `dir` rather than alongside the bot's other downloads
+	pub async fn download_in(&self, url: &str, dir: &Path, options: &DownloadOptions) -> Result<MediaResult, DownloadError> {
+		let path = dir.join(uuid::Uuid::new_v4().to_string()).into_boxed_path();
 
-		tokio::fs::create_dir_all("yt_dlp_out").await.context("creating yt_dlp_out directory")?;
+		tokio::fs::create_dir_all(dir).await?;
 
-		let url = async {
-			Ok(self
-				.0
-				.client
-				.head(url)
-				.header("User-Agent", USER_AGENT)
-				.send()
-				.await?
-				.error_for_status()?
-				.url()
-				.to_string())
+		let partial_files = PartialFiles(Some(path.clone()));
+		let result = self.download_to(url, &path, options).await;
+		if result.is_ok() {
+			partial_files.keep();
 		}
-		.await
-		.map(Cow::Owned)
-		.unwrap_or_else(|err: reqwest::Error| {
-			if cfg!(debug_assertions) {
-				log::warn!("Failed to follow redirects: {err}");
-			}
+		result
+	}
 
-			Cow::Borrowed(url)
-		});
+	/// Downloads `url` to files named after `path`, with whatever extension suits the media
+	async fn download_to(&self, url: &str, path: &Path, options: &DownloadOptions) -> Result<MediaResult, DownloadError> {
+		options.progress.report(DownloadProgress::FetchingMetadata);
+
+		// Picked from the link as posted, so the prefetch, the extractors and yt-dlp all send the same one
+		let user_agent = options.user_agent(url);
+
+		let prefetched = self.0.prefetched.lock().remove(url);
+		let probe = match prefetched {
+			Some(prefetched) => prefetched.get_or_init(|| self.probe(url, user_agent)).await.clone(),
+			None => self.probe(url, user_agent).await,
+		};
+
+		let (url, content_type, mut headers) = match probe {
+			Some(LinkProbe { url, content_type, headers }) => (Cow::Owned(url), content_type, headers),
+			None => (Cow::Borrowed(url), None, JobHeaders::new(user_agent)),
+		};
+
+		// Why the TikTok photo API couldn't download the link, if it's a photo post
+		let mut photo_err = None;
 
 		if let Some(photo_id) = tiktok::get_tiktok_photo_id_from_url(&url) {
 			// TikTok slideshow
 
-			let path = tiktok::extract_slideshow_images(photo_id, &path).await?;
+			let (slideshow, download_time) = {
+				let _permit = self.0.queue.download(options.priority).await;
+				let started = Instant::now();
+				options.progress.report(DownloadProgress::Downloading { percent: None });
+				let slideshow = tiktok::extract_slideshow_images(photo_id, path, &options.tiktok_region, &options.slideshow_options()).await;
+				(slideshow, started.elapsed())
+			};
+
+			match slideshow {
+				Ok(path) => {
+					log::info!("Downloaded TikTok photo post {url} through the photo API");
+
+					return Ok(MediaResult::new(
+						"tiktok-slideshow",
+						Processed::unprocessed(path),
+						download_time,
+						MediaMetadata::default(),
+					));
+				}
 
-			return Ok(DownloadedMedia {
-				path: path.into_boxed_path(),
-				url: None,
-			});
+				// Captchas and region blocks, but some photo posts have a video rendition yt-dlp can download
+				Err(err) => {
+					log::warn!("TikTok photo API failed for {url}, trying yt-dlp ({err})");
+					photo_err = Some(err);
+
+					// yt-dlp would take a half-written slideshow for an earlier download
+					tokio::fs::remove_file(path.with_extension("mp4")).await.ok();
+				}
+			}
 		}
 
+		let spotify_track = match spotify::get_spotify_track_id_from_url(&url) {
+			Some(track_id) => Some(
+				spotify::get_track(&self.0.client, track_id)
+					.await
+					.map_err(|err| DownloadError::extractor("spotify", err))?,
+			),
+			None => None,
+		};
+
+		let url = match &spotify_track {
+			Some(track) => {
+				// The referer and cookies were for Spotify, not wherever the track is downloaded from
+				headers = JobHeaders::new(user_agent);
+				// No preview to download
+				Cow::Owned(track.resolve(options.spotify_resolver).ok_or(DownloadError::NoMedia)?)
+			}
+			None => url,
+		};
+
 		self.update_check().await; // This will complete really quickly and do stuff in the background.
 
-		let out_path = path.with_extension("mp4");
+		let out_path = path.with_extension(if options.audio_only { "mp3" } else { "mp4" });
 
-		// TODO WAF bypass for TikTok (proof of work)
+		let ctx = ExtractContext {
+			client: &self.0.client,
+			cookies_file: options.cookies_file.as_deref(),
+			user_agent: &headers.user_agent,
+			size_limit: options.size_limit(),
+			slideshow: options.slideshow_options(),
+		};
+
+		if options.dispatch_by_content_type {
+			match content_type.as_deref() {
+				Some(content_type) if content_type.starts_with("video/") || content_type.starts_with("audio/") => {
+					return self.extract(&extractor::DirectFile, &ctx, &url, &out_path, options).await;
+				}
+
+				Some(content_type) if content_type.starts_with("image/") => {
+					// Images don't need any post-processing, but do need the right extension to show up inline
+					let ext = match content_type.trim_start_matches("image/") {
+						"jpeg" => "jpg",
+						"svg+xml" => "svg",
+						ext => ext,
+					};
+
+					let out_path = path.with_extension(ext);
+					let download_time = {
+						let _permit = self.0.queue.download(options.priority).await;
+						let started = Instant::now();
+						extractor::DirectFile
+							.extract(&ctx, &url, &out_path)
+							.await
+							.map_err(|err| DownloadError::extractor(extractor::DirectFile.name(), err))?;
+						started.elapsed()
+					};
+
+					if options.strip_image_metadata {
+						if let Err(err) = exif::strip_metadata(&out_path).await {
+							tokio::fs::remove_file(&out_path).await.ok();
+							return Err(err.into());
+						}
+					}
 
-		let result = self.0.yt_dlp.read().await.download(&url, &out_path).await;
+					return Ok(MediaResult::new(
+						extractor::DirectFile.name(),
+						Processed::unprocessed(out_path),
+						download_time,
+						MediaMetadata::default(),
+					));
+				}
 
-		if result.is_err() && url.contains("tiktok.com") {
-			// Try the fallback TikTok download script
-			log::info!("yt-dlp failed to download TikTok video, trying fallback TikTok download script...");
+				// Web pages (or servers that don't answer HEAD properly) go to yt-dlp's generic extractor
+				Some("text/html") | None => {}
 
-			let result = tiktok::run_fallback_download_script(&url, &out_path).await;
+				Some(content_type) => return Err(DownloadError::UnsupportedContentType(content_type.into())),
+			}
+		}
 
-			match result {
-				Ok(_) => {
-					return Ok(DownloadedMedia {
-						path: out_path.into_boxed_path(),
-						url: None,
-					});
+		if let Some(plugin) = options.plugins.iter().find(|plugin| plugin.matches(&url)) {
+			match self.extract(plugin, &ctx, &url, &out_path, options).await {
+				Ok(media) => return Ok(media),
+				Err(err) => log::warn!(
+					"Plugin {} failed to download {url}, falling back to the builtin extractors ({err})",
+					plugin.name()
+				),
+			}
+		}
+
+		// The direct extractors have no choice of quality
+		if !options.audio_only && options.quality.is_none() {
+			if let Some(extractor) = extractor::direct_extractors().iter().find(|extractor| extractor.matches(&url)) {
+				match self.extract(*extractor, &ctx, &url, &out_path, options).await {
+					Ok(media) => return Ok(media),
+					Err(err) => log::warn!("{} failed to download {url}, falling back to yt-dlp ({err})", extractor.name()),
 				}
+			}
+		}
 
-				Err(err) => {
-					log::error!("Fallback TikTok download script failed: {}", err);
+		// TODO WAF bypass for TikTok (proof of work)
+
+		let result = self
+			.0
+			.yt_dlp
+			.read()
+			.await
+			.download(&url, &out_path, options, &headers, &self.0.queue)
+			.await
+			.map(|mut media| {
+				// yt-dlp only sees the preview file or search result, not the track itself
+				if let Some(track) = spotify_track {
+					media.metadata.title = Some(track.title);
+					media.metadata.uploader = track.artist;
+				}
+				media
+			});
+
+		if result.is_err() && !options.audio_only {
+			for extractor in extractor::fallback_extractors().iter().filter(|extractor| extractor.matches(&url)) {
+				log::info!("yt-dlp failed to download {url}, trying {}...", extractor.name());
+
+				match self.extract(*extractor, &ctx, &url, &out_path, options).await {
+					Ok(media) => {
+						log::info!("Fallback {} downloaded {url}", extractor.name());
+						return Ok(media);
+					}
+					Err(err) => log::error!("Fallback {} failed: {}", extractor.name(), err),
 				}
 			}
 		}
 
-		result
+		match (result, photo_err) {
+			(Ok(media), Some(_)) => {
+				log::info!("Downloaded TikTok photo post {url} through yt-dlp after the photo API failed");
+				Ok(media)
+			}
+
+			(Err(video), Some(photo)) => Err(DownloadError::TikTokPhoto {
+				photo,
+				video: Box::new(video),
+			}),
+
+			(result, None) => result,
+		}
+	}
+
+	/// Processes a file the user already has, like a Discord attachment, skipping yt-dlp and the extractors
+	pub async fn download_file(&self, url: &str, file_name: &str, options: &DownloadOptions) -> Result<MediaResult, DownloadError> {
+		tokio::fs::create_dir_all("yt_dlp_out").await?;
+
+		// Keep the extension so ffprobe and ffmpeg pick the right demuxer for e.g. .mov and .mkv
+		let ext = Path::new(file_name)
+			.extension()
+			.and_then(|ext| ext.to_str())
+			.filter(|ext| ext.chars().all(|c| c.is_ascii_alphanumeric()))
+			.unwrap_or("mp4");
+
+		let out_path = Path::new("yt_dlp_out").join(uuid::Uuid::new_v4().to_string()).with_extension(ext);
+
+		let ctx = ExtractContext {
+			client: &self.0.client,
+			cookies_file: None,
+			user_agent: options.user_agent(url),
+			size_limit: options.size_limit(),
+			slideshow: options.slideshow_options(),
+		};
+
+		self.extract(&extractor::DirectFile, &ctx, url, &out_path, options).await
+	}
+
+	/// Downloads the image at `url` to a new file, if it fits in the upload limit, for a preview of media too large to upload
+	pub async fn download_thumbnail(&self, url: &str, options: &DownloadOptions) -> Result<PathBuf, ExtractError> {
+		tokio::fs::create_dir_all("yt_dlp_out").await?;
+
+		let out_path = Path::new("yt_dlp_out").join(uuid::Uuid::new_v4().to_string()).with_extension("thumbnail");
+
+		let ctx = ExtractContext {
+			client: &self.0.client,
+			cookies_file: None,
+			user_agent: options.user_agent(url),
+			size_limit: options.size_limit(),
+			slideshow: options.slideshow_options(),
+		};
+
+		match tokio::time::timeout(
+			THUMBNAIL_TIMEOUT,
+			extractor::download_file_capped(&ctx, url, &out_path, options.size_limit()),
+		)
+		.await
+		{
+			Ok(Ok(())) => Ok(out_path),
+			Ok(Err(err)) => Err(err),
+			Err(_) => {
+				tokio::fs::remove_file(&out_path).await.ok();
+				Err(ExtractError::Timeout)
+			}
+		}
+	}
+
+	async fn extract(
+		&self,
+		extractor: &dyn Extractor,
+		ctx: &ExtractContext<'_>,
+		url: &str,
+		out_path: &Path,
+		options: &DownloadOptions,
+	) -> Result<MediaResult, DownloadError> {
+		let (metadata, download_time) = match async {
+			let _permit = self.0.queue.download(options.priority).await;
+			let started = Instant::now();
+			options.progress.report(DownloadProgress::Downloading { percent: None });
+			extractor.extract(ctx, url, out_path).await.map(|metadata| (metadata, started.elapsed()))
+		}
+		.await
+		{
+			Ok(downloaded) => downloaded,
+			Err(err) => {
+				tokio::fs::remove_file(out_path).await.ok();
+				return Err(DownloadError::extractor(extractor.name(), err));
+			}
+		};
+
+		let processed = match self.encode(out_path, options).await {
+			Ok(processed) => processed,
+			Err(err) => {
+				tokio::fs::remove_file(out_path).await.ok();
+				return Err(err.into());
+			}
+		};
+
+		Ok(MediaResult::new(extractor.name(), processed, download_time, metadata))
+	}
+
+	/// Follows the link's redirects and looks up its Content-Type in the background, for a download of it that's likely to follow
+	pub fn prefetch(&self, url: &str, options: &DownloadOptions) {
+		// Already prefetching, replacing it would orphan the probe in progress
+		let probe = match self.0.prefetched.lock().entry(url.into()) {
+			Entry::Occupied(_) => return,
+			Entry::Vacant(entry) => entry.insert(Arc::new(tokio::sync::OnceCell::new())).clone(),
+		};
+
+		let this = self.clone();
+		let url = url.to_owned();
+		let user_agent = options.user_agent(&url).to_owned();
+		tokio::spawn(async move {
+			probe.get_or_init(|| this.probe(&url, &user_agent)).await;
+
+			// Forget it if the link was never downloaded after all
+			tokio::time::sleep(PREFETCH_TTL).await;
+
+			let mut prefetched = this.0.prefetched.lock();
+			if prefetched.get(url.as_str()).is_some_and(|prefetched| Arc::ptr_eq(prefetched, &probe)) {
+				prefetched.remove(url.as_str());
+			}
+		});
+	}
+
+	/// `None` if the link couldn't be reached, yt-dlp may still have better luck
+	async fn probe(&self, url: &str, user_agent: &str) -> Option<LinkProbe> {
+		match redirects::resolve(&self.0.probe_client, url, user_agent).await {
+			Ok(resolved) => Some(LinkProbe {
+				url: resolved.url.into(),
+				content_type: resolved.content_type,
+				headers: resolved.headers,
+			}),
+			Err(err) => {
+				if cfg!(debug_assertions) {
+					log::warn!("Failed to follow redirects: {err}");
+				}
+				None
+			}
+		}
+	}
+
+	pub async fn direct_url(&self, url: &str, options: &DownloadOptions) -> Result<String, DownloadError> {
+		self.0.yt_dlp.read().await.direct_url(url, options, &self.0.queue).await
+	}
+
+	/// For other ffmpeg work on downloaded media, like burning in subtitles
+	pub fn queue(&self) -> &DownloadQueue {
+		&self.0.queue
 	}
 
 	async fn update_check(&self) {
@@ -459,30 +1429,679 @@ impl YtDlpDaemon {
 	}
 }
 
-pub struct DownloadedMedia {
+#[async_trait]
+impl Downloader for YtDlpDaemon {
+	async fn download(&self, url: &str, options: &DownloadOptions) -> Result<MediaResult, DownloadError> {
+		self.download_in(url, Path::new("yt_dlp_out"), options).await
+	}
+}
+
+#[async_trait]
+impl Encoder for YtDlpDaemon {
+	async fn encode(&self, path: &Path, options: &DownloadOptions) -> Result<Processed, EncodeError> {
+		self.0.yt_dlp.read().await.post_process(path, options, &self.0.queue).await
+	}
+}
+
+/// Everything a download writes (yt-dlp's `.part` files and fragments, intermediate encodes, cookies...) is named after its path,
+/// so whatever a failed or cancelled download left behind can be found and deleted
+struct PartialFiles(Option<Box<Path>>);
+impl PartialFiles {
+	/// The download succeeded, its files are the `MediaResult`'s to delete
+	fn keep(mut self) {
+		self.0 = None;
+	}
+}
+impl Drop for PartialFiles {
+	fn drop(&mut self) {
+		let Some(path) = self.0.take() else {
+			return;
+		};
+
+		let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+			return;
+		};
+
+		runtime.spawn(async move {
+			let (Some(dir), Some(prefix)) = (path.parent(), path.file_name()) else {
+				return;
+			};
+			let prefix = prefix.to_string_lossy();
+
+			let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+				return;
+			};
+			while let Ok(Some(entry)) = entries.next_entry().await {
+				if entry.file_name().to_string_lossy().starts_with(&*prefix) {
+					tokio::fs::remove_file(entry.path()).await.ok();
+				}
+			}
+		});
+	}
+}
+
+/// Whether yt-dlp failed because there's no media at the link, as opposed to failing to download it
+fn is_unsupported(stderr: &str, options: &DownloadOptions) -> bool {
+	stderr.contains("yt_dlp.utils.UnsupportedError") || options.no_video.as_deref().is_some_and(|no_video| stderr.contains(no_video))
+}
+
+/// Per-download knobs, configured per link regex
+#[derive(Debug, Clone, Default)]
+pub struct DownloadOptions {
+	pub max_duration: Option<Duration>,
+	pub audio_only: bool,
+	pub force_reencode: bool,
+	pub target_size_mb: Option<f64>,
+	pub spotify_resolver: SpotifyResolver,
+	pub twitch_oauth_token: Option<Box<str>>,
+	pub twitch_vod_segment: Option<Duration>,
+	pub cookies_file: Option<Arc<Path>>,
+	/// Catch-all mode, where the Content-Type of the link decides how to download it
+	pub dispatch_by_content_type: bool,
+	pub plugins: Arc<[ScriptPlugin]>,
+	/// Strip EXIF and other metadata from images
+	pub strip_image_metadata: bool,
+	/// Strip container metadata from videos and audio, as part of re-encoding or with an extra remux
+	pub strip_video_metadata: bool,
+	/// Stream the download into ffmpeg's metadata remux instead of remuxing the finished file
+	pub pipe_remux: bool,
+	/// Passed to yt-dlp's `--concurrent-fragments`, for media streamed in fragments
+	pub concurrent_fragments: Option<NonZeroUsize>,
+	/// Overrides the default format selection, which picks whatever fits in Discord's upload limit
+	pub quality: Option<VideoQuality>,
+	/// Upload limit of the guild the media is going to, which server boosts raise. Discord's default limit if `None`
+	pub size_limit: Option<u64>,
+	/// Decides who gets the next download and encode slot when they're all busy
+	pub priority: Priority,
+	/// Rotation of user agents to download with, the builtin one if empty
+	pub user_agents: Arc<[Box<str>]>,
+	/// Told how far along the download is, for `/download`'s progress updates
+	pub progress: ProgressReporter,
+	/// Only download this part of the media
+	pub trim: Option<Trim>,
+	/// Fetch the site's subtitles, if it has any
+	pub subtitles: Option<SubtitleDelivery>,
+	/// Turn short clips without sound into GIFs
+	pub gif: bool,
+	/// yt-dlp errors containing this mean there's no video at the link, so it's ignored rather than reported as a failure
+	pub no_video: Option<Box<str>>,
+	/// Largest canvas slideshows are rendered at
+	pub slideshow_max_resolution: MaxResolution,
+	/// Drawn over slideshows to number each slide
+	pub slideshow_page_indicator: Option<Arc<PageIndicator>>,
+	/// Where TikTok API requests claim to come from
+	pub tiktok_region: Arc<TikTokRegion>,
+	/// Keep yt-dlp's verbose output in [`MediaResult::log`], for `/admin debug-download`
+	pub verbose: bool,
+}
+impl DownloadOptions {
+	pub fn size_limit(&self) -> u64 {
+		self.size_limit.unwrap_or(DISCORD_FILE_SIZE_LIMIT)
+	}
+
+	pub fn slideshow_options(&self) -> SlideshowOptions {
+		SlideshowOptions {
+			max_resolution: self.slideshow_max_resolution,
+			page_indicator: self.slideshow_page_indicator.clone(),
+			size_limit: self.size_limit(),
+		}
+	}
+
+	/// The user agent for every request made to download `url`. Spread across the rotation by the link, so each link keeps
+	/// looking like the same browser, even on retries
+	pub fn user_agent(&self, url: &str) -> &str {
+		use std::hash::{DefaultHasher, Hash, Hasher};
+
+		if self.user_agents.is_empty() {
+			return USER_AGENT;
+		}
+
+		let mut hasher = DefaultHasher::new();
+		url.hash(&mut hasher);
+		&self.user_agents[(hasher.finish() % self.user_agents.len() as u64) as usize]
+	}
+}
+
+/// Prefers formats that already fit in `size_limit`, with about 80% of it for the video and 20% for the audio when merging
+fn video_format_selector(size_limit: u64) -> String {
+	let total = size_limit / MB;
+	let (video, audio) = (total * 4 / 5, total / 5);
+
+	format!(
+		"http*[filesize<{total}M]/best[filesize<{total}MB]/http*[filesize<{video}M]+http*[filesize<{audio}M]/http*[filesize<{video}M]/bestvideo[filesize<{video}MB]+bestaudio[filesize<{audio}MB]/bestvideo[filesize<{video}MB]+bestaudio/best/bestvideo+bestaudio"
+	)
+}
+
+/// A single file with both video and audio that fits in `size_limit`, which can be streamed without merging. Nothing matches
+/// otherwise, so the download falls back to `video_format_selector`
+fn piped_format_selector(size_limit: u64) -> String {
+	let total = size_limit / MB;
+	format!("best[vcodec!=none][acodec!=none][filesize<{total}M]/best[vcodec!=none][acodec!=none][filesize_approx<{total}M]")
+}
+
+/// yt-dlp `--match-filter` for `max_duration`, on the length of what's left after trimming
+fn duration_filter(options: &DownloadOptions) -> Option<String> {
+	let max_duration = options.max_duration?;
+	let start = options.trim.map_or(Duration::ZERO, |trim| trim.start);
+
+	// Never longer than the trim, however long the media is
+	if options
+		.trim
+		.and_then(|trim| trim.end)
+		.is_some_and(|end| end.saturating_sub(start) <= max_duration)
+	{
+		return None;
+	}
+
+	Some(format!("duration <= {}", (max_duration + start).as_secs()))
+}
+
+/// Whether a direct media URL works for anyone else who opens it. CDNs like YouTube's sign their URLs for the IP address that
+/// asked for them, or for a few hours
+fn is_shareable_url(url: &str) -> bool {
+	let Some((address, query)) = url.split_once('?') else {
+		return true;
+	};
+
+	let host = address.split("://").nth(1).and_then(|rest| rest.split('/').next()).unwrap_or_default();
+	if host.ends_with(".googlevideo.com") {
+		return false;
+	}
+
+	!query.split('&').any(|param| {
+		let name = param.split('=').next().unwrap_or_default();
+		["ip", "expire", "expires", "x-expires"]
+			.iter()
+			.any(|signed| name.eq_ignore_ascii_case(signed))
+	})
+}
+
+/// Whether the download only needs a metadata remux, and none of the options that need yt-dlp to write the file itself
+fn can_pipe_remux(url: &str, options: &DownloadOptions) -> bool {
+	options.pipe_remux
+		&& options.strip_video_metadata
+		&& !options.audio_only
+		&& !options.force_reencode
+		&& options.quality.is_none()
+		&& options.trim.is_none()
+		&& options.subtitles.is_none()
+		&& !url.contains("twitch.tv")
+}
+
+/// Resolution picked by the user for a single download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoQuality {
+	P480,
+	P720,
+	P1080,
+	Best,
+}
+impl VideoQuality {
+	pub const CHOICES: [(&'static str, Self); 4] = [("480p", Self::P480), ("720p", Self::P720), ("1080p", Self::P1080), ("best", Self::Best)];
+
+	pub fn from_choice(choice: &str) -> Option<Self> {
+		Self::CHOICES.iter().find(|(name, _)| *name == choice).map(|(_, quality)| *quality)
+	}
+
+	/// Prefers formats that fit in `size_limit`, like `video_format_selector`, rather than the largest there is at the chosen height
+	fn format_selector(self, size_limit: u64) -> String {
+		let height = match self {
+			Self::P480 => "[height<=480]",
+			Self::P720 => "[height<=720]",
+			Self::P1080 => "[height<=1080]",
+			Self::Best => "",
+		};
+
+		let total = size_limit / MB;
+		let (video, audio) = (total * 4 / 5, total / 5);
+		let mut selector = format!(
+			"bestvideo{height}[filesize<{video}M]+bestaudio[filesize<{audio}M]/best{height}[filesize<{total}M]/bestvideo{height}[filesize<{video}M]+bestaudio/bestvideo{height}+bestaudio/best{height}"
+		);
+
+		// Anything at all rather than nothing, if the site has nothing at or under the height
+		if self != Self::Best {
+			selector.push_str("/best");
+		}
+
+		selector
+	}
+}
+
+/// Part of the media picked by the user for a single download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trim {
+	pub start: Duration,
+	/// The end of the media if `None`
+	pub end: Option<Duration>,
+}
+impl Trim {
+	/// Timestamps like `83`, `1:23`, `1:02:03` or `1h2m3s`. `Ok(None)` if neither is given
+	pub fn parse(start: Option<&str>, end: Option<&str>) -> Result<Option<Self>, &'static str> {
+		let timestamp = |timestamp: Option<&str>| match timestamp.map(str::trim).filter(|timestamp| !timestamp.is_empty()) {
+			Some(timestamp) => parse_timestamp(timestamp).map(Some).ok_or("Timestamps look like 1:23, 1:02:03 or 1h2m3s"),
+			None => Ok(None),
+		};
+
+		let trim = match (timestamp(start)?, timestamp(end)?) {
+			(None, None) => return Ok(None),
+			(start, end) => Self {
+				start: start.unwrap_or_default(),
+				end,
+			},
+		};
+
+		if trim.end.is_some_and(|end| end <= trim.start) {
+			return Err("The end has to be after the start");
+		}
+
+		Ok(Some(trim))
+	}
+
+	/// yt-dlp's `--download-sections`
+	fn download_sections(self) -> String {
+		match self.end {
+			Some(end) => format!("*{}-{}", self.start.as_secs(), end.as_secs()),
+			None => format!("*{}-inf", self.start.as_secs()),
+		}
+	}
+
+	/// ffmpeg's input options to seek to the section
+	fn ffmpeg_args(self) -> Vec<String> {
+		let mut args = vec!["-ss".to_string(), self.start.as_secs().to_string()];
+		if let Some(end) = self.end {
+			args.extend(["-to".to_string(), end.as_secs().to_string()]);
+		}
+		args
+	}
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+	let secs = if timestamp.contains(':') {
+		let mut parts = timestamp.split(':').rev();
+		let secs = parts.next()?.parse::<u64>().ok()?;
+		let mins = parts.next().map_or(Some(0), |mins| mins.parse::<u64>().ok())?;
+		let hours = parts.next().map_or(Some(0), |hours| hours.parse::<u64>().ok())?;
+		if parts.next().is_some() || secs >= 60 || (mins >= 60 && hours > 0) {
+			return None;
+		}
+		hours * 3600 + mins * 60 + secs
+	} else {
+		let captures = UNIT_TIMESTAMP.captures(timestamp)?;
+		let unit = |i: usize| captures.get(i).and_then(|n| n.as_str().parse::<u64>().ok()).unwrap_or(0);
+		unit(1) * 3600 + unit(2) * 60 + unit(3)
+	};
+
+	Some(Duration::from_secs(secs))
+}
+
+/// A downloaded file after post-processing
+pub struct Processed {
+	pub path: PathBuf,
+	/// Whether ffmpeg re-encoded the video or rendered a waveform for it
+	pub reencoded: bool,
+	pub stream: Option<StreamInfo>,
+	pub time: Duration,
+}
+impl Processed {
+	/// Media that doesn't go through post-processing, like images
+	fn unprocessed(path: PathBuf) -> Self {
+		Self {
+			path,
+			reencoded: false,
+			stream: None,
+			time: Duration::ZERO,
+		}
+	}
+}
+
+/// What ffprobe made of the file as it will be posted
+#[derive(Debug, Clone)]
+pub struct StreamInfo {
+	pub duration: Duration,
+	/// Of the first video stream
+	pub dimensions: Option<(u32, u32)>,
+	pub video_codec: Option<Box<str>>,
+	pub audio_codec: Option<Box<str>>,
+}
+impl StreamInfo {
+	fn from_probe(probe: MediaProbe) -> Option<Self> {
+		match probe {
+			MediaProbe::Probed {
+				duration,
+				dimensions,
+				video_codec,
+				audio_codec,
+				..
+			} => Some(Self {
+				duration,
+				dimensions,
+				video_codec,
+				audio_codec,
+			}),
+			MediaProbe::Corrupt => None,
+		}
+	}
+}
+
+/// How long each step of a job took, not counting the time spent queued for a slot
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JobTimings {
+	pub download: Duration,
+	pub post_process: Duration,
+}
+
+/// Everything known about a finished download, so replies and stats don't need to probe the file again
+pub struct MediaResult {
 	pub path: Box<Path>,
 	pub url: Option<Box<str>>,
+	pub metadata: MediaMetadata,
+	/// `None` for images, and media that wasn't post-processed or couldn't be probed
+	pub stream: Option<StreamInfo>,
+	pub reencoded: bool,
+	pub timings: JobTimings,
+	/// What downloaded it: `yt-dlp`, or the name of a builtin extractor or plugin
+	pub extractor: Box<str>,
+	/// The site's subtitles as an .srt, to attach alongside the video
+	pub subtitles: Option<Box<Path>>,
+	/// yt-dlp's output, if downloaded [`DownloadOptions::verbose`]ly
+	pub log: Option<String>,
+}
+impl MediaResult {
+	fn new(extractor: &str, processed: Processed, download_time: Duration, metadata: MediaMetadata) -> Self {
+		Self {
+			path: processed.path.into_boxed_path(),
+			url: None,
+			metadata,
+			stream: processed.stream,
+			reencoded: processed.reencoded,
+			timings: JobTimings {
+				download: download_time,
+				post_process: processed.time,
+			},
+			extractor: extractor.into(),
+			subtitles: None,
+			log: None,
+		}
+	}
+
+	fn from_dump(processed: Processed, download_time: Duration, dump: Option<YtDlpJsonDump>) -> Self {
+		let (url, metadata) = match dump {
+			Some(dump) => (
+				match <[_; 1]>::try_from(dump.requested_downloads) {
+					Ok([requested_download]) => requested_download.url,
+					Err(_) => dump.url,
+				}
+				.map(String::into_boxed_str),
+				MediaMetadata {
+					title: dump.title.map(String::into_boxed_str),
+					uploader: dump.uploader.map(String::into_boxed_str),
+					description: dump.description.map(String::into_boxed_str),
+					site: dump.extractor_key.map(String::into_boxed_str),
+					upload_date: dump.upload_date.map(String::into_boxed_str),
+					duration: dump.duration,
+					view_count: dump.view_count,
+					thumbnail: dump.thumbnail.map(String::into_boxed_str),
+					webpage_url: dump.webpage_url.map(String::into_boxed_str),
+				},
+			),
+			None => (None, MediaMetadata::default()),
+		};
+
+		let mut media = Self::new("yt-dlp", processed, download_time, metadata);
+		media.url = url;
+		media
+	}
 }
-impl Drop for DownloadedMedia {
+impl Drop for MediaResult {
 	fn drop(&mut self) {
 		log::info!("Deleting {}", self.path.display());
 
+		let paths = std::iter::once(self.path.clone()).chain(self.subtitles.clone()).collect::<Vec<_>>();
+
 		if let Ok(rt) = tokio::runtime::Handle::try_current() {
-			let path = self.path.clone();
-			rt.spawn(async move { tokio::fs::remove_file(&path).await });
+			rt.spawn(async move {
+				for path in paths {
+					tokio::fs::remove_file(&path).await.ok();
+				}
+			});
 		} else {
-			std::fs::remove_file(&self.path).ok();
+			for path in paths {
+				std::fs::remove_file(&path).ok();
+			}
 		}
 	}
 }
 
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct MediaMetadata {
+	pub title: Option<Box<str>>,
+	pub uploader: Option<Box<str>>,
+	pub description: Option<Box<str>>,
+	/// yt-dlp's name for the website, e.g. `TikTok`
+	pub site: Option<Box<str>>,
+	/// `YYYYMMDD`
+	pub upload_date: Option<Box<str>>,
+	/// Seconds
+	pub duration: Option<f64>,
+	pub view_count: Option<u64>,
+	/// URL of the site's thumbnail image
+	pub thumbnail: Option<Box<str>>,
+	/// Canonical link to the media's page, which the link posted may have redirected to
+	pub webpage_url: Option<Box<str>>,
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct YtDlpJsonDump {
+	#[serde(default)]
 	requested_downloads: Vec<YtDlpJsonDumpRequestedDownload>,
-	url: String,
+	url: Option<String>,
+	title: Option<String>,
+	uploader: Option<String>,
+	description: Option<String>,
+	extractor_key: Option<String>,
+	upload_date: Option<String>,
+	duration: Option<f64>,
+	view_count: Option<u64>,
+	thumbnail: Option<String>,
+	webpage_url: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize)]
 struct YtDlpJsonDumpRequestedDownload {
-	url: String,
+	url: Option<String>,
+}
+
+#[test]
+fn format_selector_size_limit() {
+	assert_eq!(
+		video_format_selector(DISCORD_FILE_SIZE_LIMIT),
+		"http*[filesize<10M]/best[filesize<10MB]/http*[filesize<8M]+http*[filesize<2M]/http*[filesize<8M]/bestvideo[filesize<8MB]+bestaudio[filesize<2MB]/bestvideo[filesize<8MB]+bestaudio/best/bestvideo+bestaudio"
+	);
+	assert!(video_format_selector(100 * MB).starts_with("http*[filesize<100M]/best[filesize<100MB]/http*[filesize<80M]+http*[filesize<20M]/"));
+	assert!(VideoQuality::Best
+		.format_selector(DISCORD_FILE_SIZE_LIMIT)
+		.starts_with("bestvideo[filesize<8M]+bestaudio[filesize<2M]/best[filesize<10M]/"));
+	assert_eq!(
+		VideoQuality::P1080.format_selector(DISCORD_FILE_SIZE_LIMIT),
+		"bestvideo[height<=1080][filesize<8M]+bestaudio[filesize<2M]/best[height<=1080][filesize<10M]/bestvideo[height<=1080][filesize<8M]+bestaudio/bestvideo[height<=1080]+bestaudio/best[height<=1080]/best"
+	);
+	assert!(VideoQuality::P480
+		.format_selector(100 * MB)
+		.starts_with("bestvideo[height<=480][filesize<80M]+bestaudio[filesize<20M]/best[height<=480][filesize<100M]/"));
+}
+
+#[test]
+fn pipe_remux() {
+	let options = DownloadOptions {
+		pipe_remux: true,
+		strip_video_metadata: true,
+		..Default::default()
+	};
+	assert!(can_pipe_remux("https://www.youtube.com/watch?v=dQw4w9WgXcQ", &options));
+
+	// Twitch needs cookies and sections that only the file download handles
+	assert!(!can_pipe_remux("https://www.twitch.tv/videos/1?t=1h2m3s", &options));
+
+	// Nothing to remux, or yt-dlp has to write the file for extraction and re-encoding
+	for options in [
+		DownloadOptions {
+			strip_video_metadata: false,
+			..options.clone()
+		},
+		DownloadOptions {
+			audio_only: true,
+			..options.clone()
+		},
+		DownloadOptions {
+			quality: Some(VideoQuality::Best),
+			..options.clone()
+		},
+	] {
+		assert!(!can_pipe_remux("https://www.youtube.com/watch?v=dQw4w9WgXcQ", &options));
+	}
+}
+
+#[test]
+fn user_agent_rotation() {
+	let url = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+
+	assert_eq!(DownloadOptions::default().user_agent(url), USER_AGENT);
+
+	let options = DownloadOptions {
+		user_agents: ["a", "b", "c"].into_iter().map(Into::into).collect(),
+		..Default::default()
+	};
+	assert_eq!(options.user_agent(url), options.clone().user_agent(url));
+	assert!(options.user_agents.iter().any(|user_agent| &**user_agent == options.user_agent(url)));
+}
+
+#[test]
+fn trim_timestamps() {
+	assert_eq!(parse_timestamp("83"), Some(Duration::from_secs(83)));
+	assert_eq!(parse_timestamp("1:23"), Some(Duration::from_secs(83)));
+	assert_eq!(parse_timestamp("1:02:03"), Some(Duration::from_secs(3723)));
+	assert_eq!(parse_timestamp("1h2m3s"), Some(Duration::from_secs(3723)));
+	assert_eq!(parse_timestamp("2m"), Some(Duration::from_secs(120)));
+	assert_eq!(parse_timestamp("1:75"), None);
+	assert_eq!(parse_timestamp("soon"), None);
+
+	assert_eq!(Trim::parse(None, Some(" ")), Ok(None));
+	assert_eq!(Trim::parse(Some("1:00"), None).unwrap().unwrap().download_sections(), "*60-inf");
+	assert_eq!(Trim::parse(None, Some("30")).unwrap().unwrap().ffmpeg_args(), ["-ss", "0", "-to", "30"]);
+	assert!(Trim::parse(Some("1:00"), Some("0:30")).is_err());
+}
+
+#[test]
+fn latest_release() {
+	let release = |tag_name: &str, draft: bool, prerelease: bool| {
+		serde_json::json!({
+			"tag_name": tag_name,
+			"draft": draft,
+			"prerelease": prerelease,
+			"assets": [{
+				"name": YT_DLP_EXE,
+				"browser_download_url": format!("https://github.com/{YT_DLP_REPO}/releases/download/{tag_name}/{YT_DLP_EXE}"),
+				"size": 3115012,
+			}],
+		})
+	};
+
+	let releases = serde_json::from_value(serde_json::json!([
+		release("2025.01.17.000000", true, false),
+		release("2025.01.16.000000", false, true),
+		release("2025.01.15.000000", false, false),
+		release("2025.01.14.000000", false, false),
+	]))
+	.unwrap();
+
+	let latest = YtDlpRelease::latest_of(github::Releases(releases)).unwrap();
+	assert_eq!(&*latest.tag_name, "2025.01.15.000000");
+	assert!(latest.browser_download_url.ends_with(&format!("/2025.01.15.000000/{YT_DLP_EXE}")));
+
+	let no_build =
+		serde_json::from_value(serde_json::json!([{ "tag_name": "2025.01.15.000000", "draft": false, "prerelease": false, "assets": [] }])).unwrap();
+	assert!(YtDlpRelease::latest_of(github::Releases(no_build)).is_none());
+}
+
+#[test]
+fn latest_release_replay() {
+	use crate::cassette::Cassette;
+
+	const FIXTURE: &str = "src/fixtures/yt_dlp_releases.json";
+
+	Cassette::run(
+		FIXTURE,
+		&format!("/repos/{YT_DLP_REPO}/releases"),
+		|| async { github::Releases::request(github::GITHUB_API, YT_DLP_REPO) },
+		|uri| async move {
+			let release = YtDlpRelease::latest_from(&uri).await.unwrap();
+
+			// Whatever was recorded, the pick is the newest stable release with a build for this platform
+			let recorded =
+				serde_json::from_slice::<Vec<serde_json::Value>>(&std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURE)).unwrap())
+					.unwrap();
+			let expected = recorded
+				.iter()
+				.find(|release| {
+					release["draft"] == false
+						&& release["prerelease"] == false
+						&& release["assets"].as_array().unwrap().iter().any(|asset| asset["name"] == YT_DLP_EXE)
+				})
+				.unwrap();
+
+			assert_eq!(*release.tag_name, *expected["tag_name"].as_str().unwrap());
+			assert!(release.size > 0);
+			assert!(release.browser_download_url.ends_with(&format!("/{}/{YT_DLP_EXE}", release.tag_name)));
+		},
+	);
+}
+
+#[test]
+fn no_video_errors() {
+	let options = DownloadOptions {
+		no_video: Some("No video could be found in this tweet".into()),
+		..Default::default()
+	};
+
+	assert!(is_unsupported("ERROR: [twitter] 1: No video could be found in this tweet", &options));
+	assert!(is_unsupported(
+		"yt_dlp.utils.UnsupportedError: Unsupported URL",
+		&DownloadOptions::default()
+	));
+	assert!(!is_unsupported(
+		"ERROR: [twitter] 1: No video could be found in this tweet",
+		&DownloadOptions::default()
+	));
+}
+
+#[test]
+fn shareable_direct_urls() {
+	assert!(is_shareable_url("https://video.twimg.com/ext_tw_video/1/pu/vid/720x1280/a.mp4?tag=12"));
+	assert!(is_shareable_url("https://example.com/video.mp4"));
+	assert!(!is_shareable_url("https://rr3---sn-aigl6nze.googlevideo.com/videoplayback?itag=18"));
+	assert!(!is_shareable_url("https://cdn.example.com/video.mp4?ip=203.0.113.7&sig=abc"));
+	assert!(!is_shareable_url(
+		"https://v16-webapp.tiktok.com/video.mp4?x-expires=1700000000&x-signature=abc"
+	));
+}
+
+#[test]
+fn trimmed_duration_filter() {
+	let options = |trim| DownloadOptions {
+		max_duration: Some(Duration::from_secs(60)),
+		trim,
+		..Default::default()
+	};
+	let trim = |start, end: Option<u64>| {
+		Some(Trim {
+			start: Duration::from_secs(start),
+			end: end.map(Duration::from_secs),
+		})
+	};
+
+	assert_eq!(duration_filter(&options(None)).as_deref(), Some("duration <= 60"));
+	assert_eq!(duration_filter(&options(trim(3600, Some(3630)))), None);
+	assert_eq!(duration_filter(&options(trim(30, None))).as_deref(), Some("duration <= 90"));
+	assert_eq!(duration_filter(&options(trim(30, Some(600)))).as_deref(), Some("duration <= 90"));
+	assert_eq!(duration_filter(&DownloadOptions::default()), None);
 }